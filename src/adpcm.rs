@@ -0,0 +1,124 @@
+use crate::{wav::riff_wave, AudyoSample, EncodeError, SampleBuffer};
+
+#[rustfmt::skip]
+const INDEX_TABLE: [i32; 16] = [
+    -1, -1, -1, -1, 2, 4, 6, 8,
+    -1, -1, -1, -1, 2, 4, 6, 8,
+];
+
+#[rustfmt::skip]
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17,
+    19, 21, 23, 25, 28, 31, 34, 37, 41, 45,
+    50, 55, 60, 66, 73, 80, 88, 97, 107, 118,
+    130, 143, 157, 173, 190, 209, 230, 253, 279, 307,
+    337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066,
+    2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358,
+    5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899,
+    15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// Predictor state of a single channel
+#[derive(Clone, Copy, Default)]
+struct Channel {
+    predictor: i32,
+    index: i32,
+}
+
+impl Channel {
+    /// Returns the nibble reconstructing closest to the sample and updates the state like a
+    /// decoder would
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step = STEP_TABLE[self.index as usize];
+        let reconstruct = |nibble: u8| {
+            let diff = ((2 * (nibble & 7) as i32 + 1) * step) >> 3;
+            let predicted = if nibble & 8 != 0 {
+                self.predictor - diff
+            } else {
+                self.predictor + diff
+            };
+            predicted.clamp(i16::MIN as i32, i16::MAX as i32)
+        };
+
+        let nibble = (0..16)
+            .min_by_key(|&nibble| (reconstruct(nibble) - sample as i32).abs())
+            .unwrap_or_default();
+
+        self.predictor = reconstruct(nibble);
+        self.index = (self.index + INDEX_TABLE[nibble as usize]).clamp(0, 88);
+        nibble
+    }
+
+    /// Starts a block at the sample, returning the block header
+    fn header(&mut self, sample: i16) -> [u8; 4] {
+        self.predictor = sample as i32;
+        let [low, high] = sample.to_le_bytes();
+        [low, high, self.index as u8, 0]
+    }
+}
+
+/// Encodes the written frames of a buffer as 4 bit IMA ADPCM in a WAV file, as used by many game engines
+/// Samples get converted to 16 bit first, the last block is padded with silence and the real
+/// length is stored in the fact chunk
+pub fn encode_ima_adpcm<S: AudyoSample>(samples: &SampleBuffer<S>) -> Result<Vec<u8>, EncodeError> {
+    let sample_rate = samples.sample_rate;
+    if sample_rate == 0 {
+        return Err(EncodeError::UnsupportedSampleRate(sample_rate));
+    }
+
+    let channels = samples.channels as usize;
+    // 256 bytes per channel at 11025 Hz, growing with the sample rate like common encoders do
+    let block_align = 256 * channels * (sample_rate as usize / 11_025).clamp(1, 4);
+    let frames_per_block = (block_align - 4 * channels) * 2 / channels + 1;
+
+    let pcm: Vec<i16> = samples.buffer[..samples.written]
+        .iter()
+        .map(|&s| s.into_sample())
+        .collect();
+    let frames = pcm.len() / channels;
+
+    let mut state = vec![Channel::default(); channels];
+    let mut data = Vec::with_capacity(frames.div_ceil(frames_per_block) * block_align);
+    for block in pcm.chunks(frames_per_block * channels) {
+        let sample = |frame: usize, channel: usize| {
+            block.get(frame * channels + channel).copied().unwrap_or(0)
+        };
+
+        for (c, channel) in state.iter_mut().enumerate() {
+            data.extend_from_slice(&channel.header(sample(0, c)));
+        }
+
+        // Each channel contributes 4 bytes (8 samples) at a time, low nibble first
+        for group in (1..frames_per_block).step_by(8) {
+            for (c, channel) in state.iter_mut().enumerate() {
+                for pair in (group..group + 8).step_by(2) {
+                    let low = channel.encode(sample(pair, c));
+                    let high = channel.encode(sample(pair + 1, c));
+                    data.push(low | (high << 4));
+                }
+            }
+        }
+    }
+
+    let mut fmt = Vec::with_capacity(20);
+    // IMA ADPCM format
+    fmt.extend_from_slice(&0x11u16.to_le_bytes());
+    fmt.extend_from_slice(&(channels as u16).to_le_bytes());
+    fmt.extend_from_slice(&sample_rate.to_le_bytes());
+    let byte_rate = sample_rate as u64 * block_align as u64 / frames_per_block as u64;
+    fmt.extend_from_slice(&(byte_rate as u32).to_le_bytes());
+    fmt.extend_from_slice(&(block_align as u16).to_le_bytes());
+    fmt.extend_from_slice(&4u16.to_le_bytes());
+    // Extra format bytes holding the frames per block
+    fmt.extend_from_slice(&2u16.to_le_bytes());
+    fmt.extend_from_slice(&(frames_per_block as u16).to_le_bytes());
+
+    // The fact chunk counts frames in 32 bits as well
+    let frames = u32::try_from(frames).map_err(|_| EncodeError::TooLarge(data.len() as u64))?;
+    riff_wave(&[
+        (*b"fmt ", fmt),
+        (*b"fact", frames.to_le_bytes().to_vec()),
+        (*b"data", data),
+    ])
+}
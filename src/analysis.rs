@@ -0,0 +1,494 @@
+//! Musical analysis built on the STFT: tempo, beats and key
+
+use crate::{
+    resample::{Quality, Resampler},
+    stft::Stft,
+    Channels, SampleBuffer,
+};
+use std::{fmt, time::Duration};
+
+#[cfg(feature = "image")]
+pub use crate::image::{spectrogram_image, ColorMap, ImageBuffer, SpectrogramOptions};
+
+/// Frame size of the onset detection STFT
+const FFT_SIZE: usize = 1024;
+/// Distance between consecutive onset detection frames
+const HOP: usize = 512;
+/// Range of tempos considered
+const MIN_BPM: f32 = 60.;
+const MAX_BPM: f32 = 200.;
+/// Tempo the weighting of candidates is centered on, listeners favour tempos around it
+const PREFERRED_BPM: f32 = 120.;
+/// Width of the weighting in octaves
+const PREFERENCE_OCTAVES: f32 = 1.;
+/// Length of the moving average removed from the onset envelope, in frames
+const MEAN_WINDOW: usize = 16;
+/// Magnitudes are compressed as ln(1 + COMPRESSION * magnitude) before taking the flux
+const COMPRESSION: f32 = 1000.;
+
+/// Chroma is analysed at this rate, which keeps the frequencies it's taken from
+const CHROMA_SAMPLE_RATE: u32 = 11_025;
+/// Frame size of the chroma STFT
+const CHROMA_FFT_SIZE: usize = 4096;
+/// Distance between consecutive chroma frames, a third of the frame size
+const CHROMA_HOP: usize = CHROMA_FFT_SIZE / 3;
+/// Frequency range chroma is taken from
+const CHROMA_MIN_FREQ: f32 = 28.;
+const CHROMA_MAX_FREQ: f32 = 3520.;
+/// Krumhansl-Kessler key profiles, how well each degree of the scale fits a key
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Estimated tempo of a buffer
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoEstimate {
+    /// Beats per minute
+    pub bpm: f32,
+    /// How strongly the onsets repeat at that tempo, from 0 to 1
+    pub confidence: f32,
+    /// Positions of the beats from the start of the buffer
+    pub beats: Vec<Duration>,
+}
+
+/// Estimates the tempo and beat positions of a buffer from the periodicity of its onsets
+/// Returns None if the buffer is too short or has no onsets to go by
+pub fn tempo(buffer: &SampleBuffer<f32>) -> Option<TempoEstimate> {
+    let envelope = onset_envelope(buffer);
+    let frame_rate = buffer.sample_rate as f32 / HOP as f32;
+
+    let min_lag = (60. * frame_rate / MAX_BPM).floor().max(1.) as usize;
+    let max_lag = (60. * frame_rate / MIN_BPM).ceil() as usize;
+    if envelope.len() <= max_lag + 1 {
+        return None;
+    }
+
+    let autocorrelation: Vec<f32> = (0..=max_lag + 1)
+        .map(|lag| {
+            envelope
+                .iter()
+                .zip(&envelope[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (envelope.len() - lag) as f32
+        })
+        .collect();
+    if autocorrelation[0] <= 0. {
+        return None;
+    }
+
+    let weight = |lag: usize| {
+        let octaves = (60. * frame_rate / lag as f32 / PREFERRED_BPM).log2() / PREFERENCE_OCTAVES;
+        (-0.5 * octaves * octaves).exp()
+    };
+    let lag = (min_lag..=max_lag)
+        .max_by(|&a, &b| {
+            (autocorrelation[a] * weight(a)).total_cmp(&(autocorrelation[b] * weight(b)))
+        })
+        .expect("lag range isn't empty");
+
+    // Parabolic interpolation between neighbouring lags gives a fractional period
+    let (before, peak, after) = (
+        autocorrelation[lag - 1],
+        autocorrelation[lag],
+        autocorrelation[lag + 1],
+    );
+    let curvature = before - 2. * peak + after;
+    let offset = if curvature < 0. {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.
+    };
+    let period = lag as f32 + offset;
+
+    Some(TempoEstimate {
+        bpm: 60. * frame_rate / period,
+        confidence: (peak / autocorrelation[0]).clamp(0., 1.),
+        beats: beats(&envelope, period)
+            .into_iter()
+            .map(|frame| {
+                Duration::from_secs_f64(
+                    (frame * HOP + FFT_SIZE / 2) as f64 / buffer.sample_rate as f64,
+                )
+            })
+            .collect(),
+    })
+}
+
+/// Returns the spectral flux of the mixed down buffer with its local mean removed
+fn onset_envelope(buffer: &SampleBuffer<f32>) -> Vec<f32> {
+    let channels = buffer.channels as usize;
+    let mono: Vec<f32> = buffer.buffer[..buffer.written]
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    if mono.len() < FFT_SIZE {
+        return Vec::new();
+    }
+
+    let stft = Stft::new(FFT_SIZE);
+    // Only whole frames, zero padding at the end would read as a drop in level
+    let spectra: Vec<Vec<f32>> = (0..=mono.len() - FFT_SIZE)
+        .step_by(HOP)
+        .map(|start| {
+            stft.forward(&mono[start..start + FFT_SIZE])[..FFT_SIZE / 2 + 1]
+                .iter()
+                .map(|c| (1. + COMPRESSION * c.norm()).ln())
+                .collect()
+        })
+        .collect();
+
+    let mut flux = vec![0.];
+    flux.extend(spectra.windows(2).map(|pair| {
+        pair[1]
+            .iter()
+            .zip(&pair[0])
+            .map(|(now, before)| (now - before).max(0.))
+            .sum::<f32>()
+    }));
+
+    (0..flux.len())
+        .map(|i| {
+            let window =
+                &flux[i.saturating_sub(MEAN_WINDOW / 2)..(i + MEAN_WINDOW / 2).min(flux.len())];
+            let mean = window.iter().sum::<f32>() / window.len() as f32;
+            (flux[i] - mean).max(0.)
+        })
+        .collect()
+}
+
+/// Places beats a period apart at the phase lining up best with the onsets
+fn beats(envelope: &[f32], period: f32) -> Vec<usize> {
+    let strength = |phase| {
+        walk(envelope, period, phase)
+            .into_iter()
+            .map(|beat| envelope[beat])
+            .sum::<f32>()
+    };
+    let phase = (0..period.ceil() as usize)
+        .max_by(|&a, &b| strength(a).total_cmp(&strength(b)))
+        .unwrap_or(0);
+
+    walk(envelope, period, phase)
+}
+
+/// Places beats from the phase on, snapping each to the strongest onset nearby and placing the
+/// next one a period after it, so that a period a little off doesn't drift away from the onsets
+fn walk(envelope: &[f32], period: f32, phase: usize) -> Vec<usize> {
+    let tolerance = (period * 0.1).round() as usize;
+    let mut beats: Vec<usize> = Vec::new();
+    let mut position = phase as f32;
+    while (position.round() as usize) < envelope.len() {
+        let center = position.round() as usize;
+        let range = center.saturating_sub(tolerance)..(center + tolerance + 1).min(envelope.len());
+        let beat = range
+            .max_by(|&a, &b| envelope[a].total_cmp(&envelope[b]))
+            .unwrap_or(center);
+
+        if beats.last() != Some(&beat) {
+            beats.push(beat);
+        }
+        if envelope[beat] > 0. {
+            position = beat as f32;
+        }
+        position += period;
+    }
+    beats
+}
+
+/// Pitch class, the notes of an octave
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PitchClass {
+    C,
+    CSharp,
+    D,
+    DSharp,
+    E,
+    F,
+    FSharp,
+    G,
+    GSharp,
+    A,
+    ASharp,
+    B,
+}
+
+impl PitchClass {
+    const ALL: [PitchClass; 12] = [
+        PitchClass::C,
+        PitchClass::CSharp,
+        PitchClass::D,
+        PitchClass::DSharp,
+        PitchClass::E,
+        PitchClass::F,
+        PitchClass::FSharp,
+        PitchClass::G,
+        PitchClass::GSharp,
+        PitchClass::A,
+        PitchClass::ASharp,
+        PitchClass::B,
+    ];
+}
+
+impl fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: [&str; 12] = [
+            "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+        ];
+        f.write_str(NAMES[*self as usize])
+    }
+}
+
+/// Mode of a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// Estimated musical key of a buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+    pub tonic: PitchClass,
+    pub mode: Mode,
+    /// Correlation of the buffer's chroma with the key's profile, from 0 to 1
+    pub confidence: f32,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Major => write!(f, "{} major", self.tonic),
+            Mode::Minor => write!(f, "{} minor", self.tonic),
+        }
+    }
+}
+
+/// Estimates the musical key of a buffer by matching its average chroma against key profiles
+/// Returns None if the buffer is too short or silent
+pub fn key(buffer: &SampleBuffer<f32>) -> Option<Key> {
+    let mut chroma = [0.; 12];
+    for frame in chromagram(buffer) {
+        chroma.iter_mut().zip(frame).for_each(|(sum, e)| *sum += e);
+    }
+    if chroma.iter().all(|&e| e == 0.) {
+        return None;
+    }
+
+    PitchClass::ALL
+        .iter()
+        .flat_map(|&tonic| {
+            [(Mode::Major, MAJOR_PROFILE), (Mode::Minor, MINOR_PROFILE)].map(|(mode, profile)| {
+                // Rotate the profile so that its first degree lands on the tonic
+                let rotated: [f32; 12] =
+                    std::array::from_fn(|c| profile[(c + 12 - tonic as usize) % 12]);
+                (tonic, mode, correlation(&chroma, &rotated))
+            })
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(tonic, mode, correlation)| Key {
+            tonic,
+            mode,
+            confidence: correlation.clamp(0., 1.),
+        })
+}
+
+/// Pearson correlation of two vectors
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.;
+    let mean_b = b.iter().sum::<f32>() / 12.;
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0., 0., 0.);
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+
+    let denominator = (variance_a * variance_b).sqrt();
+    if denominator > 0. {
+        covariance / denominator
+    } else {
+        0.
+    }
+}
+
+/// Returns the chroma of each frame of the mixed down buffer, normalized so that only the
+/// balance between pitch classes matters, indexed from C
+pub(crate) fn chromagram(buffer: &SampleBuffer<f32>) -> Vec<[f32; 12]> {
+    let mut stream = ChromaStream::new(buffer.channels, buffer.sample_rate);
+    let mut chroma = stream.push(buffer.samples());
+    chroma.extend(stream.finish());
+    chroma
+}
+
+/// Computes a chromagram a block of audio at a time, so that a source never needs to be in
+/// memory whole, giving the same frames as `chromagram` would for all the blocks
+pub(crate) struct ChromaStream {
+    channels: usize,
+    /// None if the audio already is at the chroma rate
+    resampler: Option<Resampler>,
+    /// Output frames of the resampler still to drop, compensating its delay
+    delay: usize,
+    /// Mixed down samples at the chroma rate, starting with the next frame
+    samples: Vec<f32>,
+    stft: Stft,
+    /// Pitch class of each bin, None outside the chroma frequency range
+    classes: Vec<Option<usize>>,
+}
+
+impl ChromaStream {
+    pub(crate) fn new(channels: Channels, sample_rate: u32) -> Self {
+        let resampler = (sample_rate != CHROMA_SAMPLE_RATE).then(|| {
+            Resampler::new(
+                sample_rate,
+                CHROMA_SAMPLE_RATE,
+                Channels::Mono,
+                Quality::default(),
+            )
+        });
+        let classes = (0..CHROMA_FFT_SIZE / 2 + 1)
+            .map(|bin| {
+                let freq = bin as f32 * CHROMA_SAMPLE_RATE as f32 / CHROMA_FFT_SIZE as f32;
+                (CHROMA_MIN_FREQ..=CHROMA_MAX_FREQ)
+                    .contains(&freq)
+                    .then(|| {
+                        let note = 12. * (freq / 440.).log2() + 69.;
+                        (note.round() as i32).rem_euclid(12) as usize
+                    })
+            })
+            .collect();
+
+        Self {
+            channels: channels as usize,
+            delay: resampler.as_ref().map_or(0, Resampler::delay),
+            resampler,
+            samples: Vec::new(),
+            stft: Stft::new(CHROMA_FFT_SIZE),
+            classes,
+        }
+    }
+
+    /// Adds interleaved samples, returning the chroma of the frames they complete
+    pub(crate) fn push(&mut self, samples: &[f32]) -> Vec<[f32; 12]> {
+        let channels = self.channels;
+        let mono: Vec<f32> = samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        match &mut self.resampler {
+            Some(resampler) => {
+                let resampled = resampler.process(&mono);
+                self.add(resampled);
+            }
+            None => self.samples.extend(mono),
+        }
+        self.frames()
+    }
+
+    /// Ends the audio, returning the chroma of the frames left
+    pub(crate) fn finish(mut self) -> Vec<[f32; 12]> {
+        if let Some(resampler) = &mut self.resampler {
+            let rest = resampler.flush();
+            self.add(rest);
+        }
+        self.frames()
+    }
+
+    /// Adds resampled samples, dropping those of the resampler's delay
+    fn add(&mut self, mut resampled: Vec<f32>) {
+        let skipped = self.delay.min(resampled.len());
+        resampled.drain(..skipped);
+        self.delay -= skipped;
+        self.samples.extend(resampled);
+    }
+
+    /// Returns the chroma of every complete frame, dropping samples no later frame needs
+    fn frames(&mut self) -> Vec<[f32; 12]> {
+        let mut chroma = Vec::new();
+        let mut start = 0;
+        while start + CHROMA_FFT_SIZE <= self.samples.len() {
+            chroma.push(self.frame(start));
+            start += CHROMA_HOP;
+        }
+        self.samples.drain(..start.min(self.samples.len()));
+        chroma
+    }
+
+    fn frame(&self, start: usize) -> [f32; 12] {
+        let spectrum = self
+            .stft
+            .forward(&self.samples[start..start + CHROMA_FFT_SIZE]);
+
+        let mut chroma = [0.; 12];
+        for (c, class) in spectrum.iter().zip(&self.classes) {
+            if let Some(class) = class {
+                chroma[*class] += c.norm_sqr();
+            }
+        }
+
+        let norm = chroma.iter().map(|e| e * e).sum::<f32>().sqrt();
+        if norm > 0. {
+            chroma.iter_mut().for_each(|e| *e /= norm);
+        }
+        chroma
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: u32 = 22_050;
+
+    /// Clicks of a decaying 2 kHz tone at the given tempo
+    fn click_track(bpm: f32, seconds: u64) -> SampleBuffer<f32> {
+        let mut buffer =
+            generate::silence(Duration::from_secs(seconds), SAMPLE_RATE, Channels::Mono);
+        let period = (60. / bpm * SAMPLE_RATE as f32).round() as usize;
+        let click = (SAMPLE_RATE / 100) as usize;
+        for start in (0..buffer.buffer.len()).step_by(period) {
+            for (i, sample) in buffer.buffer[start..].iter_mut().take(click).enumerate() {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                *sample = (TAU * 2000. * t).sin() * (-t * 500.).exp();
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn click_track_tempo() {
+        for bpm in [90., 120., 150.] {
+            let estimate = tempo(&click_track(bpm, 20)).unwrap();
+            assert!(
+                (estimate.bpm - bpm).abs() < bpm * 0.01,
+                "{bpm}: {}",
+                estimate.bpm
+            );
+            assert!(estimate.confidence > 0.5, "{bpm}: {}", estimate.confidence);
+
+            // Beats land on clicks, a frame of the envelope either way
+            let period = 60. / bpm as f64;
+            let hop = HOP as f64 / SAMPLE_RATE as f64;
+            for beat in &estimate.beats {
+                let offset = beat.as_secs_f64() % period;
+                let off_click = offset.min(period - offset);
+                assert!(off_click < 2. * hop, "{bpm}: beat at {beat:?}");
+            }
+            let expected = (20. / period) as usize;
+            assert!(estimate.beats.len().abs_diff(expected) <= 1, "{bpm}");
+        }
+    }
+
+    #[test]
+    fn no_tempo_without_onsets() {
+        let silence = generate::silence(Duration::from_secs(10), SAMPLE_RATE, Channels::Mono);
+        assert_eq!(tempo(&silence), None);
+        assert_eq!(tempo(&click_track(120., 1)), None);
+    }
+}
@@ -0,0 +1,475 @@
+//! Transcoding whole directories in parallel
+
+use crate::{
+    scheduler::{Priority, Scheduler, Task},
+    transcode_vorbis, TranscodeError, TranscodeOptions,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+use thiserror::Error;
+
+/// Options controlling `transcode_dir`
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    transcode: TranscodeOptions,
+    threads: usize,
+    pub(crate) recursive: bool,
+    checkpoint: Option<PathBuf>,
+    scheduler: Option<Scheduler>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            transcode: TranscodeOptions::default(),
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            recursive: true,
+            checkpoint: None,
+            scheduler: None,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Creates default options, using a thread per available core and descending into
+    /// subdirectories
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets options used for transcoding each file
+    pub fn transcode_options(mut self, options: TranscodeOptions) -> Self {
+        self.transcode = options;
+        self
+    }
+
+    /// Sets the number of files transcoded at once
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets whether files in subdirectories get transcoded too
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Records every transcoded file in a checkpoint file, so that a run started again with the
+    /// same checkpoint, e.g. after being interrupted, skips files whose outputs are still there
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Runs the files as batch priority tasks of a scheduler rather than on threads of their
+    /// own, so realtime tasks spawned on it take precedence
+    /// At most `threads` files are still transcoded at once
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+}
+
+/// Outcome of transcoding a single file
+#[derive(Debug)]
+pub struct FileResult {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Size of the written file in bytes
+    pub result: Result<u64, BatchError>,
+    /// Whether the file was transcoded by an earlier run and skipped, as recorded by the
+    /// checkpoint
+    pub resumed: bool,
+}
+
+/// Enum representing errors of transcoding a single file
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Transcode(#[from] TranscodeError),
+    #[error("job was cancelled before the file was transcoded")]
+    Cancelled,
+    #[error("another input gets transcoded to the same output: {0}")]
+    OutputCollision(PathBuf),
+}
+
+/// State of a single file of a `BatchJob`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileState {
+    Pending,
+    Running,
+    /// Transcoded, or skipped as already done, into a file of the given size
+    Done(u64),
+    Failed,
+    Cancelled,
+}
+
+/// Number of files of a `BatchJob` in each state
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchProgress {
+    pub total: usize,
+    pub pending: usize,
+    pub running: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+}
+
+impl BatchProgress {
+    /// Returns whether no file is waiting or being transcoded
+    pub fn is_finished(&self) -> bool {
+        self.pending == 0 && self.running == 0
+    }
+}
+
+/// Batch transcode running in the background, started by `BatchJob::start`
+/// Dropping the handle lets the job run to completion
+#[derive(Debug)]
+pub struct BatchJob {
+    shared: Arc<Shared>,
+    workers: Vec<Worker>,
+}
+
+type Finished = Vec<(usize, FileResult)>;
+
+#[derive(Debug)]
+enum Worker {
+    Thread(JoinHandle<Finished>),
+    Task(Task<Finished>),
+}
+
+impl Worker {
+    fn join(self) -> Finished {
+        match self {
+            Worker::Thread(handle) => handle.join(),
+            Worker::Task(task) => task.join(),
+        }
+        .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    jobs: Vec<(PathBuf, PathBuf)>,
+    options: BatchOptions,
+    next: AtomicUsize,
+    cancelled: AtomicBool,
+    states: Mutex<Vec<FileState>>,
+    checkpoint: Option<Mutex<File>>,
+}
+
+impl BatchJob {
+    /// Starts transcoding every file in `input_dir` to ogg vorbis, mirroring the directory
+    /// structure in `output_dir`, like `transcode_dir`
+    /// Fails before transcoding anything if two inputs only differ in extension, e.g. a.mp3 and
+    /// a.flac, since both would be written to a.ogg
+    pub fn start(
+        input_dir: impl AsRef<Path>,
+        output_dir: impl AsRef<Path>,
+        options: &BatchOptions,
+    ) -> io::Result<Self> {
+        let input_dir = input_dir.as_ref();
+        let output_dir = output_dir.as_ref();
+
+        let mut inputs = Vec::new();
+        collect_files(input_dir, options.recursive, &mut inputs)?;
+        inputs.sort();
+
+        let mut outputs = HashMap::new();
+        let jobs = inputs
+            .into_iter()
+            .map(|input| {
+                let output = output_path(input_dir, output_dir, &input);
+                if let Some(other) = outputs.insert(output.clone(), input.clone()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} and {} would both be transcoded to {}",
+                            other.display(),
+                            input.display(),
+                            output.display()
+                        ),
+                    ));
+                }
+                Ok((input, output))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let (completed, checkpoint) = match &options.checkpoint {
+            Some(path) => {
+                let completed = read_checkpoint(path)?;
+                let file = File::options().create(true).append(true).open(path)?;
+                (completed, Some(file))
+            }
+            None => (HashMap::new(), None),
+        };
+        Ok(Self::with_jobs(jobs, options, &completed, checkpoint))
+    }
+
+    /// Starts transcoding each input into its output on a pool of threads, skipping those
+    /// `completed` lists with their outputs intact and recording finished ones in `checkpoint`
+    fn with_jobs(
+        jobs: Vec<(PathBuf, PathBuf)>,
+        options: &BatchOptions,
+        completed: &HashMap<PathBuf, u64>,
+        checkpoint: Option<File>,
+    ) -> Self {
+        let states = jobs
+            .iter()
+            .map(|(input, output)| match completed.get(input) {
+                // Outputs deleted or changed since get transcoded again
+                Some(&size) if output.metadata().is_ok_and(|m| m.len() == size) => {
+                    FileState::Done(size)
+                }
+                _ => FileState::Pending,
+            })
+            .collect();
+
+        let threads = options.threads.min(jobs.len());
+        let shared = Arc::new(Shared {
+            jobs,
+            options: options.clone(),
+            next: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            states: Mutex::new(states),
+            checkpoint: checkpoint.map(Mutex::new),
+        });
+        let workers = (0..threads)
+            .map(|_| {
+                let shared = shared.clone();
+                match &options.scheduler {
+                    Some(scheduler) => {
+                        Worker::Task(scheduler.spawn(Priority::Batch, move || shared.work()))
+                    }
+                    None => Worker::Thread(thread::spawn(move || shared.work())),
+                }
+            })
+            .collect();
+
+        Self { shared, workers }
+    }
+
+    /// Returns the state of each file, in job order
+    pub fn files(&self) -> Vec<(PathBuf, FileState)> {
+        let states = self.shared.states();
+        self.shared
+            .jobs
+            .iter()
+            .zip(states.iter())
+            .map(|((input, _), &state)| (input.clone(), state))
+            .collect()
+    }
+
+    /// Returns how many files are in each state
+    pub fn progress(&self) -> BatchProgress {
+        let mut progress = BatchProgress::default();
+        for state in self.shared.states().iter() {
+            progress.total += 1;
+            match state {
+                FileState::Pending => progress.pending += 1,
+                FileState::Running => progress.running += 1,
+                FileState::Done(_) => progress.done += 1,
+                FileState::Failed => progress.failed += 1,
+                FileState::Cancelled => progress.cancelled += 1,
+            }
+        }
+        progress
+    }
+
+    /// Stops starting new files, the ones being transcoded still finish
+    /// Files transcoded so far stay in the checkpoint, so a new run can pick up from here
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for the job to finish, returning a result for each file, in job order
+    pub fn wait(self) -> Vec<FileResult> {
+        let mut results: Finished = self.workers.into_iter().flat_map(Worker::join).collect();
+
+        // Files no worker got to, because they were skipped, cancelled, or there were none
+        let mut handled = vec![false; self.shared.jobs.len()];
+        for (i, _) in &results {
+            handled[*i] = true;
+        }
+        let states = self.shared.states().clone();
+        for (i, ((input, output), state)) in self.shared.jobs.iter().zip(states).enumerate() {
+            if handled[i] {
+                continue;
+            }
+            let (result, resumed) = match state {
+                FileState::Done(size) => (Ok(size), true),
+                _ => (Err(BatchError::Cancelled), false),
+            };
+            results.push((
+                i,
+                FileResult {
+                    input: input.clone(),
+                    output: output.clone(),
+                    result,
+                    resumed,
+                },
+            ));
+        }
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+}
+
+impl Shared {
+    /// Takes the next pending job until none are left or the job gets cancelled
+    fn work(&self) -> Finished {
+        let mut done = Vec::new();
+        loop {
+            let i = self.next.fetch_add(1, Ordering::Relaxed);
+            let Some((input, output)) = self.jobs.get(i) else {
+                break;
+            };
+
+            {
+                let mut states = self.states();
+                if states[i] != FileState::Pending {
+                    continue;
+                }
+                if self.cancelled.load(Ordering::Relaxed) {
+                    states[i] = FileState::Cancelled;
+                    continue;
+                }
+                states[i] = FileState::Running;
+            }
+
+            let result = transcode_file(input, output, &self.options.transcode);
+            if let Ok(size) = result {
+                self.record(input, size);
+            }
+            self.states()[i] = match result {
+                Ok(size) => FileState::Done(size),
+                Err(_) => FileState::Failed,
+            };
+            done.push((
+                i,
+                FileResult {
+                    input: input.clone(),
+                    output: output.clone(),
+                    result,
+                    resumed: false,
+                },
+            ));
+        }
+        done
+    }
+
+    fn states(&self) -> std::sync::MutexGuard<'_, Vec<FileState>> {
+        self.states.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends a transcoded file to the checkpoint
+    /// Failing to write it only means the file gets transcoded again on resuming, so errors are ignored
+    fn record(&self, input: &Path, size: u64) {
+        let Some(checkpoint) = &self.checkpoint else {
+            return;
+        };
+        let mut file = checkpoint.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = writeln!(file, "{size}\t{}", input.display()).and_then(|_| file.flush());
+    }
+}
+
+/// Reads the inputs recorded in a checkpoint along with the sizes of their outputs
+/// A missing checkpoint is empty, and lines cut short by an interruption are ignored
+fn read_checkpoint(path: &Path) -> io::Result<HashMap<PathBuf, u64>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut completed = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some((size, input)) = line.split_once('\t') {
+            if let Ok(size) = size.parse() {
+                completed.insert(PathBuf::from(input), size);
+            }
+        }
+    }
+    Ok(completed)
+}
+
+/// Transcodes every file in `input_dir` to ogg vorbis, mirroring the directory structure in
+/// `output_dir`
+/// Returns a result for each file, in path order
+pub fn transcode_dir(
+    input_dir: impl AsRef<Path>,
+    output_dir: impl AsRef<Path>,
+    options: &BatchOptions,
+) -> io::Result<Vec<FileResult>> {
+    Ok(BatchJob::start(input_dir, output_dir, options)?.wait())
+}
+
+/// Returns where the transcoded version of `input` gets written
+pub(crate) fn output_path(input_dir: &Path, output_dir: &Path, input: &Path) -> PathBuf {
+    let relative = input.strip_prefix(input_dir).unwrap_or(input);
+    output_dir.join(relative).with_extension("ogg")
+}
+
+/// Transcodes each input into its output on a pool of threads, returning results in job order
+/// The checkpoint isn't used, the caller decides what needs transcoding
+#[cfg(feature = "watch")]
+pub(crate) fn transcode_files(
+    jobs: Vec<(PathBuf, PathBuf)>,
+    options: &BatchOptions,
+) -> Vec<FileResult> {
+    BatchJob::with_jobs(jobs, options, &HashMap::new(), None).wait()
+}
+
+pub(crate) fn collect_files(
+    dir: &Path,
+    recursive: bool,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            if recursive {
+                collect_files(&entry.path(), recursive, files)?;
+            }
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn transcode_file(
+    input: &Path,
+    output: &Path,
+    options: &TranscodeOptions,
+) -> Result<u64, BatchError> {
+    let mut options = options.clone();
+    if let Some(extension) = input.extension().and_then(|e| e.to_str()) {
+        options = options.hint_extension(extension);
+    }
+
+    let encoded = transcode_vorbis(File::open(input)?, &options)?;
+
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(output, &encoded)?;
+    Ok(encoded.len() as u64)
+}
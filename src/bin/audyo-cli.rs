@@ -0,0 +1,137 @@
+//! Command line interface to audyo
+
+use audyo::{
+    decode_dyn_with, encode_wav, probe_with, transcode_vorbis, DecodeOptions, TranscodeOptions,
+    WavFormat, WavOptions,
+};
+use std::{
+    env,
+    fs::{self, File},
+    path::Path,
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+usage:
+    audyo-cli decode <input> <output.wav>
+    audyo-cli transcode <input> <output> [--to vorbis] [--bitrate <bitrate>|auto|source]
+    audyo-cli probe [--json] <input>
+
+bitrates may use a k suffix, e.g. 128k";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["decode", input, output] => decode_file(input, output),
+        ["transcode", input, output, flags @ ..] => transcode_file(input, output, flags),
+        ["probe", input] => probe_file(input, false),
+        ["probe", "--json", input] | ["probe", input, "--json"] => probe_file(input, true),
+        ["help" | "--help" | "-h"] => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns decode options hinting the probe with the file's extension
+fn decode_options(path: &str) -> DecodeOptions {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(extension) => DecodeOptions::new().hint_extension(extension),
+        None => DecodeOptions::new(),
+    }
+}
+
+fn open(path: &str) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("couldn't open {path}: {e}"))
+}
+
+fn decode_file(input: &str, output: &str) -> Result<(), String> {
+    let (_, buffer) = decode_dyn_with(open(input)?, &decode_options(input))
+        .map_err(|e| format!("couldn't decode {input}: {e}"))?;
+    // Doubles hold every sample format exactly, so the source's bit depth is kept
+    let options = WavOptions::new().format(WavFormat::lossless(buffer.sample_format()));
+    let wav = encode_wav(&buffer.converted::<f64>(), &options)
+        .map_err(|e| format!("couldn't encode {output}: {e}"))?;
+    fs::write(output, wav).map_err(|e| format!("couldn't write {output}: {e}"))
+}
+
+fn transcode_file(input: &str, output: &str, flags: &[&str]) -> Result<(), String> {
+    let mut options = TranscodeOptions::new().decode_options(decode_options(input));
+
+    let mut flags = flags.iter();
+    while let Some(&flag) = flags.next() {
+        let value = *flags
+            .next()
+            .ok_or_else(|| format!("{flag} needs a value\n\n{USAGE}"))?;
+        match flag {
+            "--to" if value == "vorbis" => {}
+            "--to" => return Err(format!("unsupported output codec {value}, only vorbis is")),
+            "--bitrate" => options = parse_bitrate(options, value)?,
+            _ => return Err(format!("unknown option {flag}\n\n{USAGE}")),
+        }
+    }
+
+    let encoded = transcode_vorbis(open(input)?, &options)
+        .map_err(|e| format!("couldn't transcode {input}: {e}"))?;
+    fs::write(output, encoded).map_err(|e| format!("couldn't write {output}: {e}"))
+}
+
+fn parse_bitrate(options: TranscodeOptions, value: &str) -> Result<TranscodeOptions, String> {
+    let invalid = || format!("invalid bitrate {value}");
+
+    Ok(match value {
+        "auto" => options.auto_bitrate(),
+        "source" => options.source_bitrate(),
+        _ => {
+            let (digits, scale) = match value.strip_suffix(['k', 'K']) {
+                Some(digits) => (digits, 1000),
+                None => (value, 1),
+            };
+            let bitrate: u64 = digits.parse().map_err(|_| invalid())?;
+            options.bitrate(bitrate.checked_mul(scale).ok_or_else(invalid)?)
+        }
+    })
+}
+
+fn probe_file(input: &str, json: bool) -> Result<(), String> {
+    let report = probe_with(open(input)?, &decode_options(input))
+        .map_err(|e| format!("couldn't probe {input}: {e}"))?;
+
+    if json {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+
+    println!("format: {}", report.format.unwrap_or("unknown"));
+    println!("codec: {}", report.codec.unwrap_or("unknown"));
+    println!("channels: {:?}", report.channels);
+    println!("sample rate: {} Hz", report.sample_rate);
+    println!("duration: {:.3} s", report.duration.as_secs_f64());
+    println!("frames: {}", report.frames);
+    println!("stream bitrate: {} bps", report.stream_bitrate);
+    if let Some(bitrate) = report.container_bitrate {
+        println!("container bitrate: {bitrate} bps");
+    }
+    for tag in &report.metadata.tags {
+        println!("tag {}: {}", tag.key, tag.value);
+    }
+    for chapter in &report.metadata.chapters {
+        println!(
+            "chapter {:.3} s: {}",
+            chapter.start.as_secs_f64(),
+            chapter.name.as_deref().unwrap_or("")
+        );
+    }
+    Ok(())
+}
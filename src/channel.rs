@@ -0,0 +1,117 @@
+use crate::{AudyoSample, BufferError, SampleBuffer};
+use std::iter::StepBy;
+use symphonia::core::sample::Sample;
+
+/// Strided view over a single channel of an interleaved buffer
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelView<'a, S: Sample> {
+    samples: &'a [S],
+    stride: usize,
+}
+
+impl<'a, S: Sample> ChannelView<'a, S> {
+    /// Returns number of samples in the channel
+    pub fn len(&self) -> usize {
+        self.samples.len().div_ceil(self.stride)
+    }
+
+    /// Returns whether the channel holds no samples
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the sample of the given frame
+    pub fn get(&self, frame: usize) -> Option<S> {
+        self.samples.get(frame * self.stride).copied()
+    }
+
+    /// Returns an iterator over the channel's samples
+    pub fn iter(&self) -> StepBy<std::slice::Iter<'a, S>> {
+        self.samples.iter().step_by(self.stride)
+    }
+}
+
+impl<'a, S: Sample> IntoIterator for ChannelView<'a, S> {
+    type Item = &'a S;
+    type IntoIter = StepBy<std::slice::Iter<'a, S>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Mutable strided view over a single channel of an interleaved buffer
+#[derive(Debug)]
+pub struct ChannelViewMut<'a, S: Sample> {
+    samples: &'a mut [S],
+    stride: usize,
+}
+
+impl<S: Sample> ChannelViewMut<'_, S> {
+    /// Returns number of samples in the channel
+    pub fn len(&self) -> usize {
+        self.samples.len().div_ceil(self.stride)
+    }
+
+    /// Returns whether the channel holds no samples
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the sample of the given frame
+    pub fn get(&self, frame: usize) -> Option<S> {
+        self.samples.get(frame * self.stride).copied()
+    }
+
+    /// Returns a mutable reference to the sample of the given frame
+    pub fn get_mut(&mut self, frame: usize) -> Option<&mut S> {
+        self.samples.get_mut(frame * self.stride)
+    }
+
+    /// Returns an iterator over the channel's samples
+    pub fn iter(&self) -> StepBy<std::slice::Iter<'_, S>> {
+        self.samples.iter().step_by(self.stride)
+    }
+
+    /// Returns an iterator over mutable references to the channel's samples
+    pub fn iter_mut(&mut self) -> StepBy<std::slice::IterMut<'_, S>> {
+        self.samples.iter_mut().step_by(self.stride)
+    }
+}
+
+impl<'a, S: Sample> IntoIterator for ChannelViewMut<'a, S> {
+    type Item = &'a mut S;
+    type IntoIter = StepBy<std::slice::IterMut<'a, S>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.samples.iter_mut().step_by(self.stride)
+    }
+}
+
+impl<S: AudyoSample> SampleBuffer<S> {
+    /// Returns a view over the samples of one channel, without copying them
+    pub fn channel(&self, index: usize) -> Result<ChannelView<'_, S>, BufferError> {
+        let stride = self.channels as usize;
+        if index >= stride {
+            return Err(BufferError::ChannelOutOfRange(index));
+        }
+
+        Ok(ChannelView {
+            samples: self.buffer.get(index..).unwrap_or_default(),
+            stride,
+        })
+    }
+
+    /// Returns a mutable view over the samples of one channel, without copying them
+    pub fn channel_mut(&mut self, index: usize) -> Result<ChannelViewMut<'_, S>, BufferError> {
+        let stride = self.channels as usize;
+        if index >= stride {
+            return Err(BufferError::ChannelOutOfRange(index));
+        }
+
+        Ok(ChannelViewMut {
+            samples: self.buffer.get_mut(index..).unwrap_or_default(),
+            stride,
+        })
+    }
+}
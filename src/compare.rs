@@ -0,0 +1,251 @@
+//! Objective A/B comparison of a transcode against its original, for picking encoder settings
+//! without listening to every candidate
+
+use crate::{diff::alignment, stft::Stft, BufferError, SampleBuffer};
+use std::borrow::Cow;
+
+const FRAME: usize = 2048;
+const HOP: usize = 1024;
+/// Frames of the original with a mean square below this, -70 dBFS, are left out of spectral
+/// measurements, since any noise in them is dominated by the absolute threshold of hearing
+const SILENT_ENERGY: f64 = 1e-7;
+/// Level a full scale sine is assumed to play back at, in dB SPL, placing the threshold of
+/// hearing relative to sample values
+const FULL_SCALE_SPL: f64 = 92.;
+/// Width of the critical bands noise and masking are measured in, in Bark
+const BAND_WIDTH: f64 = 1.;
+/// How far below a band's energy its masking threshold sits, in dB
+const MASK_OFFSET_DB: f64 = 12.;
+/// Masking spreads towards higher bands with this slope, in dB per Bark
+const SPREAD_UP_DB: f64 = 12.;
+/// Masking spreads towards lower bands with this slope, in dB per Bark
+const SPREAD_DOWN_DB: f64 = 27.;
+
+/// Measurements taken by `ab`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbReport {
+    /// Integrated loudness of the original, in LUFS
+    pub original_loudness: Option<f32>,
+    /// Integrated loudness of the transcode before matching, in LUFS
+    pub transcoded_loudness: Option<f32>,
+    /// Gain applied to the transcode to match the original's loudness, in dB
+    pub gain_db: f32,
+    /// Frames the transcode was shifted by to line up, positive if it starts late
+    pub offset: i64,
+    /// Number of frames compared after aligning
+    pub compared_frames: usize,
+    /// Ratio of the original's energy to that of the difference, in dB, infinite if identical
+    pub snr_db: f32,
+    /// Mean log spectral distance, in dB, 0 for identical spectra
+    pub spectral_distance_db: f32,
+    /// Mean ratio of the difference to the original's masking threshold over critical bands,
+    /// in dB, a simplified form of PEAQ's noise-to-mask ratio
+    /// Below 0 dB most of the coding noise is masked and likely inaudible
+    pub noise_to_mask_db: f32,
+    /// Share of analyzed frames where the difference exceeds the masking threshold in any band
+    pub disturbed_frames: f32,
+}
+
+/// Compares a transcode against its original after matching their loudness and lining them up,
+/// so that gain changes and encoder delay don't count as difference
+/// Channel layouts must match, the transcode gets resampled to the original's rate if needed
+pub fn ab(
+    original: &SampleBuffer<f32>,
+    transcoded: &SampleBuffer<f32>,
+) -> Result<AbReport, BufferError> {
+    if transcoded.channels != original.channels {
+        return Err(BufferError::ChannelsMismatch(
+            original.channels,
+            transcoded.channels,
+        ));
+    }
+    let transcoded = if transcoded.sample_rate == original.sample_rate {
+        Cow::Borrowed(transcoded)
+    } else {
+        Cow::Owned(transcoded.resampled(original.sample_rate, crate::resample::Quality::default()))
+    };
+
+    let original_loudness = original.loudness();
+    let transcoded_loudness = transcoded.loudness();
+    let gain_db = match (original_loudness, transcoded_loudness) {
+        (Some(original), Some(transcoded)) => original - transcoded,
+        _ => 0.,
+    };
+    let gain = 10f32.powf(gain_db / 20.);
+
+    let channels = original.channels as usize;
+    let a = &original.buffer[..original.written];
+    let b = &transcoded.buffer[..transcoded.written];
+    let offset = alignment(a, b, channels);
+    let (start_a, start_b) = if offset >= 0 {
+        (0, offset as usize)
+    } else {
+        ((-offset) as usize, 0)
+    };
+    let a = a.get(start_a * channels..).unwrap_or_default();
+    let b = b.get(start_b * channels..).unwrap_or_default();
+    let compared_frames = a.len().min(b.len()) / channels;
+
+    let (mut signal, mut noise) = (0f64, 0f64);
+    for (&x, &y) in a.iter().zip(b) {
+        signal += (x as f64).powi(2);
+        noise += (x as f64 - (y * gain) as f64).powi(2);
+    }
+
+    let bands = Bands::new(original.sample_rate);
+    let stft = Stft::new(FRAME);
+    let mut spectral = Spectral::default();
+    for channel in 0..channels {
+        let split = |samples: &[f32], gain: f32| -> Vec<f32> {
+            samples
+                .chunks_exact(channels)
+                .take(compared_frames)
+                .map(|frame| frame[channel] * gain)
+                .collect()
+        };
+        spectral.measure(&stft, &bands, &split(a, 1.), &split(b, gain));
+    }
+
+    Ok(AbReport {
+        original_loudness,
+        transcoded_loudness,
+        gain_db,
+        offset,
+        compared_frames,
+        snr_db: (10. * (signal / noise).log10()) as f32,
+        spectral_distance_db: spectral.distance(),
+        noise_to_mask_db: spectral.noise_to_mask(),
+        disturbed_frames: spectral.disturbed(),
+    })
+}
+
+/// Critical band every spectrum bin falls into, and the threshold of hearing in it
+struct Bands {
+    of_bin: Vec<usize>,
+    count: usize,
+    /// Power of a bin's sine that's just audible in quiet
+    threshold: Vec<f64>,
+}
+
+impl Bands {
+    fn new(sample_rate: u32) -> Self {
+        let bark = |freq: f64| 13. * (0.00076 * freq).atan() + 3.5 * (freq / 7500.).powi(2).atan();
+        let of_bin: Vec<usize> = (0..=FRAME / 2)
+            .map(|bin| {
+                let freq = bin as f64 * sample_rate as f64 / FRAME as f64;
+                (bark(freq) / BAND_WIDTH) as usize
+            })
+            .collect();
+        let count = of_bin.last().map_or(0, |&band| band + 1);
+
+        // Terhardt's approximation, with the power a full scale sine has in its peak bin
+        let full_scale = (FRAME as f64 / 4.).powi(2);
+        let threshold = (0..=FRAME / 2)
+            .map(|bin| {
+                let khz = (bin as f64 * sample_rate as f64 / FRAME as f64 / 1000.).max(0.02);
+                let spl = 3.64 * khz.powf(-0.8) - 6.5 * (-0.6 * (khz - 3.3).powi(2)).exp()
+                    + 1e-3 * khz.powi(4);
+                full_scale * 10f64.powf((spl - FULL_SCALE_SPL) / 10.)
+            })
+            .collect();
+        Self {
+            of_bin,
+            count,
+            threshold,
+        }
+    }
+}
+
+/// Sums of per-frame spectral measurements over every channel
+#[derive(Default)]
+struct Spectral {
+    frames: usize,
+    distance: f64,
+    noise_to_mask: f64,
+    disturbed: usize,
+}
+
+impl Spectral {
+    fn measure(&mut self, stft: &Stft, bands: &Bands, a: &[f32], b: &[f32]) {
+        let mut start = 0;
+        while start < a.len() {
+            let end = (start + FRAME).min(a.len());
+            let energy = a[start..end]
+                .iter()
+                .map(|&x| (x as f64).powi(2))
+                .sum::<f64>();
+            if energy / (end - start) as f64 >= SILENT_ENERGY {
+                self.frame(stft, bands, &a[start..end], &b[start..end]);
+            }
+            start += HOP;
+        }
+    }
+
+    fn frame(&mut self, stft: &Stft, bands: &Bands, a: &[f32], b: &[f32]) {
+        let (a, b) = (stft.forward(a), stft.forward(b));
+        let mut squared = 0.;
+        let mut signal = vec![0f64; bands.count];
+        let mut noise = vec![0f64; bands.count];
+        let mut quiet = vec![0f64; bands.count];
+        for (bin, &band) in bands.of_bin.iter().enumerate() {
+            let (pa, pb) = (a[bin].norm_sqr() as f64, b[bin].norm_sqr() as f64);
+            // Differences below the threshold of hearing don't count
+            let floor = bands.threshold[bin];
+            squared += (10. * ((pa + floor) / (pb + floor)).log10()).powi(2);
+            signal[band] += pa;
+            quiet[band] += floor;
+            // The transform is linear, so this is the spectrum of the difference
+            noise[band] += (a[bin] - b[bin]).norm_sqr() as f64;
+        }
+        self.distance += (squared / bands.of_bin.len() as f64).sqrt();
+
+        let offset = 10f64.powf(-MASK_OFFSET_DB / 10.);
+        let mut ratio = 0.;
+        let mut disturbed = false;
+        for (band, &noise) in noise.iter().enumerate() {
+            let mask: f64 = signal
+                .iter()
+                .enumerate()
+                .map(|(masker, &energy)| {
+                    let distance = (band as f64 - masker as f64) * BAND_WIDTH;
+                    let slope = if distance >= 0. {
+                        SPREAD_UP_DB
+                    } else {
+                        SPREAD_DOWN_DB
+                    };
+                    energy * 10f64.powf(-slope * distance.abs() / 10.)
+                })
+                .sum::<f64>()
+                * offset
+                + quiet[band];
+            ratio += noise / mask;
+            disturbed |= noise > mask;
+        }
+        self.noise_to_mask += ratio / bands.count as f64;
+        self.disturbed += disturbed as usize;
+        self.frames += 1;
+    }
+
+    fn distance(&self) -> f32 {
+        self.mean(self.distance) as f32
+    }
+
+    fn noise_to_mask(&self) -> f32 {
+        if self.frames == 0 {
+            return f32::NEG_INFINITY;
+        }
+        (10. * self.mean(self.noise_to_mask).log10()) as f32
+    }
+
+    fn disturbed(&self) -> f32 {
+        self.mean(self.disturbed as f64) as f32
+    }
+
+    fn mean(&self, sum: f64) -> f64 {
+        if self.frames > 0 {
+            sum / self.frames as f64
+        } else {
+            0.
+        }
+    }
+}
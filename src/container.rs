@@ -0,0 +1,1013 @@
+//! Muxing encoded packets into Ogg, WebM and MP4 containers, and remuxing between them
+
+use crate::{
+    channels, default_track, probe_format, sample_rate, Channels, DecodeError, DecodeOptions,
+};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::io::{self, Seek, SeekFrom, Write};
+use symphonia::core::{
+    codecs::{CODEC_TYPE_AAC, CODEC_TYPE_OPUS, CODEC_TYPE_VORBIS},
+    io::MediaSource,
+};
+use thiserror::Error;
+
+/// Vendor string written into synthesized Vorbis comment headers
+const VENDOR: &str = "audyo";
+/// Size of the Vorbis identification header
+const VORBIS_IDENT_LEN: usize = 30;
+/// WebM blocks store their timestamp relative to the cluster in an i16 of milliseconds
+pub(crate) const MAX_CLUSTER_MS: u64 = i16::MAX as u64;
+/// Opus decoders need 80 ms of preroll after seeking
+const OPUS_SEEK_PREROLL_NS: u64 = 80_000_000;
+
+/// Codec of an encoded stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Vorbis,
+    Opus,
+    Aac,
+}
+
+/// Description of an encoded stream
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub codec: Codec,
+    /// Sample rate packet timestamps are expressed in
+    pub sample_rate: u32,
+    pub channels: Channels,
+    /// Codec header packets, e.g. identification, comment and setup headers for Vorbis, or the
+    /// AudioSpecificConfig for AAC
+    pub headers: Vec<Vec<u8>>,
+    /// Samples to discard at the start of the stream
+    pub delay: u32,
+}
+
+/// Encoded packet of a stream
+#[derive(Clone, Debug)]
+pub struct Packet {
+    pub data: Vec<u8>,
+    /// First sample of the packet
+    pub timestamp: u64,
+    /// Number of samples in the packet
+    pub duration: u64,
+}
+
+/// Reads the encoded packets of the default track in source, e.g. the output of `encode_vorbis`
+pub fn demux(source: impl MediaSource + 'static) -> Result<(Track, Vec<Packet>), DecodeError> {
+    // Packets are copied as they are, so their timestamps must keep counting the encoder delay
+    let options = DecodeOptions::new().keep_padding(true);
+    let mut reader = probe_format(Box::new(source), &options)?.format;
+
+    let track = default_track(&*reader).ok_or(DecodeError::PropertyLacking("default track"))?;
+    let id = track.id;
+    let channels = channels(&track.codec_params, false);
+    let sample_rate = sample_rate(&track.codec_params)?;
+    let delay = track.codec_params.delay;
+    let codec = match track.codec_params.codec {
+        CODEC_TYPE_VORBIS => Codec::Vorbis,
+        CODEC_TYPE_OPUS => Codec::Opus,
+        CODEC_TYPE_AAC => Codec::Aac,
+        _ => return Err(DecodeError::PropertyLacking("vorbis, opus or aac codec")),
+    };
+
+    let mut packets = Vec::new();
+    while let Ok(packet) = reader.next_packet() {
+        if packet.track_id() == id {
+            packets.push(Packet {
+                timestamp: packet.ts(),
+                duration: packet.dur(),
+                data: packet.data.into_vec(),
+            });
+        }
+    }
+
+    // Vorbis setup headers are only complete once the reader got past them
+    let extra_data = reader
+        .tracks()
+        .iter()
+        .find(|t| t.id == id)
+        .and_then(|t| t.codec_params.extra_data.as_deref())
+        .ok_or(DecodeError::PropertyLacking("codec headers"))?;
+
+    let headers = match codec {
+        Codec::Vorbis if extra_data.len() > VORBIS_IDENT_LEN => vec![
+            extra_data[..VORBIS_IDENT_LEN].to_vec(),
+            vorbis_comment_header(),
+            extra_data[VORBIS_IDENT_LEN..].to_vec(),
+        ],
+        Codec::Vorbis => return Err(DecodeError::PropertyLacking("vorbis setup header")),
+        Codec::Opus if extra_data.len() >= 19 => vec![opus_head(extra_data), opus_tags_header()],
+        Codec::Opus => return Err(DecodeError::PropertyLacking("opus identification header")),
+        Codec::Aac => vec![extra_data.to_vec()],
+    };
+
+    // MP4 only records the channels and Opus pre-skip in the codec configuration
+    let channels = channels.or_else(|e| match codec {
+        Codec::Opus => layout_of(headers[0][9] as usize),
+        Codec::Aac => extra_data
+            .get(1)
+            .map_or(Err(e), |config| layout_of((config >> 3 & 0x0F) as usize)),
+        Codec::Vorbis => Err(e),
+    })?;
+    let delay = match codec {
+        Codec::Opus => delay.unwrap_or(u16::from_le_bytes([headers[0][10], headers[0][11]]) as u32),
+        _ => delay.unwrap_or(0),
+    };
+
+    Ok((
+        Track {
+            codec,
+            sample_rate,
+            channels,
+            headers,
+            delay,
+        },
+        packets,
+    ))
+}
+
+/// Container format written by `remux`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Ogg,
+    Webm,
+    Mp4,
+}
+
+impl Container {
+    /// Returns whether the container can hold streams of the codec
+    /// Ogg and WebM take Vorbis and Opus, MP4 takes Opus and AAC
+    pub fn supports(self, codec: Codec) -> bool {
+        matches!(
+            (self, codec),
+            (
+                Container::Ogg | Container::Webm,
+                Codec::Vorbis | Codec::Opus
+            ) | (Container::Mp4, Codec::Opus | Codec::Aac)
+        )
+    }
+
+    /// Fails with `io::ErrorKind::Unsupported` unless the container can hold the codec
+    pub(crate) fn check(self, codec: Codec) -> io::Result<()> {
+        if self.supports(codec) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{codec:?} can't be muxed into {self:?}"),
+            ))
+        }
+    }
+}
+
+/// Copies the encoded packets of the default track in source into another container, without
+/// re-encoding them
+/// Returns the writer once the container is complete
+pub fn remux<W: Write + Seek>(
+    source: impl MediaSource + 'static,
+    container: Container,
+    writer: W,
+) -> Result<W, RemuxError> {
+    let (track, packets) = demux(source)?;
+
+    Ok(match container {
+        Container::Ogg => {
+            let mut muxer = OggMuxer::new(writer, &track)?;
+            for packet in &packets {
+                muxer.write_packet(packet)?;
+            }
+            muxer.finish()?
+        }
+        Container::Webm => {
+            let mut muxer = WebmMuxer::new(writer, &track)?;
+            for packet in &packets {
+                muxer.write_packet(packet)?;
+            }
+            muxer.finish()?
+        }
+        Container::Mp4 => {
+            let mut muxer = Mp4Muxer::new(writer, &track)?;
+            for packet in &packets {
+                muxer.write_packet(packet)?;
+            }
+            muxer.finish()?
+        }
+    })
+}
+
+/// Enum representing remuxing errors
+#[derive(Error, Debug)]
+pub enum RemuxError {
+    #[error(transparent)]
+    Demux(#[from] DecodeError),
+    #[error("couldn't write container: {0}")]
+    Mux(#[from] io::Error),
+}
+
+fn vorbis_comment_header() -> Vec<u8> {
+    let mut header = b"\x03vorbis".to_vec();
+    header.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    header.extend_from_slice(VENDOR.as_bytes());
+    // No comments, followed by the framing bit
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.push(1);
+    header
+}
+
+/// Returns the identification header of an Opus stream, converting it from the big endian
+/// fields of an MP4 dOps box, which symphonia passes on after the magic
+fn opus_head(extra_data: &[u8]) -> Vec<u8> {
+    // Version 1 in Ogg and WebM, 0 in MP4
+    if extra_data[8] != 0 {
+        return extra_data.to_vec();
+    }
+
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, extra_data[9]]);
+    head.extend_from_slice(&u16::from_be_bytes([extra_data[10], extra_data[11]]).to_le_bytes());
+    let rate = u32::from_be_bytes(extra_data[12..16].try_into().unwrap());
+    head.extend_from_slice(&rate.to_le_bytes());
+    head.extend_from_slice(&i16::from_be_bytes([extra_data[16], extra_data[17]]).to_le_bytes());
+    head.extend_from_slice(&extra_data[18..]);
+    head
+}
+
+fn layout_of(count: usize) -> Result<Channels, DecodeError> {
+    match count {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        count => Err(DecodeError::UnsupportedChannelCount(count)),
+    }
+}
+
+fn opus_tags_header() -> Vec<u8> {
+    let mut header = b"OpusTags".to_vec();
+    header.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    header.extend_from_slice(VENDOR.as_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header
+}
+
+/// Muxer writing a single stream into an Ogg container
+pub struct OggMuxer<W: Write> {
+    writer: PacketWriter<'static, W>,
+    serial: u32,
+    /// The last packet is held back until it's known to end the stream
+    pending: Option<Packet>,
+}
+
+impl<W: Write> OggMuxer<W> {
+    /// Creates a muxer, writing the track's headers right away
+    pub fn new(writer: W, track: &Track) -> io::Result<Self> {
+        Container::Ogg.check(track.codec)?;
+        let mut writer = PacketWriter::new(writer);
+        let serial = rand::random();
+
+        for (i, header) in track.headers.iter().enumerate() {
+            // The first header gets a page of its own, the rest end the header pages
+            let end = if i == 0 || i + 1 == track.headers.len() {
+                PacketWriteEndInfo::EndPage
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            writer.write_packet(header.clone(), serial, end, 0)?;
+        }
+
+        Ok(Self {
+            writer,
+            serial,
+            pending: None,
+        })
+    }
+
+    /// Appends a packet to the stream
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        if let Some(previous) = self.pending.replace(packet.clone()) {
+            self.write(previous, PacketWriteEndInfo::NormalPacket)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the stream and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(last) = self.pending.take() {
+            self.write(last, PacketWriteEndInfo::EndStream)?;
+        }
+        let mut writer = self.writer.into_inner();
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, packet: Packet, end: PacketWriteEndInfo) -> io::Result<()> {
+        // Granule positions mark the end of the packet
+        let granule = packet.timestamp + packet.duration;
+        self.writer
+            .write_packet(packet.data, self.serial, end, granule)
+    }
+}
+
+/// Muxer writing a single stream into a WebM container
+pub struct WebmMuxer<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    /// Position of the segment size, patched once the segment is complete
+    segment_size_at: u64,
+    /// Position of the duration value, patched once the segment is complete
+    duration_at: u64,
+    cluster: Vec<u8>,
+    cluster_start: u64,
+    end_ms: u64,
+}
+
+impl<W: Write + Seek> WebmMuxer<W> {
+    /// Creates a muxer, writing the container and track headers right away
+    pub fn new(mut writer: W, track: &Track) -> io::Result<Self> {
+        let header = webm_header(track)?;
+        let start = writer.stream_position()?;
+        writer.write_all(&header.data)?;
+
+        Ok(Self {
+            writer,
+            sample_rate: track.sample_rate,
+            segment_size_at: start + header.segment_size_at as u64,
+            duration_at: start + header.duration_at as u64,
+            cluster: Vec::new(),
+            cluster_start: 0,
+            end_ms: 0,
+        })
+    }
+
+    /// Appends a packet to the stream
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        let start_ms = self.milliseconds(packet.timestamp);
+        self.end_ms = self
+            .end_ms
+            .max(self.milliseconds(packet.timestamp + packet.duration));
+
+        if self.cluster.is_empty() || start_ms.saturating_sub(self.cluster_start) > MAX_CLUSTER_MS {
+            self.flush_cluster()?;
+            self.cluster_start = start_ms;
+            uint_element(&mut self.cluster, 0xE7, start_ms);
+        }
+
+        let relative = (start_ms.saturating_sub(self.cluster_start)) as i16;
+        simple_block(&mut self.cluster, relative, &packet.data);
+        Ok(())
+    }
+
+    /// Completes the segment and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_cluster()?;
+
+        let end = self.writer.stream_position()?;
+        let segment_size = end - self.segment_size_at - 8;
+        self.writer.seek(SeekFrom::Start(self.segment_size_at))?;
+        let mut encoded = segment_size.to_be_bytes();
+        encoded[0] = 0x01;
+        self.writer.write_all(&encoded)?;
+
+        self.writer.seek(SeekFrom::Start(self.duration_at))?;
+        self.writer.write_all(&(self.end_ms as f64).to_be_bytes())?;
+
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn milliseconds(&self, samples: u64) -> u64 {
+        samples * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    fn flush_cluster(&mut self) -> io::Result<()> {
+        if self.cluster.is_empty() {
+            return Ok(());
+        }
+
+        let mut cluster = Vec::new();
+        element(&mut cluster, 0x1F43B675, &self.cluster);
+        self.cluster.clear();
+        self.writer.write_all(&cluster)
+    }
+}
+
+/// Muxer writing a single stream into an MP4 container
+/// Packets go straight into the media data, and the sample tables follow it once the stream
+/// is complete, so players that can't seek the input need the whole file before playing
+pub struct Mp4Muxer<W: Write + Seek> {
+    writer: W,
+    track: Track,
+    /// Position of the mdat box, whose size is patched once the stream is complete
+    mdat_at: u64,
+    samples: Mp4Samples,
+}
+
+impl<W: Write + Seek> Mp4Muxer<W> {
+    /// Creates a muxer, writing the file type and the start of the media data right away
+    pub fn new(mut writer: W, track: &Track) -> io::Result<Self> {
+        Container::Mp4.check(track.codec)?;
+
+        let mut header = Vec::new();
+        mp4_box(
+            &mut header,
+            b"ftyp",
+            &brands(b"isom", &[b"isom", b"iso2", b"mp41"]),
+        );
+        writer.write_all(&header)?;
+        let mdat_at = writer.stream_position()?;
+        // A 64 bit size, patched once known
+        writer.write_all(&1u32.to_be_bytes())?;
+        writer.write_all(b"mdat")?;
+        writer.write_all(&0u64.to_be_bytes())?;
+
+        Ok(Self {
+            writer,
+            track: track.clone(),
+            mdat_at,
+            samples: Mp4Samples::default(),
+        })
+    }
+
+    /// Appends a packet to the stream
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        self.samples.push(packet)?;
+        self.writer.write_all(&packet.data)
+    }
+
+    /// Writes the sample tables and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.mdat_at + 8))?;
+        self.writer.write_all(&(end - self.mdat_at).to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+
+        self.samples.offset = self.mdat_at + 16;
+        self.writer
+            .write_all(&mp4_moov(&self.track, &self.samples, false)?)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Sizes and durations of the samples of an MP4 track
+#[derive(Debug, Default)]
+pub(crate) struct Mp4Samples {
+    sizes: Vec<u32>,
+    /// Runs of equally long samples, as counts and durations
+    durations: Vec<(u32, u32)>,
+    /// Position of the first sample in the file
+    offset: u64,
+}
+
+impl Mp4Samples {
+    pub(crate) fn push(&mut self, packet: &Packet) -> io::Result<()> {
+        let too_long = || io::Error::new(io::ErrorKind::InvalidInput, "packet too long for MP4");
+        let size = u32::try_from(packet.data.len()).map_err(|_| too_long())?;
+        let duration = u32::try_from(packet.duration).map_err(|_| too_long())?;
+
+        self.sizes.push(size);
+        match self.durations.last_mut() {
+            Some((count, last)) if *last == duration => *count += 1,
+            _ => self.durations.push((1, duration)),
+        }
+        Ok(())
+    }
+
+    fn duration(&self) -> u64 {
+        self.durations
+            .iter()
+            .map(|&(count, duration)| count as u64 * duration as u64)
+            .sum()
+    }
+}
+
+/// Returns the movie box describing the track, with no samples of its own if `fragmented`
+/// since movie fragments carry them
+pub(crate) fn mp4_moov(
+    track: &Track,
+    samples: &Mp4Samples,
+    fragmented: bool,
+) -> io::Result<Vec<u8>> {
+    let timescale = track.sample_rate.max(1);
+    let duration = samples.duration();
+
+    let mut mvhd = Vec::new();
+    mvhd.extend_from_slice(&[0; 16]);
+    mvhd.extend_from_slice(&timescale.to_be_bytes());
+    mvhd.extend_from_slice(&duration.to_be_bytes());
+    // Normal rate and volume, then reserved bytes
+    mvhd.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    mvhd.extend_from_slice(&0x0100u16.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 10]);
+    mvhd.extend_from_slice(&UNITY_MATRIX);
+    mvhd.extend_from_slice(&[0; 24]);
+    // Next track id
+    mvhd.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut tkhd = Vec::new();
+    tkhd.extend_from_slice(&[0; 16]);
+    tkhd.extend_from_slice(&1u32.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 4]);
+    tkhd.extend_from_slice(&duration.to_be_bytes());
+    // Reserved, layer, alternate group, full volume and reserved
+    tkhd.extend_from_slice(&[0; 12]);
+    tkhd.extend_from_slice(&0x0100u16.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 2]);
+    tkhd.extend_from_slice(&UNITY_MATRIX);
+    // No width or height, it's audio
+    tkhd.extend_from_slice(&[0; 8]);
+
+    let mut mdhd = Vec::new();
+    mdhd.extend_from_slice(&[0; 16]);
+    mdhd.extend_from_slice(&timescale.to_be_bytes());
+    mdhd.extend_from_slice(&duration.to_be_bytes());
+    // Undetermined language, packed as three 5 bit letters
+    mdhd.extend_from_slice(&0x55C4u16.to_be_bytes());
+    mdhd.extend_from_slice(&[0; 2]);
+
+    let mut hdlr = vec![0; 4];
+    hdlr.extend_from_slice(b"soun");
+    hdlr.extend_from_slice(&[0; 12]);
+    hdlr.extend_from_slice(b"SoundHandler\0");
+
+    let mut dinf = Vec::new();
+    let mut dref = 1u32.to_be_bytes().to_vec();
+    // Samples are in this file
+    full_box(&mut dref, b"url ", 0, 1, &[]);
+    full_box(&mut dinf, b"dref", 0, 0, &dref);
+
+    let mut stbl = Vec::new();
+    let mut stsd = 1u32.to_be_bytes().to_vec();
+    sample_entry(&mut stsd, track)?;
+    full_box(&mut stbl, b"stsd", 0, 0, &stsd);
+
+    let mut stts = (samples.durations.len() as u32).to_be_bytes().to_vec();
+    for &(count, duration) in &samples.durations {
+        stts.extend_from_slice(&count.to_be_bytes());
+        stts.extend_from_slice(&duration.to_be_bytes());
+    }
+    full_box(&mut stbl, b"stts", 0, 0, &stts);
+
+    // Every sample in a single chunk, the media data
+    let count = samples.sizes.len() as u32;
+    let mut stsc = Vec::new();
+    let mut co64 = Vec::new();
+    if count > 0 {
+        for value in [1, 1, count, 1] {
+            stsc.extend_from_slice(&value.to_be_bytes());
+        }
+        co64.extend_from_slice(&1u32.to_be_bytes());
+        co64.extend_from_slice(&samples.offset.to_be_bytes());
+    } else {
+        stsc.extend_from_slice(&0u32.to_be_bytes());
+        co64.extend_from_slice(&0u32.to_be_bytes());
+    }
+    full_box(&mut stbl, b"stsc", 0, 0, &stsc);
+
+    let mut stsz = 0u32.to_be_bytes().to_vec();
+    stsz.extend_from_slice(&count.to_be_bytes());
+    for size in &samples.sizes {
+        stsz.extend_from_slice(&size.to_be_bytes());
+    }
+    full_box(&mut stbl, b"stsz", 0, 0, &stsz);
+    full_box(&mut stbl, b"co64", 0, 0, &co64);
+
+    let mut minf = Vec::new();
+    full_box(&mut minf, b"smhd", 0, 0, &[0; 4]);
+    mp4_box(&mut minf, b"dinf", &dinf);
+    mp4_box(&mut minf, b"stbl", &stbl);
+
+    let mut mdia = Vec::new();
+    full_box(&mut mdia, b"mdhd", 1, 0, &mdhd);
+    full_box(&mut mdia, b"hdlr", 0, 0, &hdlr);
+    mp4_box(&mut mdia, b"minf", &minf);
+
+    let mut trak = Vec::new();
+    // Enabled and part of the presentation
+    full_box(&mut trak, b"tkhd", 1, 3, &tkhd);
+    if track.delay > 0 && !fragmented {
+        // Edits out the encoder delay, starting playback at the first real sample
+        let mut elst = 1u32.to_be_bytes().to_vec();
+        elst.extend_from_slice(&duration.saturating_sub(track.delay as u64).to_be_bytes());
+        elst.extend_from_slice(&(track.delay as u64).to_be_bytes());
+        elst.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        let mut edts = Vec::new();
+        full_box(&mut edts, b"elst", 1, 0, &elst);
+        mp4_box(&mut trak, b"edts", &edts);
+    }
+    mp4_box(&mut trak, b"mdia", &mdia);
+
+    let mut moov = Vec::new();
+    full_box(&mut moov, b"mvhd", 1, 0, &mvhd);
+    mp4_box(&mut moov, b"trak", &trak);
+    if fragmented {
+        // Defaults of the track's fragments, which give every sample's size and duration
+        let mut trex = 1u32.to_be_bytes().to_vec();
+        trex.extend_from_slice(&1u32.to_be_bytes());
+        trex.extend_from_slice(&[0; 12]);
+        let mut mvex = Vec::new();
+        full_box(&mut mvex, b"trex", 0, 0, &trex);
+        mp4_box(&mut moov, b"mvex", &mvex);
+    }
+
+    let mut out = Vec::new();
+    mp4_box(&mut out, b"moov", &moov);
+    Ok(out)
+}
+
+/// Returns the initialization segment of a fragmented MP4 stream, which movie fragments from
+/// `mp4_fragment` follow
+pub(crate) fn mp4_init(track: &Track) -> io::Result<Vec<u8>> {
+    Container::Mp4.check(track.codec)?;
+    let mut init = Vec::new();
+    mp4_box(
+        &mut init,
+        b"ftyp",
+        &brands(b"iso6", &[b"iso6", b"cmfc", b"mp41"]),
+    );
+    init.extend_from_slice(&mp4_moov(track, &Mp4Samples::default(), true)?);
+    Ok(init)
+}
+
+/// Returns a movie fragment holding packets, the `sequence`th fragment of the stream
+pub(crate) fn mp4_fragment(sequence: u32, packets: &[Packet]) -> io::Result<Vec<u8>> {
+    let mut samples = Mp4Samples::default();
+    let mut trun = (packets.len() as u32).to_be_bytes().to_vec();
+    // Data offset, patched once the size of the fragment is known
+    trun.extend_from_slice(&[0; 4]);
+    for packet in packets {
+        samples.push(packet)?;
+        trun.extend_from_slice(&(packet.duration as u32).to_be_bytes());
+        trun.extend_from_slice(&(packet.data.len() as u32).to_be_bytes());
+    }
+
+    let mut traf = Vec::new();
+    // Track 1, with offsets relative to the start of the fragment
+    full_box(&mut traf, b"tfhd", 0, 0x02_0000, &1u32.to_be_bytes());
+    let start = packets.first().map_or(0, |p| p.timestamp);
+    full_box(&mut traf, b"tfdt", 1, 0, &start.to_be_bytes());
+    // With data offset, sample durations and sample sizes
+    full_box(&mut traf, b"trun", 0, 0x0301, &trun);
+
+    let mut moof = Vec::new();
+    full_box(&mut moof, b"mfhd", 0, 0, &sequence.to_be_bytes());
+    mp4_box(&mut moof, b"traf", &traf);
+    let mut fragment = Vec::new();
+    mp4_box(&mut fragment, b"moof", &moof);
+
+    // Past the box header, version, flags and sample count of the trun at the end of the moof
+    let offset_at = fragment.len() - trun.len() + 4;
+    let data_offset = fragment.len() as u32 + 8;
+    fragment[offset_at..offset_at + 4].copy_from_slice(&data_offset.to_be_bytes());
+
+    let data: Vec<u8> = packets.iter().flat_map(|p| &p.data).copied().collect();
+    mp4_box(&mut fragment, b"mdat", &data);
+    Ok(fragment)
+}
+
+/// Identity transform of the movie and track headers
+const UNITY_MATRIX: [u8; 36] = [
+    0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+    0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, //
+    0, 0, 0, 0, 0, 0, 0, 0, 0x40, 0, 0, 0,
+];
+
+/// Returns the body of a file type box
+fn brands(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+    let mut body = major.to_vec();
+    body.extend_from_slice(&0u32.to_be_bytes());
+    for brand in compatible {
+        body.extend_from_slice(*brand);
+    }
+    body
+}
+
+/// Writes the audio sample entry of the track, with the codec's configuration
+fn sample_entry(out: &mut Vec<u8>, track: &Track) -> io::Result<()> {
+    let header = track.headers.first().map_or(&[][..], |h| h);
+    let (kind, config) = match track.codec {
+        Codec::Opus => (b"Opus", opus_specific_box(header)?),
+        Codec::Aac => (b"mp4a", aac_esds(header)),
+        Codec::Vorbis => return Container::Mp4.check(track.codec),
+    };
+
+    // Reserved, data reference index and reserved
+    let mut entry = vec![0; 6];
+    entry.extend_from_slice(&1u16.to_be_bytes());
+    entry.extend_from_slice(&[0; 8]);
+    entry.extend_from_slice(&(track.channels as u16).to_be_bytes());
+    // Sample size, predefined and reserved
+    entry.extend_from_slice(&16u16.to_be_bytes());
+    entry.extend_from_slice(&[0; 4]);
+    // A 16.16 fixed point rate, the rates too high for it are only known from the timescale
+    let rate = u16::try_from(track.sample_rate).unwrap_or(0) as u32;
+    entry.extend_from_slice(&(rate << 16).to_be_bytes());
+    entry.extend_from_slice(&config);
+    mp4_box(out, kind, &entry);
+    Ok(())
+}
+
+/// Returns the dOps box of an Opus track, which holds the fields of the identification header
+/// in big endian
+fn opus_specific_box(head: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "malformed OpusHead header");
+    if head.len() < 19 || &head[..8] != b"OpusHead" {
+        return Err(invalid());
+    }
+
+    let mut body = vec![0, head[9]];
+    body.extend_from_slice(&u16::from_le_bytes([head[10], head[11]]).to_be_bytes());
+    body.extend_from_slice(&u32::from_le_bytes(head[12..16].try_into().unwrap()).to_be_bytes());
+    body.extend_from_slice(&i16::from_le_bytes([head[16], head[17]]).to_be_bytes());
+    body.push(head[18]);
+    if head[18] != 0 {
+        // Stream count, coupled count and a mapping entry per channel
+        let mapping = head.get(19..21 + head[9] as usize).ok_or_else(invalid)?;
+        body.extend_from_slice(mapping);
+    }
+
+    let mut out = Vec::new();
+    mp4_box(&mut out, b"dOps", &body);
+    Ok(out)
+}
+
+/// Returns the esds box of an AAC track, wrapping its AudioSpecificConfig in MPEG-4
+/// descriptors
+fn aac_esds(config: &[u8]) -> Vec<u8> {
+    let mut specific = Vec::new();
+    descriptor(&mut specific, 0x05, config);
+
+    // MPEG-4 audio in an audio stream, with unknown buffer size and bitrates
+    let mut decoder_config = vec![0x40, 0x15];
+    decoder_config.extend_from_slice(&[0; 11]);
+    decoder_config.extend_from_slice(&specific);
+
+    let mut es = 1u16.to_be_bytes().to_vec();
+    es.push(0);
+    descriptor(&mut es, 0x04, &decoder_config);
+    // Sync layer configuration predefined for MP4 files
+    descriptor(&mut es, 0x06, &[0x02]);
+
+    let mut body = Vec::new();
+    descriptor(&mut body, 0x03, &es);
+    let mut out = Vec::new();
+    full_box(&mut out, b"esds", 0, 0, &body);
+    out
+}
+
+/// Writes an MPEG-4 descriptor, its size taking 7 bits per byte
+fn descriptor(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    out.push(tag);
+    let len = body.len() as u32;
+    for shift in [21, 14, 7] {
+        if len >= 1 << shift {
+            out.push(0x80 | (len >> shift) as u8 & 0x7F);
+        }
+    }
+    out.push(len as u8 & 0x7F);
+    out.extend_from_slice(body);
+}
+
+/// Writes an ISO base media file format box
+fn mp4_box(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+}
+
+/// Writes a box whose body starts with a version and flags
+fn full_box(out: &mut Vec<u8>, kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) {
+    let mut full = ((version as u32) << 24 | flags).to_be_bytes().to_vec();
+    full.extend_from_slice(body);
+    mp4_box(out, kind, &full);
+}
+
+/// EBML header, segment start, info and tracks of a WebM file
+pub(crate) struct WebmHeader {
+    pub(crate) data: Vec<u8>,
+    /// Offset of the 8 byte segment size, unknown until patched
+    pub(crate) segment_size_at: usize,
+    /// Offset of the f64 duration in milliseconds, 0 until patched
+    pub(crate) duration_at: usize,
+}
+
+pub(crate) fn webm_header(track: &Track) -> io::Result<WebmHeader> {
+    let mut ebml = Vec::new();
+    uint_element(&mut ebml, 0x4286, 1);
+    uint_element(&mut ebml, 0x42F7, 1);
+    uint_element(&mut ebml, 0x42F2, 4);
+    uint_element(&mut ebml, 0x42F3, 8);
+    element(&mut ebml, 0x4282, b"webm");
+    uint_element(&mut ebml, 0x4287, 4);
+    uint_element(&mut ebml, 0x4285, 2);
+    let mut header = Vec::new();
+    element(&mut header, 0x1A45DFA3, &ebml);
+
+    // Segment with an 8 byte size of all ones, meaning unknown
+    id(&mut header, 0x18538067);
+    let segment_size_at = header.len();
+    header.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+    let mut info = Vec::new();
+    uint_element(&mut info, 0x2AD7B1, 1_000_000);
+    element(&mut info, 0x4D80, VENDOR.as_bytes());
+    element(&mut info, 0x5741, VENDOR.as_bytes());
+    // Past the 2 byte id and 1 byte size
+    let duration_offset = info.len() + 3;
+    element(&mut info, 0x4489, &0f64.to_be_bytes());
+    id(&mut header, 0x1549A966);
+    size(&mut header, info.len() as u64);
+    let duration_at = header.len() + duration_offset;
+    header.extend_from_slice(&info);
+
+    let mut audio = Vec::new();
+    element(&mut audio, 0xB5, &(track.sample_rate as f64).to_be_bytes());
+    uint_element(&mut audio, 0x9F, track.channels as u64);
+
+    let mut entry = Vec::new();
+    uint_element(&mut entry, 0xD7, 1);
+    uint_element(&mut entry, 0x73C5, 1);
+    // Audio track
+    uint_element(&mut entry, 0x83, 2);
+    match track.codec {
+        Codec::Vorbis => {
+            element(&mut entry, 0x86, b"A_VORBIS");
+            element(&mut entry, 0x63A2, &xiph_lace(&track.headers));
+        }
+        Codec::Opus => {
+            element(&mut entry, 0x86, b"A_OPUS");
+            element(&mut entry, 0x63A2, track.headers.first().map_or(&[], |h| h));
+            let delay_ns = track.delay as u64 * 1_000_000_000 / track.sample_rate.max(1) as u64;
+            uint_element(&mut entry, 0x56AA, delay_ns);
+            uint_element(&mut entry, 0x56BB, OPUS_SEEK_PREROLL_NS);
+        }
+        // Fails, WebM doesn't allow AAC
+        Codec::Aac => Container::Webm.check(track.codec)?,
+    }
+    element(&mut entry, 0xE1, &audio);
+
+    let mut tracks = Vec::new();
+    element(&mut tracks, 0xAE, &entry);
+    element(&mut header, 0x1654AE6B, &tracks);
+
+    Ok(WebmHeader {
+        data: header,
+        segment_size_at,
+        duration_at,
+    })
+}
+
+/// Writes a block of the only track, given its timestamp relative to the cluster
+pub(crate) fn simple_block(out: &mut Vec<u8>, relative_ms: i16, data: &[u8]) {
+    // Track number, relative timestamp and keyframe flag
+    let mut block = vec![0x81];
+    block.extend_from_slice(&relative_ms.to_be_bytes());
+    block.push(0x80);
+    block.extend_from_slice(data);
+    element(out, 0xA3, &block);
+}
+
+/// Packs headers the way Matroska expects Xiph codec private data
+fn xiph_lace(headers: &[Vec<u8>]) -> Vec<u8> {
+    let mut laced = vec![headers.len().saturating_sub(1) as u8];
+    for header in headers.iter().take(headers.len().saturating_sub(1)) {
+        laced.extend(std::iter::repeat_n(255, header.len() / 255));
+        laced.push((header.len() % 255) as u8);
+    }
+    for header in headers {
+        laced.extend_from_slice(header);
+    }
+    laced
+}
+
+fn id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// Writes an EBML variable size integer using as few bytes as possible
+fn size(out: &mut Vec<u8>, size: u64) {
+    // A value of all ones is reserved for unknown sizes
+    let len = (1..=8)
+        .find(|&len| size < (1 << (7 * len)) - 1)
+        .unwrap_or(8);
+    let marked = size | 1 << (7 * len);
+    out.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+pub(crate) fn element(out: &mut Vec<u8>, element_id: u32, body: &[u8]) {
+    id(out, element_id);
+    size(out, body.len() as u64);
+    out.extend_from_slice(body);
+}
+
+pub(crate) fn uint_element(out: &mut Vec<u8>, element_id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(out, element_id, &bytes[skip..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn opus_track() -> Track {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 2]);
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&44100u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        Track {
+            codec: Codec::Opus,
+            sample_rate: 48000,
+            channels: Channels::Stereo,
+            headers: vec![head, opus_tags_header()],
+            delay: 312,
+        }
+    }
+
+    /// Packets of made up data, the muxers never look inside
+    fn packets(count: u64) -> Vec<Packet> {
+        (0..count)
+            .map(|i| Packet {
+                data: vec![i as u8; 10 + i as usize],
+                timestamp: i * 960,
+                duration: if i + 1 == count { 480 } else { 960 },
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "isomp4")]
+    #[test]
+    fn mp4_roundtrip() {
+        let aac = Track {
+            codec: Codec::Aac,
+            sample_rate: 44100,
+            channels: Channels::Mono,
+            // AAC-LC at 44.1 kHz, mono
+            headers: vec![vec![0x12, 0x08]],
+            delay: 0,
+        };
+
+        for track in [opus_track(), aac] {
+            let packets = packets(50);
+            let mut muxer = Mp4Muxer::new(Cursor::new(Vec::new()), &track).unwrap();
+            for packet in &packets {
+                muxer.write_packet(packet).unwrap();
+            }
+            let mp4 = muxer.finish().unwrap().into_inner();
+
+            let (demuxed, demuxed_packets) = demux(Cursor::new(mp4)).unwrap();
+            assert_eq!(demuxed.codec, track.codec);
+            assert_eq!(demuxed.channels, track.channels);
+            assert_eq!(demuxed.sample_rate, track.sample_rate);
+            assert_eq!(demuxed.headers[0], track.headers[0]);
+            assert_eq!(demuxed.delay, track.delay);
+            assert_eq!(demuxed_packets.len(), packets.len());
+            for (demuxed, packet) in demuxed_packets.iter().zip(&packets) {
+                assert_eq!(demuxed.data, packet.data);
+                assert_eq!(demuxed.timestamp, packet.timestamp);
+                assert_eq!(demuxed.duration, packet.duration);
+            }
+        }
+    }
+
+    #[test]
+    fn containers_refuse_codecs_they_cant_hold() {
+        let vorbis = Track {
+            codec: Codec::Vorbis,
+            ..opus_track()
+        };
+        let aac = Track {
+            codec: Codec::Aac,
+            ..opus_track()
+        };
+        let unsupported = |result: io::Result<()>| result.unwrap_err().kind();
+        assert_eq!(
+            unsupported(Mp4Muxer::new(Cursor::new(Vec::new()), &vorbis).map(drop)),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            unsupported(WebmMuxer::new(Cursor::new(Vec::new()), &aac).map(drop)),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            unsupported(OggMuxer::new(Vec::new(), &aac).map(drop)),
+            io::ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn webm_without_sample_rate() {
+        let track = Track {
+            sample_rate: 0,
+            ..opus_track()
+        };
+        let mut muxer = WebmMuxer::new(Cursor::new(Vec::new()), &track).unwrap();
+        for packet in packets(3) {
+            muxer.write_packet(&packet).unwrap();
+        }
+        muxer.finish().unwrap();
+    }
+}
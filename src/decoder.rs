@@ -0,0 +1,263 @@
+use crate::{
+    channels, frames_to_ts, lost_frames, n_frames, probe_format, ring_buffer, sample_rate,
+    ts_to_frames, wav::WavChunks, AudyoSample, Channels, DecodeError, DecodeOptions, Metadata,
+    RingConsumer, SampleBuffer, TimeBase,
+};
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use symphonia::core::{
+    codecs::Decoder as CodecDecoder,
+    errors::Error as SymphoniaError,
+    formats::{FormatReader, SeekMode, SeekTo},
+    io::MediaSource,
+};
+
+/// Frames decoded ahead of the requested one after seeking, since decoders may produce no
+/// audio for the first packets after a reset, e.g. Vorbis needs a whole block to overlap with
+pub(crate) const SEEK_PREROLL: u64 = 8192;
+
+/// Decoder pulling one packet's worth of frames at a time out of a source, for playback and
+/// other uses that shouldn't hold the whole file in memory
+pub struct Decoder<S: AudyoSample> {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    channels: Channels,
+    sample_rate: u32,
+    time_base: Option<TimeBase>,
+    n_frames: Option<u64>,
+    max_frames_per_packet: Option<u64>,
+    metadata: Metadata,
+    /// Frame the next chunk starts at
+    position: u64,
+    /// Frame requested by the last seek, until decoding reaches it
+    target: Option<u64>,
+    finished: bool,
+    _sample: std::marker::PhantomData<S>,
+}
+
+/// Frames of one packet yielded by a `Decoder`, along with where they start in the stream
+#[derive(Clone, Debug)]
+pub struct DecodedChunk<S: AudyoSample> {
+    start: u64,
+    buffer: SampleBuffer<S>,
+}
+
+impl<S: AudyoSample> DecodedChunk<S> {
+    /// Returns the frame the chunk starts at, going by packet timestamps
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the time the chunk starts at
+    pub fn timestamp(&self) -> Duration {
+        TimeBase::new(1, self.buffer.sample_rate).duration(self.start)
+    }
+
+    /// Returns the chunk's frames
+    pub fn buffer(&self) -> &SampleBuffer<S> {
+        &self.buffer
+    }
+
+    /// Returns the chunk's frames, dropping the position
+    pub fn into_buffer(self) -> SampleBuffer<S> {
+        self.buffer
+    }
+}
+
+impl<S: AudyoSample> Decoder<S> {
+    /// Opens the source for decoding
+    pub fn new(source: impl MediaSource + 'static) -> Result<Self, DecodeError> {
+        Self::with_options(source, &Default::default())
+    }
+
+    /// Opens the source for decoding using given options
+    pub fn with_options(
+        source: impl MediaSource + 'static,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError> {
+        let mut source: Box<dyn MediaSource> = Box::new(source);
+        let chunks = WavChunks::read(&mut *source)?;
+        let mut probed = probe_format(source, options)?;
+        let mut metadata = Metadata::read(&mut probed);
+        chunks.apply(&mut metadata);
+        let reader = probed.format;
+
+        let track = options.select_track(&*reader)?;
+        let track_id = track.id;
+        let channels = channels(&track.codec_params, options.downmix)?;
+        let sample_rate = sample_rate(&track.codec_params)?;
+        let time_base = track.codec_params.time_base.map(TimeBase::from);
+        let n_frames = n_frames(&track.codec_params);
+        let max_frames_per_packet = track.codec_params.max_frames_per_packet;
+
+        let decoder = options
+            .codecs()
+            .make(&track.codec_params, &options.decoder_options)?;
+
+        Ok(Self {
+            reader,
+            decoder,
+            track_id,
+            channels,
+            sample_rate,
+            time_base,
+            n_frames,
+            max_frames_per_packet,
+            metadata,
+            position: 0,
+            target: None,
+            finished: false,
+            _sample: Default::default(),
+        })
+    }
+
+    /// Returns source's channel layout
+    pub fn channels(&self) -> Channels {
+        self.channels
+    }
+
+    /// Returns source's sample rate
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns number of frames the source claims to contain, if known
+    pub fn frames(&self) -> Option<u64> {
+        self.n_frames
+    }
+
+    /// Returns tags, chapters and other metadata read when opening the source
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the frame the next chunk starts at
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Decodes the next packet, returns None at the end of the stream
+    /// Corrupt packets yield silence, so later chunks keep their timing
+    pub fn next_chunk(&mut self) -> Result<Option<DecodedChunk<S>>, DecodeError> {
+        while !self.finished {
+            let Ok(packet) = self.reader.next_packet() else {
+                break;
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let mut chunk = SampleBuffer::new(0, self.channels, self.sample_rate);
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => chunk.copy_samples(decoded)?,
+                Err(SymphoniaError::DecodeError(_)) => {
+                    chunk.write_silence(lost_frames(
+                        packet.dur,
+                        self.time_base,
+                        self.sample_rate,
+                        self.max_frames_per_packet,
+                    ))?;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Err(e.into());
+                }
+            }
+            if chunk.written == 0 {
+                continue;
+            }
+
+            // After seeking, frames before the requested one get dropped, going by packet
+            // timestamps
+            let mut start = self.ts_to_frames(packet.ts());
+            let mut skip = 0;
+            if let Some(target) = self.target {
+                if start + chunk.written_frames() as u64 <= target {
+                    continue;
+                }
+                skip = target.saturating_sub(start) as usize;
+                start = start.max(target);
+                self.target = None;
+            }
+
+            let channels = self.channels as usize;
+            let mut samples = chunk.buffer.into_vec();
+            samples.truncate(chunk.written);
+            samples.drain(..skip * channels);
+            self.position = start + (samples.len() / channels) as u64;
+            return Ok(Some(DecodedChunk {
+                start,
+                buffer: SampleBuffer::from_samples(samples, self.channels, self.sample_rate),
+            }));
+        }
+
+        self.finished = true;
+        Ok(None)
+    }
+
+    /// Seeks so that the next chunk starts exactly at the given frame
+    /// The container seeks to a packet before it, decoded frames up to the requested one are
+    /// dropped
+    pub fn seek_to_frame(&mut self, frame: u64) -> Result<(), DecodeError> {
+        self.reader.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: self.frames_to_ts(frame.saturating_sub(SEEK_PREROLL)),
+                track_id: self.track_id,
+            },
+        )?;
+        self.decoder.reset();
+
+        self.position = frame;
+        self.target = Some(frame);
+        self.finished = false;
+        Ok(())
+    }
+
+    /// Seeks so that the next chunk starts exactly at the given time, rounded down to a frame
+    pub fn seek_to(&mut self, time: Duration) -> Result<(), DecodeError> {
+        self.seek_to_frame(TimeBase::new(1, self.sample_rate).timestamp(time))
+    }
+
+    fn ts_to_frames(&self, ts: u64) -> u64 {
+        ts_to_frames(ts, self.time_base, self.sample_rate)
+    }
+
+    fn frames_to_ts(&self, frames: u64) -> u64 {
+        frames_to_ts(frames, self.time_base, self.sample_rate)
+    }
+}
+
+impl<S: AudyoSample + Send + 'static> Decoder<S> {
+    /// Moves the decoder to a new thread pushing decoded frames into a ring buffer holding up to
+    /// `capacity` frames, so a realtime consumer can pop them without copying whole buffers
+    /// The thread exits at the end of the stream, on error or once the consumer is dropped
+    pub fn spawn_into_ring(
+        mut self,
+        capacity: usize,
+    ) -> (RingConsumer<S>, JoinHandle<Result<(), DecodeError>>) {
+        let (mut producer, consumer) = ring_buffer(capacity, self.channels, self.sample_rate);
+
+        let thread = thread::spawn(move || {
+            while let Some(chunk) = self.next_chunk()? {
+                if !producer.push_all(chunk.buffer().samples()) {
+                    break;
+                }
+            }
+            Ok(())
+        });
+
+        (consumer, thread)
+    }
+}
+
+impl<S: AudyoSample> Iterator for Decoder<S> {
+    type Item = Result<DecodedChunk<S>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{stft::Stft, BufferError, SampleBuffer, TimeBase};
+use std::{ops::Range, time::Duration};
+
+const FRAME_SIZE: usize = 2048;
+const HOP: usize = FRAME_SIZE / 4;
+/// Noise power is subtracted this many times over, so that its fluctuations above the average
+/// get removed too
+const OVERSUBTRACTION: f32 = 2.;
+/// Lowest gain a bin gets, -26 dB, leaving a little of the noise in rather than the isolated
+/// tones, known as musical noise, full removal leaves behind
+const GAIN_FLOOR: f32 = 0.05;
+/// How much of the previous frame's power carries over into the estimate for the next, taming
+/// musical noise further
+const POWER_SMOOTHING: f32 = 0.5;
+
+/// Average spectrum of background noise, learned from a stretch of a recording without wanted
+/// signal, e.g. the room tone before someone starts speaking
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseProfile {
+    /// Mean power of each bin
+    power: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl SampleBuffer<f32> {
+    /// Learns the noise profile of the frames within the range, averaged over channels
+    /// The range should hold only noise, and last at least a few tenths of a second
+    pub fn noise_profile(&self, range: Range<Duration>) -> NoiseProfile {
+        let channels = self.channels as usize;
+        let time_base = TimeBase::new(1, self.sample_rate);
+        let frames = self.written_frames();
+        let start = (time_base.timestamp(range.start) as usize).min(frames);
+        let end = (time_base.timestamp(range.end) as usize).clamp(start, frames);
+
+        let stft = Stft::new(FRAME_SIZE);
+        let mut power = vec![0f32; FRAME_SIZE];
+        let mut count = 0;
+        for c in 0..channels {
+            let noise = channel(&self.buffer[start * channels..end * channels], channels, c);
+            for spectrum in stft.spectrogram(&noise, HOP) {
+                for (p, bin) in power.iter_mut().zip(spectrum) {
+                    *p += bin.norm_sqr();
+                }
+                count += 1;
+            }
+        }
+        power.iter_mut().for_each(|p| *p /= count.max(1) as f32);
+
+        NoiseProfile {
+            power,
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// Returns the buffer with the profiled noise removed by spectral subtraction
+    /// The profile must come from audio at the same sample rate
+    pub fn denoise(&self, profile: &NoiseProfile) -> Result<SampleBuffer<f32>, BufferError> {
+        if profile.sample_rate != self.sample_rate {
+            return Err(BufferError::SampleRateMismatch(
+                self.sample_rate,
+                profile.sample_rate,
+            ));
+        }
+
+        let channels = self.channels as usize;
+        let stft = Stft::new(FRAME_SIZE);
+        let mut output = vec![0.; self.buffer.len()];
+        for c in 0..channels {
+            let input = channel(&self.buffer, channels, c);
+            let cleaned = denoise_channel(&stft, &input, &profile.power);
+            for (out, s) in output.iter_mut().skip(c).step_by(channels).zip(cleaned) {
+                *out = s;
+            }
+        }
+
+        let mut denoised = SampleBuffer::from_samples(output, self.channels, self.sample_rate);
+        denoised.written = self.written;
+        Ok(denoised)
+    }
+}
+
+fn channel(samples: &[f32], channels: usize, c: usize) -> Vec<f32> {
+    samples.iter().skip(c).step_by(channels).copied().collect()
+}
+
+fn denoise_channel(stft: &Stft, input: &[f32], noise: &[f32]) -> Vec<f32> {
+    let len = input.len();
+    // Pad the start, so the first frame is centered on the first sample
+    let padded: Vec<f32> = std::iter::repeat_n(0., FRAME_SIZE / 2)
+        .chain(input.iter().copied())
+        .collect();
+
+    let mut output = vec![0.; len + FRAME_SIZE];
+    let mut norm = vec![0.; len + FRAME_SIZE];
+    let mut smoothed = vec![0f32; FRAME_SIZE];
+
+    for start in (0..len + FRAME_SIZE / 2).step_by(HOP) {
+        let mut spectrum = stft.forward(padded.get(start..).unwrap_or(&[]));
+        for ((bin, &noise), power) in spectrum.iter_mut().zip(noise).zip(&mut smoothed) {
+            *power = POWER_SMOOTHING * *power + (1. - POWER_SMOOTHING) * bin.norm_sqr();
+            let gain = if *power > 0. {
+                (1. - OVERSUBTRACTION * noise / *power).max(0.).sqrt()
+            } else {
+                0.
+            };
+            *bin *= gain.max(GAIN_FLOOR);
+        }
+
+        for (i, s) in stft.inverse(spectrum).into_iter().enumerate() {
+            if let Some(o) = output.get_mut(start + i) {
+                *o += s;
+                norm[start + i] += stft.window()[i].powi(2);
+            }
+        }
+    }
+
+    output
+        .iter()
+        .zip(&norm)
+        .skip(FRAME_SIZE / 2)
+        .take(len)
+        .map(|(&s, &n)| if n > 1e-3 { s / n } else { 0. })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, Channels};
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    /// Returns the clean signal: a second of silence, then two of a tone, and it with noise added
+    fn noisy() -> (SampleBuffer<f32>, SampleBuffer<f32>) {
+        let mut clean = generate::silence(Duration::from_secs(1), SAMPLE_RATE, Channels::Stereo);
+        let mut tone = generate::sine(440., Duration::from_secs(2), SAMPLE_RATE, Channels::Stereo);
+        tone.buffer.iter_mut().for_each(|s| *s *= 0.5);
+        clean.extend_from_buffer(&tone).unwrap();
+
+        let noise = generate::white_noise(Duration::from_secs(3), SAMPLE_RATE, Channels::Stereo);
+        let samples = clean
+            .samples()
+            .iter()
+            .zip(noise.samples())
+            .map(|(s, n)| s + 0.05 * n)
+            .collect();
+        let noisy = SampleBuffer::from_samples(samples, Channels::Stereo, SAMPLE_RATE);
+        (clean, noisy)
+    }
+
+    /// Returns the signal to noise ratio in dB of the frames from `start` on
+    fn snr(clean: &SampleBuffer<f32>, signal: &SampleBuffer<f32>, start: usize) -> f32 {
+        let (clean, signal) = (&clean.samples()[start..], &signal.samples()[start..]);
+        let power: f32 = clean.iter().map(|s| s * s).sum();
+        let error: f32 = clean.iter().zip(signal).map(|(c, s)| (c - s).powi(2)).sum();
+        10. * (power / error).log10()
+    }
+
+    #[test]
+    fn improves_snr() {
+        let (clean, noisy) = noisy();
+        let profile = noisy.noise_profile(Duration::ZERO..Duration::from_millis(900));
+        let denoised = noisy.denoise(&profile).unwrap();
+        assert_eq!(denoised.written_frames(), noisy.written_frames());
+
+        // Past the onset of the tone, where the smoothing is still catching up
+        let start = (SAMPLE_RATE as usize + FRAME_SIZE) * 2;
+        let (before, after) = (snr(&clean, &noisy, start), snr(&clean, &denoised, start));
+        assert!(after > before + 6., "{before} dB -> {after} dB");
+
+        // Noise on its own is brought down, peaks of it above the oversubtraction get through
+        let noise = |buffer: &SampleBuffer<f32>| -> f32 {
+            buffer.samples()[..SAMPLE_RATE as usize]
+                .iter()
+                .map(|s| s * s)
+                .sum()
+        };
+        let reduction = 10. * (noise(&denoised) / noise(&noisy)).log10();
+        assert!(reduction < -10., "{reduction} dB");
+    }
+
+    #[test]
+    fn profile_must_match_the_sample_rate() {
+        let (_, noisy) = noisy();
+        let other = generate::white_noise(Duration::from_secs(1), 8000, Channels::Mono);
+        let profile = other.noise_profile(Duration::ZERO..Duration::from_secs(1));
+        assert!(matches!(
+            noisy.denoise(&profile),
+            Err(BufferError::SampleRateMismatch(SAMPLE_RATE, 8000))
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{BufferError, SampleBuffer};
+#[cfg(feature = "resampler")]
+use std::borrow::Cow;
+
+/// Furthest offset in frames searched when aligning buffers, enough for common encoder delays
+const MAX_OFFSET: usize = 2048;
+/// Number of frames the alignment is measured over
+const ALIGNMENT_FRAMES: usize = 8192;
+/// Normalized correlation an offset must improve on the best one by to replace it
+const ALIGNMENT_MARGIN: f64 = 1e-4;
+
+/// Differences between two buffers, as returned by `SampleBuffer::diff`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferDiff {
+    /// Frames the other buffer was shifted by to line up, positive if it starts late
+    pub offset: i64,
+    /// Number of frames compared after aligning
+    pub compared_frames: usize,
+    /// How many frames longer (positive) or shorter (negative) the other buffer is
+    pub frame_difference: i64,
+    /// Largest absolute difference between samples
+    pub max_difference: f32,
+    /// Root mean square of the differences between samples
+    pub rms_error: f32,
+    /// First compared frame, counted in this buffer, where any sample differs
+    pub first_divergent_frame: Option<usize>,
+}
+
+impl BufferDiff {
+    /// Returns whether every compared sample is equal
+    pub fn is_identical(&self) -> bool {
+        self.first_divergent_frame.is_none()
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Compares the buffer against another, lining them up first so that e.g. encoder delay
+    /// doesn't count as difference
+    /// Channel layouts must match, and sample rates unless the `resampler` feature is enabled,
+    /// in which case the other buffer gets resampled to this one's rate
+    pub fn diff(&self, other: &SampleBuffer<f32>) -> Result<BufferDiff, BufferError> {
+        if other.channels != self.channels {
+            return Err(BufferError::ChannelsMismatch(self.channels, other.channels));
+        }
+        #[cfg(not(feature = "resampler"))]
+        if other.sample_rate != self.sample_rate {
+            return Err(BufferError::SampleRateMismatch(
+                self.sample_rate,
+                other.sample_rate,
+            ));
+        }
+        #[cfg(feature = "resampler")]
+        let other = if other.sample_rate == self.sample_rate {
+            Cow::Borrowed(other)
+        } else {
+            Cow::Owned(other.resampled(self.sample_rate, crate::resample::Quality::default()))
+        };
+
+        let channels = self.channels as usize;
+        let a = &self.buffer[..self.written];
+        let b = &other.buffer[..other.written];
+        let offset = alignment(a, b, channels);
+
+        // Frame of each buffer the comparison starts at
+        let (start_a, start_b) = if offset >= 0 {
+            (0, offset as usize)
+        } else {
+            ((-offset) as usize, 0)
+        };
+        let a = a.get(start_a * channels..).unwrap_or_default();
+        let b = b.get(start_b * channels..).unwrap_or_default();
+
+        let mut max_difference = 0f32;
+        let mut squared = 0f64;
+        let mut first_divergent_frame = None;
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            let difference = (x - y).abs();
+            if difference > 0. && first_divergent_frame.is_none() {
+                first_divergent_frame = Some(start_a + i / channels);
+            }
+            max_difference = max_difference.max(difference);
+            squared += (difference as f64).powi(2);
+        }
+
+        let compared = a.len().min(b.len());
+        Ok(BufferDiff {
+            offset,
+            compared_frames: compared / channels,
+            frame_difference: (other.written / channels) as i64 - (self.written / channels) as i64,
+            max_difference,
+            rms_error: if compared > 0 {
+                (squared / compared as f64).sqrt() as f32
+            } else {
+                0.
+            },
+            first_divergent_frame,
+        })
+    }
+}
+
+/// Returns the offset of b relative to a maximizing their normalized cross-correlation near the
+/// start, preferring smaller offsets on ties
+pub(crate) fn alignment(a: &[f32], b: &[f32], channels: usize) -> i64 {
+    let mono = |samples: &[f32]| -> Vec<f32> {
+        samples
+            .chunks_exact(channels)
+            .take(ALIGNMENT_FRAMES + MAX_OFFSET)
+            .map(|frame| frame.iter().sum())
+            .collect()
+    };
+    let (a, b) = (mono(a), mono(b));
+
+    let correlation = |offset: i64| -> f64 {
+        let (a, b) = if offset >= 0 {
+            (&a[..], b.get(offset as usize..).unwrap_or_default())
+        } else {
+            (a.get((-offset) as usize..).unwrap_or_default(), &b[..])
+        };
+        let (mut product, mut energy_a, mut energy_b) = (0f64, 0f64, 0f64);
+        for (&x, &y) in a.iter().zip(b).take(ALIGNMENT_FRAMES) {
+            product += x as f64 * y as f64;
+            energy_a += x as f64 * x as f64;
+            energy_b += y as f64 * y as f64;
+        }
+        let energy = (energy_a * energy_b).sqrt();
+        if energy > 0. {
+            product / energy
+        } else {
+            0.
+        }
+    };
+
+    let mut best = (0, correlation(0));
+    for distance in 1..=MAX_OFFSET as i64 {
+        for offset in [distance, -distance] {
+            let value = correlation(offset);
+            // Periodic signals correlate about as well a period off, those don't count
+            if value > best.1 + ALIGNMENT_MARGIN {
+                best = (offset, value);
+            }
+        }
+    }
+    best.0
+}
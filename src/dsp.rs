@@ -0,0 +1,125 @@
+use crate::{
+    resample::Quality,
+    stft::{Complex, Stft},
+    SampleBuffer,
+};
+use std::f32::consts::TAU;
+
+/// Phase vocoder frame size
+const FRAME_SIZE: usize = 2048;
+/// Phase vocoder synthesis hop
+const HOP: usize = FRAME_SIZE / 4;
+
+impl SampleBuffer<f32> {
+    /// Returns the buffer stretched in time by `factor` without changing its pitch
+    /// Factors above 1 make it longer, below 1 shorter
+    pub fn time_stretch(&self, factor: f32) -> SampleBuffer<f32> {
+        let channels = self.channels as usize;
+        let frames = self.buffer.len() / channels;
+        let output_frames = (frames as f64 * factor as f64).round() as usize;
+
+        let stft = Stft::new(FRAME_SIZE);
+        let mut output = vec![0.; output_frames * channels];
+
+        for c in 0..channels {
+            let input: Vec<f32> = self
+                .buffer
+                .iter()
+                .skip(c)
+                .step_by(channels)
+                .copied()
+                .collect();
+            let stretched = stretch_channel(&stft, &input, factor, output_frames);
+
+            for (out, s) in output.iter_mut().skip(c).step_by(channels).zip(stretched) {
+                *out = s;
+            }
+        }
+
+        SampleBuffer::from_samples(output, self.channels, self.sample_rate)
+    }
+
+    /// Returns the buffer shifted in pitch by `semitones` without changing its duration
+    pub fn pitch_shift(&self, semitones: f32) -> SampleBuffer<f32> {
+        let ratio = 2f32.powf(semitones / 12.);
+        let frames = self.duration;
+
+        // Stretching and then playing back faster shifts the pitch up by the same ratio
+        let stretched = self.time_stretch(ratio);
+        let rate = (self.sample_rate as f64 * ratio as f64).round() as u32;
+        let shifted = SampleBuffer::from_samples(stretched.buffer.into_vec(), self.channels, rate)
+            .resampled(self.sample_rate, Quality::default());
+
+        let mut samples = shifted.buffer.into_vec();
+        samples.resize(frames * self.channels as usize, 0.);
+        SampleBuffer::from_samples(samples, self.channels, self.sample_rate)
+    }
+}
+
+fn stretch_channel(stft: &Stft, input: &[f32], factor: f32, output_len: usize) -> Vec<f32> {
+    let bins = FRAME_SIZE;
+    let analysis_hop = HOP as f64 / factor as f64;
+
+    // Pad the start, so the first frame is centered on the first sample
+    let padded: Vec<f32> = std::iter::repeat_n(0., FRAME_SIZE / 2)
+        .chain(input.iter().copied())
+        .collect();
+
+    let mut output = vec![0.; output_len + FRAME_SIZE];
+    let mut norm = vec![0.; output_len + FRAME_SIZE];
+
+    let mut previous_phase = vec![0.; bins];
+    let mut phase = vec![0.; bins];
+    let mut previous_position = 0;
+
+    for m in 0.. {
+        let out_start = m * HOP;
+        if out_start >= output_len + FRAME_SIZE / 2 {
+            break;
+        }
+
+        let position = (m as f64 * analysis_hop).round() as usize;
+        let spectrum = stft.forward(padded.get(position..).unwrap_or(&[]));
+        let hop = (position - previous_position) as f32;
+
+        let synthesis: Vec<_> = spectrum
+            .iter()
+            .enumerate()
+            .map(|(k, c)| {
+                let (magnitude, current) = c.to_polar();
+                let omega = TAU * k as f32 / bins as f32;
+
+                if m == 0 {
+                    phase[k] = current;
+                } else {
+                    // Deviation from the bin's expected phase advance gives its true frequency
+                    let delta = current - previous_phase[k] - omega * hop;
+                    let delta = delta - TAU * (delta / TAU).round();
+                    let frequency = omega + if hop > 0. { delta / hop } else { 0. };
+                    phase[k] += frequency * HOP as f32;
+                    phase[k] %= TAU;
+                }
+                previous_phase[k] = current;
+
+                Complex::from_polar(magnitude, phase[k])
+            })
+            .collect();
+
+        previous_position = position;
+
+        for (i, s) in stft.inverse(synthesis).into_iter().enumerate() {
+            if let Some(o) = output.get_mut(out_start + i) {
+                *o += s;
+                norm[out_start + i] += stft.window()[i].powi(2);
+            }
+        }
+    }
+
+    output
+        .iter()
+        .zip(&norm)
+        .skip(FRAME_SIZE / 2)
+        .take(output_len)
+        .map(|(&s, &n)| if n > 1e-3 { s / n } else { 0. })
+        .collect()
+}
@@ -0,0 +1,148 @@
+use crate::{
+    decode_reader, i24, probe_format, u24, wav::WavChunks, AudyoSample, Channels, DecodeError,
+    DecodeInfo, DecodeOptions, SampleBuffer,
+};
+use symphonia::core::{io::MediaSource, sample::SampleFormat};
+
+/// Buffer of samples in any of the supported sample types
+#[derive(Clone, Debug)]
+pub enum DynSampleBuffer {
+    U8(SampleBuffer<u8>),
+    U16(SampleBuffer<u16>),
+    U24(SampleBuffer<u24>),
+    U32(SampleBuffer<u32>),
+    I8(SampleBuffer<i8>),
+    I16(SampleBuffer<i16>),
+    I24(SampleBuffer<i24>),
+    I32(SampleBuffer<i32>),
+    F32(SampleBuffer<f32>),
+    F64(SampleBuffer<f64>),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $buffer:ident => $e:expr) => {
+        match $self {
+            DynSampleBuffer::U8($buffer) => $e,
+            DynSampleBuffer::U16($buffer) => $e,
+            DynSampleBuffer::U24($buffer) => $e,
+            DynSampleBuffer::U32($buffer) => $e,
+            DynSampleBuffer::I8($buffer) => $e,
+            DynSampleBuffer::I16($buffer) => $e,
+            DynSampleBuffer::I24($buffer) => $e,
+            DynSampleBuffer::I32($buffer) => $e,
+            DynSampleBuffer::F32($buffer) => $e,
+            DynSampleBuffer::F64($buffer) => $e,
+        }
+    };
+}
+
+macro_rules! impl_from {
+    ($($variant:ident($t:ty)),*) => {
+        $(
+            impl From<SampleBuffer<$t>> for DynSampleBuffer {
+                fn from(buffer: SampleBuffer<$t>) -> Self {
+                    Self::$variant(buffer)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    U8(u8),
+    U16(u16),
+    U24(u24),
+    U32(u32),
+    I8(i8),
+    I16(i16),
+    I24(i24),
+    I32(i32),
+    F32(f32),
+    F64(f64)
+);
+
+impl DynSampleBuffer {
+    /// Returns buffer duration in samples
+    pub fn duration(&self) -> usize {
+        dispatch!(self, b => b.duration())
+    }
+
+    /// Returns buffer's channel layout
+    pub fn channels(&self) -> Channels {
+        dispatch!(self, b => b.channels())
+    }
+
+    /// Returns buffer's sample rate
+    pub fn sample_rate(&self) -> u32 {
+        dispatch!(self, b => b.sample_rate())
+    }
+
+    /// Returns the format of contained samples
+    pub fn sample_format(&self) -> SampleFormat {
+        match self {
+            Self::U8(_) => SampleFormat::U8,
+            Self::U16(_) => SampleFormat::U16,
+            Self::U24(_) => SampleFormat::U24,
+            Self::U32(_) => SampleFormat::U32,
+            Self::I8(_) => SampleFormat::S8,
+            Self::I16(_) => SampleFormat::S16,
+            Self::I24(_) => SampleFormat::S24,
+            Self::I32(_) => SampleFormat::S32,
+            Self::F32(_) => SampleFormat::F32,
+            Self::F64(_) => SampleFormat::F64,
+        }
+    }
+
+    /// Returns an equivalent buffer with the desired sample format
+    pub fn converted<T: AudyoSample + 'static>(&self) -> SampleBuffer<T> {
+        dispatch!(self, b => b.converted())
+    }
+}
+
+/// Decodes an audio file in source, preserving the source's native sample format
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode_dyn(
+    source: impl MediaSource + 'static,
+) -> Result<(DecodeInfo, DynSampleBuffer), DecodeError> {
+    decode_dyn_with(source, &Default::default())
+}
+
+/// Decodes an audio file in source using given options, preserving the source's native sample
+/// format
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode_dyn_with(
+    mut source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<(DecodeInfo, DynSampleBuffer), DecodeError> {
+    let len = source.byte_len();
+    let chunks = WavChunks::read(&mut source)?;
+    let reader = probe_format(Box::new(source), options)?;
+
+    let params = &options.select_track(&*reader.format)?.codec_params;
+
+    // Lossy codecs usually don't declare a format and decode to floats
+    let format = params
+        .sample_format
+        .or(params.bits_per_sample.map(|bits| match bits {
+            ..=8 => SampleFormat::U8,
+            9..=16 => SampleFormat::S16,
+            17..=24 => SampleFormat::S24,
+            _ => SampleFormat::S32,
+        }))
+        .unwrap_or(SampleFormat::F32);
+
+    let (mut info, buffer) = match format {
+        SampleFormat::U8 => decode_reader::<u8>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::U16 => decode_reader::<u16>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::U24 => decode_reader::<u24>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::U32 => decode_reader::<u32>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::S8 => decode_reader::<i8>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::S16 => decode_reader::<i16>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::S24 => decode_reader::<i24>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::S32 => decode_reader::<i32>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::F32 => decode_reader::<f32>(reader, len, options).map(|(i, b)| (i, b.into())),
+        SampleFormat::F64 => decode_reader::<f64>(reader, len, options).map(|(i, b)| (i, b.into())),
+    }?;
+    chunks.apply(&mut info.metadata);
+    Ok((info, buffer))
+}
@@ -0,0 +1,284 @@
+use crate::SampleBuffer;
+use std::{collections::VecDeque, f32::consts::PI, time::Duration};
+
+/// Limiter lookahead in seconds
+const LIMITER_LOOKAHEAD: f32 = 0.005;
+/// Limiter release time in seconds
+const LIMITER_RELEASE: f32 = 0.05;
+/// Gate attack time in seconds
+const GATE_ATTACK: f32 = 0.001;
+/// Gate release time in seconds
+const GATE_RELEASE: f32 = 0.05;
+/// Oversampling factor used to estimate true peaks
+const OVERSAMPLING: usize = 4;
+/// Taps on each side of the true peak interpolation kernel
+const INTERPOLATION_TAPS: usize = 8;
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+fn amplitude_to_db(amplitude: f32) -> f32 {
+    20. * amplitude.max(f32::MIN_POSITIVE).log10()
+}
+
+/// Returns a one-pole smoothing coefficient for the given time constant
+fn smoothing(seconds: f32, sample_rate: u32) -> f32 {
+    if seconds > 0. {
+        (-1. / (seconds * sample_rate as f32)).exp()
+    } else {
+        0.
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Applies a lookahead limiter keeping true peaks below the ceiling in dBFS
+    pub fn limit(&mut self, ceiling_db: f32) {
+        let channels = self.channels as usize;
+        let frames = self.buffer.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        let ceiling = db_to_amplitude(ceiling_db);
+        let lookahead = ((LIMITER_LOOKAHEAD * self.sample_rate as f32) as usize).max(1);
+
+        // Gain each frame requires to stay below the ceiling, linked across channels
+        let peaks = true_peaks(&self.buffer, channels);
+        let required: Vec<f32> = peaks
+            .iter()
+            .map(|&p| if p > ceiling { ceiling / p } else { 1. })
+            .collect();
+
+        // Minimum over a window reaching lookahead frames around each frame, averaging over half
+        // of it afterwards can't exceed the required gain at the peak
+        let minimum = sliding_min(&required, lookahead);
+        let half = lookahead / 2;
+        let mut sum: f64 = minimum[..half.min(frames)].iter().map(|&g| g as f64).sum();
+        let mut gain = Vec::with_capacity(frames);
+        for i in 0..frames {
+            if i + half < frames {
+                sum += minimum[i + half] as f64;
+            }
+            if i > half {
+                sum -= minimum[i - half - 1] as f64;
+            }
+            let len = (i + half).min(frames - 1) - i.saturating_sub(half) + 1;
+            gain.push((sum / len as f64) as f32);
+        }
+
+        let release = smoothing(LIMITER_RELEASE, self.sample_rate);
+        let mut current = 1f32;
+        for (frame, &g) in self.buffer.chunks_exact_mut(channels).zip(&gain) {
+            current = if g < current {
+                g
+            } else {
+                g + (current - g) * release
+            };
+            for sample in frame {
+                *sample *= current;
+            }
+        }
+    }
+
+    /// Applies a compressor with threshold in dBFS, with detection linked across channels
+    pub fn compress(&mut self, threshold_db: f32, ratio: f32, attack: Duration, release: Duration) {
+        let attack = smoothing(attack.as_secs_f32(), self.sample_rate);
+        let release = smoothing(release.as_secs_f32(), self.sample_rate);
+
+        let channels = self.channels as usize;
+        let mut envelope = 0f32;
+        for frame in self.buffer.chunks_exact_mut(channels) {
+            let level = frame.iter().fold(0f32, |a, s| a.max(s.abs()));
+            envelope = follow(envelope, level, attack, release);
+
+            let level_db = amplitude_to_db(envelope);
+            if level_db > threshold_db {
+                let gain = db_to_amplitude((threshold_db - level_db) * (1. - 1. / ratio));
+                for sample in frame {
+                    *sample *= gain;
+                }
+            }
+        }
+    }
+
+    /// Applies a noise gate silencing everything below threshold in dBFS
+    pub fn gate(&mut self, threshold_db: f32) {
+        let attack = smoothing(GATE_ATTACK, self.sample_rate);
+        let release = smoothing(GATE_RELEASE, self.sample_rate);
+        let threshold = db_to_amplitude(threshold_db);
+
+        let channels = self.channels as usize;
+        let mut envelope = 0f32;
+        let mut gain = 0f32;
+        for frame in self.buffer.chunks_exact_mut(channels) {
+            let level = frame.iter().fold(0f32, |a, s| a.max(s.abs()));
+            envelope = follow(envelope, level, attack, release);
+
+            let target = if envelope >= threshold { 1. } else { 0. };
+            gain = follow(gain, target, attack, release);
+
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// Moves towards the target using attack smoothing when rising and release when falling
+fn follow(current: f32, target: f32, attack: f32, release: f32) -> f32 {
+    let coeff = if target > current { attack } else { release };
+    target + (current - target) * coeff
+}
+
+/// Returns estimated true peak of each frame across all channels
+fn true_peaks(samples: &[f32], channels: usize) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    let kernel: Vec<Vec<f32>> = (1..OVERSAMPLING)
+        .map(|phase| {
+            let offset = phase as f32 / OVERSAMPLING as f32;
+            (0..2 * INTERPOLATION_TAPS)
+                .map(|tap| {
+                    let x = tap as f32 - INTERPOLATION_TAPS as f32 + 1. - offset;
+                    let sinc = (PI * x).sin() / (PI * x);
+                    let window = 0.5 + 0.5 * (PI * x / INTERPOLATION_TAPS as f32).cos();
+                    sinc * window
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut peaks = vec![0f32; frames];
+    for c in 0..channels {
+        let at = |i: isize| {
+            if i < 0 || i as usize >= frames {
+                0.
+            } else {
+                samples[i as usize * channels + c]
+            }
+        };
+
+        for (i, peak) in peaks.iter_mut().enumerate() {
+            *peak = peak.max(at(i as isize).abs());
+
+            // Points between this frame and the next one
+            for phase in &kernel {
+                let value: f32 = phase
+                    .iter()
+                    .enumerate()
+                    .map(|(tap, &k)| {
+                        at(i as isize + tap as isize - INTERPOLATION_TAPS as isize + 1) * k
+                    })
+                    .sum();
+                *peak = peak.max(value.abs());
+            }
+        }
+    }
+
+    peaks
+}
+
+/// Returns the minimum of each `radius` frames neighbourhood
+fn sliding_min(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut result = Vec::with_capacity(values.len());
+    let mut window: VecDeque<usize> = VecDeque::new();
+
+    for i in 0..values.len() + radius {
+        if i < values.len() {
+            while window.back().is_some_and(|&j| values[j] >= values[i]) {
+                window.pop_back();
+            }
+            window.push_back(i);
+        }
+
+        if i >= radius {
+            let center = i - radius;
+            while window.front().is_some_and(|&j| j + radius < center) {
+                window.pop_front();
+            }
+            result.push(values[window[0]]);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, Channels};
+
+    /// Frame the full scale part of `burst` starts at
+    const BURST: usize = 24_000;
+
+    /// Quiet tone with a full scale burst starting at `BURST`
+    fn burst() -> SampleBuffer<f32> {
+        let mut buffer = generate::sine(100., Duration::from_secs(1), 48_000, Channels::Stereo);
+        for (i, frame) in buffer.buffer.chunks_exact_mut(2).enumerate() {
+            let gain = if (BURST..BURST + 4800).contains(&i) {
+                1.
+            } else {
+                0.1
+            };
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+        buffer
+    }
+
+    #[test]
+    fn true_peaks_stay_below_the_ceiling() {
+        for ceiling_db in [-1., -6.] {
+            for mut buffer in [
+                burst(),
+                generate::white_noise(Duration::from_millis(500), 44_100, Channels::Stereo),
+                generate::sweep(
+                    20.,
+                    20_000.,
+                    Duration::from_millis(500),
+                    44_100,
+                    Channels::Mono,
+                ),
+            ] {
+                buffer.limit(ceiling_db);
+                let channels = buffer.channels as usize;
+                let peak = true_peaks(&buffer.buffer, channels)
+                    .into_iter()
+                    .fold(0f32, f32::max);
+                // Interpolating the output again overshoots a little between frames
+                assert!(
+                    amplitude_to_db(peak) <= ceiling_db + 0.1,
+                    "{ceiling_db} dB: {} dB",
+                    amplitude_to_db(peak)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gain_reduction_leads_by_the_lookahead() {
+        let input = burst();
+        let mut output = input.clone();
+        output.limit(-6.);
+        let lookahead = (LIMITER_LOOKAHEAD * 48_000.) as usize;
+
+        // Nothing is delayed, every frame is its input scaled down
+        let gains: Vec<f32> = output
+            .samples()
+            .iter()
+            .zip(input.samples())
+            .step_by(2)
+            .map(|(&out, &input)| if input == 0. { 1. } else { out / input })
+            .collect();
+        assert!(gains.iter().all(|&g| (0. ..=1.).contains(&g)));
+
+        // Reduction starts within the lookahead and the averaging after it, and is fully applied
+        // by the time the burst starts
+        let first = gains.iter().position(|&g| g < 1.).unwrap();
+        assert!(
+            (BURST - 2 * lookahead..BURST - lookahead).contains(&first),
+            "{first}"
+        );
+        let required = db_to_amplitude(-6.);
+        assert!(gains[BURST..BURST + 4800].iter().all(|&g| g <= required));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{AudyoSample, BufferError, SampleBuffer};
+use std::{ops::Range, time::Duration};
+
+impl<S: AudyoSample> SampleBuffer<S> {
+    /// Returns the buffer repeated `n` times back to back
+    pub fn repeat(&self, n: usize) -> SampleBuffer<S> {
+        SampleBuffer::from_samples(self.buffer.repeat(n), self.channels, self.sample_rate)
+    }
+
+    /// Returns an iterator over consecutive sub-buffers of `frames_per_chunk` written frames each
+    /// The final chunk is zero-padded to full length if `pad` is set, otherwise it may be shorter
+    /// Fails with `BufferError::EmptyChunk` if `frames_per_chunk` is 0
+    pub fn chunks_frames(
+        &self,
+        frames_per_chunk: usize,
+        pad: bool,
+    ) -> Result<impl Iterator<Item = SampleBuffer<S>> + '_, BufferError> {
+        if frames_per_chunk == 0 {
+            return Err(BufferError::EmptyChunk);
+        }
+        let len = frames_per_chunk.saturating_mul(self.channels as usize);
+
+        Ok(self.buffer[..self.written].chunks(len).map(move |chunk| {
+            let mut samples = chunk.to_vec();
+            if pad {
+                samples.resize(len, S::MID);
+            }
+            SampleBuffer::from_samples(samples, self.channels, self.sample_rate)
+        }))
+    }
+
+    /// Reverses the order of the written frames, keeping the order of channels within them
+    pub fn reverse(&mut self) {
+        let channels = self.channels as usize;
+        let written = &mut self.buffer[..self.written];
+        written.reverse();
+        for frame in written.chunks_exact_mut(channels) {
+            frame.reverse();
+        }
+    }
+
+    /// Inserts the written frames of another buffer before frame `at`
+    pub fn insert(&mut self, at: usize, other: &SampleBuffer<S>) -> Result<(), BufferError> {
+        if other.channels != self.channels {
+            return Err(BufferError::ChannelsMismatch(self.channels, other.channels));
+        }
+        if other.sample_rate != self.sample_rate {
+            return Err(BufferError::SampleRateMismatch(
+                self.sample_rate,
+                other.sample_rate,
+            ));
+        }
+        if at > self.written_frames() {
+            return Err(BufferError::FrameOutOfRange(at));
+        }
+
+        let channels = self.channels as usize;
+        let (at, inserted) = (at * channels, &other.buffer[..other.written]);
+        let len = self.buffer.len();
+        self.buffer.resize(len + inserted.len(), S::MID)?;
+        self.buffer.copy_within(at..len, at + inserted.len());
+        self.buffer[at..at + inserted.len()].copy_from_slice(inserted);
+        self.written += inserted.len();
+        self.duration += inserted.len() / channels;
+        Ok(())
+    }
+
+    /// Removes a range of written frames, returning them as a buffer of their own, e.g. to
+    /// insert them again elsewhere
+    pub fn remove(&mut self, range: Range<usize>) -> Result<SampleBuffer<S>, BufferError> {
+        let frames = self.written_frames();
+        if range.end > frames {
+            return Err(BufferError::FrameOutOfRange(range.end));
+        }
+        if range.start > range.end {
+            return Err(BufferError::FrameOutOfRange(range.start));
+        }
+
+        let channels = self.channels as usize;
+        let (start, end) = (range.start * channels, range.end * channels);
+        let removed = self.buffer[start..end].to_vec();
+        let len = self.buffer.len();
+        self.buffer.copy_within(end..len, start);
+        self.buffer.truncate(len - removed.len());
+        self.written -= removed.len();
+        self.duration -= range.len();
+        Ok(SampleBuffer::from_samples(
+            removed,
+            self.channels,
+            self.sample_rate,
+        ))
+    }
+
+    /// Inserts silence lasting `duration` before the audio
+    /// Only fails for a memory-mapped buffer whose file can't grow
+    pub fn pad_start(&mut self, duration: Duration) -> Result<(), BufferError> {
+        let padding = self.frames_in(duration) * self.channels as usize;
+        let len = self.buffer.len();
+        self.buffer.resize(len + padding, S::MID)?;
+        self.buffer.copy_within(..len, padding);
+        self.buffer[..padding].fill(S::MID);
+        self.written += padding;
+        self.duration += padding / self.channels as usize;
+        Ok(())
+    }
+
+    /// Appends silence lasting `duration` after the written frames, dropping any unfilled ones
+    /// Only fails for a memory-mapped buffer whose file can't grow
+    pub fn pad_end(&mut self, duration: Duration) -> Result<(), BufferError> {
+        let padding = self.frames_in(duration) * self.channels as usize;
+        self.buffer.truncate(self.written);
+        self.buffer.resize(self.written + padding, S::MID)?;
+        self.written += padding;
+        self.duration = self.written_frames();
+        Ok(())
+    }
+
+    pub(crate) fn frames_in(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.sample_rate as f64).round() as _
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Returns the buffer looped until it lasts `target`
+    /// Seams get a linear crossfade of the given length, if any
+    pub fn loop_to_duration(&self, target: Duration, crossfade: Option<Duration>) -> Self {
+        let channels = self.channels as usize;
+        let frames = self.buffer.len() / channels;
+        let target = self.frames_in(target);
+        if frames == 0 {
+            return SampleBuffer::new(target, self.channels, self.sample_rate);
+        }
+
+        let crossfade = crossfade.map_or(0, |c| self.frames_in(c)).min(frames / 2);
+
+        let mut output = Vec::with_capacity(target * channels + self.buffer.len());
+        output.extend_from_slice(&self.buffer);
+
+        while output.len() < target * channels {
+            // Blend the head of the next repetition into the tail of the previous one
+            let start = output.len() - crossfade * channels;
+            for i in 0..crossfade {
+                let fade_in = (i as f32 + 0.5) / crossfade as f32;
+                let fade_out = 1. - fade_in;
+
+                for c in 0..channels {
+                    let out = &mut output[start + i * channels + c];
+                    *out = *out * fade_out + self.buffer[i * channels + c] * fade_in;
+                }
+            }
+
+            output.extend_from_slice(&self.buffer[crossfade * channels..]);
+        }
+
+        output.truncate(target * channels);
+        SampleBuffer::from_samples(output, self.channels, self.sample_rate)
+    }
+}
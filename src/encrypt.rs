@@ -0,0 +1,368 @@
+//! Encryption of encoded output as it's produced, for products that must never write plaintext
+//! audio to disk
+//!
+//! Output gets split into chunks sealed one at a time by an AEAD cipher the caller provides,
+//! e.g. `aes_gcm::Aes256Gcm` with their own key, a `Cipher` with the `aes-gcm` feature. Each
+//! chunk's nonce counts it and marks the last one, the STREAM construction, so that chunks can't
+//! be reordered, dropped or cut off without opening failing
+
+use crate::EncodeSink;
+use std::io::{self, Read};
+
+const MAGIC: &[u8; 8] = b"AUDYOENC";
+const VERSION: u8 = 1;
+/// Magic, version, chunk size and nonce prefix
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + NONCE_PREFIX_LEN;
+/// Random part of the nonces, the rest being the chunk counter and the last chunk flag
+const NONCE_PREFIX_LEN: usize = 7;
+/// Bit of a chunk's length marking it as the last one
+const LAST: u32 = 1 << 31;
+/// Size of the chunks unless set otherwise
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// Room left for the tag when reading chunks
+const MAX_TAG_LEN: usize = 64;
+
+/// Authenticated cipher with a 12 byte nonce, e.g. AES-256-GCM
+/// Implementations with the aead crates map to `AeadInPlace::encrypt_in_place` and
+/// `decrypt_in_place`, which take a `Vec<u8>` as the buffer
+pub trait Cipher {
+    /// Encrypts data in place, appending the authentication tag
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Checks and removes the authentication tag, decrypting data in place
+    /// Fails if the data, nonce or associated data don't match what was sealed
+    fn open(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()>;
+}
+
+#[cfg(feature = "aes-gcm")]
+impl Cipher for aes_gcm::Aes256Gcm {
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()> {
+        use aes_gcm::AeadInPlace;
+        self.encrypt_in_place(nonce.into(), aad, data)
+            .map_err(|_| io::Error::other("chunk too large to encrypt"))
+    }
+
+    fn open(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()> {
+        use aes_gcm::AeadInPlace;
+        self.decrypt_in_place(nonce.into(), aad, data)
+            .map_err(|_| invalid("encrypted chunk failed to authenticate"))
+    }
+}
+
+/// Returns the nonce of a chunk
+fn nonce(prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Sink encrypting what gets written into it before passing it on, e.g. wrapping the file a
+/// `StreamingEncoder` writes to
+/// Output is buffered a chunk at a time, so no plaintext reaches the inner sink, and `finish`
+/// must be called to seal the last chunk. `DecryptingReader` reads the output back
+pub struct EncryptingSink<K: EncodeSink, C: Cipher> {
+    sink: K,
+    cipher: C,
+    chunk_size: usize,
+    /// Written once the first chunk is sealed, and authenticated along with every chunk
+    header: Option<[u8; HEADER_LEN]>,
+    buffer: Vec<u8>,
+    counter: u32,
+    finished: bool,
+}
+
+impl<K: EncodeSink, C: Cipher> EncryptingSink<K, C> {
+    /// Creates a sink sealing 64 KiB chunks with the cipher
+    pub fn new(sink: K, cipher: C) -> Self {
+        Self {
+            sink,
+            cipher,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            header: None,
+            buffer: Vec::new(),
+            counter: 0,
+            finished: false,
+        }
+    }
+
+    /// Sets the size of the chunks, at least 1 byte and at most 1 GiB
+    /// Smaller chunks reach the inner sink sooner, at the cost of a tag and length per chunk
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.clamp(1, 1 << 30);
+        self
+    }
+
+    /// Returns the inner sink
+    pub fn into_inner(self) -> K {
+        self.sink
+    }
+
+    /// Writes the header if it hasn't been yet, returning it
+    fn header(&mut self) -> io::Result<[u8; HEADER_LEN]> {
+        if let Some(header) = self.header {
+            return Ok(header);
+        }
+        let mut header = [0; HEADER_LEN];
+        header[..MAGIC.len()].copy_from_slice(MAGIC);
+        header[MAGIC.len()] = VERSION;
+        header[MAGIC.len() + 1..][..4].copy_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        header[HEADER_LEN - NONCE_PREFIX_LEN..].copy_from_slice(&rand::random::<[u8; 7]>());
+        self.sink.write_block(&header)?;
+        self.header = Some(header);
+        Ok(header)
+    }
+
+    /// Seals and passes on the first `len` buffered bytes
+    fn seal(&mut self, len: usize, last: bool) -> io::Result<()> {
+        let header = self.header()?;
+        if self.counter == u32::MAX {
+            return Err(io::Error::other("too many chunks to encrypt"));
+        }
+        let nonce = nonce(&header[HEADER_LEN - NONCE_PREFIX_LEN..], self.counter, last);
+        self.counter += 1;
+
+        let rest = self.buffer.split_off(len);
+        let mut chunk = std::mem::replace(&mut self.buffer, rest);
+        self.cipher.seal(&nonce, &header, &mut chunk)?;
+
+        let mut length = chunk.len() as u32;
+        if last {
+            length |= LAST;
+        }
+        self.sink.write_block(&length.to_le_bytes())?;
+        self.sink.write_block(&chunk)
+    }
+
+    fn check_unfinished(&self) -> io::Result<()> {
+        if self.finished {
+            return Err(io::Error::other("encrypted stream already finished"));
+        }
+        Ok(())
+    }
+}
+
+impl<K: EncodeSink, C: Cipher> EncodeSink for EncryptingSink<K, C> {
+    /// Fails once the sink has been finished
+    fn write_block(&mut self, block: &[u8]) -> io::Result<()> {
+        self.check_unfinished()?;
+        self.buffer.extend_from_slice(block);
+        // A full chunk is only sealed once more follows, since the last one is sealed differently
+        while self.buffer.len() > self.chunk_size {
+            self.seal(self.chunk_size, false)?;
+        }
+        Ok(())
+    }
+
+    /// Passes on the chunks sealed so far, a partial chunk stays buffered until it's full
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    /// Fails if the sink has already been finished, since the last chunk was sealed then
+    fn finish(&mut self) -> io::Result<()> {
+        self.check_unfinished()?;
+        self.finished = true;
+        self.seal(self.buffer.len(), true)?;
+        self.sink.finish()
+    }
+}
+
+/// Reader decrypting the output of an `EncryptingSink`, yielding the plaintext as chunks pass
+/// authentication, e.g. to decode encrypted audio in memory through `ReadOnlySource`
+/// Fails with `io::ErrorKind::InvalidData` on tampered data, the wrong key or data following the
+/// last chunk, and with `io::ErrorKind::UnexpectedEof` if the stream got cut off
+pub struct DecryptingReader<R: Read, C: Cipher> {
+    reader: R,
+    cipher: C,
+    header: Option<[u8; HEADER_LEN]>,
+    chunk: Vec<u8>,
+    /// Position of the unread plaintext in the chunk
+    pos: usize,
+    counter: u32,
+    finished: bool,
+}
+
+impl<R: Read, C: Cipher> DecryptingReader<R, C> {
+    /// Creates a reader opening chunks read from reader with the cipher
+    pub fn new(reader: R, cipher: C) -> Self {
+        Self {
+            reader,
+            cipher,
+            header: None,
+            chunk: Vec::new(),
+            pos: 0,
+            counter: 0,
+            finished: false,
+        }
+    }
+
+    /// Returns the inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads and checks the header if it hasn't been yet, returning it with the chunk size
+    fn header(&mut self) -> io::Result<([u8; HEADER_LEN], usize)> {
+        let header = match self.header {
+            Some(header) => header,
+            None => {
+                let mut header = [0; HEADER_LEN];
+                self.reader.read_exact(&mut header)?;
+                if &header[..MAGIC.len()] != MAGIC || header[MAGIC.len()] != VERSION {
+                    return Err(invalid("not an encrypted audyo stream"));
+                }
+                *self.header.insert(header)
+            }
+        };
+        let chunk_size = u32::from_le_bytes(header[MAGIC.len() + 1..][..4].try_into().unwrap());
+        Ok((header, chunk_size as usize))
+    }
+
+    /// Reads and opens the next chunk
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let (header, chunk_size) = self.header()?;
+
+        let mut length = [0; 4];
+        self.reader.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length);
+        let last = length & LAST != 0;
+        let length = (length & !LAST) as usize;
+        if length > chunk_size + MAX_TAG_LEN {
+            return Err(invalid("encrypted chunk longer than the chunk size"));
+        }
+
+        // The length isn't authenticated yet, so the chunk only grows as data arrives
+        self.chunk.clear();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut self.chunk)?;
+        if self.chunk.len() < length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let nonce = nonce(&header[HEADER_LEN - NONCE_PREFIX_LEN..], self.counter, last);
+        self.cipher
+            .open(&nonce, &header, &mut self.chunk)
+            .map_err(|_| invalid("encrypted chunk failed authentication"))?;
+
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| invalid("too many encrypted chunks"))?;
+        if last && !self.at_end()? {
+            return Err(invalid("data after the last encrypted chunk"));
+        }
+        self.pos = 0;
+        self.finished = last;
+        Ok(())
+    }
+
+    /// Returns whether the reader has nothing left
+    fn at_end(&mut self) -> io::Result<bool> {
+        loop {
+            match self.reader.read(&mut [0]) {
+                Ok(read) => return Ok(read == 0),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read, C: Cipher> Read for DecryptingReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keyless stand-in for an AEAD, appending a checksum of the nonce, associated data and data
+    struct Checksum;
+
+    impl Checksum {
+        fn tag(nonce: &[u8], aad: &[u8], data: &[u8]) -> [u8; 8] {
+            let bytes = nonce.iter().chain(aad).chain(data);
+            let hash = bytes.fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+            });
+            hash.to_le_bytes()
+        }
+    }
+
+    impl Cipher for Checksum {
+        fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()> {
+            let tag = Self::tag(nonce, aad, data);
+            data.extend_from_slice(&tag);
+            Ok(())
+        }
+
+        fn open(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut Vec<u8>) -> io::Result<()> {
+            let len = data.len().checked_sub(8).ok_or_else(|| invalid("no tag"))?;
+            if data[len..] != Self::tag(nonce, aad, &data[..len]) {
+                return Err(invalid("wrong tag"));
+            }
+            data.truncate(len);
+            Ok(())
+        }
+    }
+
+    fn encrypt(data: &[u8]) -> Vec<u8> {
+        let mut sink = EncryptingSink::new(Vec::new(), Checksum).chunk_size(16);
+        sink.write_block(data).unwrap();
+        sink.finish().unwrap();
+        sink.into_inner()
+    }
+
+    fn decrypt(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plain = Vec::new();
+        DecryptingReader::new(data, Checksum).read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    #[test]
+    fn finishing_twice_fails() {
+        let mut sink = EncryptingSink::new(Vec::new(), Checksum);
+        sink.write_block(b"audio").unwrap();
+        sink.finish().unwrap();
+        assert!(sink.finish().is_err());
+        assert!(sink.write_block(b"more").is_err());
+        assert_eq!(decrypt(&sink.into_inner()).unwrap(), b"audio");
+    }
+
+    #[test]
+    fn trailing_data_fails() {
+        let mut encrypted = encrypt(&[7; 40]);
+        assert_eq!(decrypt(&encrypted).unwrap(), [7; 40]);
+        encrypted.push(0);
+        let error = decrypt(&encrypted).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn forged_lengths_hit_the_end_of_the_stream() {
+        // Claims 1 GiB chunks and a last chunk of nearly that, holding a few bytes
+        let mut encrypted = encrypt(b"audio");
+        encrypted[MAGIC.len() + 1..][..4].copy_from_slice(&(1u32 << 30).to_le_bytes());
+        encrypted[HEADER_LEN..][..4].copy_from_slice(&(LAST | 1 << 30).to_le_bytes());
+        let error = decrypt(&encrypted).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
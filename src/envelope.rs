@@ -0,0 +1,227 @@
+use crate::SampleBuffer;
+use std::{f32::consts::PI, time::Duration};
+
+/// Lowest gain a decibel curve interpolates from or to, -100 dB, standing in for silence
+const DECIBEL_FLOOR: f32 = 1e-5;
+/// Spacing of the points a ducking envelope gets, in seconds
+const DUCKING_RESOLUTION: f64 = 0.01;
+
+/// Shape of the transition from one automation point to the next
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Curve {
+    /// Keeps the previous gain until the point, then jumps
+    Hold,
+    /// Straight line between the gains
+    #[default]
+    Linear,
+    /// Straight line between the gains in dB, sounding even for long fades
+    Decibel,
+    /// Cosine shaped, easing in and out of both points
+    Smooth,
+}
+
+/// Point of an `Envelope`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    time: Duration,
+    gain: f32,
+    /// Shape of the transition from the previous point
+    curve: Curve,
+}
+
+/// Gain automation over time given by points, e.g. for fades with custom shapes or ducking
+/// music under speech
+/// Gain holds at the first point's value before it and at the last one's after it, an empty
+/// envelope leaves audio unchanged
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Envelope {
+    points: Vec<Point>,
+}
+
+impl Envelope {
+    /// Creates an empty envelope
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a point reaching `gain`, a linear factor, at `time`, transitioning from the previous
+    /// point along `curve`
+    /// A point at the same time as an existing one follows it, making a jump
+    pub fn point(mut self, time: Duration, gain: f32, curve: Curve) -> Self {
+        let i = self.points.partition_point(|p| p.time <= time);
+        self.points.insert(i, Point { time, gain, curve });
+        self
+    }
+
+    /// Adds a point like `point`, with gain in dB
+    pub fn point_db(self, time: Duration, gain_db: f32, curve: Curve) -> Self {
+        self.point(time, 10f32.powf(gain_db / 20.), curve)
+    }
+
+    /// Returns an envelope ducking by `depth_db` while the sidechain, e.g. a voice-over, is
+    /// above `threshold_db` in dBFS, moving into and out of the duck over `attack` and
+    /// `release`
+    pub fn ducking(
+        sidechain: &SampleBuffer<f32>,
+        threshold_db: f32,
+        depth_db: f32,
+        attack: Duration,
+        release: Duration,
+    ) -> Self {
+        let channels = sidechain.channels as usize;
+        let step = ((sidechain.sample_rate as f64 * DUCKING_RESOLUTION) as usize).max(1);
+        let threshold = 10f32.powf(threshold_db / 20.);
+        let ducked = 10f32.powf(-depth_db.abs() / 20.);
+        let smoothing = |time: Duration| {
+            let steps = time.as_secs_f64() / DUCKING_RESOLUTION;
+            if steps > 0. {
+                (-1. / steps).exp() as f32
+            } else {
+                0.
+            }
+        };
+        let (attack, release) = (smoothing(attack), smoothing(release));
+
+        let mut envelope = Self::new();
+        let mut gain = 1f32;
+        for (i, block) in sidechain.samples().chunks(step * channels).enumerate() {
+            let level = block.iter().fold(0f32, |a, s| a.max(s.abs()));
+            let target = if level >= threshold { ducked } else { 1. };
+            let coeff = if target < gain { attack } else { release };
+            gain = target + (gain - target) * coeff;
+
+            let time = Duration::from_secs_f64((i * step) as f64 / sidechain.sample_rate as f64);
+            envelope.points.push(Point {
+                time,
+                gain,
+                curve: Curve::Linear,
+            });
+        }
+        envelope
+    }
+
+    /// Returns the gain at `time`
+    pub fn gain_at(&self, time: Duration) -> f32 {
+        let i = self.points.partition_point(|p| p.time <= time);
+        match (
+            i.checked_sub(1).map(|i| &self.points[i]),
+            self.points.get(i),
+        ) {
+            (Some(from), Some(to)) => {
+                let span = (to.time - from.time).as_secs_f32();
+                to.curve
+                    .interpolate(from.gain, to.gain, (time - from.time).as_secs_f32() / span)
+            }
+            (Some(point), None) | (None, Some(point)) => point.gain,
+            (None, None) => 1.,
+        }
+    }
+}
+
+impl Curve {
+    /// Returns the gain at `t` of the way from `from` to `to`
+    fn interpolate(self, from: f32, to: f32, t: f32) -> f32 {
+        match self {
+            Curve::Hold => from,
+            Curve::Linear => from + (to - from) * t,
+            Curve::Decibel => {
+                let (from, to) = (from.max(DECIBEL_FLOOR), to.max(DECIBEL_FLOOR));
+                from * (to / from).powf(t)
+            }
+            Curve::Smooth => from + (to - from) * (0.5 - 0.5 * (PI * t).cos()),
+        }
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Scales the samples by the envelope's gain at each frame
+    pub fn apply_envelope(&mut self, envelope: &Envelope) {
+        let channels = self.channels as usize;
+        let rate = self.sample_rate as f64;
+        for (i, frame) in self.buffer.chunks_exact_mut(channels).enumerate() {
+            let gain = envelope.gain_at(Duration::from_secs_f64(i as f64 / rate));
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, Channels};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn gain_at_breakpoints() {
+        for curve in [Curve::Hold, Curve::Linear, Curve::Decibel, Curve::Smooth] {
+            let envelope = Envelope::new()
+                .point(ms(100), 1., curve)
+                .point(ms(200), 0.25, curve)
+                .point(ms(300), 0.5, curve);
+            // Held outside the points, reached exactly at each of them
+            for (time, gain) in [(0, 1.), (100, 1.), (200, 0.25), (300, 0.5), (1000, 0.5)] {
+                let at = envelope.gain_at(ms(time));
+                assert!(close(at, gain), "{curve:?} at {time} ms: {at}");
+            }
+        }
+        assert_eq!(Envelope::new().gain_at(ms(100)), 1.);
+    }
+
+    #[test]
+    fn curves_between_breakpoints() {
+        let gain = |curve, time| {
+            Envelope::new()
+                .point(ms(0), 1., Curve::Linear)
+                .point(ms(100), 0.25, curve)
+                .gain_at(ms(time))
+        };
+        assert!(close(gain(Curve::Hold, 50), 1.));
+        assert!(close(gain(Curve::Linear, 50), 0.625));
+        assert!(close(gain(Curve::Linear, 25), 0.8125));
+        // Halfway in dB between 0 and -12 dB
+        assert!(close(gain(Curve::Decibel, 50), 0.5));
+        assert!(close(gain(Curve::Smooth, 50), 0.625));
+        // Easing out of the first point, slower than a line a quarter of the way
+        assert!(gain(Curve::Smooth, 25) > gain(Curve::Linear, 25));
+    }
+
+    #[test]
+    fn points_at_the_same_time_jump() {
+        let envelope =
+            Envelope::new()
+                .point(ms(100), 1., Curve::Linear)
+                .point(ms(100), 0., Curve::Linear);
+        assert_eq!(envelope.gain_at(ms(99)), 1.);
+        assert_eq!(envelope.gain_at(ms(100)), 0.);
+
+        let mut buffer = generate::sine(100., ms(200), 1000, Channels::Stereo);
+        let original = buffer.clone();
+        buffer.apply_envelope(&envelope);
+        assert_eq!(buffer.samples()[..200], original.samples()[..200]);
+        assert!(buffer.samples()[200..].iter().all(|&s| s == 0.));
+    }
+
+    #[test]
+    fn ducks_under_the_sidechain() {
+        let mut sidechain = generate::silence(ms(500), 8000, Channels::Mono);
+        sidechain
+            .extend_from_buffer(&generate::sine(200., ms(1000), 8000, Channels::Mono))
+            .unwrap();
+        let envelope = Envelope::ducking(&sidechain, -20., 12., ms(20), ms(200));
+
+        assert!(close(envelope.gain_at(ms(400)), 1.));
+        // Most of the way down after a few attack times
+        let ducked = 10f32.powf(-12. / 20.);
+        assert!((envelope.gain_at(ms(1400)) - ducked).abs() < 1e-3);
+        assert!(envelope.gain_at(ms(510)) > envelope.gain_at(ms(600)));
+    }
+}
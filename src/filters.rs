@@ -0,0 +1,322 @@
+use crate::SampleBuffer;
+use std::f64::consts::PI;
+
+/// Normalized biquad filter coefficients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoeffs {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl BiquadCoeffs {
+    /// Normalizes coefficients by a0
+    fn new(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Returns cosine of the angular frequency and alpha for the given parameters
+    fn params(sample_rate: u32, freq: f64, q: f64) -> (f64, f64) {
+        let w0 = 2. * PI * freq / sample_rate as f64;
+        (w0.cos(), w0.sin() / (2. * q))
+    }
+
+    /// Creates low-pass filter coefficients
+    pub fn low_pass(sample_rate: u32, freq: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        Self::new(
+            (1. - cos) / 2.,
+            1. - cos,
+            (1. - cos) / 2.,
+            1. + alpha,
+            -2. * cos,
+            1. - alpha,
+        )
+    }
+
+    /// Creates high-pass filter coefficients
+    pub fn high_pass(sample_rate: u32, freq: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        Self::new(
+            (1. + cos) / 2.,
+            -(1. + cos),
+            (1. + cos) / 2.,
+            1. + alpha,
+            -2. * cos,
+            1. - alpha,
+        )
+    }
+
+    /// Creates band-pass filter coefficients with 0 dB peak gain
+    pub fn band_pass(sample_rate: u32, freq: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        Self::new(alpha, 0., -alpha, 1. + alpha, -2. * cos, 1. - alpha)
+    }
+
+    /// Creates notch filter coefficients
+    pub fn notch(sample_rate: u32, freq: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        Self::new(1., -2. * cos, 1., 1. + alpha, -2. * cos, 1. - alpha)
+    }
+
+    /// Creates peaking EQ coefficients
+    pub fn peaking(sample_rate: u32, freq: f64, q: f64, gain_db: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        let a = 10f64.powf(gain_db / 40.);
+        Self::new(
+            1. + alpha * a,
+            -2. * cos,
+            1. - alpha * a,
+            1. + alpha / a,
+            -2. * cos,
+            1. - alpha / a,
+        )
+    }
+
+    /// Creates low shelf coefficients
+    pub fn low_shelf(sample_rate: u32, freq: f64, q: f64, gain_db: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        let a = 10f64.powf(gain_db / 40.);
+        let sqrt = 2. * a.sqrt() * alpha;
+        Self::new(
+            a * ((a + 1.) - (a - 1.) * cos + sqrt),
+            2. * a * ((a - 1.) - (a + 1.) * cos),
+            a * ((a + 1.) - (a - 1.) * cos - sqrt),
+            (a + 1.) + (a - 1.) * cos + sqrt,
+            -2. * ((a - 1.) + (a + 1.) * cos),
+            (a + 1.) + (a - 1.) * cos - sqrt,
+        )
+    }
+
+    /// Creates high shelf coefficients
+    pub fn high_shelf(sample_rate: u32, freq: f64, q: f64, gain_db: f64) -> Self {
+        let (cos, alpha) = Self::params(sample_rate, freq, q);
+        let a = 10f64.powf(gain_db / 40.);
+        let sqrt = 2. * a.sqrt() * alpha;
+        Self::new(
+            a * ((a + 1.) + (a - 1.) * cos + sqrt),
+            -2. * a * ((a - 1.) + (a + 1.) * cos),
+            a * ((a + 1.) + (a - 1.) * cos - sqrt),
+            (a + 1.) - (a - 1.) * cos + sqrt,
+            2. * ((a - 1.) - (a + 1.) * cos),
+            (a + 1.) - (a - 1.) * cos - sqrt,
+        )
+    }
+}
+
+/// Biquad filter state for a single channel
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad {
+    coeffs: BiquadCoeffs,
+    s1: f64,
+    s2: f64,
+}
+
+impl Biquad {
+    /// Creates a filter with cleared state
+    pub fn new(coeffs: BiquadCoeffs) -> Self {
+        Self {
+            coeffs,
+            s1: 0.,
+            s2: 0.,
+        }
+    }
+
+    /// Filters a single sample
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.process_f64(sample as f64) as f32
+    }
+
+    /// Filters a single double precision sample
+    pub fn process_f64(&mut self, x: f64) -> f64 {
+        let c = &self.coeffs;
+        let y = c.b0 * x + self.s1;
+        self.s1 = c.b1 * x - c.a1 * y + self.s2;
+        self.s2 = c.b2 * x - c.a2 * y;
+        y
+    }
+
+    /// Clears filter state
+    pub fn reset(&mut self) {
+        self.s1 = 0.;
+        self.s2 = 0.;
+    }
+}
+
+/// Shape of a parametric EQ band
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BandShape {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+/// Band of a parametric EQ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    pub shape: BandShape,
+    pub freq: f64,
+    pub q: f64,
+    pub gain_db: f64,
+}
+
+impl EqBand {
+    /// Returns band coefficients for the given sample rate
+    pub fn coeffs(&self, sample_rate: u32) -> BiquadCoeffs {
+        match self.shape {
+            BandShape::Peak => BiquadCoeffs::peaking(sample_rate, self.freq, self.q, self.gain_db),
+            BandShape::LowShelf => {
+                BiquadCoeffs::low_shelf(sample_rate, self.freq, self.q, self.gain_db)
+            }
+            BandShape::HighShelf => {
+                BiquadCoeffs::high_shelf(sample_rate, self.freq, self.q, self.gain_db)
+            }
+        }
+    }
+}
+
+macro_rules! impl_filters {
+    ($($t:ty => $process:ident),*) => {
+        $(
+            impl SampleBuffer<$t> {
+                /// Runs each channel through a biquad filter
+                pub fn filter(&mut self, coeffs: &BiquadCoeffs) {
+                    let channels = self.channels as usize;
+                    let mut filters = vec![Biquad::new(*coeffs); channels];
+
+                    for frame in self.buffer.chunks_exact_mut(channels) {
+                        for (sample, filter) in frame.iter_mut().zip(&mut filters) {
+                            *sample = filter.$process(*sample);
+                        }
+                    }
+                }
+
+                /// Applies a parametric EQ made of the given bands
+                pub fn equalize(&mut self, bands: &[EqBand]) {
+                    for band in bands {
+                        self.filter(&band.coeffs(self.sample_rate));
+                    }
+                }
+
+                /// Subtracts each channel's mean, removing any constant bias
+                pub fn remove_dc_offset(&mut self) {
+                    let channels = self.channels as usize;
+                    let frames = self.buffer.len() / channels;
+                    if frames == 0 {
+                        return;
+                    }
+
+                    let mut sums = vec![0f64; channels];
+                    for frame in self.buffer.chunks_exact(channels) {
+                        for (sum, &sample) in sums.iter_mut().zip(frame) {
+                            *sum += sample as f64;
+                        }
+                    }
+
+                    let offsets: Vec<$t> =
+                        sums.iter().map(|&s| (s / frames as f64) as $t).collect();
+                    for frame in self.buffer.chunks_exact_mut(channels) {
+                        for (sample, &offset) in frame.iter_mut().zip(&offsets) {
+                            *sample -= offset;
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_filters!(f32 => process, f64 => process_f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, TAU};
+
+    const SAMPLE_RATE: u32 = 48_000;
+    const CUTOFF: f64 = 1000.;
+
+    /// Returns the gain in dB the filter applies to a steady tone
+    fn gain_db(coeffs: BiquadCoeffs, freq: f64) -> f64 {
+        let mut filter = Biquad::new(coeffs);
+        let rms = |samples: &[f64]| {
+            (samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64).sqrt()
+        };
+        let input: Vec<f64> = (0..SAMPLE_RATE)
+            .map(|i| (TAU * freq * i as f64 / SAMPLE_RATE as f64).sin())
+            .collect();
+        let output: Vec<f64> = input.iter().map(|&x| filter.process_f64(x)).collect();
+        // Only the second half, once the filter has settled
+        let half = input.len() / 2;
+        20. * (rms(&output[half..]) / rms(&input[half..])).log10()
+    }
+
+    #[test]
+    fn magnitude_at_the_cutoff() {
+        let q = FRAC_1_SQRT_2;
+        for (name, coeffs, expected) in [
+            (
+                "low pass",
+                BiquadCoeffs::low_pass(SAMPLE_RATE, CUTOFF, q),
+                -3.01,
+            ),
+            (
+                "high pass",
+                BiquadCoeffs::high_pass(SAMPLE_RATE, CUTOFF, q),
+                -3.01,
+            ),
+            (
+                "band pass",
+                BiquadCoeffs::band_pass(SAMPLE_RATE, CUTOFF, 2.),
+                0.,
+            ),
+            (
+                "peaking",
+                BiquadCoeffs::peaking(SAMPLE_RATE, CUTOFF, 1., 6.),
+                6.,
+            ),
+            (
+                "peaking cut",
+                BiquadCoeffs::peaking(SAMPLE_RATE, CUTOFF, 1., -6.),
+                -6.,
+            ),
+            // Shelves are halfway there at their corner frequency
+            (
+                "low shelf",
+                BiquadCoeffs::low_shelf(SAMPLE_RATE, CUTOFF, q, 6.),
+                3.,
+            ),
+            (
+                "high shelf",
+                BiquadCoeffs::high_shelf(SAMPLE_RATE, CUTOFF, q, -6.),
+                -3.,
+            ),
+        ] {
+            let gain = gain_db(coeffs, CUTOFF);
+            assert!((gain - expected).abs() < 0.05, "{name}: {gain} dB");
+        }
+
+        let notch = gain_db(BiquadCoeffs::notch(SAMPLE_RATE, CUTOFF, 2.), CUTOFF);
+        assert!(notch < -40., "notch: {notch} dB");
+    }
+
+    #[test]
+    fn magnitude_away_from_the_cutoff() {
+        let q = FRAC_1_SQRT_2;
+        let low_pass = BiquadCoeffs::low_pass(SAMPLE_RATE, CUTOFF, q);
+        let high_pass = BiquadCoeffs::high_pass(SAMPLE_RATE, CUTOFF, q);
+        // 12 dB per octave past the cutoff, flat well below it
+        assert!(gain_db(low_pass, CUTOFF / 10.).abs() < 0.01);
+        assert!((gain_db(low_pass, CUTOFF * 4.) + 24.).abs() < 1.);
+        assert!(gain_db(high_pass, CUTOFF * 10.).abs() < 0.01);
+        assert!((gain_db(high_pass, CUTOFF / 4.) + 24.).abs() < 1.);
+    }
+}
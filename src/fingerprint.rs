@@ -0,0 +1,120 @@
+//! Perceptual fingerprints for duplicate detection, hashing how the chroma of the audio evolves
+//!
+//! This is a custom chroma hash: it borrows Chromaprint's idea but isn't compatible with
+//! Chromaprint or AcoustID, so fingerprints can only be compared with each other, not looked up
+
+use crate::{
+    analysis::{chromagram, ChromaStream},
+    DecodeError, DecodeOptions, Decoder, SampleBuffer,
+};
+use symphonia::core::io::MediaSource;
+
+/// Shortest overlap considered when aligning fingerprints, as a fraction of the shorter one
+const MIN_OVERLAP: f32 = 0.5;
+
+/// Sequence of 32 bit subfingerprints describing how the chroma (energy per pitch class) of the
+/// audio evolves, so that it survives lossy encoding, resampling and level changes
+/// Only comparable with other fingerprints from this crate
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint(Vec<u32>);
+
+impl Fingerprint {
+    /// Returns the subfingerprints
+    pub fn subfingerprints(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Returns the fraction of matching bits between two fingerprints at their best alignment,
+    /// close to 1 for the same recording and well below that for unrelated audio
+    pub fn similarity(&self, other: &Fingerprint) -> f32 {
+        let (a, b) = (&self.0, &other.0);
+        let shorter = a.len().min(b.len());
+        if shorter == 0 {
+            return 0.;
+        }
+        let min_overlap = ((shorter as f32 * MIN_OVERLAP).ceil() as usize).max(1);
+
+        // Offset of b relative to a
+        (-(b.len() as isize - min_overlap as isize)..=(a.len() - min_overlap) as isize)
+            .map(|offset| {
+                let (a, b) = if offset >= 0 {
+                    (&a[offset as usize..], &b[..])
+                } else {
+                    (&a[..], &b[(-offset) as usize..])
+                };
+                let overlap = a.len().min(b.len());
+                let differing: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+                1. - differing as f32 / (overlap * 32) as f32
+            })
+            .fold(0., f32::max)
+    }
+}
+
+/// Computes the fingerprint of a buffer
+pub fn fingerprint(buffer: &SampleBuffer<f32>) -> Fingerprint {
+    let chroma = chromagram(buffer);
+    Fingerprint(
+        chroma
+            .windows(2)
+            .map(|pair| subfingerprint(&pair[0], &pair[1]))
+            .collect(),
+    )
+}
+
+/// Computes the fingerprint of an audio file in source while decoding it, without holding more
+/// than a packet of its audio in memory, equal to `fingerprint` of the decoded buffer
+pub fn fingerprint_source(source: impl MediaSource + 'static) -> Result<Fingerprint, DecodeError> {
+    fingerprint_source_with(source, &Default::default())
+}
+
+/// Computes the fingerprint of an audio file in source while decoding it using given options,
+/// like `fingerprint_source`
+pub fn fingerprint_source_with(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<Fingerprint, DecodeError> {
+    let mut decoder = Decoder::<f32>::with_options(source, options)?;
+    let mut stream = ChromaStream::new(decoder.channels(), decoder.sample_rate());
+
+    let mut subfingerprints = Vec::new();
+    let mut previous = None;
+    let mut add = |chroma: Vec<[f32; 12]>| {
+        for current in chroma {
+            if let Some(previous) = &previous {
+                subfingerprints.push(subfingerprint(previous, &current));
+            }
+            previous = Some(current);
+        }
+    };
+    while let Some(chunk) = decoder.next_chunk()? {
+        add(stream.push(chunk.buffer().samples()));
+    }
+    add(stream.finish());
+
+    Ok(Fingerprint(subfingerprints))
+}
+
+/// Packs how chroma changed between two frames into 32 bits
+fn subfingerprint(previous: &[f32; 12], current: &[f32; 12]) -> u32 {
+    let mut bits = 0;
+
+    for c in 0..12 {
+        let next = (c + 1) % 12;
+        // Which pitch classes got stronger
+        if current[c] > previous[c] {
+            bits |= 1 << c;
+        }
+        // How the balance between neighbouring pitch classes moved
+        if current[c] - current[next] > previous[c] - previous[next] {
+            bits |= 1 << (12 + c);
+        }
+    }
+    // Balance between pitch classes a fifth apart
+    for c in 0..8 {
+        if current[c] > current[(c + 7) % 12] {
+            bits |= 1 << (24 + c);
+        }
+    }
+
+    bits
+}
@@ -0,0 +1,133 @@
+use crate::{AudyoSample, BufferError, Channels, SampleBuffer};
+use symphonia::core::sample::Sample;
+
+/// Buffer of frames with a channel count known at compile time, so per-frame loops can be
+/// unrolled in hot DSP code
+/// Mono and stereo buffers convert to and from `SampleBuffer`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedBuffer<S: Sample, const C: usize> {
+    frames: Vec<[S; C]>,
+    sample_rate: u32,
+}
+
+impl<S: Sample, const C: usize> FixedBuffer<S, C> {
+    /// Creates a buffer of the given number of frames filled with silence
+    pub fn new(frames: usize, sample_rate: u32) -> Self {
+        Self {
+            frames: vec![[S::MID; C]; frames],
+            sample_rate,
+        }
+    }
+
+    /// Creates a buffer from frames
+    pub fn from_frames(frames: Vec<[S; C]>, sample_rate: u32) -> Self {
+        Self {
+            frames,
+            sample_rate,
+        }
+    }
+
+    /// Creates a buffer from interleaved samples
+    pub fn from_interleaved(samples: &[S], sample_rate: u32) -> Result<Self, BufferError> {
+        if !samples.len().is_multiple_of(C) {
+            return Err(BufferError::PartialFrame(samples.len()));
+        }
+
+        Ok(Self::from_frames(
+            samples
+                .chunks_exact(C)
+                .map(|frame| std::array::from_fn(|c| frame[c]))
+                .collect(),
+            sample_rate,
+        ))
+    }
+
+    /// Creates a buffer from one plane of samples per channel, cut to the shortest one
+    pub fn from_planes(planes: [&[S]; C], sample_rate: u32) -> Self {
+        let len = planes.iter().map(|plane| plane.len()).min().unwrap_or(0);
+        Self::from_frames(
+            (0..len)
+                .map(|i| std::array::from_fn(|c| planes[c][i]))
+                .collect(),
+            sample_rate,
+        )
+    }
+
+    /// Returns the frames
+    pub fn frames(&self) -> &[[S; C]] {
+        &self.frames
+    }
+
+    /// Returns the frames for modification
+    pub fn frames_mut(&mut self) -> &mut [[S; C]] {
+        &mut self.frames
+    }
+
+    /// Returns number of frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns whether the buffer holds no frames
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns buffer's sample rate
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Appends a frame
+    pub fn push(&mut self, frame: [S; C]) {
+        self.frames.push(frame);
+    }
+
+    /// Returns the samples interleaved
+    pub fn interleaved(&self) -> Vec<S> {
+        self.frames.iter().flatten().copied().collect()
+    }
+
+    /// Returns one plane of samples per channel
+    pub fn planes(&self) -> [Vec<S>; C] {
+        std::array::from_fn(|c| self.frames.iter().map(|frame| frame[c]).collect())
+    }
+}
+
+macro_rules! impl_conversions {
+    ($($c:literal => $channels:ident),*) => {
+        $(
+            impl<
+                    S: AudyoSample,
+                > TryFrom<SampleBuffer<S>> for FixedBuffer<S, $c>
+            {
+                type Error = BufferError;
+
+                fn try_from(buffer: SampleBuffer<S>) -> Result<Self, BufferError> {
+                    if buffer.channels != Channels::$channels {
+                        return Err(BufferError::ChannelsMismatch(
+                            Channels::$channels,
+                            buffer.channels,
+                        ));
+                    }
+                    Self::from_interleaved(&buffer.buffer, buffer.sample_rate)
+                }
+            }
+
+            impl<
+                    S: AudyoSample,
+                > From<FixedBuffer<S, $c>> for SampleBuffer<S>
+            {
+                fn from(buffer: FixedBuffer<S, $c>) -> Self {
+                    SampleBuffer::from_samples(
+                        buffer.frames.into_iter().flatten().collect(),
+                        Channels::$channels,
+                        buffer.sample_rate,
+                    )
+                }
+            }
+        )*
+    };
+}
+
+impl_conversions!(1 => Mono, 2 => Stereo);
@@ -0,0 +1,107 @@
+use crate::{Channels, SampleBuffer};
+use rand::Rng;
+use std::{f64::consts::TAU, time::Duration};
+
+/// Lowest frequency `sweep` starts or ends at, since an exponential sweep can't reach 0 Hz
+const MIN_SWEEP_FREQ: f32 = 1.;
+
+fn frames(duration: Duration, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64).round() as _
+}
+
+/// Creates a buffer with every channel carrying the same signal
+fn from_fn(
+    duration: Duration,
+    sample_rate: u32,
+    channels: Channels,
+    mut f: impl FnMut(usize) -> f32,
+) -> SampleBuffer<f32> {
+    let samples = (0..frames(duration, sample_rate))
+        .flat_map(|i| {
+            let sample = f(i);
+            std::iter::repeat_n(sample, channels as usize)
+        })
+        .collect();
+
+    SampleBuffer::from_samples(samples, channels, sample_rate)
+}
+
+/// Generates a full scale sine wave
+pub fn sine(
+    freq: f32,
+    duration: Duration,
+    sample_rate: u32,
+    channels: Channels,
+) -> SampleBuffer<f32> {
+    from_fn(duration, sample_rate, channels, |i| {
+        (TAU * freq as f64 * i as f64 / sample_rate as f64).sin() as f32
+    })
+}
+
+/// Generates full scale uniform white noise, identical on every channel
+pub fn white_noise(duration: Duration, sample_rate: u32, channels: Channels) -> SampleBuffer<f32> {
+    let mut rng = rand::thread_rng();
+    from_fn(duration, sample_rate, channels, |_| {
+        rng.gen_range(-1.0..=1.0)
+    })
+}
+
+/// Generates digital silence
+pub fn silence(duration: Duration, sample_rate: u32, channels: Channels) -> SampleBuffer<f32> {
+    SampleBuffer::from_samples(
+        vec![0.; frames(duration, sample_rate) * channels as usize],
+        channels,
+        sample_rate,
+    )
+}
+
+/// Generates a full scale exponential sine sweep between two frequencies
+/// Frequencies below 1 Hz, including 0, negative ones and NaN, are raised to 1 Hz
+pub fn sweep(
+    start_freq: f32,
+    end_freq: f32,
+    duration: Duration,
+    sample_rate: u32,
+    channels: Channels,
+) -> SampleBuffer<f32> {
+    let [start, end] = [start_freq, end_freq].map(|f| match f.is_nan() {
+        true => MIN_SWEEP_FREQ as f64,
+        false => f.clamp(MIN_SWEEP_FREQ, f32::MAX) as f64,
+    });
+    let length = duration.as_secs_f64();
+    let rate = (end / start).ln();
+
+    from_fn(duration, sample_rate, channels, |i| {
+        let t = i as f64 / sample_rate as f64;
+        let phase = if rate.abs() < f64::EPSILON {
+            TAU * start * t
+        } else {
+            TAU * start * length / rate * ((t / length * rate).exp() - 1.)
+        };
+        phase.sin() as f32
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_from_any_frequency() {
+        let duration = Duration::from_millis(100);
+        for (start, end) in [
+            (0., 1000.),
+            (-20., 1000.),
+            (f32::NAN, 1000.),
+            (20., f32::INFINITY),
+        ] {
+            let sweep = sweep(start, end, duration, 8000, Channels::Mono);
+            assert_eq!(sweep.samples().len(), 800);
+            assert!(sweep.samples().iter().all(|s| s.abs() <= 1.));
+        }
+
+        let clamped = sweep(0., 1000., duration, 8000, Channels::Mono);
+        let from_min = sweep(MIN_SWEEP_FREQ, 1000., duration, 8000, Channels::Mono);
+        assert_eq!(clamped.samples(), from_min.samples());
+    }
+}
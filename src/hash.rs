@@ -0,0 +1,78 @@
+//! Hashing of decoded audio, to recognize the same PCM in any container, codec or sample type
+
+use crate::{AudyoSample, SampleBuffer};
+use symphonia::core::conv::IntoSample;
+
+/// FNV-1a, 64 bit variant, simple and stable across platforms and versions
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl<S: AudyoSample> SampleBuffer<S> {
+    /// Returns a hash of the audio, equal for buffers decoded from the same PCM whatever the
+    /// container, codec or sample type, e.g. to find duplicates across a library
+    /// Samples are quantized to 16 bits, so that sources differing only in bit depth, e.g. a CD
+    /// rip as 16-bit WAV and 24-bit FLAC, match, and digital silence at either end, which rippers
+    /// and encoders add or drop, is left out. Audio passed through a lossy codec won't match,
+    /// which acoustic fingerprints are for
+    pub fn content_hash(&self) -> u64 {
+        // Every sample type goes through i32 first, which holds 24-bit and float samples decoded
+        // from integers exactly, so that dropping the low bits rounds them all the same way
+        let quantized = |s: &S| (IntoSample::<i32>::into_sample(*s) >> 16) as i16;
+        let silent = |frame: &[S]| frame.iter().all(|s| quantized(s) == 0);
+        let channels = self.channels as usize;
+        let samples = self.samples();
+        let start = samples
+            .chunks_exact(channels)
+            .position(|f| !silent(f))
+            .unwrap_or(samples.len() / channels);
+        let end = samples
+            .chunks_exact(channels)
+            .rposition(|f| !silent(f))
+            .map_or(start, |end| end + 1);
+
+        let mut hash = fnv(FNV_OFFSET, &(channels as u32).to_le_bytes());
+        hash = fnv(hash, &self.sample_rate.to_le_bytes());
+        for sample in &samples[start * channels..end * channels] {
+            hash = fnv(hash, &quantized(sample).to_le_bytes());
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate, i24, Channels, SampleBuffer};
+    use std::time::Duration;
+
+    #[test]
+    fn hash_is_independent_of_sample_type() {
+        let tone = generate::sine(440., Duration::from_millis(50), 8000, Channels::Stereo);
+        for bits in [16, 24] {
+            // As decoded from integer PCM of that bit depth
+            let scale = (1 << (bits - 1)) as f32;
+            let samples = tone
+                .samples()
+                .iter()
+                .map(|s| (s * scale).floor().min(scale - 1.));
+            let pcm = SampleBuffer::from_samples(
+                samples.map(|s| s / scale).collect(),
+                Channels::Stereo,
+                8000,
+            );
+
+            let hash = pcm.content_hash();
+            assert_eq!(pcm.converted::<f64>().content_hash(), hash);
+            assert_eq!(pcm.converted::<i32>().content_hash(), hash);
+            assert_eq!(pcm.converted::<i24>().content_hash(), hash);
+            if bits == 16 {
+                assert_eq!(pcm.converted::<i16>().content_hash(), hash);
+            }
+        }
+    }
+}
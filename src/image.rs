@@ -0,0 +1,265 @@
+use crate::{stft::Stft, SampleBuffer};
+
+/// RGB image with 8 bits per channel, rows top to bottom
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl ImageBuffer {
+    /// Returns width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns pixels row by row
+    pub fn pixels(&self) -> &[[u8; 3]] {
+        &self.pixels
+    }
+
+    /// Returns the pixel at the given column and row
+    pub fn get(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+
+    /// Encodes the image as an uncompressed PNG file
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            // No filter
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bit RGB, default compression, filtering and no interlacing
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &ihdr);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Mapping of levels to colors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMap {
+    /// Black through white
+    Grayscale,
+    /// Black through purple, red and yellow to white
+    #[default]
+    Heat,
+}
+
+impl ColorMap {
+    /// Returns the color of a level between 0 and 1
+    fn color(self, level: f32) -> [u8; 3] {
+        const HEAT: [[f32; 3]; 5] = [
+            [0., 0., 0.],
+            [90., 0., 120.],
+            [220., 30., 40.],
+            [255., 200., 0.],
+            [255., 255., 255.],
+        ];
+
+        let level = level.clamp(0., 1.);
+        match self {
+            ColorMap::Grayscale => [(level * 255.).round() as u8; 3],
+            ColorMap::Heat => {
+                let position = level * (HEAT.len() - 1) as f32;
+                let i = (position as usize).min(HEAT.len() - 2);
+                let t = position - i as f32;
+                let (from, to) = (HEAT[i], HEAT[i + 1]);
+                [0, 1, 2].map(|c| (from[c] + (to[c] - from[c]) * t).round() as u8)
+            }
+        }
+    }
+}
+
+/// Options controlling `spectrogram_image`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpectrogramOptions {
+    fft_size: usize,
+    hop: usize,
+    width: Option<usize>,
+    height: Option<usize>,
+    min_db: f32,
+    max_db: f32,
+    color_map: ColorMap,
+}
+
+impl Default for SpectrogramOptions {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            hop: 512,
+            width: None,
+            height: None,
+            min_db: -100.,
+            max_db: 0.,
+            color_map: ColorMap::default(),
+        }
+    }
+}
+
+impl SpectrogramOptions {
+    /// Creates default options, a 2048 point FFT every 512 frames mapped from -100 to 0 dB
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the FFT size, which trades time resolution for frequency resolution
+    pub fn fft_size(mut self, fft_size: usize) -> Self {
+        self.fft_size = fft_size.max(2);
+        self
+    }
+
+    /// Sets the number of frames between consecutive spectra
+    pub fn hop(mut self, hop: usize) -> Self {
+        self.hop = hop.max(1);
+        self
+    }
+
+    /// Scales the image to the given size, by default it has a column per spectrum and a row
+    /// per frequency bin
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = Some(width.max(1));
+        self.height = Some(height.max(1));
+        self
+    }
+
+    /// Sets the levels mapped to the ends of the color map, relative to a full scale sine
+    pub fn range_db(mut self, min_db: f32, max_db: f32) -> Self {
+        self.min_db = min_db;
+        self.max_db = max_db;
+        self
+    }
+
+    /// Sets the color map
+    pub fn color_map(mut self, color_map: ColorMap) -> Self {
+        self.color_map = color_map;
+        self
+    }
+}
+
+/// Renders the spectrogram of a buffer, with time running left to right and frequency bottom
+/// to top
+/// Each pixel shows the loudest level among the spectra and bins it covers
+pub fn spectrogram_image(buffer: &SampleBuffer<f32>, options: &SpectrogramOptions) -> ImageBuffer {
+    let channels = buffer.channels as usize;
+    let mono: Vec<f32> = buffer.buffer[..buffer.written]
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    let stft = Stft::new(options.fft_size);
+    let bins = options.fft_size / 2 + 1;
+    // A full scale sine peaks at a quarter of the FFT size with a Hann window
+    let full_scale = options.fft_size as f32 / 4.;
+    let levels: Vec<Vec<f32>> = stft
+        .spectrogram(&mono, options.hop)
+        .iter()
+        .map(|spectrum| {
+            spectrum[..bins]
+                .iter()
+                .map(|c| 20. * (c.norm() / full_scale).max(f32::MIN_POSITIVE).log10())
+                .collect()
+        })
+        .collect();
+
+    let width = options.width.unwrap_or(levels.len());
+    let height = options.height.unwrap_or(bins);
+    let span = options.max_db - options.min_db;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        // Highest frequencies at the top
+        let row = height - 1 - y;
+        let bin_range = covered(row, height, bins);
+
+        for x in 0..width {
+            let level = levels[covered(x, width, levels.len())]
+                .iter()
+                .flat_map(|spectrum| &spectrum[bin_range.clone()])
+                .copied()
+                .fold(f32::NEG_INFINITY, f32::max);
+            pixels.push(
+                options
+                    .color_map
+                    .color((level - options.min_db) / span.max(f32::MIN_POSITIVE)),
+            );
+        }
+    }
+
+    ImageBuffer {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Returns the range of `len` items covered by pixel `i` out of `pixels`, never empty
+fn covered(i: usize, pixels: usize, len: usize) -> std::ops::Range<usize> {
+    let start = i * len / pixels;
+    let end = ((i + 1) * len / pixels).max(start + 1).min(len);
+    start.min(end - 1)..end
+}
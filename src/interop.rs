@@ -0,0 +1,106 @@
+use crate::{AudyoSample, BufferError, Channels, SampleBuffer};
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Layout, Signal, SignalSpec};
+
+/// Returns the layout of frames with `n` channels
+fn layout_of(n: usize) -> Result<Channels, BufferError> {
+    match n {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        n => Err(BufferError::UnsupportedChannelCount(n)),
+    }
+}
+
+impl<S: AudyoSample> SampleBuffer<S> {
+    /// Returns the written frames as arrays of `N` samples, e.g. to process them a frame at a
+    /// time without indexing channels
+    /// `N` must match the channel count
+    pub fn frames<const N: usize>(&self) -> Result<impl Iterator<Item = [S; N]> + '_, BufferError> {
+        let channels = layout_of(N)?;
+        if channels != self.channels {
+            return Err(BufferError::ChannelsMismatch(self.channels, channels));
+        }
+        Ok(self.buffer[..self.written]
+            .chunks_exact(N)
+            .map(|frame| frame.try_into().expect("chunks have N samples")))
+    }
+
+    /// Creates a buffer from frames of `N` samples, e.g. the output of `frames` after processing
+    pub fn from_frames<const N: usize>(
+        frames: impl IntoIterator<Item = [S; N]>,
+        sample_rate: u32,
+    ) -> Result<Self, BufferError> {
+        let channels = layout_of(N)?;
+        let samples = frames.into_iter().flatten().collect();
+        Ok(Self::from_samples(samples, channels, sample_rate))
+    }
+}
+
+#[cfg(feature = "dasp")]
+impl<S: AudyoSample + dasp::Sample> SampleBuffer<S> {
+    /// Returns the written frames as a dasp signal, e.g. to run them through dasp's
+    /// interpolators, envelopes or sample rate converters
+    /// `N` must match the channel count. Once the frames run out the signal is exhausted and
+    /// yields silence
+    pub fn signal<const N: usize>(
+        &self,
+    ) -> Result<impl dasp::Signal<Frame = [S; N]> + '_, BufferError>
+    where
+        [S; N]: dasp::Frame<Sample = S>,
+    {
+        Ok(dasp::signal::from_iter(self.frames::<N>()?))
+    }
+
+    /// Creates a buffer from the first `frames` frames of a dasp signal, fewer if it's exhausted
+    /// earlier
+    pub fn from_signal<const N: usize>(
+        signal: impl dasp::Signal<Frame = [S; N]>,
+        frames: usize,
+        sample_rate: u32,
+    ) -> Result<Self, BufferError>
+    where
+        [S; N]: dasp::Frame<Sample = S>,
+    {
+        Self::from_frames(signal.until_exhausted().take(frames), sample_rate)
+    }
+}
+
+impl<S: AudyoSample> TryFrom<AudioBufferRef<'_>> for SampleBuffer<S> {
+    type Error = BufferError;
+
+    /// Copies a buffer decoded by symphonia, converting its samples
+    /// Fails for layouts other than mono and stereo
+    fn try_from(buffer: AudioBufferRef<'_>) -> Result<Self, Self::Error> {
+        let spec = *buffer.spec();
+        let channels = layout_of(spec.channels.count())?;
+
+        let mut converted = SampleBuffer::new(buffer.frames(), channels, spec.rate);
+        converted.copy_samples(buffer)?;
+        Ok(converted)
+    }
+}
+
+impl<S: AudyoSample> From<&SampleBuffer<S>> for AudioBuffer<S> {
+    /// Copies the written frames into a planar symphonia buffer, e.g. for passing them to
+    /// symphonia based code with `as_audio_buffer_ref`
+    fn from(buffer: &SampleBuffer<S>) -> Self {
+        let layout = match buffer.channels {
+            Channels::Mono => Layout::Mono,
+            Channels::Stereo => Layout::Stereo,
+        };
+        let frames = buffer.written_frames();
+        let spec = SignalSpec::new_with_layout(buffer.sample_rate, layout);
+
+        let mut planar = AudioBuffer::new(frames as u64, spec);
+        planar.render_reserved(Some(frames));
+        let channels = buffer.channels as usize;
+        for (c, plane) in planar.planes_mut().planes().iter_mut().enumerate() {
+            for (out, frame) in plane
+                .iter_mut()
+                .zip(buffer.samples().chunks_exact(channels))
+            {
+                *out = frame[c];
+            }
+        }
+        planar
+    }
+}
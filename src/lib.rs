@@ -1,18 +1,137 @@
-use std::num::{NonZeroU32, NonZeroU8};
+use decoder::SEEK_PREROLL;
+use sample::sealed::Sealed;
+use std::fmt;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use storage::Storage;
 use symphonia::core::{
-    audio::{AudioBufferRef, Layout},
-    conv::FromSample,
+    audio::{AudioBuffer, AudioBufferRef, Layout, Signal},
+    codecs::{CodecParameters, CodecRegistry, DecoderOptions, CODEC_TYPE_OPUS},
     errors::Error as SymphoniaError,
-    io::{MediaSource, MediaSourceStream},
-    sample::Sample,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track},
+    io::{MediaSource, MediaSourceStream, ReadOnlySource},
+    probe::{Hint, Probe, ProbeResult},
 };
 use thiserror::Error;
-use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder, VorbisError};
+use vorbis_rs::VorbisError;
+use wav::WavChunks;
 
-pub use symphonia::core::sample::{i24, u24};
+pub use symphonia::core::sample::{i24, u24, SampleFormat};
+
+mod adpcm;
+#[cfg(feature = "dsp")]
+pub mod analysis;
+#[cfg(feature = "batch")]
+pub mod batch;
+mod channel;
+#[cfg(feature = "dsp")]
+pub mod compare;
+pub mod container;
+mod decoder;
+#[cfg(feature = "dsp")]
+mod denoise;
+mod diff;
+#[cfg(feature = "dsp")]
+mod dsp;
+mod dyn_buffer;
+mod dynamics;
+mod edit;
+mod encrypt;
+mod envelope;
+pub mod filters;
+#[cfg(feature = "fingerprint")]
+pub mod fingerprint;
+mod fixed;
+pub mod generate;
+mod hash;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "image")]
+mod image;
+mod interop;
+mod loudness;
+mod metadata;
+mod mix;
+#[cfg(feature = "opus")]
+mod opus;
+mod probe;
+pub mod qc;
+pub mod repair;
+#[cfg(feature = "resampler")]
+pub mod resample;
+mod ring;
+pub mod rtp;
+mod sample;
+pub mod scheduler;
+pub mod segment;
+mod shared;
+#[cfg(feature = "simd")]
+mod simd;
+mod sink;
+mod stereo;
+#[cfg(feature = "dsp")]
+pub mod stft;
+mod storage;
+#[cfg(feature = "stream")]
+pub mod stream;
+mod streaming;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod transcode;
+pub mod util;
+pub mod vad;
+mod visit;
+#[cfg(feature = "watch")]
+pub mod watch;
+mod wav;
+
+pub use adpcm::encode_ima_adpcm;
+#[cfg(feature = "aes-gcm")]
+pub use aes_gcm;
+pub use channel::{ChannelView, ChannelViewMut};
+#[cfg(feature = "dasp")]
+pub use dasp;
+pub use decoder::{DecodedChunk, Decoder};
+#[cfg(feature = "dsp")]
+pub use denoise::NoiseProfile;
+pub use diff::BufferDiff;
+pub use dyn_buffer::{decode_dyn, decode_dyn_with, DynSampleBuffer};
+pub use encrypt::{Cipher, DecryptingReader, EncryptingSink};
+pub use envelope::{Curve, Envelope};
+pub use fixed::FixedBuffer;
+#[cfg(feature = "http")]
+pub use http::{decode_url, HttpSource};
+pub use metadata::{Chapter, CueSheet, CueTrack, Lyrics, Metadata, Padding, Picture, Tag};
+pub use mix::mix;
+#[cfg(feature = "opus")]
+pub use opus::OpusDecoder;
+pub use probe::{
+    extract_artwork, extract_artwork_with, list_streams, list_streams_with, probe, probe_duration,
+    probe_duration_with, probe_with, try_probe, try_probe_with, AudioStream, FormatInfo,
+    ProbeReport,
+};
+pub use ring::{ring_buffer, RingConsumer, RingProducer};
+pub use sample::AudyoSample;
+pub use shared::SharedBuffer;
+pub use sink::{
+    encode_vorbis_into, validate_vorbis_bitrate, validate_vorbis_sample_rate, EncodeSink,
+    StreamingEncoder,
+};
+pub use streaming::StreamingDecoder;
+pub use transcode::{
+    concat_transcode, encode_vorbis_verified, split_on_silence, suggest_bitrate, transcode_multi,
+    transcode_vorbis, verify_encoded, ContentHint, EncodeReport, Preset, SplitOptions, SplitTrack,
+    TranscodeError, TranscodeOptions,
+};
+pub use visit::{decode_visit, AudioChunk};
+pub use wav::{
+    encode_wav, BroadcastInfo, LoopKind, SampleLoop, SamplerInfo, WavFormat, WavOptions,
+};
 
 /// Enum representing a channel layout
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Channels {
     Mono = 1,
     Stereo = 2,
@@ -20,61 +139,105 @@ pub enum Channels {
 
 /// Buffer containing samples
 #[derive(Clone, Debug)]
-pub struct SampleBuffer<
-    S: Sample
-        + FromSample<u8>
-        + FromSample<u16>
-        + FromSample<u24>
-        + FromSample<u32>
-        + FromSample<i8>
-        + FromSample<i16>
-        + FromSample<i24>
-        + FromSample<i32>
-        + FromSample<f32>
-        + FromSample<f64>,
-> {
-    buffer: Box<[S]>,
+pub struct SampleBuffer<S: AudyoSample> {
+    buffer: Storage<S>,
     written: usize,
     duration: usize,
     channels: Channels,
     sample_rate: u32,
 }
 
-impl<
-        S: Sample
-            + FromSample<u8>
-            + FromSample<u16>
-            + FromSample<u24>
-            + FromSample<u32>
-            + FromSample<i8>
-            + FromSample<i16>
-            + FromSample<i24>
-            + FromSample<i32>
-            + FromSample<f32>
-            + FromSample<f64>,
-    > SampleBuffer<S>
-{
+impl<S: AudyoSample> SampleBuffer<S> {
     /// Creates a buffer given parameters and fills it with silence
     pub fn new(duration: usize, channels: Channels, sample_rate: u32) -> Self {
         Self {
-            buffer: vec![S::MID; channels as usize * duration].into_boxed_slice(),
+            buffer: vec![S::MID; channels as usize * duration].into(),
+            written: 0,
+            duration,
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Creates a buffer like `new`, backed by a memory-mapped temporary file in dir instead of
+    /// heap memory, so the kernel can page samples out, e.g. while decoding multi-hour files
+    /// The file is deleted right away and its space freed with the buffer
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn memory_mapped(
+        duration: usize,
+        channels: Channels,
+        sample_rate: u32,
+        dir: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<Self> {
+        let mut buffer = Storage::Mapped(storage::mapped::Mapped::new(dir.as_ref())?);
+        buffer.resize(channels as usize * duration, S::MID)?;
+        Ok(Self {
+            buffer,
             written: 0,
             duration,
             channels,
             sample_rate,
+        })
+    }
+
+    /// Returns whether the samples live in a memory-mapped file rather than on the heap
+    pub fn is_memory_mapped(&self) -> bool {
+        self.buffer.is_mapped()
+    }
+
+    /// Creates a buffer from interleaved samples
+    pub fn from_samples(buffer: Vec<S>, channels: Channels, sample_rate: u32) -> Self {
+        Self {
+            duration: buffer.len() / channels as usize,
+            written: buffer.len(),
+            buffer: buffer.into(),
+            channels,
+            sample_rate,
         }
     }
 
+    /// Empties the buffer and sets it up for new contents, keeping the allocation
+    fn reset(
+        &mut self,
+        duration: usize,
+        channels: Channels,
+        sample_rate: u32,
+    ) -> std::io::Result<()> {
+        self.buffer.clear();
+        self.buffer.resize(channels as usize * duration, S::MID)?;
+        self.written = 0;
+        self.duration = duration;
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
     /// Returns a reference to contained samples
     pub fn samples(&self) -> &[S] {
         &self.buffer
     }
 
-    /// Returns buffer duration in samples
+    /// Returns buffer duration in frames, i.e. samples per channel
     pub fn duration(&self) -> usize {
         self.duration
     }
 
+    /// Returns number of frames actually filled by decoding
+    pub fn written_frames(&self) -> usize {
+        self.written / self.channels as usize
+    }
+
+    /// Returns whether every allocated sample has been filled
+    pub fn is_complete(&self) -> bool {
+        self.written_frames() >= self.duration
+    }
+
+    /// Drops the unfilled silence at the end of the buffer
+    pub fn truncate_to_written(&mut self) {
+        self.buffer.truncate(self.written);
+        self.duration = self.written_frames();
+    }
+
     /// Returns buffer's channel layout
     pub fn channels(&self) -> Channels {
         self.channels
@@ -85,43 +248,179 @@ impl<
         self.sample_rate
     }
 
-    fn copy_samples(&mut self, buffer: AudioBufferRef<'_>) {
+    /// Reorders channels, so that output channel `i` contains input channel `map[i]`
+    pub fn remap_channels(&mut self, map: &[usize]) -> Result<(), BufferError> {
+        let channels = self.channels as usize;
+        if map.len() != channels {
+            return Err(BufferError::ChannelMapLength(map.len()));
+        }
+        if let Some(&i) = map.iter().find(|&&i| i >= channels) {
+            return Err(BufferError::ChannelOutOfRange(i));
+        }
+
+        let mut frame = vec![S::MID; channels];
+        for chunk in self.buffer.chunks_exact_mut(channels) {
+            for (f, &i) in frame.iter_mut().zip(map) {
+                *f = chunk[i];
+            }
+            chunk.copy_from_slice(&frame);
+        }
+
+        Ok(())
+    }
+
+    /// Swaps two channels
+    pub fn swap_channels(&mut self, a: usize, b: usize) -> Result<(), BufferError> {
+        let channels = self.channels as usize;
+        if let Some(i) = [a, b].into_iter().find(|&i| i >= channels) {
+            return Err(BufferError::ChannelOutOfRange(i));
+        }
+
+        for chunk in self.buffer.chunks_exact_mut(channels) {
+            chunk.swap(a, b);
+        }
+
+        Ok(())
+    }
+
+    fn copy_samples(&mut self, buffer: AudioBufferRef<'_>) -> std::io::Result<()> {
+        if buffer.spec().channels.count() > self.channels as usize {
+            let mut mixed = AudioBuffer::<f64>::new(buffer.capacity() as u64, *buffer.spec());
+            buffer.convert(&mut mixed);
+            let samples: Vec<S> = downmix(&mixed).into_iter().map(S::from_sample).collect();
+            return self.write_samples(&samples);
+        }
+
         let mut buffer2 = buffer.make_equivalent();
         buffer.convert(&mut buffer2);
 
         let p = buffer2.planes();
         let planes = p.planes();
-        let interleaved = interleave(planes, self.channels);
+        let interleaved = util::interleave(&planes[..self.channels as usize]);
+        self.write_samples(&interleaved)
+    }
 
-        self.buffer[self.written..self.written + interleaved.len()].copy_from_slice(&interleaved);
-        self.written += interleaved.len();
+    /// Appends interleaved frames after the written ones, growing the buffer as needed
+    pub fn push_frames(&mut self, samples: &[S]) -> Result<(), BufferError> {
+        if !samples.len().is_multiple_of(self.channels as usize) {
+            return Err(BufferError::PartialFrame(samples.len()));
+        }
+
+        Ok(self.write_samples(samples)?)
+    }
+
+    /// Appends the samples of another buffer after the written ones, growing the buffer as needed
+    pub fn extend_from_buffer(&mut self, other: &SampleBuffer<S>) -> Result<(), BufferError> {
+        if other.channels != self.channels {
+            return Err(BufferError::ChannelsMismatch(self.channels, other.channels));
+        }
+        if other.sample_rate != self.sample_rate {
+            return Err(BufferError::SampleRateMismatch(
+                self.sample_rate,
+                other.sample_rate,
+            ));
+        }
+
+        Ok(self.write_samples(&other.buffer[..other.written])?)
+    }
+
+    /// Fills a range of frames by repeating the frames before it, or after it at the start
+    fn conceal(&mut self, gap: Range<usize>) {
+        let channels = self.channels as usize;
+        let frames = self.written_frames();
+        let len = gap.len();
+
+        let (source, period) = if gap.start > 0 {
+            let period = len.min(gap.start);
+            (gap.start - period, period)
+        } else if gap.end < frames {
+            (gap.end, len.min(frames - gap.end))
+        } else {
+            return;
+        };
+
+        for i in 0..len {
+            let from = (source + i % period) * channels;
+            let to = (gap.start + i) * channels;
+            self.buffer.copy_within(from..from + channels, to);
+        }
+    }
+
+    /// Keeps only the written frames within the range, moving them to the start
+    fn retain_frames(&mut self, frames: Range<usize>) {
+        let channels = self.channels as usize;
+        self.buffer
+            .copy_within(frames.start * channels..frames.end * channels, 0);
+        self.written = frames.len() * channels;
+        self.truncate_to_written();
+    }
+
+    fn write_samples(&mut self, samples: &[S]) -> std::io::Result<()> {
+        let end = self.grow(samples.len())?;
+        self.buffer[self.written..end].copy_from_slice(samples);
+        self.written = end;
+        self.buffer.release(end);
+        Ok(())
+    }
+
+    /// Appends frames of silence after the written ones, growing the buffer as needed
+    fn write_silence(&mut self, frames: usize) -> std::io::Result<()> {
+        let end = self.grow(frames * self.channels as usize)?;
+        self.buffer[self.written..end].fill(S::MID);
+        self.written = end;
+        self.buffer.release(end);
+        Ok(())
+    }
+
+    /// Makes room for samples after the written ones, returning where they end
+    fn grow(&mut self, samples: usize) -> std::io::Result<usize> {
+        let end = self.written + samples;
+        if end > self.buffer.len() {
+            self.buffer.resize(end, S::MID)?;
+            self.duration = end / self.channels as usize;
+        }
+        Ok(end)
     }
 
     /// Returns an equivalent buffer with the desired sample format
-    pub fn converted<
-        T: Sample
-            + FromSample<S>
-            + FromSample<u8>
-            + FromSample<u16>
-            + FromSample<u24>
-            + FromSample<u32>
-            + FromSample<i8>
-            + FromSample<i16>
-            + FromSample<i24>
-            + FromSample<i32>
-            + FromSample<f32>
-            + FromSample<f64>,
-    >(
-        &self,
-    ) -> SampleBuffer<T> {
+    pub fn converted<T: AudyoSample + 'static>(&self) -> SampleBuffer<T>
+    where
+        S: 'static,
+    {
+        #[cfg(feature = "simd")]
+        let buffer = simd::convert(&self.buffer);
+        #[cfg(not(feature = "simd"))]
+        let buffer = self
+            .buffer
+            .iter()
+            .copied()
+            .map(Sealed::convert)
+            .collect::<Vec<_>>();
+
         SampleBuffer {
-            buffer: self
-                .buffer
-                .iter()
-                .copied()
-                .map(FromSample::from_sample)
-                .collect::<Vec<T>>()
-                .into_boxed_slice(),
+            buffer: buffer.into(),
+            written: self.written,
+            duration: self.duration,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// Converts the buffer to the desired sample format, reusing its allocation when both
+    /// formats have the same size and alignment, e.g. `i32` and `f32` or `u32` and `i32`
+    /// Other pairs fall back to `converted`
+    pub fn convert_in_place<T: AudyoSample + 'static>(self) -> SampleBuffer<T>
+    where
+        S: 'static,
+    {
+        if size_of::<S>() != size_of::<T>() || align_of::<S>() != align_of::<T>() {
+            return self.converted();
+        }
+
+        let buffer = self.buffer.map_in_place(Sealed::convert);
+
+        SampleBuffer {
+            buffer,
             written: self.written,
             duration: self.duration,
             channels: self.channels,
@@ -130,132 +429,795 @@ impl<
     }
 }
 
-fn interleave<T: Copy, V: AsRef<[T]>>(samples: &[V], channels: Channels) -> Vec<T> {
-    match channels {
-        Channels::Mono => samples[0].as_ref().to_vec(),
-        Channels::Stereo => samples[0]
-            .as_ref()
-            .iter()
-            .zip(samples[1].as_ref().iter())
-            .flat_map(|(&l, &r)| [l, r])
-            .collect(),
+/// Mixes a buffer of more than two channels down to interleaved stereo
+/// Gains are normalized, so a full scale signal on every channel doesn't clip
+fn downmix(buffer: &AudioBuffer<f64>) -> Vec<f64> {
+    use symphonia::core::audio::Channels as Positions;
+    const SIDE: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+    let gains: Vec<(f64, f64)> = buffer
+        .spec()
+        .channels
+        .iter()
+        .map(|position| match position {
+            Positions::FRONT_LEFT => (1., 0.),
+            Positions::FRONT_RIGHT => (0., 1.),
+            Positions::LFE1 | Positions::LFE2 => (0., 0.),
+            Positions::FRONT_LEFT_CENTRE
+            | Positions::FRONT_LEFT_WIDE
+            | Positions::FRONT_LEFT_HIGH
+            | Positions::REAR_LEFT
+            | Positions::REAR_LEFT_CENTRE
+            | Positions::SIDE_LEFT
+            | Positions::TOP_FRONT_LEFT
+            | Positions::TOP_REAR_LEFT => (SIDE, 0.),
+            Positions::FRONT_RIGHT_CENTRE
+            | Positions::FRONT_RIGHT_WIDE
+            | Positions::FRONT_RIGHT_HIGH
+            | Positions::REAR_RIGHT
+            | Positions::REAR_RIGHT_CENTRE
+            | Positions::SIDE_RIGHT
+            | Positions::TOP_FRONT_RIGHT
+            | Positions::TOP_REAR_RIGHT => (0., SIDE),
+            // Center channels and ones of unknown position go to both sides
+            _ => (SIDE, SIDE),
+        })
+        .collect();
+    let left: f64 = gains.iter().map(|g| g.0).sum();
+    let right: f64 = gains.iter().map(|g| g.1).sum();
+    let norm = left.max(right).max(1.);
+
+    let planes = buffer.planes();
+    let planes = planes.planes();
+    let mut mixed = Vec::with_capacity(buffer.frames() * 2);
+    for i in 0..buffer.frames() {
+        let (mut left, mut right) = (0., 0.);
+        for (plane, (l, r)) in planes.iter().zip(&gains) {
+            left += plane[i] * l;
+            right += plane[i] * r;
+        }
+        mixed.extend([left / norm, right / norm]);
+    }
+    mixed
+}
+
+/// Duration of a single timestamp unit as a fraction of a second
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeBase {
+    numer: u32,
+    denom: u32,
+}
+
+impl TimeBase {
+    /// Creates a time base of `numer / denom` seconds
+    /// Panics if `denom` is zero, see [`TimeBase::try_new`] for untrusted values
+    pub const fn new(numer: u32, denom: u32) -> Self {
+        assert!(denom != 0, "time base can't have a zero denominator");
+        Self { numer, denom }
+    }
+
+    /// Creates a time base of `numer / denom` seconds, or `None` if `denom` is zero
+    pub const fn try_new(numer: u32, denom: u32) -> Option<Self> {
+        if denom == 0 {
+            return None;
+        }
+        Some(Self { numer, denom })
+    }
+
+    /// Returns time base numerator
+    pub fn numer(&self) -> u32 {
+        self.numer
+    }
+
+    /// Returns time base denominator
+    pub fn denom(&self) -> u32 {
+        self.denom
+    }
+
+    /// Converts a timestamp to a duration
+    pub fn duration(&self, timestamp: u64) -> Duration {
+        let nanos = timestamp as u128 * self.numer as u128 * 1_000_000_000 / self.denom as u128;
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+
+    /// Converts a duration to a timestamp, rounding down
+    /// Saturates at `u64::MAX`, which any duration takes with a zero numerator
+    pub fn timestamp(&self, duration: Duration) -> u64 {
+        (duration.as_nanos() * self.denom as u128)
+            .checked_div(self.numer as u128 * 1_000_000_000)
+            .map_or(u64::MAX, |timestamp| timestamp.min(u64::MAX as u128) as u64)
+    }
+}
+
+impl From<symphonia::core::units::TimeBase> for TimeBase {
+    fn from(tb: symphonia::core::units::TimeBase) -> Self {
+        Self::new(tb.numer, tb.denom)
+    }
+}
+
+fn frames_to_duration(frames: u64, sample_rate: u32) -> Duration {
+    TimeBase::new(1, sample_rate).duration(frames)
+}
+
+/// Information about a decoded source
+#[derive(Clone, Debug)]
+pub struct DecodeInfo {
+    /// Average bitrate of the whole source, container overhead included
+    /// None if the source length is unknown
+    pub container_bitrate: Option<u64>,
+    /// Average bitrate of the audio stream, computed from its packet sizes
+    pub stream_bitrate: u64,
+    /// Number of frames the source claimed to contain, if known
+    pub expected_frames: Option<u64>,
+    /// Number of frames actually decoded
+    pub decoded_frames: u64,
+    /// Exact duration of the decoded audio
+    pub duration: Duration,
+    /// Time base of the decoded track's timestamps, if known
+    pub time_base: Option<TimeBase>,
+    /// Timestamp of the first frame
+    pub start: Duration,
+    /// Tags and chapters read from the source
+    pub metadata: Metadata,
+    /// Ranges of the decoded audio lost to corrupt packets, filled according to the `DecodePolicy`
+    pub corrupted: Vec<Range<Duration>>,
+    /// Decoding statistics, if requested with `DecodeOptions::collect_stats`
+    pub stats: Option<DecodeStats>,
+    /// Whether the decoded audio matches the source's checksum, if requested with
+    /// `DecodeOptions::verify` and the codec supports it
+    pub verified: Option<bool>,
+}
+
+impl DecodeInfo {
+    /// Returns how many frames more (positive) or less (negative) were decoded than expected
+    pub fn frame_discrepancy(&self) -> i64 {
+        self.expected_frames
+            .map_or(0, |expected| self.decoded_frames as i64 - expected as i64)
+    }
+
+    /// Returns how many times faster than realtime the source was decoded, if stats were collected
+    pub fn realtime_factor(&self) -> Option<f64> {
+        self.stats
+            .map(|stats| self.duration.as_secs_f64() / stats.elapsed.as_secs_f64())
+    }
+}
+
+/// Statistics gathered while decoding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Number of packets of the decoded track that decoded successfully
+    pub packets_decoded: u64,
+    /// Number of packets belonging to other tracks
+    pub packets_skipped: u64,
+    /// Number of corrupt packets dropped without ending decoding
+    pub errors_recovered: u64,
+    /// Wall clock time spent decoding
+    pub elapsed: Duration,
+}
+
+/// Options controlling decoding
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    extension: Option<String>,
+    mime: Option<String>,
+    collect_stats: bool,
+    keep_padding: bool,
+    downmix: bool,
+    policy: DecodePolicy,
+    #[cfg(all(feature = "mmap", unix))]
+    mapped: Option<std::path::PathBuf>,
+    max_frames: Option<u64>,
+    max_bytes: Option<u64>,
+    /// Time range decoded instead of the whole track, seeking to its start
+    range: Option<Range<Duration>>,
+    registry: Option<Registry>,
+    decoder_options: DecoderOptions,
+    format_options: Option<FormatOptions>,
+    track: Option<u32>,
+}
+
+/// Codec and format registries used instead of symphonia's defaults
+#[derive(Clone)]
+struct Registry {
+    codecs: Arc<CodecRegistry>,
+    probe: Arc<Probe>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Registry")
     }
 }
 
-fn deintereave<T: Copy>(samples: &[T], channels: Channels) -> Vec<Vec<T>> {
-    match channels {
-        Channels::Mono => vec![samples.to_vec()],
-        Channels::Stereo => {
-            let mut result = vec![
-                Vec::with_capacity(samples.len() / 2),
-                Vec::with_capacity(samples.len() / 2),
-            ];
+/// How audio of corrupt packets, which can't be decoded, gets filled in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodePolicy {
+    /// Fills lost audio with silence
+    #[default]
+    Silence,
+    /// Fills lost audio by repeating the audio right before it, which is much less noticeable
+    /// than silence for short gaps
+    Conceal,
+}
+
+impl DecodeOptions {
+    /// Creates default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hints the probe with the source's file extension, e.g. "flac"
+    pub fn hint_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    /// Hints the probe with the source's MIME type, e.g. "audio/flac"
+    pub fn hint_mime(mut self, mime: impl Into<String>) -> Self {
+        self.mime = Some(mime.into());
+        self
+    }
+
+    /// Enables gathering `DecodeStats` while decoding
+    pub fn collect_stats(mut self, collect: bool) -> Self {
+        self.collect_stats = collect;
+        self
+    }
+
+    /// Keeps the encoder delay and padding at the ends of the stream instead of trimming them
+    /// Trimming uses what the container reports, e.g. Opus pre-skip or LAME headers in MP3
+    pub fn keep_padding(mut self, keep: bool) -> Self {
+        self.keep_padding = keep;
+        self
+    }
+
+    /// Downmixes sources with more channels than stereo, e.g. 5.1, instead of failing with
+    /// `DecodeError::UnsupportedLayout`
+    /// The LFE channel is dropped and the others get mixed in at -3 dB where they're off to one
+    /// side or the center
+    pub fn downmix(mut self, downmix: bool) -> Self {
+        self.downmix = downmix;
+        self
+    }
+
+    /// Decodes into buffers backed by a memory-mapped temporary file in dir, e.g.
+    /// `std::env::temp_dir()`, bounding memory use of very long decodes
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn memory_mapped(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.mapped = Some(dir.into());
+        self
+    }
+
+    /// Fails with `DecodeError::FrameLimitExceeded` instead of decoding more frames, e.g. to
+    /// protect services from inputs claiming or producing implausible lengths
+    pub fn max_frames(mut self, frames: u64) -> Self {
+        self.max_frames = Some(frames);
+        self
+    }
 
-            for i in (0..samples.len()).step_by(2) {
-                result[0].push(samples[i]);
-                result[1].push(samples[i + 1]);
+    /// Fails with `DecodeError::ByteLimitExceeded` instead of letting decoded samples take more
+    /// memory
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Decodes using custom codec and format registries instead of symphonia's defaults, e.g. to
+    /// add a proprietary codec
+    /// `symphonia::default::register_enabled_codecs` and `register_enabled_formats` fill new
+    /// registries with the defaults to build upon
+    pub fn with_registry(
+        mut self,
+        codecs: impl Into<Arc<CodecRegistry>>,
+        probe: impl Into<Arc<Probe>>,
+    ) -> Self {
+        self.registry = Some(Registry {
+            codecs: codecs.into(),
+            probe: probe.into(),
+        });
+        self
+    }
+
+    /// Sets how audio of corrupt packets gets filled in
+    pub fn policy(mut self, policy: DecodePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the options symphonia's decoders get created with
+    pub fn decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.decoder_options = options;
+        self
+    }
+
+    /// Checks the decoded audio against checksums stored in the source where the codec supports
+    /// it, e.g. the MD5 of a FLAC stream, with the outcome in `DecodeInfo::verified`
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.decoder_options.verify = verify;
+        self
+    }
+
+    /// Sets the options symphonia's demuxers get created with
+    /// Replaces the gapless setting `keep_padding` otherwise picks
+    pub fn format_options(mut self, options: FormatOptions) -> Self {
+        self.format_options = Some(options);
+        self
+    }
+
+    /// Decodes the track with the given id, as listed by `list_streams`, instead of the default
+    /// one, e.g. another language of a film's soundtrack
+    pub fn track(mut self, id: u32) -> Self {
+        self.track = Some(id);
+        self
+    }
+
+    /// Decodes only the audio within the range, cut at exact frames, seeking to its start
+    pub(crate) fn range(mut self, range: Option<Range<Duration>>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Returns the track to decode, the one picked with `track` or else the default one
+    fn select_track<'a>(&self, reader: &'a dyn FormatReader) -> Result<&'a Track, DecodeError> {
+        match self.track {
+            Some(id) => reader
+                .tracks()
+                .iter()
+                .find(|t| t.id == id)
+                .ok_or(DecodeError::TrackNotFound(id)),
+            None => default_track(reader).ok_or(DecodeError::PropertyLacking("default track")),
+        }
+    }
+
+    fn format(&self) -> FormatOptions {
+        self.format_options.unwrap_or(FormatOptions {
+            enable_gapless: !self.keep_padding,
+            ..Default::default()
+        })
+    }
+
+    /// Returns whether decoding a source with either options gives the same result
+    pub(crate) fn decodes_like(&self, other: &DecodeOptions) -> bool {
+        let registries = match (&self.registry, &other.registry) {
+            (Some(a), Some(b)) => {
+                Arc::ptr_eq(&a.codecs, &b.codecs) && Arc::ptr_eq(&a.probe, &b.probe)
             }
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        let (format, other_format) = (self.format(), other.format());
+
+        #[cfg(all(feature = "mmap", unix))]
+        if self.mapped != other.mapped {
+            return false;
+        }
+        registries
+            && self.extension == other.extension
+            && self.mime == other.mime
+            && self.collect_stats == other.collect_stats
+            && self.keep_padding == other.keep_padding
+            && self.downmix == other.downmix
+            && self.policy == other.policy
+            && self.max_frames == other.max_frames
+            && self.max_bytes == other.max_bytes
+            && self.range == other.range
+            && self.decoder_options.verify == other.decoder_options.verify
+            && format.enable_gapless == other_format.enable_gapless
+            && format.prebuild_seek_index == other_format.prebuild_seek_index
+            && format.seek_index_fill_rate == other_format.seek_index_fill_rate
+            && self.track == other.track
+    }
+
+    fn codecs(&self) -> &CodecRegistry {
+        match &self.registry {
+            Some(registry) => &registry.codecs,
+            #[cfg(feature = "opus")]
+            None => opus::codecs(),
+            #[cfg(not(feature = "opus"))]
+            None => symphonia::default::get_codecs(),
+        }
+    }
+
+    fn probe(&self) -> &Probe {
+        match &self.registry {
+            Some(registry) => &registry.probe,
+            None => symphonia::default::get_probe(),
+        }
+    }
+
+    /// Checks whether a buffer of `frames` frames stays within the limits
+    fn check_limits<S>(&self, frames: u64, channels: Channels) -> Result<(), DecodeError> {
+        if let Some(max) = self.max_frames.filter(|&max| frames > max) {
+            return Err(DecodeError::FrameLimitExceeded(max));
+        }
+        let bytes = frames.saturating_mul((channels as usize * size_of::<S>()) as u64);
+        if let Some(max) = self.max_bytes.filter(|&max| bytes > max) {
+            return Err(DecodeError::ByteLimitExceeded(max));
+        }
+        Ok(())
+    }
 
-            result
+    fn hint(&self) -> Hint {
+        let mut hint = Hint::new();
+        if let Some(extension) = &self.extension {
+            hint.with_extension(extension);
+        }
+        if let Some(mime) = &self.mime {
+            hint.mime_type(mime);
         }
+        hint
     }
 }
 
 /// Decodes an audio file in source
-/// Returns a tuple of the source bitrate and a buffer with decoded samples
-pub fn decode<
-    S: Sample
-        + FromSample<u8>
-        + FromSample<u16>
-        + FromSample<u24>
-        + FromSample<u32>
-        + FromSample<i8>
-        + FromSample<i16>
-        + FromSample<i24>
-        + FromSample<i32>
-        + FromSample<f32>
-        + FromSample<f64>,
->(
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode<S: AudyoSample>(
     source: impl MediaSource + 'static,
-) -> Result<(u64, SampleBuffer<S>), DecodeError> {
-    let len = source
-        .byte_len()
-        .ok_or(DecodeError::PropertyLacking("source length"))?;
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    decode_with(source, &Default::default())
+}
 
-    let stream = MediaSourceStream::new(Box::new(source), Default::default());
+/// Decodes an audio file in source using given options
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode_with<S: AudyoSample>(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    decode_source(Box::new(source), options)
+}
 
-    let probed = symphonia::default::get_probe().format(
-        &Default::default(),
-        stream,
-        &Default::default(),
-        &Default::default(),
-    )?;
+/// Decodes an audio file read from a non-seekable stream, e.g. stdin
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode_stream<S: AudyoSample>(
+    reader: impl Read + Send + Sync + 'static,
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    decode_stream_with(reader, &Default::default())
+}
 
-    let mut reader = probed.format;
+/// Decodes an audio file read from a non-seekable stream using given options
+/// Returns a tuple of information about the source and a buffer with decoded samples
+pub fn decode_stream_with<S: AudyoSample>(
+    reader: impl Read + Send + Sync + 'static,
+    options: &DecodeOptions,
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    decode_source(Box::new(ReadOnlySource::new(reader)), options)
+}
 
-    let track = reader
-        .default_track()
-        .ok_or(DecodeError::PropertyLacking("default track"))?;
+/// Decodes an audio file in source into an existing buffer, reusing its allocation
+/// Returns information about the source
+pub fn decode_into<S: AudyoSample>(
+    source: impl MediaSource + 'static,
+    buffer: &mut SampleBuffer<S>,
+) -> Result<DecodeInfo, DecodeError> {
+    decode_into_with(source, &Default::default(), buffer)
+}
+
+/// Decodes an audio file in source into an existing buffer using given options
+/// Returns information about the source
+pub fn decode_into_with<S: AudyoSample>(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+    buffer: &mut SampleBuffer<S>,
+) -> Result<DecodeInfo, DecodeError> {
+    let mut source: Box<dyn MediaSource> = Box::new(source);
+    let len = source.byte_len();
+    let chunks = WavChunks::read(&mut *source)?;
+    let mut info = decode_reader_into(probe_format(source, options)?, len, options, buffer)?;
+    chunks.apply(&mut info.metadata);
+    Ok(info)
+}
+
+fn decode_source<S: AudyoSample>(
+    mut source: Box<dyn MediaSource>,
+    options: &DecodeOptions,
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    let len = source.byte_len();
+    let chunks = WavChunks::read(&mut *source)?;
+    let (mut info, buffer) = decode_reader(probe_format(source, options)?, len, options)?;
+    chunks.apply(&mut info.metadata);
+    Ok((info, buffer))
+}
+
+fn decode_reader<S: AudyoSample>(
+    probed: ProbeResult,
+    len: Option<u64>,
+    options: &DecodeOptions,
+) -> Result<(DecodeInfo, SampleBuffer<S>), DecodeError> {
+    #[cfg(all(feature = "mmap", unix))]
+    let mut buffer = match &options.mapped {
+        Some(dir) => SampleBuffer::memory_mapped(0, Channels::Mono, 0, dir)?,
+        None => SampleBuffer::new(0, Channels::Mono, 0),
+    };
+    #[cfg(not(all(feature = "mmap", unix)))]
+    let mut buffer = SampleBuffer::new(0, Channels::Mono, 0);
+    let info = decode_reader_into(probed, len, options, &mut buffer)?;
+    Ok((info, buffer))
+}
+
+fn decode_reader_into<S: AudyoSample>(
+    mut probed: ProbeResult,
+    len: Option<u64>,
+    options: &DecodeOptions,
+    buffer: &mut SampleBuffer<S>,
+) -> Result<DecodeInfo, DecodeError> {
+    let started = Instant::now();
+    let reader = &mut probed.format;
+    let track = options.select_track(&**reader)?;
     let id = track.id;
 
-    let n_frames = track
-        .codec_params
-        .n_frames
-        .ok_or(DecodeError::PropertyLacking("n_frames"))?;
-    let mut buffer = SampleBuffer::new(
-        n_frames as _,
-        track
-            .codec_params
-            .channel_layout
-            .map(|l| match l {
-                Layout::Mono => Channels::Mono,
-                Layout::Stereo => Channels::Stereo,
-                _ => panic!(),
-            })
-            .or(track.codec_params.channels.map(|c| {
-                if c.count() > 1 {
-                    Channels::Stereo
-                } else {
-                    Channels::Mono
-                }
-            }))
-            .ok_or(DecodeError::PropertyLacking("channel layout"))?,
-        track
-            .codec_params
-            .sample_rate
-            .ok_or(DecodeError::PropertyLacking("sample rate"))? as _,
-    );
-
-    let mut decoder =
-        symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
-
-    let bitrate = len
-        / track
-            .codec_params
-            .time_base
-            .ok_or(DecodeError::PropertyLacking("time base"))?
-            .calc_time(n_frames)
-            .seconds
-        * 8;
-
-    loop {
-        let packet = match reader.next_packet() {
-            Ok(p) => p,
-            _ => break,
-        };
+    let sample_rate = sample_rate(&track.codec_params)?;
+    let range = options.range.as_ref().map(|range| {
+        let time_base = TimeBase::new(1, sample_rate);
+        time_base.timestamp(range.start)..time_base.timestamp(range.end)
+    });
 
+    // Streams of unknown length start out empty, and every stream grows past what was reserved
+    // as packets get decoded
+    let n_frames = n_frames(&track.codec_params).map(|n| match &range {
+        Some(range) => n.min(range.end).saturating_sub(range.start),
+        None => n,
+    });
+    let channels = channels(&track.codec_params, options.downmix)?;
+    // Refusing upfront saves decoding a source that's going to fail anyway
+    if let Some(n) = n_frames {
+        options.check_limits::<S>(n, channels)?;
+    }
+    let allocated = n_frames.map_or(0, |n| n.min(MAX_RESERVED_FRAMES) as usize);
+    buffer.reset(allocated, channels, sample_rate)?;
+
+    let mut decoder = options
+        .codecs()
+        .make(&track.codec_params, &options.decoder_options)?;
+
+    let time_base = track.codec_params.time_base.map(TimeBase::from);
+    let max_packet = track.codec_params.max_frames_per_packet;
+    let skip = pre_skip(&track.codec_params);
+    let start = time_base.map_or(Duration::ZERO, |tb| {
+        tb.duration(track.codec_params.start_ts)
+    }) + options
+        .range
+        .as_ref()
+        .map_or(Duration::ZERO, |range| range.start);
+
+    // The container seeks to a packet before the range, decoded frames up to its start are
+    // dropped
+    if let Some(range) = range.as_ref().filter(|range| range.start > 0) {
+        let frame = range.start.saturating_sub(SEEK_PREROLL) + skip;
+        reader.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: frames_to_ts(frame, time_base, sample_rate),
+                track_id: id,
+            },
+        )?;
+        decoder.reset();
+    }
+    // Frame of the stream the buffer starts at, going by packet timestamps
+    let mut origin = 0;
+
+    let mut stream_len = 0;
+    let mut stats = DecodeStats::default();
+    // Frames replaced with silence
+    let mut corrupted: Vec<Range<usize>> = Vec::new();
+
+    while let Ok(packet) = reader.next_packet() {
+        scheduler::yield_now();
         if packet.track_id() != id {
+            stats.packets_skipped += 1;
             continue;
         }
 
+        let frame = ts_to_frames(packet.ts(), time_base, sample_rate).saturating_sub(skip);
+        if let Some(range) = &range {
+            if frame >= range.end {
+                break;
+            }
+            if buffer.written == 0 {
+                origin = frame;
+            }
+        }
+        // Only frames of the range count towards the limits
+        let kept = |frames: usize| match &range {
+            Some(range) => (frames as u64)
+                .min(range.end.saturating_sub(origin))
+                .saturating_sub(range.start.saturating_sub(origin)),
+            None => frames as u64,
+        };
+
+        if range.as_ref().is_none_or(|range| frame >= range.start) {
+            stream_len += packet.buf().len() as u64;
+        }
+
         match decoder.decode(&packet) {
-            Ok(decoded) => buffer.copy_samples(decoded),
-            Err(SymphoniaError::DecodeError(_)) => (),
+            Ok(decoded) => {
+                stats.packets_decoded += 1;
+                let frames = buffer.written_frames() + decoded.frames();
+                options.check_limits::<S>(kept(frames), channels)?;
+                buffer.copy_samples(decoded)?;
+            }
+            Err(SymphoniaError::DecodeError(_)) => {
+                stats.errors_recovered += 1;
+
+                // The packet's audio is replaced with silence, so later audio keeps its timing
+                let frames = lost_frames(packet.dur, time_base, buffer.sample_rate, max_packet);
+                let start = buffer.written_frames();
+                options.check_limits::<S>(kept(start + frames), channels)?;
+                buffer.write_silence(frames)?;
+
+                let end = buffer.written_frames();
+                match corrupted.last_mut() {
+                    Some(last) if last.end == start => last.end = end,
+                    _ => corrupted.push(start..end),
+                }
+            }
             _ => break,
         }
     }
 
-    Ok((bitrate, buffer))
+    if options.policy == DecodePolicy::Conceal {
+        for gap in &corrupted {
+            buffer.conceal(gap.clone());
+        }
+    }
+
+    if let Some(range) = &range {
+        let written = buffer.written_frames();
+        let lead = (range.start.saturating_sub(origin) as usize).min(written);
+        let len = (range.end.saturating_sub(range.start.max(origin)) as usize).min(written - lead);
+        buffer.retain_frames(lead..lead + len);
+        corrupted = corrupted
+            .into_iter()
+            .map(|gap| {
+                gap.start.clamp(lead, lead + len) - lead..gap.end.clamp(lead, lead + len) - lead
+            })
+            .filter(|gap| !gap.is_empty())
+            .collect();
+    }
+
+    let verified = decoder.finalize().verify_ok;
+    let metadata = Metadata::read(&mut probed);
+
+    let decoded_frames = (buffer.written / channels as usize) as u64;
+    let duration = frames_to_duration(decoded_frames, buffer.sample_rate);
+    let seconds = duration.as_secs_f64();
+
+    Ok(DecodeInfo {
+        container_bitrate: len.map(|len| bitrate(len, seconds)),
+        stream_bitrate: bitrate(stream_len, seconds),
+        expected_frames: n_frames,
+        decoded_frames,
+        duration,
+        time_base,
+        start,
+        metadata,
+        corrupted: corrupted
+            .into_iter()
+            .map(|gap| {
+                frames_to_duration(gap.start as u64, buffer.sample_rate)
+                    ..frames_to_duration(gap.end as u64, buffer.sample_rate)
+            })
+            .collect(),
+        stats: options.collect_stats.then(|| DecodeStats {
+            elapsed: started.elapsed(),
+            ..stats
+        }),
+        verified,
+    })
+}
+
+/// Returns the track decoded unless another is picked: the reader's default track, unless it's
+/// lacking a sample rate, like video tracks do, and another track has one
+fn default_track(reader: &dyn FormatReader) -> Option<&Track> {
+    reader
+        .default_track()
+        .filter(|t| t.codec_params.sample_rate.is_some())
+        .or_else(|| {
+            reader
+                .tracks()
+                .iter()
+                .find(|t| t.codec_params.sample_rate.is_some())
+        })
+        .or_else(|| reader.default_track())
+}
+
+fn probe_format(
+    source: Box<dyn MediaSource>,
+    options: &DecodeOptions,
+) -> Result<ProbeResult, DecodeError> {
+    let stream = MediaSourceStream::new(source, Default::default());
+
+    Ok(options.probe().format(
+        &options.hint(),
+        stream,
+        &options.format(),
+        &Default::default(),
+    )?)
+}
+
+fn bitrate(bytes: u64, seconds: f64) -> u64 {
+    if seconds > 0. {
+        (bytes as f64 * 8. / seconds).round() as _
+    } else {
+        0
+    }
+}
+
+/// Returns the channel layout a source decodes to
+/// Sources with more channels than stereo fail unless they get downmixed
+fn channels(params: &CodecParameters, downmix: bool) -> Result<Channels, DecodeError> {
+    match (params.channel_layout, params.channels) {
+        (Some(Layout::Mono), _) => Ok(Channels::Mono),
+        (Some(Layout::Stereo), _) => Ok(Channels::Stereo),
+        (Some(layout), _) if downmix && layout.into_channels().count() > 2 => Ok(Channels::Stereo),
+        (None, Some(c)) if downmix && c.count() > 2 => Ok(Channels::Stereo),
+        (Some(layout), _) => Err(DecodeError::UnsupportedLayout(layout)),
+        (None, Some(c)) => match c.count() {
+            1 => Ok(Channels::Mono),
+            2 => Ok(Channels::Stereo),
+            count => Err(DecodeError::UnsupportedChannelCount(count)),
+        },
+        (None, None) => Err(DecodeError::PropertyLacking("channel layout")),
+    }
+}
+
+/// Returns the Opus pre-skip, which Ogg lengths and packet durations count but decoding drops,
+/// or 0 for other codecs
+fn pre_skip(params: &CodecParameters) -> u64 {
+    match params.codec {
+        CODEC_TYPE_OPUS => params.delay.unwrap_or(0) as u64,
+        _ => 0,
+    }
+}
+
+/// Returns the length of the track in its time base, as it decodes
+fn n_frames(params: &CodecParameters) -> Option<u64> {
+    params
+        .n_frames
+        .map(|length| length.saturating_sub(pre_skip(params)))
+}
+
+fn sample_rate(params: &CodecParameters) -> Result<u32, DecodeError> {
+    params
+        .sample_rate
+        .ok_or(DecodeError::PropertyLacking("sample rate"))
+}
+
+/// Returns the frame a timestamp in the time base falls on
+pub(crate) fn ts_to_frames(ts: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+    time_base.map_or(ts, |tb| {
+        (ts as u128 * tb.numer as u128 * sample_rate as u128 / tb.denom as u128) as u64
+    })
+}
+
+/// Returns the timestamp in the time base a frame falls on
+pub(crate) fn frames_to_ts(frames: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+    time_base.map_or(frames, |tb| {
+        (frames as u128 * tb.denom as u128 / (tb.numer as u128 * sample_rate as u128)) as u64
+    })
+}
+
+/// Most frames reserved upfront for the length a source claims, about 3 minutes at 48 kHz
+/// Longer sources grow the buffer as they decode, so a forged length can't exhaust memory
+const MAX_RESERVED_FRAMES: u64 = 1 << 23;
+
+/// Most frames of silence a corrupt packet gets replaced with, whatever duration it claims
+const MAX_LOST_FRAMES: u64 = 1 << 16;
+
+/// Returns how many frames of silence stand in for a corrupt packet lasting `dur` in the time
+/// base, capped to the most frames a packet of the track holds
+pub(crate) fn lost_frames(
+    dur: u64,
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+    max_frames_per_packet: Option<u64>,
+) -> usize {
+    let frames = time_base.map_or(dur as u128, |tb| {
+        dur as u128 * tb.numer as u128 * sample_rate as u128 / tb.denom as u128
+    });
+    let max = max_frames_per_packet.map_or(MAX_LOST_FRAMES, |max| max.min(MAX_LOST_FRAMES));
+    frames.min(max as u128) as usize
 }
 
 /// Enum representing decoding errors
@@ -263,25 +1225,113 @@ pub fn decode<
 #[error(transparent)]
 pub enum DecodeError {
     Symphonia(#[from] SymphoniaError),
+    Io(#[from] std::io::Error),
     #[error("source lacks property: {0}")]
     PropertyLacking(&'static str),
+    #[error("unsupported channel layout: {0:?}")]
+    UnsupportedLayout(Layout),
+    #[error("unsupported channel count: {0}")]
+    UnsupportedChannelCount(usize),
+    #[error("source exceeds the limit of {0} frames")]
+    FrameLimitExceeded(u64),
+    #[error("decoded audio exceeds the limit of {0} bytes")]
+    ByteLimitExceeded(u64),
+    #[error("unsupported sample rate: {0}")]
+    UnsupportedSampleRate(u32),
+    #[error("malformed source")]
+    Malformed,
+    #[error("source has no track with id {0}")]
+    TrackNotFound(u32),
+}
+
+/// Enum representing encoding errors
+#[derive(Error, Debug)]
+pub enum EncodeError {
+    #[error("unsupported sample rate: {0}")]
+    UnsupportedSampleRate(u32),
+    #[error("bitrate out of range: {0}")]
+    BitrateOutOfRange(u64),
+    #[error(transparent)]
+    Vorbis(#[from] VorbisError),
+    #[error(transparent)]
+    Buffer(#[from] BufferError),
+    #[error("realtime deadline missed, encoding took {0:?}")]
+    DeadlineMissed(Duration),
+    #[error("output too large for its format: {0} bytes")]
+    TooLarge(u64),
+}
+
+/// Enum representing errors of buffer operations
+#[derive(Error, Debug)]
+pub enum BufferError {
+    #[error("channel index out of range: {0}")]
+    ChannelOutOfRange(usize),
+    #[error("channel map length doesn't match channel count: {0}")]
+    ChannelMapLength(usize),
+    #[error("sample rates don't match: {0} and {1}")]
+    SampleRateMismatch(u32, u32),
+    #[error("channel layouts don't match: {0:?} and {1:?}")]
+    ChannelsMismatch(Channels, Channels),
+    #[error("no input buffers")]
+    NoInputs,
+    #[error("sample count isn't a whole number of frames: {0}")]
+    PartialFrame(usize),
+    #[error("frame index out of range: {0}")]
+    FrameOutOfRange(usize),
+    #[error("unsupported channel count: {0}")]
+    UnsupportedChannelCount(usize),
+    #[error("chunks must hold at least one frame")]
+    EmptyChunk,
+    #[error("unsupported sample rate: {0}")]
+    UnsupportedSampleRate(u32),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
 /// Function for encoding a buffer using ogg vorbis given an average bitrate
-pub fn encode_vorbis(samples: &SampleBuffer<f32>, bitrate: u64) -> Result<Vec<u8>, VorbisError> {
-    let mut encoder = VorbisEncoderBuilder::new(
-        NonZeroU32::new(samples.sample_rate).unwrap(),
-        NonZeroU8::new(samples.channels as _).unwrap(),
-        Vec::new(),
-    )?
-    .bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr {
-        average_bitrate: NonZeroU32::new(bitrate as u32).unwrap(),
-    })
-    .build()?;
+/// Samples of any supported format get converted to floats a block at a time
+pub fn encode_vorbis<S: AudyoSample>(
+    samples: &SampleBuffer<S>,
+    bitrate: u64,
+) -> Result<Vec<u8>, EncodeError> {
+    encode_vorbis_into(samples, bitrate, Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "zero denominator")]
+    fn time_base_rejects_zero_denominator() {
+        TimeBase::new(1, 0);
+    }
+
+    #[test]
+    fn time_base_try_new() {
+        assert_eq!(TimeBase::try_new(1, 0), None);
+        assert_eq!(TimeBase::try_new(1, 1000), Some(TimeBase::new(1, 1000)));
+    }
 
-    for chunk in samples.samples().chunks(2048) {
-        encoder.encode_audio_block(deintereave(chunk, samples.channels))?;
+    #[test]
+    fn zero_time_base_saturates() {
+        let tb = TimeBase::new(0, 1000);
+        assert_eq!(tb.duration(u64::MAX), Duration::ZERO);
+        assert_eq!(tb.timestamp(Duration::from_secs(1)), u64::MAX);
     }
 
-    encoder.finish()
+    #[test]
+    fn lost_frames_are_capped() {
+        let tb = Some(TimeBase::new(1, 1000));
+        assert_eq!(lost_frames(20, tb, 48000, None), 960);
+        assert_eq!(
+            lost_frames(u64::MAX, tb, 48000, None),
+            MAX_LOST_FRAMES as usize
+        );
+        assert_eq!(lost_frames(u64::MAX, None, 48000, Some(4096)), 4096);
+        assert_eq!(
+            lost_frames(u64::MAX, None, 48000, Some(u64::MAX)),
+            MAX_LOST_FRAMES as usize
+        );
+    }
 }
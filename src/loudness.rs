@@ -0,0 +1,95 @@
+use crate::{
+    filters::{Biquad, BiquadCoeffs},
+    SampleBuffer,
+};
+use std::f64::consts::PI;
+
+/// Gating blocks are 400 ms long and 75% overlapping, so they advance 100 ms at a time
+const STEPS_PER_BLOCK: usize = 4;
+const STEP_SECONDS: f64 = 0.1;
+/// Blocks quieter than this don't count at all, in LUFS
+const ABSOLUTE_GATE: f64 = -70.;
+/// Blocks this much quieter than the absolute-gated loudness don't count, in LU
+const RELATIVE_GATE: f64 = -10.;
+
+impl SampleBuffer<f32> {
+    /// Returns the integrated loudness in LUFS, K-weighted and gated as specified by ITU-R
+    /// BS.1770
+    /// None if the buffer is shorter than one 400 ms block or entirely below the -70 LUFS gate
+    pub fn loudness(&self) -> Option<f32> {
+        let channels = self.channels as usize;
+        let step = (self.sample_rate as f64 * STEP_SECONDS).round() as usize;
+        if step == 0 {
+            return None;
+        }
+
+        // Energy of each 100 ms step, summed over channels, all weighted 1 in mono and stereo
+        let frames = self.written_frames();
+        let mut steps = vec![0f64; frames / step];
+        for channel in 0..channels {
+            let mut filters = k_weighting(self.sample_rate);
+            for (i, frame) in self.buffer[..frames * channels]
+                .chunks_exact(channels)
+                .enumerate()
+            {
+                let y = filters
+                    .iter_mut()
+                    .fold(frame[channel] as f64, |x, f| f.process_f64(x));
+                if let Some(energy) = steps.get_mut(i / step) {
+                    *energy += y * y;
+                }
+            }
+        }
+
+        let block_len = (step * STEPS_PER_BLOCK) as f64;
+        let blocks: Vec<f64> = steps
+            .windows(STEPS_PER_BLOCK)
+            .map(|w| w.iter().sum::<f64>() / block_len)
+            .filter(|&energy| lufs(energy) > ABSOLUTE_GATE)
+            .collect();
+        let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+            let (sum, count) = blocks.fold((0., 0), |(sum, count), e| (sum + e, count + 1));
+            (count > 0).then(|| sum / count as f64)
+        };
+
+        let gate = lufs(mean(&mut blocks.iter().copied())?) + RELATIVE_GATE;
+        let gated = mean(&mut blocks.iter().copied().filter(|&e| lufs(e) > gate))?;
+        Some(lufs(gated) as f32)
+    }
+}
+
+/// Converts a mean square to loudness, the offset makes a 1 kHz full scale sine -3.01 LUFS
+fn lufs(energy: f64) -> f64 {
+    -0.691 + 10. * energy.max(f64::MIN_POSITIVE).log10()
+}
+
+/// Returns the K-weighting filter stages: a high shelf modelling the head, then a high-pass
+/// Coefficients are derived from the analog prototypes, which reproduces those BS.1770 lists for
+/// 48 kHz exactly and works at any other rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let k = |freq: f64| (PI * freq / sample_rate as f64).tan();
+
+    let (k_shelf, q) = (k(1681.974450955533), 0.7071752369554196);
+    let vh = 10f64.powf(3.999843853973347 / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1. + k_shelf / q + k_shelf * k_shelf;
+    let shelf = BiquadCoeffs {
+        b0: (vh + vb * k_shelf / q + k_shelf * k_shelf) / a0,
+        b1: 2. * (k_shelf * k_shelf - vh) / a0,
+        b2: (vh - vb * k_shelf / q + k_shelf * k_shelf) / a0,
+        a1: 2. * (k_shelf * k_shelf - 1.) / a0,
+        a2: (1. - k_shelf / q + k_shelf * k_shelf) / a0,
+    };
+
+    let (k_pass, q) = (k(38.13547087602444), 0.5003270373238773);
+    let a0 = 1. + k_pass / q + k_pass * k_pass;
+    let high_pass = BiquadCoeffs {
+        b0: 1.,
+        b1: -2.,
+        b2: 1.,
+        a1: 2. * (k_pass * k_pass - 1.) / a0,
+        a2: (1. - k_pass / q + k_pass * k_pass) / a0,
+    };
+
+    [Biquad::new(shelf), Biquad::new(high_pass)]
+}
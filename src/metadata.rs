@@ -0,0 +1,444 @@
+use crate::{BroadcastInfo, SamplerInfo};
+use std::{ops::Range, time::Duration};
+use symphonia::core::{
+    formats::Cue,
+    meta::{MetadataRevision, StandardTagKey, StandardVisualKey},
+    probe::ProbeResult,
+};
+
+/// Metadata read from a source
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// Tags in the order they appear in the source, e.g. Vorbis comments
+    pub tags: Vec<Tag>,
+    /// Chapters from the Ogg chapter extension (CHAPTERxxx tags), ordered by start time
+    pub chapters: Vec<Chapter>,
+    /// Embedded artwork, e.g. cover art
+    pub pictures: Vec<Picture>,
+    /// Unsynchronized lyrics, from LYRICS and UNSYNCEDLYRICS Vorbis comments or ID3v2 USLT frames
+    pub lyrics: Vec<Lyrics>,
+    /// Track layout from a FLAC CUESHEET block
+    pub cue_sheet: Option<CueSheet>,
+    /// Loop points and root note from a WAV smpl chunk
+    pub sampler: Option<SamplerInfo>,
+    /// Originator, timecode reference and coding history from a WAV bext chunk
+    pub broadcast: Option<BroadcastInfo>,
+}
+
+impl Metadata {
+    /// Returns the value of the first tag with the given key, ignoring case
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|t| t.key.eq_ignore_ascii_case(key))
+            .map(|t| t.value.as_str())
+    }
+
+    pub(crate) fn read(probed: &mut ProbeResult) -> Self {
+        let mut metadata = Self::default();
+
+        if let Some(revision) = probed
+            .metadata
+            .get()
+            .as_mut()
+            .and_then(|m| m.skip_to_latest())
+        {
+            metadata.add_revision(revision);
+        }
+        if let Some(revision) = probed.format.metadata().skip_to_latest() {
+            metadata.add_revision(revision);
+        }
+
+        metadata.chapters = parse_chapters(&metadata.tags);
+        let sample_rate = probed
+            .format
+            .default_track()
+            .and_then(|t| t.codec_params.sample_rate);
+        metadata.cue_sheet = sample_rate.and_then(|rate| CueSheet::new(probed.format.cues(), rate));
+        metadata
+    }
+
+    fn add_revision(&mut self, revision: &MetadataRevision) {
+        self.lyrics.extend(
+            revision
+                .tags()
+                .iter()
+                .filter(|t| t.std_key == Some(StandardTagKey::Lyrics))
+                .map(|t| Lyrics {
+                    // ID3v2 frames carry their language in the key, e.g. USLT!eng
+                    language: t.key.split_once('!').map(|(_, lang)| lang.to_owned()),
+                    text: t.value.to_string(),
+                }),
+        );
+        self.tags.extend(revision.tags().iter().map(|t| Tag {
+            key: t.key.clone(),
+            value: t.value.to_string(),
+        }));
+        self.pictures
+            .extend(revision.visuals().iter().map(|v| Picture {
+                media_type: v.media_type.clone(),
+                kind: v.usage.map_or(0, picture_kind),
+                dimensions: v.dimensions.map(|size| (size.width, size.height)),
+                data: v.data.to_vec(),
+            }));
+    }
+}
+
+/// Embedded picture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Picture {
+    /// MIME type of the data, e.g. image/jpeg
+    pub media_type: String,
+    /// Picture type as numbered by ID3v2 and FLAC, e.g. 3 for the front cover
+    pub kind: u32,
+    /// Width and height in pixels, as declared by the source
+    pub dimensions: Option<(u32, u32)>,
+    /// Encoded image
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    /// Returns the picture as a METADATA_BLOCK_PICTURE Vorbis comment
+    pub(crate) fn to_tag(&self) -> Tag {
+        let (width, height) = self.dimensions.unwrap_or_default();
+
+        let mut block = Vec::with_capacity(32 + self.media_type.len() + self.data.len());
+        block.extend_from_slice(&self.kind.to_be_bytes());
+        block.extend_from_slice(&(self.media_type.len() as u32).to_be_bytes());
+        block.extend_from_slice(self.media_type.as_bytes());
+        // Empty description
+        block.extend_from_slice(&0u32.to_be_bytes());
+        // Width, height, unknown color depth and palette size
+        for value in [width, height, 0, 0, self.data.len() as u32] {
+            block.extend_from_slice(&value.to_be_bytes());
+        }
+        block.extend_from_slice(&self.data);
+
+        Tag {
+            key: "METADATA_BLOCK_PICTURE".to_string(),
+            value: base64(&block),
+        }
+    }
+}
+
+/// Returns the ID3v2 picture type of a visual
+fn picture_kind(key: StandardVisualKey) -> u32 {
+    match key {
+        StandardVisualKey::FileIcon => 1,
+        StandardVisualKey::OtherIcon => 2,
+        StandardVisualKey::FrontCover => 3,
+        StandardVisualKey::BackCover => 4,
+        StandardVisualKey::Leaflet => 5,
+        StandardVisualKey::Media => 6,
+        StandardVisualKey::LeadArtistPerformerSoloist => 7,
+        StandardVisualKey::ArtistPerformer => 8,
+        StandardVisualKey::Conductor => 9,
+        StandardVisualKey::BandOrchestra => 10,
+        StandardVisualKey::Composer => 11,
+        StandardVisualKey::Lyricist => 12,
+        StandardVisualKey::RecordingLocation => 13,
+        StandardVisualKey::RecordingSession => 14,
+        StandardVisualKey::Performance => 15,
+        StandardVisualKey::ScreenCapture => 16,
+        StandardVisualKey::Illustration => 18,
+        StandardVisualKey::BandArtistLogo => 19,
+        StandardVisualKey::PublisherStudioLogo => 20,
+    }
+}
+
+pub(crate) fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Metadata key-value pair
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+/// Chapter marker
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    /// Offset of the chapter start from the start of the audio
+    pub start: Duration,
+    pub name: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Collects chapters from CHAPTERxxx, CHAPTERxxxNAME and CHAPTERxxxURL tags
+fn parse_chapters(tags: &[Tag]) -> Vec<Chapter> {
+    let mut chapters: Vec<(u32, Option<Duration>, Chapter)> = Vec::new();
+
+    for tag in tags {
+        let key = tag.key.to_ascii_uppercase();
+        let Some(rest) = key.strip_prefix("CHAPTER") else {
+            continue;
+        };
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let Ok(index) = rest[..digits].parse::<u32>() else {
+            continue;
+        };
+
+        let i = match chapters.iter().position(|c| c.0 == index) {
+            Some(i) => i,
+            None => {
+                chapters.push((
+                    index,
+                    None,
+                    Chapter {
+                        start: Duration::ZERO,
+                        name: None,
+                        url: None,
+                    },
+                ));
+                chapters.len() - 1
+            }
+        };
+
+        let value = tag.value.trim();
+        match &rest[digits..] {
+            "" => chapters[i].1 = parse_timestamp(value),
+            "NAME" => chapters[i].2.name = Some(value.to_owned()),
+            "URL" => chapters[i].2.url = Some(value.to_owned()),
+            _ => (),
+        }
+    }
+
+    let mut chapters: Vec<_> = chapters
+        .into_iter()
+        .filter_map(|(_, start, chapter)| start.map(|start| Chapter { start, ..chapter }))
+        .collect();
+    chapters.sort_by_key(|c| c.start);
+    chapters
+}
+
+/// Unsynchronized lyrics, the whole text without timing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lyrics {
+    /// ISO 639-2 code of the language, e.g. eng, if the source records one
+    pub language: Option<String>,
+    /// Lines of the lyrics separated by newlines
+    pub text: String,
+}
+
+impl Lyrics {
+    /// Returns the lines of the lyrics
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.text.lines()
+    }
+}
+
+/// Track layout of a disc image, as stored in a FLAC CUESHEET block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CueSheet {
+    /// Tracks ordered by their offset, without the lead-out
+    pub tracks: Vec<CueTrack>,
+    /// Offset in frames of the lead-out, where the last track ends
+    pub lead_out: u64,
+    sample_rate: u32,
+}
+
+/// Track of a `CueSheet`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CueTrack {
+    /// Track number, from 1 to 99 on a CD
+    pub number: u32,
+    /// Offset in frames of the track from the start of the audio
+    pub offset: u64,
+    /// International Standard Recording Code of the track, if recorded
+    pub isrc: Option<String>,
+    /// Offsets in frames of the track's index points relative to the track's offset, in order
+    /// The first is usually index 1, where the track starts, or index 0, where the pregap leading
+    /// to index 1 starts
+    pub indices: Vec<u64>,
+}
+
+/// Number of the lead-out track on a CD
+const CD_LEAD_OUT: u32 = 170;
+/// Number of the lead-out track elsewhere
+const LEAD_OUT: u32 = 255;
+
+impl CueSheet {
+    /// Collects the tracks from a reader's cues, None without a lead-out or tracks
+    fn new(cues: &[Cue], sample_rate: u32) -> Option<Self> {
+        let (lead_out, tracks): (Vec<_>, Vec<_>) = cues
+            .iter()
+            .partition(|cue| matches!(cue.index, CD_LEAD_OUT | LEAD_OUT));
+        let lead_out = lead_out.first()?.start_ts;
+        let mut tracks: Vec<_> = tracks
+            .into_iter()
+            .map(|cue| CueTrack {
+                number: cue.index,
+                offset: cue.start_ts,
+                isrc: cue
+                    .tags
+                    .iter()
+                    .find(|t| t.std_key == Some(StandardTagKey::IdentIsrc))
+                    .map(|t| t.value.to_string())
+                    .filter(|isrc| !isrc.is_empty()),
+                indices: cue.points.iter().map(|p| p.start_offset_ts).collect(),
+            })
+            .collect();
+        if tracks.is_empty() {
+            return None;
+        }
+        tracks.sort_by_key(|t| t.offset);
+
+        Some(Self {
+            tracks,
+            lead_out,
+            sample_rate,
+        })
+    }
+
+    /// Returns the time from the start of the audio to where the track at index starts, or to
+    /// the lead-out after the last track
+    pub fn start(&self, index: usize) -> Duration {
+        let offset = self.tracks.get(index).map_or(self.lead_out, |t| t.offset);
+        Duration::from_secs_f64(offset as f64 / self.sample_rate as f64)
+    }
+
+    /// Returns the times each track starts and ends, for splitting the audio into tracks
+    pub fn ranges(&self) -> Vec<Range<Duration>> {
+        (0..self.tracks.len())
+            .map(|i| self.start(i)..self.start(i + 1))
+            .collect()
+    }
+}
+
+/// Silence around the audio, as recorded in an iTunSMPB tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Padding {
+    /// Frames of silence before the audio
+    pub start: u64,
+    /// Frames of silence after the audio
+    pub end: u64,
+    /// Frames of audio between them
+    pub frames: u64,
+}
+
+/// Key of the tag iTunes introduced for gapless playback, read by many players beyond it
+pub(crate) const PADDING_KEY: &str = "iTunSMPB";
+
+impl Metadata {
+    /// Returns the padding recorded in an iTunSMPB tag, which gapless-aware players skip
+    pub fn padding(&self) -> Option<Padding> {
+        let mut fields = self
+            .get(PADDING_KEY)?
+            .split_whitespace()
+            .map(|field| u64::from_str_radix(field, 16));
+        fields.next()?.ok()?;
+        Some(Padding {
+            start: fields.next()?.ok()?,
+            end: fields.next()?.ok()?,
+            frames: fields.next()?.ok()?,
+        })
+    }
+}
+
+/// Writes padding as an iTunSMPB tag
+pub(crate) fn padding_tag(padding: &Padding) -> Tag {
+    Tag {
+        key: PADDING_KEY.to_owned(),
+        value: format!(
+            " 00000000 {:08X} {:08X} {:016X}{}",
+            padding.start,
+            padding.end,
+            padding.frames,
+            " 00000000".repeat(8)
+        ),
+    }
+}
+
+/// Returns whether the tag is one of the CHAPTERxxx tags
+pub(crate) fn is_chapter_tag(tag: &Tag) -> bool {
+    let key = tag.key.to_ascii_uppercase();
+    key.strip_prefix("CHAPTER")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Writes chapters as CHAPTERxxx, CHAPTERxxxNAME and CHAPTERxxxURL tags
+pub(crate) fn chapter_tags(chapters: &[Chapter]) -> Vec<Tag> {
+    let mut tags = Vec::with_capacity(chapters.len());
+    for (i, chapter) in chapters.iter().enumerate() {
+        let key = format!("CHAPTER{:03}", i + 1);
+        let millis = chapter.start.as_millis();
+        tags.push(Tag {
+            key: key.clone(),
+            value: format!(
+                "{:02}:{:02}:{:02}.{:03}",
+                millis / 3_600_000,
+                millis / 60_000 % 60,
+                millis / 1000 % 60,
+                millis % 1000
+            ),
+        });
+        if let Some(name) = &chapter.name {
+            tags.push(Tag {
+                key: format!("{key}NAME"),
+                value: name.clone(),
+            });
+        }
+        if let Some(url) = &chapter.url {
+            tags.push(Tag {
+                key: format!("{key}URL"),
+                value: url.clone(),
+            });
+        }
+    }
+    tags
+}
+
+/// Parses a HH:MM:SS.sss timestamp
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let mut parts = value.rsplitn(3, ':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    let hours: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+
+    if !(0. ..60.).contains(&seconds) {
+        return None;
+    }
+
+    let whole = hours
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_mul(60)?;
+    Duration::from_secs(whole).checked_add(Duration::try_from_secs_f64(seconds).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(
+            parse_timestamp("00:01:02.5"),
+            Some(Duration::from_millis(62_500))
+        );
+        assert_eq!(parse_timestamp("3.25"), Some(Duration::from_millis(3250)));
+        assert_eq!(parse_timestamp("00:00:60"), None);
+        assert_eq!(parse_timestamp("5124095576030431:00:59.5"), None);
+        assert_eq!(parse_timestamp("0:18446744073709551615:00"), None);
+    }
+}
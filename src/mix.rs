@@ -0,0 +1,56 @@
+use crate::{BufferError, SampleBuffer};
+#[cfg(feature = "resampler")]
+use std::borrow::Cow;
+
+/// Sums the written frames of buffers, each scaled by its gain
+/// All inputs must share a channel layout, and a sample rate unless the `resampler` feature
+/// is enabled, in which case inputs get resampled to the rate of the first one
+pub fn mix(inputs: &[(&SampleBuffer<f32>, f32)]) -> Result<SampleBuffer<f32>, BufferError> {
+    let (first, _) = inputs.first().ok_or(BufferError::NoInputs)?;
+
+    let mut duration = 0;
+    for (buffer, _) in inputs {
+        if buffer.sample_rate == 0 {
+            return Err(BufferError::UnsupportedSampleRate(0));
+        }
+        if buffer.channels != first.channels {
+            return Err(BufferError::ChannelsMismatch(
+                first.channels,
+                buffer.channels,
+            ));
+        }
+        #[cfg(not(feature = "resampler"))]
+        if buffer.sample_rate != first.sample_rate {
+            return Err(BufferError::SampleRateMismatch(
+                first.sample_rate,
+                buffer.sample_rate,
+            ));
+        }
+        duration = duration.max(
+            (buffer.written_frames() as u64 * first.sample_rate as u64)
+                .div_ceil(buffer.sample_rate as u64) as usize,
+        );
+    }
+
+    let mut output = SampleBuffer::new(duration, first.channels, first.sample_rate);
+
+    for &(buffer, gain) in inputs {
+        #[cfg(feature = "resampler")]
+        let buffer = if buffer.sample_rate == first.sample_rate {
+            Cow::Borrowed(buffer)
+        } else {
+            Cow::Owned(buffer.resampled(first.sample_rate, crate::resample::Quality::default()))
+        };
+
+        for (out, &sample) in output
+            .buffer
+            .iter_mut()
+            .zip(&buffer.buffer[..buffer.written])
+        {
+            *out += sample * gain;
+        }
+    }
+
+    output.written = output.buffer.len();
+    Ok(output)
+}
@@ -0,0 +1,160 @@
+//! Opus decoding, which symphonia has no decoder for, trimming the pre-skip the Ogg mapping
+//! leaves in so that decoded lengths are sample accurate
+
+use opus_decoder::OpusMultistreamDecoder;
+use std::sync::OnceLock;
+use symphonia::core::{
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
+    codecs::{
+        CodecDescriptor, CodecParameters, CodecRegistry, Decoder, DecoderOptions, FinalizeResult,
+        CODEC_TYPE_OPUS,
+    },
+    errors::{Error, Result},
+    formats::Packet,
+    support_codec,
+};
+
+/// Rate Opus always decodes at, whatever the source was recorded at
+const SAMPLE_RATE: u32 = 48_000;
+/// Longest packet Opus allows, 120ms
+const MAX_FRAMES: usize = 5760;
+
+/// Opus decoder for symphonia's codec registry, part of the default one with the `opus` feature
+/// The pre-skip of the stream's OpusHead is dropped from the start and its output gain applied,
+/// while the end gets trimmed to the container's granule position like other codecs
+pub struct OpusDecoder {
+    decoder: OpusMultistreamDecoder,
+    params: CodecParameters,
+    pre_skip: u64,
+    gain: f32,
+    pcm: Vec<f32>,
+    buffer: AudioBuffer<f32>,
+}
+
+/// Stream layout of an OpusHead, as the multistream decoder takes it
+struct Head {
+    channels: usize,
+    streams: usize,
+    coupled: usize,
+    mapping: Vec<u8>,
+    pre_skip: u16,
+    /// Output gain in dB, Q7.8 fixed point
+    gain: i16,
+}
+
+impl Head {
+    fn parse(head: &[u8]) -> Option<Self> {
+        if head.len() < 19 || !head.starts_with(b"OpusHead") {
+            return None;
+        }
+        let channels = head[9] as usize;
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]);
+        let gain = i16::from_le_bytes([head[16], head[17]]);
+
+        let (streams, coupled, mapping) = match head[18] {
+            // A single stream, coupled if stereo
+            0 if (1..=2).contains(&channels) => (1, channels - 1, (0..channels as u8).collect()),
+            0 => return None,
+            _ => {
+                let mapping = head.get(21..21 + channels)?;
+                (head[19] as usize, head[20] as usize, mapping.to_vec())
+            }
+        };
+        Some(Self {
+            channels,
+            streams,
+            coupled,
+            mapping,
+            pre_skip,
+            gain,
+        })
+    }
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+        let head = params
+            .extra_data
+            .as_deref()
+            .and_then(Head::parse)
+            .ok_or(Error::Unsupported("opus: missing or malformed OpusHead"))?;
+        let channels = params
+            .channels
+            .filter(|c| c.count() == head.channels)
+            .ok_or(Error::Unsupported(
+                "opus: channels don't match the OpusHead",
+            ))?;
+
+        let decoder = OpusMultistreamDecoder::new(
+            SAMPLE_RATE,
+            head.channels,
+            head.streams,
+            head.coupled,
+            &head.mapping,
+        )
+        .map_err(|_| Error::Unsupported("opus: unsupported channel mapping"))?;
+
+        Ok(Self {
+            decoder,
+            params: params.clone(),
+            pre_skip: head.pre_skip as u64,
+            gain: 10f32.powf(head.gain as f32 / (20. * 256.)),
+            pcm: vec![0.; MAX_FRAMES * head.channels],
+            buffer: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(SAMPLE_RATE, channels)),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+    }
+
+    fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buffer.clear();
+        let frames = self
+            .decoder
+            .decode_float(packet.buf(), &mut self.pcm, false)
+            .map_err(|_| Error::DecodeError("opus: invalid packet"))?;
+
+        self.buffer.render_reserved(Some(frames));
+        let channels = self.buffer.spec().channels.count();
+        for (c, plane) in self.buffer.planes_mut().planes().iter_mut().enumerate() {
+            for (out, frame) in plane.iter_mut().zip(self.pcm.chunks_exact(channels)) {
+                *out = frame[c] * self.gain;
+            }
+        }
+
+        // Timestamps count from the start of the stream, pre-skip included
+        let skip = self.pre_skip.saturating_sub(packet.ts()).min(frames as u64) as usize;
+        let start = skip.max(packet.trim_start() as usize).min(frames);
+        let end = (packet.trim_end() as usize).min(frames - start);
+        self.buffer.trim(start, end);
+        Ok(self.buffer.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buffer.as_audio_buffer_ref()
+    }
+}
+
+/// Codec registry of symphonia's enabled codecs plus Opus
+pub(crate) fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut codecs = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut codecs);
+        codecs.register_all::<OpusDecoder>();
+        codecs
+    })
+}
@@ -0,0 +1,555 @@
+use crate::{
+    bitrate, channels, default_track, frames_to_duration, n_frames, pre_skip, probe_format,
+    sample_rate,
+    wav::{self, WavChunks},
+    Channels, DecodeError, DecodeOptions, Metadata, Picture,
+};
+use std::{
+    fmt::Write,
+    io::{Cursor, Read, SeekFrom},
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+use symphonia::core::{
+    audio::Layout,
+    errors::Error as SymphoniaError,
+    io::{MediaSource, ReadBytes, ReadOnlySource},
+    units::TimeBase,
+};
+
+/// Description of a source, gathered without decoding its audio
+#[derive(Clone, Debug)]
+pub struct ProbeReport {
+    /// Container format, e.g. "ogg", if it could be recognized
+    pub format: Option<&'static str>,
+    /// Short name of the default track's codec, e.g. "vorbis"
+    pub codec: Option<&'static str>,
+    pub channels: Channels,
+    pub sample_rate: u32,
+    /// Number of frames in the default track
+    pub frames: u64,
+    pub duration: Duration,
+    /// Average bitrate of the whole source, container overhead included
+    /// None if the source length is unknown
+    pub container_bitrate: Option<u64>,
+    /// Average bitrate of the audio stream, computed from its packet sizes
+    pub stream_bitrate: u64,
+    /// Tags and chapters read from the source
+    pub metadata: Metadata,
+}
+
+impl ProbeReport {
+    /// Serializes the report as a JSON object, like `ffprobe -print_format json`
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+
+        let _ = write!(json, "\"format\":{}", optional_string(self.format));
+        let _ = write!(json, ",\"codec\":{}", optional_string(self.codec));
+        let _ = write!(json, ",\"channels\":{}", self.channels as u8);
+        let _ = write!(json, ",\"sample_rate\":{}", self.sample_rate);
+        let _ = write!(json, ",\"frames\":{}", self.frames);
+        let _ = write!(json, ",\"duration\":{}", self.duration.as_secs_f64());
+        let _ = write!(
+            json,
+            ",\"container_bitrate\":{}",
+            self.container_bitrate
+                .map_or("null".to_string(), |b| b.to_string())
+        );
+        let _ = write!(json, ",\"stream_bitrate\":{}", self.stream_bitrate);
+
+        json.push_str(",\"tags\":[");
+        for (i, tag) in self.metadata.tags.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"key\":{},\"value\":{}}}",
+                string(&tag.key),
+                string(&tag.value)
+            );
+        }
+
+        json.push_str("],\"chapters\":[");
+        for (i, chapter) in self.metadata.chapters.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start\":{},\"name\":{},\"url\":{}}}",
+                chapter.start.as_secs_f64(),
+                optional_string(chapter.name.as_deref()),
+                optional_string(chapter.url.as_deref())
+            );
+        }
+
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Quotes and escapes a JSON string
+fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn optional_string(s: Option<&str>) -> String {
+    s.map_or("null".to_string(), string)
+}
+
+/// Describes the format, codec and properties of an audio file in source
+/// Packets are read to measure the stream, but not decoded
+pub fn probe(source: impl MediaSource + 'static) -> Result<ProbeReport, DecodeError> {
+    probe_with(source, &Default::default())
+}
+
+/// Describes the format, codec and properties of an audio file in source using given options
+/// Packets are read to measure the stream, but not decoded
+pub fn probe_with(
+    mut source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<ProbeReport, DecodeError> {
+    let format = if source.is_seekable() {
+        let format = sniff_format(&mut source)?;
+        source.seek(SeekFrom::Start(0))?;
+        format
+    } else {
+        None
+    };
+    let chunks = WavChunks::read(&mut source)?;
+
+    let len = source.byte_len();
+    let mut probed = probe_format(Box::new(source), options)?;
+    let track = options.select_track(&*probed.format)?;
+    let id = track.id;
+    let params = track.codec_params.clone();
+
+    let mut stream_len = 0;
+    let mut counted_length = 0;
+    while let Ok(packet) = probed.format.next_packet() {
+        if packet.track_id() == id {
+            stream_len += packet.buf().len() as u64;
+            counted_length += packet.dur;
+        }
+    }
+
+    let sample_rate = sample_rate(&params)?;
+    let length = params
+        .n_frames
+        .unwrap_or(counted_length)
+        .saturating_sub(pre_skip(&params));
+    let frames = to_frames(length, params.time_base, sample_rate);
+    let duration = frames_to_duration(frames, sample_rate);
+    let seconds = duration.as_secs_f64();
+
+    let mut metadata = Metadata::read(&mut probed);
+    chunks.apply(&mut metadata);
+
+    Ok(ProbeReport {
+        format,
+        codec: options
+            .codecs()
+            .get_codec(params.codec)
+            .map(|d| d.short_name),
+        channels: channels(&params, options.downmix)?,
+        sample_rate,
+        frames,
+        duration,
+        container_bitrate: len.map(|len| bitrate(len, seconds)),
+        stream_bitrate: bitrate(stream_len, seconds),
+        metadata,
+    })
+}
+
+/// Audio stream of a source, e.g. one of the languages of a film's soundtrack
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioStream {
+    /// Id picking the stream to decode with `DecodeOptions::track`
+    pub id: u32,
+    /// Short name of the codec, e.g. "aac", None if no enabled decoder supports it
+    pub codec: Option<&'static str>,
+    /// Language as recorded by the container, e.g. "eng", which Matroska does
+    pub language: Option<String>,
+    /// Number of channels, if the container states it
+    pub channels: Option<usize>,
+    pub sample_rate: u32,
+    /// Number of frames, if the container states it
+    pub frames: Option<u64>,
+    /// Whether this is the stream decoded unless another one is picked
+    pub default: bool,
+}
+
+/// Lists the audio streams in source, e.g. an MP4 or Matroska video, from its headers alone
+/// Each can then be decoded with `DecodeOptions::track`, or transcoded on its own by passing such
+/// options to `TranscodeOptions::decode_options`
+pub fn list_streams(source: impl MediaSource + 'static) -> Result<Vec<AudioStream>, DecodeError> {
+    list_streams_with(source, &Default::default())
+}
+
+/// Lists the audio streams in source using given options, like `list_streams`
+pub fn list_streams_with(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<Vec<AudioStream>, DecodeError> {
+    let probed = probe_format(Box::new(source), options)?;
+    let default = default_track(&*probed.format).map(|t| t.id);
+
+    Ok(probed
+        .format
+        .tracks()
+        .iter()
+        // Video and subtitle tracks have no sample rate
+        .filter_map(|track| {
+            let params = &track.codec_params;
+            let sample_rate = params.sample_rate?;
+            Some(AudioStream {
+                id: track.id,
+                codec: options
+                    .codecs()
+                    .get_codec(params.codec)
+                    .map(|d| d.short_name),
+                language: track.language.clone(),
+                channels: params
+                    .channels
+                    .or_else(|| params.channel_layout.map(Layout::into_channels))
+                    .map(|c| c.count()),
+                sample_rate,
+                frames: n_frames(params)
+                    .map(|length| to_frames(length, params.time_base, sample_rate)),
+                default: Some(track.id) == default,
+            })
+        })
+        .collect())
+}
+
+/// Packets `probe_duration` reads, when the headers don't state the length, before estimating
+/// the rest from their average size
+const MAX_SCANNED_PACKETS: usize = 4096;
+
+/// Returns the duration of an audio file in source, for e.g. library scanners
+/// Uses the length stated in the headers where there is one, otherwise packets are read without
+/// decoding them, and past the first few thousand the remaining duration is estimated from
+/// their average size and the source's length
+pub fn probe_duration(source: impl MediaSource + 'static) -> Result<Duration, DecodeError> {
+    probe_duration_with(source, &Default::default())
+}
+
+/// Returns the duration of an audio file in source using given options, like `probe_duration`
+pub fn probe_duration_with(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<Duration, DecodeError> {
+    let len = source.byte_len();
+    let mut probed = probe_format(Box::new(source), options)?;
+    let track = options.select_track(&*probed.format)?;
+    let id = track.id;
+    let params = track.codec_params.clone();
+    let sample_rate = sample_rate(&params)?;
+
+    let length = match params.n_frames {
+        Some(length) => length,
+        None => {
+            let (mut stream_len, mut counted_length) = (0, 0);
+            let mut scanned = 0;
+            let mut ended = true;
+            while let Ok(packet) = probed.format.next_packet() {
+                if packet.track_id() != id {
+                    continue;
+                }
+                stream_len += packet.buf().len() as u64;
+                counted_length += packet.dur;
+                scanned += 1;
+                if scanned == MAX_SCANNED_PACKETS && len.is_some() {
+                    ended = false;
+                    break;
+                }
+            }
+
+            match len {
+                // Only the bytes after the scanned packets are estimated, leaving out the headers
+                // and tags before them, e.g. ID3 artwork
+                Some(len) if !ended && stream_len > 0 => {
+                    let left = len.saturating_sub(probed.format.into_inner().pos());
+                    counted_length
+                        + (counted_length as f64 * left as f64 / stream_len as f64) as u64
+                }
+                _ => counted_length,
+            }
+        }
+    }
+    .saturating_sub(pre_skip(&params));
+
+    let frames = to_frames(length, params.time_base, sample_rate);
+    Ok(frames_to_duration(frames, sample_rate))
+}
+
+/// Converts a track length to frames
+/// Some containers, e.g. Matroska, count in their own time base rather than in frames
+fn to_frames(length: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+    time_base.map_or(length, |tb| {
+        (length as u128 * tb.numer as u128 * sample_rate as u128 / tb.denom.max(1) as u128)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    })
+}
+
+/// Picture type of front covers, as numbered by ID3v2 and FLAC
+const FRONT_COVER: u32 = 3;
+
+/// Returns the embedded artwork of an audio file in source, preferring the front cover, or None
+/// if it has none
+/// Only the headers and tags get read, no audio packets, so this is fast even for large files
+pub fn extract_artwork(source: impl MediaSource + 'static) -> Result<Option<Picture>, DecodeError> {
+    extract_artwork_with(source, &Default::default())
+}
+
+/// Returns the embedded artwork of an audio file in source using given options, like
+/// `extract_artwork`
+pub fn extract_artwork_with(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<Option<Picture>, DecodeError> {
+    let mut probed = probe_format(Box::new(source), options)?;
+    let mut pictures = Metadata::read(&mut probed).pictures;
+
+    let i = pictures
+        .iter()
+        .position(|p| p.kind == FRONT_COVER)
+        .unwrap_or(0);
+    Ok((i < pictures.len()).then(|| pictures.swap_remove(i)))
+}
+
+/// Highest sample rate `try_probe` accepts
+const MAX_SAMPLE_RATE: u32 = 768_000;
+
+/// Properties of a source's default track, as validated by `try_probe`
+#[derive(Clone, Debug)]
+pub struct FormatInfo {
+    /// Container format, e.g. "ogg", if it could be recognized
+    pub format: Option<&'static str>,
+    /// Short name of the default track's codec, e.g. "vorbis"
+    pub codec: &'static str,
+    pub channels: Channels,
+    pub sample_rate: u32,
+    /// Number of frames in the default track, if the container states it
+    pub frames: Option<u64>,
+    pub duration: Option<Duration>,
+}
+
+/// Start of the source read ahead of symphonia by `try_probe` to validate, where WAV and AIFF
+/// files state their format
+const HEAD_LEN: u64 = 64 * 1024;
+
+/// Describes an audio file in source from its headers alone, meant for untrusted input
+/// Unlike `probe`, no packets get read and sources a decode would fail on are rejected: unknown
+/// codecs, implausible sample rates or channel layouts, and headers demuxers would panic on,
+/// which get checked before they read them. A panic inside a demuxer otherwise becomes
+/// `DecodeError::Malformed`
+pub fn try_probe(source: impl MediaSource + 'static) -> Result<FormatInfo, DecodeError> {
+    try_probe_with(source, &Default::default())
+}
+
+/// Describes an audio file in source from its headers alone using given options, like
+/// `try_probe`
+/// The stated length is checked against the options' limits, counting memory as `f32` samples
+pub fn try_probe_with(
+    source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<FormatInfo, DecodeError> {
+    let options = options.clone();
+    panic::catch_unwind(AssertUnwindSafe(move || strict_probe(source, &options)))
+        .unwrap_or(Err(DecodeError::Malformed))
+}
+
+fn strict_probe(
+    mut source: impl MediaSource + 'static,
+    options: &DecodeOptions,
+) -> Result<FormatInfo, DecodeError> {
+    let mut head = Vec::new();
+    (&mut source).take(HEAD_LEN).read_to_end(&mut head)?;
+    wav::check_header(&head)?;
+    let format = sniff_format(&mut &head[..])?;
+
+    // Sources that can't seek back get the head replayed ahead of the rest
+    let source: Box<dyn MediaSource> = if source.is_seekable() {
+        source.seek(SeekFrom::Start(0))?;
+        Box::new(source)
+    } else {
+        Box::new(ReadOnlySource::new(Cursor::new(head).chain(source)))
+    };
+    let probed = probe_format(source, options)?;
+    let params = &options.select_track(&*probed.format)?.codec_params;
+
+    let codec = options
+        .codecs()
+        .get_codec(params.codec)
+        .ok_or(SymphoniaError::Unsupported("codec"))?
+        .short_name;
+    let sample_rate = sample_rate(params)?;
+    if !(1..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        return Err(DecodeError::UnsupportedSampleRate(sample_rate));
+    }
+    let channels = channels(params, options.downmix)?;
+
+    let frames = n_frames(params).map(|length| to_frames(length, params.time_base, sample_rate));
+    if let Some(frames) = frames {
+        options.check_limits::<f32>(frames, channels)?;
+    }
+
+    Ok(FormatInfo {
+        format,
+        codec,
+        channels,
+        sample_rate,
+        frames,
+        duration: frames.map(|frames| frames_to_duration(frames, sample_rate)),
+    })
+}
+
+/// Recognizes the container format from the magic bytes at the start of the source
+fn sniff_format(source: &mut impl Read) -> Result<Option<&'static str>, DecodeError> {
+    let mut head = [0; 12];
+    let mut read = 0;
+    while read < head.len() {
+        match source.read(&mut head[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    let head = &head[..read];
+
+    Ok(match head {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', ..] => Some("aiff"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'c', b'a', b'f', b'f', ..] => Some("caf"),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => Some("matroska"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("mp4"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        // ADTS headers have the MPEG layer bits cleared
+        [0xff, b, ..] if b & 0xf6 == 0xf0 => Some("adts"),
+        [0xff, b, ..] if b & 0xe0 == 0xe0 => Some("mp3"),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "wav", feature = "pcm"))]
+    fn tone_wav() -> Vec<u8> {
+        let tone = crate::generate::sine(440., Duration::from_millis(100), 8000, Channels::Stereo);
+        crate::encode_wav(&tone, &Default::default()).unwrap()
+    }
+
+    #[test]
+    #[cfg(all(feature = "wav", feature = "pcm", feature = "adpcm"))]
+    fn headers_symphonia_panics_on() {
+        let mut wav = tone_wav();
+        let silence = crate::generate::silence(Duration::from_millis(100), 8000, Channels::Stereo);
+        let mut adpcm = crate::encode_ima_adpcm(&silence).unwrap();
+        assert!(strict_probe(Cursor::new(wav.clone()), &Default::default()).is_ok());
+        assert!(strict_probe(Cursor::new(adpcm.clone()), &Default::default()).is_ok());
+
+        // Sample rate, and block length shorter than the IMA ADPCM block headers
+        wav[24..28].fill(0);
+        adpcm[32..34].copy_from_slice(&4u16.to_le_bytes());
+        let seekable = strict_probe(Cursor::new(wav.clone()), &Default::default());
+        assert!(matches!(
+            seekable,
+            Err(DecodeError::UnsupportedSampleRate(0))
+        ));
+        let unseekable = ReadOnlySource::new(Cursor::new(wav));
+        let unseekable = strict_probe(unseekable, &Default::default());
+        assert!(matches!(
+            unseekable,
+            Err(DecodeError::UnsupportedSampleRate(0))
+        ));
+        let adpcm = strict_probe(Cursor::new(adpcm), &Default::default());
+        assert!(matches!(adpcm, Err(DecodeError::Malformed)));
+    }
+
+    #[test]
+    #[cfg(all(feature = "wav", feature = "pcm"))]
+    fn unseekable_sources_get_probed_whole() {
+        let source = ReadOnlySource::new(Cursor::new(tone_wav()));
+        let info = strict_probe(source, &Default::default()).unwrap();
+        let properties = (info.format, info.sample_rate, info.frames);
+        assert_eq!(properties, (Some("wav"), 8000, Some(800)));
+    }
+
+    #[test]
+    fn zero_time_base_denominator() {
+        let tb = TimeBase { numer: 1, denom: 0 };
+        assert_eq!(to_frames(10, Some(tb), 8000), 80_000);
+        let tb = TimeBase {
+            numer: 1,
+            denom: 1000,
+        };
+        assert_eq!(to_frames(u64::MAX, Some(tb), 768_000), u64::MAX);
+    }
+
+    /// Source of known length that can't seek, as served over HTTP without range requests
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    struct Unseekable(Cursor<Vec<u8>>);
+
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    impl Read for Unseekable {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    impl std::io::Seek for Unseekable {
+        fn seek(&mut self, _: SeekFrom) -> std::io::Result<u64> {
+            Err(std::io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    impl MediaSource for Unseekable {
+        fn is_seekable(&self) -> bool {
+            false
+        }
+
+        fn byte_len(&self) -> Option<u64> {
+            Some(self.0.get_ref().len() as u64)
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "ogg", feature = "vorbis"))]
+    fn estimate_leaves_out_tags() {
+        let tone = crate::generate::sine(440., Duration::from_secs(240), 8000, Channels::Mono);
+        // Stands in for embedded artwork, larger than the audio
+        let tags = [crate::Tag {
+            key: "COMMENT".into(),
+            value: "x".repeat(1 << 20),
+        }];
+        let ogg = crate::sink::encode_vorbis_tagged(&tone, 16_000, &tags, Vec::new()).unwrap();
+
+        let duration = probe_duration(Unseekable(Cursor::new(ogg))).unwrap();
+        let error = duration.as_secs_f64() - 240.;
+        assert!(error.abs() < 5., "estimated {duration:?}");
+    }
+}
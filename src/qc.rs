@@ -0,0 +1,148 @@
+//! Quality checks for rejecting broken audio before processing it further
+
+use crate::{frames_to_duration, SampleBuffer};
+use std::time::Duration;
+
+/// Samples at or above this level count as full scale
+const CLIP_LEVEL: f32 = 0.999;
+/// Consecutive full scale samples in a channel needed to count as clipping
+const MIN_CLIP_RUN: usize = 3;
+/// Frames with every sample below this level count as digital silence, one 16 bit step
+const SILENCE_LEVEL: f32 = 1. / 32_768.;
+
+/// Measurements taken by `analyze`
+#[derive(Clone, Debug, PartialEq)]
+pub struct QcReport {
+    /// Number of runs of consecutive full scale samples
+    pub clip_events: usize,
+    /// Number of samples in those runs
+    pub clipped_samples: usize,
+    /// Mean of each channel
+    pub dc_offset: Vec<f32>,
+    /// Longest stretch of digital silence
+    pub longest_silence: Duration,
+    /// Difference in dB between the loudest and quietest channel's RMS level
+    pub channel_imbalance_db: f32,
+}
+
+/// Limits a report must stay within to pass
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QcThresholds {
+    pub max_clip_events: usize,
+    pub max_dc_offset: f32,
+    pub max_silence: Duration,
+    pub max_channel_imbalance_db: f32,
+}
+
+impl Default for QcThresholds {
+    fn default() -> Self {
+        Self {
+            max_clip_events: 0,
+            max_dc_offset: 0.01,
+            max_silence: Duration::from_secs(10),
+            max_channel_imbalance_db: 6.,
+        }
+    }
+}
+
+/// Problem found by a check
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QcIssue {
+    /// Number of clip events
+    Clipping(usize),
+    /// Channel index and its offset
+    DcOffset(usize, f32),
+    /// Longest silence
+    Silence(Duration),
+    /// Imbalance in dB
+    ChannelImbalance(f32),
+}
+
+impl QcReport {
+    /// Returns every limit the report exceeds
+    pub fn issues(&self, thresholds: &QcThresholds) -> Vec<QcIssue> {
+        let mut issues = Vec::new();
+
+        if self.clip_events > thresholds.max_clip_events {
+            issues.push(QcIssue::Clipping(self.clip_events));
+        }
+        for (c, &offset) in self.dc_offset.iter().enumerate() {
+            if offset.abs() > thresholds.max_dc_offset {
+                issues.push(QcIssue::DcOffset(c, offset));
+            }
+        }
+        if self.longest_silence > thresholds.max_silence {
+            issues.push(QcIssue::Silence(self.longest_silence));
+        }
+        if self.channel_imbalance_db > thresholds.max_channel_imbalance_db {
+            issues.push(QcIssue::ChannelImbalance(self.channel_imbalance_db));
+        }
+
+        issues
+    }
+
+    /// Returns whether the report stays within all limits
+    pub fn passes(&self, thresholds: &QcThresholds) -> bool {
+        self.issues(thresholds).is_empty()
+    }
+}
+
+/// Measures clipping, DC offset, silence and channel imbalance of a buffer
+pub fn analyze(buffer: &SampleBuffer<f32>) -> QcReport {
+    let channels = buffer.channels as usize;
+    let frames = buffer.buffer.len() / channels;
+
+    let mut clip_events = 0;
+    let mut clipped_samples = 0;
+    let mut runs = vec![0; channels];
+    let mut sums = vec![0f64; channels];
+    let mut squares = vec![0f64; channels];
+    let mut silence = 0;
+    let mut longest_silence = 0;
+
+    for frame in buffer.buffer.chunks_exact(channels) {
+        for (c, &sample) in frame.iter().enumerate() {
+            sums[c] += sample as f64;
+            squares[c] += sample as f64 * sample as f64;
+
+            if sample.abs() >= CLIP_LEVEL {
+                runs[c] += 1;
+                if runs[c] == MIN_CLIP_RUN {
+                    clip_events += 1;
+                    clipped_samples += MIN_CLIP_RUN;
+                } else if runs[c] > MIN_CLIP_RUN {
+                    clipped_samples += 1;
+                }
+            } else {
+                runs[c] = 0;
+            }
+        }
+
+        if frame.iter().all(|s| s.abs() < SILENCE_LEVEL) {
+            silence += 1;
+            longest_silence = longest_silence.max(silence);
+        } else {
+            silence = 0;
+        }
+    }
+
+    let frames_f = frames.max(1) as f64;
+    let dc_offset = sums.iter().map(|&s| (s / frames_f) as f32).collect();
+
+    let rms: Vec<f64> = squares.iter().map(|&s| (s / frames_f).sqrt()).collect();
+    let loudest = rms.iter().copied().fold(0., f64::max);
+    let quietest = rms.iter().copied().fold(f64::INFINITY, f64::min);
+    let channel_imbalance_db = if loudest > 0. {
+        (20. * (loudest / quietest.max(f64::MIN_POSITIVE)).log10()) as f32
+    } else {
+        0.
+    };
+
+    QcReport {
+        clip_events,
+        clipped_samples,
+        dc_offset,
+        longest_silence: frames_to_duration(longest_silence, buffer.sample_rate),
+        channel_imbalance_db,
+    }
+}
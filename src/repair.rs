@@ -0,0 +1,265 @@
+//! Salvaging damaged Ogg streams, which the normal decode path rejects or cuts short: pages are
+//! found again after garbage, pages cut short and the packets they break are dropped, and the
+//! rest gets written out as a fresh stream with consistent page numbers and checksums
+
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+const CAPTURE: &[u8; 4] = b"OggS";
+/// Size of a page header without the segment table
+const HEADER_LEN: usize = 27;
+/// Header flag of pages starting with the rest of the previous page's last packet
+const CONTINUED: u8 = 0x01;
+
+/// Ogg's CRC-32: polynomial 0x04c11db7, unreflected, starting from 0
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+/// What `repair_ogg` found and fixed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Pages whose packets were carried over
+    pub pages_kept: usize,
+    /// Kept pages that had a wrong checksum, damaged in place rather than cut short
+    pub checksums_fixed: usize,
+    /// Pages dropped for being cut short, by the end of the file or by another page
+    pub pages_dropped: usize,
+    /// Packets dropped for having a part on a missing page
+    pub packets_dropped: usize,
+    /// Bytes skipped for not belonging to any page
+    pub bytes_skipped: usize,
+}
+
+/// Page of the input, with its segment table and body
+struct Page<'a> {
+    flags: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    segments: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> Page<'a> {
+    /// Parses the page at the start of data, None if it isn't one or the data ends within it
+    /// Also returns the page's length and whether its checksum matches
+    fn parse(data: &'a [u8]) -> Option<(Self, usize, bool)> {
+        if data.len() < HEADER_LEN || &data[..4] != CAPTURE || data[4] != 0 {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+        let body_start = HEADER_LEN + data[26] as usize;
+        let segments = data.get(HEADER_LEN..body_start)?;
+        let len = body_start + segments.iter().map(|&s| s as usize).sum::<usize>();
+        let body = data.get(body_start..len)?;
+
+        // The checksum is computed with its own field zeroed
+        let crc = crc_update(crc_update(0, &data[..22]), &[0; 4]);
+        let crc = crc_update(crc_update(crc, &data[26..body_start]), body);
+
+        let page = Self {
+            flags: data[5],
+            granule: u64::from_le_bytes(data[6..14].try_into().unwrap()),
+            serial: u32_at(14),
+            sequence: u32_at(18),
+            segments,
+            body,
+        };
+        Some((page, len, crc == u32_at(22)))
+    }
+}
+
+/// State of a logical stream while repairing
+#[derive(Default)]
+struct Stream {
+    /// Start of a packet continuing on the next page
+    partial: Option<Vec<u8>>,
+    /// Whether the stream is in the middle of a packet whose start went missing
+    orphaned: bool,
+    sequence: Option<u32>,
+    /// The last packet is held back until it's known to end the stream
+    pending: Option<(Vec<u8>, PacketWriteEndInfo, u64)>,
+}
+
+/// Reads a damaged Ogg stream from input and writes what could be salvaged into output
+/// Pages with a wrong checksum are kept if they are intact otherwise, since Vorbis and Opus
+/// decoders can get through damaged packets, while pages cut short, e.g. by a truncated
+/// download or an interrupted write, are dropped along with packets left incomplete
+/// Fails with `io::ErrorKind::InvalidData` if input has no Ogg pages at all
+pub fn repair_ogg(mut input: impl Read, output: impl Write) -> io::Result<RepairReport> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    let mut report = RepairReport::default();
+    let mut streams: HashMap<u32, Stream> = HashMap::new();
+    // Serials in the order their streams began, so that they end in the same order
+    let mut serials = Vec::new();
+    let mut writer = PacketWriter::new(output);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let Some(start) = find_capture(&data[pos..]) else {
+            report.bytes_skipped += data.len() - pos;
+            break;
+        };
+        report.bytes_skipped += start;
+        pos += start;
+
+        let (page, len, valid) = match Page::parse(&data[pos..]) {
+            Some(parsed) => parsed,
+            None => {
+                // Either the file ends within the page, or this is a stray capture pattern
+                match find_capture(&data[pos + 1..]) {
+                    Some(next) if data.get(pos + 4) == Some(&0) => {
+                        report.pages_dropped += 1;
+                        pos += 1 + next;
+                    }
+                    Some(next) => {
+                        report.bytes_skipped += 1 + next;
+                        pos += 1 + next;
+                    }
+                    None => {
+                        report.pages_dropped += 1;
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+
+        // A page cut short by another is read as running into the next, so its checksum is
+        // wrong and another page starts within it
+        if !valid {
+            if let Some(next) = find_capture(&data[pos + 1..pos + len]) {
+                report.pages_dropped += 1;
+                pos += 1 + next;
+                continue;
+            }
+            report.checksums_fixed += 1;
+        }
+        pos += len;
+        report.pages_kept += 1;
+
+        let stream = streams.entry(page.serial).or_insert_with(|| {
+            serials.push(page.serial);
+            Stream::default()
+        });
+        copy_packets(&page, stream, &mut writer, &mut report)?;
+    }
+
+    if report.pages_kept == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no ogg pages found",
+        ));
+    }
+
+    for serial in serials {
+        let stream = streams.get_mut(&serial).expect("every serial has a stream");
+        if stream.partial.take().is_some() {
+            report.packets_dropped += 1;
+        }
+        if let Some((packet, _, granule)) = stream.pending.take() {
+            writer.write_packet(packet, serial, PacketWriteEndInfo::EndStream, granule)?;
+        }
+    }
+    writer.into_inner().flush()?;
+    Ok(report)
+}
+
+/// Returns where the next capture pattern starts
+fn find_capture(data: &[u8]) -> Option<usize> {
+    data.windows(CAPTURE.len()).position(|w| w == CAPTURE)
+}
+
+/// Splits a page into packets, joining them with parts from earlier pages, and writes the
+/// complete ones, each page's last one ending an output page with its granule position
+fn copy_packets<W: Write>(
+    page: &Page,
+    stream: &mut Stream,
+    writer: &mut PacketWriter<'static, W>,
+    report: &mut RepairReport,
+) -> io::Result<()> {
+    // A gap in the page numbers means pages of this stream went missing
+    let follows = stream
+        .sequence
+        .is_none_or(|sequence| page.sequence == sequence.wrapping_add(1));
+    stream.sequence = Some(page.sequence);
+
+    let continued = page.flags & CONTINUED != 0;
+    let mut packet = match stream.partial.take() {
+        Some(partial) if continued && follows => Some(partial),
+        Some(_) => {
+            report.packets_dropped += 1;
+            None
+        }
+        None => None,
+    };
+    // A packet whose start is missing only counts once, however many pages it spans
+    let was_orphaned = stream.orphaned && follows;
+    stream.orphaned = continued && packet.is_none();
+    if stream.orphaned && !was_orphaned {
+        report.packets_dropped += 1;
+    }
+
+    let last_end = page.segments.iter().rposition(|&s| s < 255);
+    let mut offset = 0;
+    for (i, &segment) in page.segments.iter().enumerate() {
+        let data = &page.body[offset..offset + segment as usize];
+        offset += segment as usize;
+        if !stream.orphaned {
+            packet.get_or_insert_with(Vec::new).extend_from_slice(data);
+        }
+        if segment == 255 {
+            continue;
+        }
+
+        // Packets left without their start are skipped up to their end
+        if std::mem::take(&mut stream.orphaned) {
+            continue;
+        }
+        let complete = packet.take().unwrap_or_default();
+        let end = if Some(i) == last_end {
+            PacketWriteEndInfo::EndPage
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        if let Some((previous, previous_end, granule)) =
+            stream.pending.replace((complete, end, page.granule))
+        {
+            writer.write_packet(previous, page.serial, previous_end, granule)?;
+        }
+    }
+
+    stream.partial = packet;
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use crate::{Channels, SampleBuffer};
+use std::f64::consts::PI;
+
+/// Resampling quality presets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// Short kernel, suitable for previews and realtime use
+    Fast,
+    /// Balance between speed and quality
+    #[default]
+    Medium,
+    /// Long kernel with a steep cutoff, for archival and mastering use
+    Best,
+}
+
+/// Window function applied to the sinc kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Hann,
+    Blackman,
+    BlackmanHarris,
+}
+
+impl Window {
+    /// Evaluates the window at `x` in range -1..=1
+    fn at(self, x: f64) -> f64 {
+        // Shift from -1..=1 to 0..=1
+        let x = (x + 1.) / 2.;
+        let a = 2. * PI * x;
+
+        match self {
+            Window::Hann => 0.5 - 0.5 * a.cos(),
+            Window::Blackman => 0.42 - 0.5 * a.cos() + 0.08 * (2. * a).cos(),
+            Window::BlackmanHarris => {
+                0.35875 - 0.48829 * a.cos() + 0.14128 * (2. * a).cos() - 0.01168 * (3. * a).cos()
+            }
+        }
+    }
+}
+
+/// Parameters of the windowed sinc interpolation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SincParams {
+    /// Number of sinc zero crossings on each side of the kernel
+    pub zero_crossings: usize,
+    /// Cutoff frequency relative to the lower of the two nyquist frequencies
+    pub cutoff: f64,
+    /// Number of kernel table entries per input sample
+    pub oversampling: usize,
+    /// Window applied to the kernel
+    pub window: Window,
+}
+
+impl From<Quality> for SincParams {
+    fn from(quality: Quality) -> Self {
+        match quality {
+            Quality::Fast => Self {
+                zero_crossings: 8,
+                cutoff: 0.9,
+                oversampling: 128,
+                window: Window::Hann,
+            },
+            Quality::Medium => Self {
+                zero_crossings: 32,
+                cutoff: 0.95,
+                oversampling: 256,
+                window: Window::Blackman,
+            },
+            Quality::Best => Self {
+                zero_crossings: 128,
+                cutoff: 0.97,
+                oversampling: 512,
+                window: Window::BlackmanHarris,
+            },
+        }
+    }
+}
+
+impl Default for SincParams {
+    fn default() -> Self {
+        Quality::default().into()
+    }
+}
+
+/// Streaming sinc resampler working on interleaved samples
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channels: usize,
+    step: f64,
+    half_len: usize,
+    oversampling: usize,
+    table: Vec<f64>,
+    history: Vec<f64>,
+    position: f64,
+    delay: usize,
+    input_frames: u64,
+    output_frames: u64,
+}
+
+impl Resampler {
+    /// Creates a resampler converting from one sample rate to another
+    pub fn new(from: u32, to: u32, channels: Channels, params: impl Into<SincParams>) -> Self {
+        let params = params.into();
+        let ratio = to as f64 / from as f64;
+        let oversampling = params.oversampling.max(1);
+
+        // Cutoff in cycles per input sample
+        let cutoff = 0.5 * params.cutoff * ratio.min(1.);
+        let half_len = (params.zero_crossings.max(1) as f64 / (2. * cutoff)).ceil() as usize;
+
+        let table = (0..=half_len * oversampling + 1)
+            .map(|i| {
+                let x = i as f64 / oversampling as f64;
+                if x > half_len as f64 {
+                    return 0.;
+                }
+
+                let sinc = if x == 0. {
+                    1.
+                } else {
+                    (2. * PI * cutoff * x).sin() / (2. * PI * cutoff * x)
+                };
+
+                2. * cutoff * sinc * params.window.at(x / half_len as f64)
+            })
+            .collect();
+
+        let step = 1. / ratio;
+        // Round the delay up to whole output frames, so it can be compensated exactly
+        let delay = (half_len as f64 * ratio).ceil() as usize;
+
+        Self {
+            channels: channels as usize,
+            step,
+            half_len,
+            oversampling,
+            table,
+            history: Vec::new(),
+            position: -(delay as f64 * step),
+            delay,
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    /// Returns the delay introduced by the resampler in output frames
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    /// Resamples a block of interleaved samples
+    /// Returns as many output samples as the buffered input allows
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let input: Vec<f64> = input.iter().map(|&s| s as f64).collect();
+        self.process_f64(&input)
+            .into_iter()
+            .map(|s| s as f32)
+            .collect()
+    }
+
+    /// Resamples a block of interleaved double precision samples
+    pub fn process_f64(&mut self, input: &[f64]) -> Vec<f64> {
+        self.input_frames += (input.len() / self.channels) as u64;
+        self.history.extend_from_slice(input);
+
+        let mut output = Vec::new();
+        self.render(&mut output, u64::MAX);
+        output
+    }
+
+    /// Returns the remaining output, after which the resampler is ready for a new stream
+    pub fn flush(&mut self) -> Vec<f32> {
+        self.flush_f64().into_iter().map(|s| s as f32).collect()
+    }
+
+    /// Returns the remaining output in double precision, like `flush`
+    pub fn flush_f64(&mut self) -> Vec<f64> {
+        let total = self.delay as u64 + (self.input_frames as f64 / self.step).ceil() as u64;
+        let mut output = Vec::new();
+
+        while self.output_frames < total {
+            self.history
+                .resize(self.history.len() + self.half_len * self.channels, 0.);
+            let remaining = total - self.output_frames;
+            self.render(&mut output, remaining);
+        }
+
+        self.reset();
+        output
+    }
+
+    /// Discards all buffered input
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.position = -(self.delay as f64 * self.step);
+        self.input_frames = 0;
+        self.output_frames = 0;
+    }
+
+    fn kernel(&self, x: f64) -> f64 {
+        let index = x.abs() * self.oversampling as f64;
+        let i = index as usize;
+        let frac = index - i as f64;
+
+        self.table[i] * (1. - frac) + self.table[i + 1] * frac
+    }
+
+    fn render(&mut self, output: &mut Vec<f64>, limit: u64) {
+        let frames = self.history.len() / self.channels;
+        let mut rendered = 0;
+
+        // Each output frame needs half_len input frames past its center
+        while rendered < limit
+            && (self.position.floor() as i64 + self.half_len as i64) < frames as i64
+        {
+            let center = self.position.floor() as i64;
+            // Frames before the start of the stream are silence
+            let first = (center - self.half_len as i64 + 1).max(0);
+            let last = center + self.half_len as i64;
+
+            let start = output.len();
+            output.resize(start + self.channels, 0.);
+
+            for j in first..=last {
+                let weight = self.kernel(self.position - j as f64);
+                let j = j as usize;
+                let frame = &self.history[j * self.channels..(j + 1) * self.channels];
+
+                for (out, &sample) in output[start..].iter_mut().zip(frame) {
+                    *out += sample * weight;
+                }
+            }
+
+            self.position += self.step;
+            rendered += 1;
+        }
+
+        self.output_frames += rendered;
+
+        // Drop input frames no longer reachable by the kernel
+        let keep_from = (self.position.floor() as i64 - self.half_len as i64 + 1).max(0) as usize;
+        let keep_from = keep_from.min(frames);
+        self.history.drain(..keep_from * self.channels);
+        self.position -= keep_from as f64;
+    }
+}
+
+/// Largest correction applied to the ratio, far above the drift of real clocks
+const MAX_DRIFT_PPM: f64 = 1000.;
+/// Time the controller takes to correct a fill level error, in seconds
+const RESPONSE_TIME: f64 = 10.;
+/// Integration time of the controller, four times the response time for critical damping
+const INTEGRAL_TIME: f64 = 4. * RESPONSE_TIME;
+/// Time constant of the fill level smoothing, in seconds
+const FILL_SMOOTHING: f64 = 1.;
+
+/// Resampler correcting for clock drift between a capture device and a sink draining at its own
+/// pace, e.g. a network stream, so the buffer between them neither runs dry nor overflows
+/// The ratio gets nudged by a few ppm at a time to keep the sink's buffer at the target fill level
+#[derive(Clone, Debug)]
+pub struct DriftCompensator {
+    resampler: Resampler,
+    nominal_step: f64,
+    sample_rate: u32,
+    target: f64,
+    fill: Option<f64>,
+    integral: f64,
+    correction: f64,
+}
+
+impl DriftCompensator {
+    /// Creates a compensator converting from the device's to the sink's nominal sample rate,
+    /// aiming for `target_frames` frames buffered in the sink
+    pub fn new(
+        from: u32,
+        to: u32,
+        channels: Channels,
+        target_frames: usize,
+        params: impl Into<SincParams>,
+    ) -> Self {
+        let resampler = Resampler::new(from, to, channels, params);
+        Self {
+            nominal_step: resampler.step,
+            resampler,
+            sample_rate: to,
+            target: target_frames.max(1) as f64,
+            fill: None,
+            integral: 0.,
+            correction: 0.,
+        }
+    }
+
+    /// Resamples a block of interleaved samples, given the number of frames currently buffered
+    /// in the sink, e.g. a ring buffer's `available_frames`
+    pub fn process(&mut self, input: &[f32], buffered_frames: usize) -> Vec<f32> {
+        let frames = input.len() / self.resampler.channels;
+        self.update(buffered_frames, frames as f64 / self.nominal_step);
+        self.resampler.process(input)
+    }
+
+    /// Returns the current correction of the ratio in ppm
+    /// Positive values mean the sink runs slow, so fewer frames get produced
+    pub fn drift_ppm(&self) -> f64 {
+        self.correction * 1e6
+    }
+
+    /// Discards all buffered input and the drift estimate
+    pub fn reset(&mut self) {
+        self.resampler.reset();
+        self.resampler.step = self.nominal_step;
+        self.fill = None;
+        self.integral = 0.;
+        self.correction = 0.;
+    }
+
+    /// Updates the correction out of the fill level, `frames` output frames after the last
+    /// update
+    fn update(&mut self, buffered_frames: usize, frames: f64) {
+        let seconds = frames / self.sample_rate.max(1) as f64;
+        // Block sized reads make the fill level jitter, only its trend matters
+        let alpha = 1. - (-seconds / FILL_SMOOTHING).exp();
+        let fill = match self.fill {
+            Some(fill) => fill + alpha * (buffered_frames as f64 - fill),
+            None => buffered_frames as f64,
+        };
+        self.fill = Some(fill);
+
+        // Error in seconds, so the loop behaves the same at every rate and target
+        let error = (fill - self.target) / self.sample_rate.max(1) as f64;
+        let max = MAX_DRIFT_PPM / 1e6;
+        let integral = self.integral + error * seconds / (RESPONSE_TIME * INTEGRAL_TIME);
+        let correction = error / RESPONSE_TIME + integral;
+        // Stop integrating while saturated, so the correction recovers quickly
+        if correction.abs() <= max {
+            self.integral = integral;
+        }
+
+        self.correction = correction.clamp(-max, max);
+        self.resampler.step = self.nominal_step * (1. + self.correction);
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Returns the written frames of the buffer resampled to the given sample rate
+    /// The resampler delay is compensated, so the output stays aligned to the input
+    pub fn resampled(&self, sample_rate: u32, params: impl Into<SincParams>) -> SampleBuffer<f32> {
+        if sample_rate == self.sample_rate {
+            return self.clone();
+        }
+
+        let mut resampler = Resampler::new(self.sample_rate, sample_rate, self.channels, params);
+        let mut samples = resampler.process(&self.buffer[..self.written]);
+        samples.extend(resampler.flush());
+        samples.drain(..(resampler.delay() * self.channels as usize).min(samples.len()));
+
+        SampleBuffer::from_samples(samples, self.channels, sample_rate)
+    }
+}
+
+impl SampleBuffer<f64> {
+    /// Returns the buffer resampled to the given sample rate in double precision, like the
+    /// `f32` version
+    pub fn resampled(&self, sample_rate: u32, params: impl Into<SincParams>) -> SampleBuffer<f64> {
+        if sample_rate == self.sample_rate {
+            return self.clone();
+        }
+
+        let mut resampler = Resampler::new(self.sample_rate, sample_rate, self.channels, params);
+        let mut samples = resampler.process_f64(&self.buffer[..self.written]);
+        samples.extend(resampler.flush_f64());
+        samples.drain(..(resampler.delay() * self.channels as usize).min(samples.len()));
+
+        SampleBuffer::from_samples(samples, self.channels, sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+    use std::time::Duration;
+
+    /// Returns the frequency of a tone from its rising zero crossings, skipping the edges
+    fn frequency(samples: &[f32], sample_rate: u32) -> f64 {
+        let samples = &samples[samples.len() / 10..samples.len() * 9 / 10];
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0. && w[1] >= 0.)
+            .map(|(i, _)| i)
+            .collect();
+        let periods = (crossings.len() - 1) as f64;
+        let span = (crossings[crossings.len() - 1] - crossings[0]) as f64;
+        periods * sample_rate as f64 / span
+    }
+
+    #[test]
+    fn output_length_follows_the_ratio() {
+        for (from, to) in [
+            (44_100, 48_000),
+            (48_000, 44_100),
+            (8000, 48_000),
+            (48_000, 8000),
+        ] {
+            let input = generate::silence(Duration::from_millis(250), from, Channels::Stereo);
+            let output = input.resampled(to, Quality::Fast);
+            assert_eq!(output.sample_rate(), to);
+            assert_eq!(output.written_frames(), to as usize / 4, "{from} -> {to}");
+        }
+    }
+
+    #[test]
+    fn sine_keeps_its_frequency() {
+        for (from, to) in [(44_100, 48_000), (48_000, 22_050)] {
+            let input = generate::sine(1000., Duration::from_secs(1), from, Channels::Mono);
+            let output = input.resampled(to, Quality::Medium);
+            let freq = frequency(output.samples(), to);
+            assert!((freq - 1000.).abs() < 0.5, "{from} -> {to}: {freq} Hz");
+        }
+    }
+
+    #[test]
+    fn unity_gain_at_dc() {
+        let input = SampleBuffer::from_samples(vec![0.5f32; 4800], Channels::Mono, 48_000);
+        for quality in [Quality::Fast, Quality::Medium, Quality::Best] {
+            let output = input.resampled(44_100, quality);
+            let samples = output.samples();
+            // The kernel sees silence around the edges, only the middle is settled
+            for &s in &samples[samples.len() / 4..samples.len() * 3 / 4] {
+                assert!((s - 0.5).abs() < 1e-3, "{quality:?}: {s}");
+            }
+        }
+    }
+}
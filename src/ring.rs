@@ -0,0 +1,262 @@
+use crate::Channels;
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use symphonia::core::sample::Sample;
+
+/// How long a blocked producer sleeps before checking for free space again
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Ring<S> {
+    samples: Box<[UnsafeCell<S>]>,
+    channels: Channels,
+    sample_rate: u32,
+    /// Total number of samples read
+    read: AtomicUsize,
+    /// Total number of samples written
+    written: AtomicUsize,
+    /// Either end has been dropped
+    closed: AtomicBool,
+}
+
+// SAFETY: the producer only writes samples the consumer has released and the consumer only reads
+// samples the producer has published, the counters order the accesses
+unsafe impl<S: Send> Sync for Ring<S> {}
+
+impl<S> Ring<S> {
+    fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+}
+
+/// Creates a single producer, single consumer ring buffer holding up to `capacity` interleaved
+/// frames
+/// Neither end locks or allocates after creation, so the consumer can run on a realtime thread
+pub fn ring_buffer<S: Sample>(
+    capacity: usize,
+    channels: Channels,
+    sample_rate: u32,
+) -> (RingProducer<S>, RingConsumer<S>) {
+    let ring = Arc::new(Ring {
+        samples: (0..capacity.max(1) * channels as usize)
+            .map(|_| UnsafeCell::new(S::MID))
+            .collect(),
+        channels,
+        sample_rate,
+        read: AtomicUsize::new(0),
+        written: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+    });
+
+    (RingProducer { ring: ring.clone() }, RingConsumer { ring })
+}
+
+/// Writing end of a ring buffer
+pub struct RingProducer<S> {
+    ring: Arc<Ring<S>>,
+}
+
+impl<S: Sample> RingProducer<S> {
+    /// Returns number of frames that can be pushed without waiting
+    pub fn free_frames(&self) -> usize {
+        let ring = &self.ring;
+        let used = ring.written.load(Ordering::Relaxed) - ring.read.load(Ordering::Acquire);
+        (ring.capacity() - used) / ring.channels as usize
+    }
+
+    /// Pushes as many whole frames of the interleaved samples as fit
+    /// Returns number of samples pushed
+    pub fn push(&mut self, samples: &[S]) -> usize {
+        let ring = &self.ring;
+        let channels = ring.channels as usize;
+        let len = (samples.len() / channels).min(self.free_frames()) * channels;
+
+        let written = ring.written.load(Ordering::Relaxed);
+        for (i, &sample) in samples[..len].iter().enumerate() {
+            // SAFETY: the slot has been read by the consumer, which won't touch it again until
+            // it's published below
+            unsafe { *ring.samples[(written + i) % ring.capacity()].get() = sample };
+        }
+        ring.written.store(written + len, Ordering::Release);
+
+        len
+    }
+
+    /// Pushes all whole frames of the interleaved samples, waiting for the consumer to make room
+    /// Returns false if the consumer has been dropped
+    pub fn push_all(&mut self, mut samples: &[S]) -> bool {
+        let channels = self.ring.channels as usize;
+        while samples.len() >= channels {
+            if self.is_closed() {
+                return false;
+            }
+
+            let pushed = self.push(samples);
+            samples = &samples[pushed..];
+            if pushed == 0 {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        true
+    }
+
+    /// Returns whether the consumer has been dropped
+    pub fn is_closed(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<S> Drop for RingProducer<S> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Reading end of a ring buffer
+pub struct RingConsumer<S> {
+    ring: Arc<Ring<S>>,
+}
+
+impl<S: Sample> RingConsumer<S> {
+    /// Returns the channel layout of the frames
+    pub fn channels(&self) -> Channels {
+        self.ring.channels
+    }
+
+    /// Returns the sample rate of the frames
+    pub fn sample_rate(&self) -> u32 {
+        self.ring.sample_rate
+    }
+
+    /// Returns number of frames ready to be popped
+    pub fn available_frames(&self) -> usize {
+        let ring = &self.ring;
+        let available = ring.written.load(Ordering::Acquire) - ring.read.load(Ordering::Relaxed);
+        available / ring.channels as usize
+    }
+
+    /// Pops as many whole frames as are available and fit into `output`
+    /// Returns number of samples popped
+    pub fn pop(&mut self, output: &mut [S]) -> usize {
+        let ring = &self.ring;
+        let channels = ring.channels as usize;
+        let len = (output.len() / channels).min(self.available_frames()) * channels;
+
+        let read = ring.read.load(Ordering::Relaxed);
+        for (i, sample) in output[..len].iter_mut().enumerate() {
+            // SAFETY: the slot has been published by the producer, which won't touch it again
+            // until it's released below
+            *sample = unsafe { *ring.samples[(read + i) % ring.capacity()].get() };
+        }
+        ring.read.store(read + len, Ordering::Release);
+
+        len
+    }
+
+    /// Returns whether the producer has been dropped and every frame has been popped
+    pub fn is_finished(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire) && self.available_frames() == 0
+    }
+}
+
+impl<S> Drop for RingConsumer<S> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_and_empty() {
+        let (mut producer, mut consumer) = ring_buffer::<i32>(4, Channels::Stereo, 48_000);
+        let mut output = [0; 16];
+        assert_eq!(consumer.available_frames(), 0);
+        assert_eq!(consumer.pop(&mut output), 0);
+
+        // Only whole frames that fit get pushed
+        assert_eq!(producer.free_frames(), 4);
+        assert_eq!(producer.push(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]), 8);
+        assert_eq!(producer.free_frames(), 0);
+        assert_eq!(producer.push(&[9, 10]), 0);
+        assert_eq!(consumer.available_frames(), 4);
+
+        // Only whole frames that fit get popped
+        assert_eq!(consumer.pop(&mut output[..3]), 2);
+        assert_eq!(output[..2], [1, 2]);
+        assert_eq!(producer.free_frames(), 1);
+        assert_eq!(consumer.pop(&mut output), 6);
+        assert_eq!(output[..6], [3, 4, 5, 6, 7, 8]);
+        assert_eq!(consumer.available_frames(), 0);
+        assert_eq!(producer.free_frames(), 4);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let (mut producer, mut consumer) = ring_buffer::<i32>(3, Channels::Mono, 48_000);
+        let mut output = [0; 2];
+        // Odd sized pushes and pops against the capacity move the ends around every slot
+        for i in 0..100 {
+            assert_eq!(producer.push(&[2 * i, 2 * i + 1]), 2);
+            assert_eq!(consumer.pop(&mut output), 2);
+            assert_eq!(output, [2 * i, 2 * i + 1]);
+        }
+    }
+
+    #[test]
+    fn closing_either_end() {
+        let (mut producer, mut consumer) = ring_buffer::<i32>(4, Channels::Mono, 48_000);
+        producer.push(&[1, 2]);
+        drop(producer);
+        // Frames pushed before dropping are still there
+        assert!(!consumer.is_finished());
+        let mut output = [0; 4];
+        assert_eq!(consumer.pop(&mut output), 2);
+        assert!(consumer.is_finished());
+
+        let (mut producer, consumer) = ring_buffer::<i32>(1, Channels::Mono, 48_000);
+        drop(consumer);
+        assert!(producer.is_closed());
+        assert!(!producer.push_all(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn stress_across_threads() {
+        const SAMPLES: i32 = 200_000;
+        let (mut producer, mut consumer) = ring_buffer::<i32>(61, Channels::Stereo, 48_000);
+
+        let writer = thread::spawn(move || {
+            let samples: Vec<i32> = (0..SAMPLES).collect();
+            // Varying chunk sizes, so pushes land at every offset into the ring
+            let mut start = 0;
+            for len in (1..37).map(|n| n * 2).cycle() {
+                if start == samples.len() {
+                    break;
+                }
+                let end = (start + len).min(samples.len());
+                assert!(producer.push_all(&samples[start..end]));
+                start = end;
+            }
+        });
+
+        let mut next = 0;
+        let mut output = [0; 50];
+        while !consumer.is_finished() {
+            let popped = consumer.pop(&mut output);
+            for &sample in &output[..popped] {
+                assert_eq!(sample, next);
+                next += 1;
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(next, SAMPLES);
+    }
+}
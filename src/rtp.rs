@@ -0,0 +1,122 @@
+//! RTP payloading of encoded Opus packets (RFC 7587), for VoIP and WebRTC style transports
+
+use crate::container::{Codec, Packet, Track};
+use thiserror::Error;
+
+/// Opus RTP timestamps always count at 48 kHz, whatever the input sample rate
+const CLOCK_RATE: u64 = 48_000;
+/// First payload type of the dynamic range
+const DEFAULT_PAYLOAD_TYPE: u8 = 111;
+/// Length of an RTP header without CSRCs or extensions
+const HEADER_LEN: usize = 12;
+
+/// Builds RTP packets out of Opus packets, e.g. demuxed from an Ogg Opus file, managing
+/// sequence numbers and timestamps
+/// Sequence number, timestamp and SSRC start out random, as RFC 3550 recommends
+#[derive(Clone, Debug)]
+pub struct RtpPacketizer {
+    payload_type: u8,
+    ssrc: u32,
+    sequence: u16,
+    /// RTP timestamp of the stream's first sample
+    timestamp_offset: u32,
+    sample_rate: u32,
+    channels: u32,
+    /// Timestamp the next packet starts at if there's no gap, in the track's sample rate
+    next: Option<u64>,
+}
+
+impl RtpPacketizer {
+    /// Creates a packetizer for an Opus track
+    pub fn new(track: &Track) -> Result<Self, RtpError> {
+        if track.codec != Codec::Opus {
+            return Err(RtpError::UnsupportedCodec(track.codec));
+        }
+        if track.sample_rate == 0 {
+            return Err(RtpError::UnsupportedSampleRate(track.sample_rate));
+        }
+
+        Ok(Self {
+            payload_type: DEFAULT_PAYLOAD_TYPE,
+            ssrc: rand::random(),
+            sequence: rand::random(),
+            timestamp_offset: rand::random(),
+            sample_rate: track.sample_rate,
+            channels: track.channels as u32,
+            next: None,
+        })
+    }
+
+    /// Sets the payload type, as negotiated in SDP, 111 by default
+    /// Only the lower 7 bits are used
+    pub fn payload_type(mut self, payload_type: u8) -> Self {
+        self.payload_type = payload_type & 0x7F;
+        self
+    }
+
+    /// Sets the synchronization source identifier
+    pub fn ssrc(mut self, ssrc: u32) -> Self {
+        self.ssrc = ssrc;
+        self
+    }
+
+    /// Sets the sequence number of the next packet
+    pub fn sequence(mut self, sequence: u16) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Returns the synchronization source identifier
+    pub fn source_id(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// Returns the sequence number the next packet gets
+    pub fn next_sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Returns the RTP timestamp of a sample of the stream
+    pub fn rtp_timestamp(&self, sample: u64) -> u32 {
+        let ticks = sample as u128 * CLOCK_RATE as u128 / self.sample_rate as u128;
+        self.timestamp_offset.wrapping_add(ticks as u32)
+    }
+
+    /// Returns an RTP packet carrying the Opus packet
+    /// The marker bit is set on the first packet and after gaps, e.g. left by discontinuous
+    /// transmission, as the start of a talkspurt
+    pub fn packetize(&mut self, packet: &Packet) -> Vec<u8> {
+        let marker = self.next.is_none_or(|next| packet.timestamp > next);
+        self.next = Some(packet.timestamp + packet.duration);
+
+        let mut rtp = Vec::with_capacity(HEADER_LEN + packet.data.len());
+        // Version 2, no padding, extension or CSRCs
+        rtp.push(0x80);
+        rtp.push(self.payload_type | (marker as u8) << 7);
+        rtp.extend_from_slice(&self.sequence.to_be_bytes());
+        rtp.extend_from_slice(&self.rtp_timestamp(packet.timestamp).to_be_bytes());
+        rtp.extend_from_slice(&self.ssrc.to_be_bytes());
+        rtp.extend_from_slice(&packet.data);
+
+        self.sequence = self.sequence.wrapping_add(1);
+        rtp
+    }
+
+    /// Returns the SDP media attributes describing the payload
+    pub fn sdp_attributes(&self) -> String {
+        let stereo = (self.channels > 1) as u8;
+        format!(
+            "a=rtpmap:{pt} opus/48000/2\r\na=fmtp:{pt} stereo={stereo};sprop-stereo={stereo}\r\n",
+            pt = self.payload_type
+        )
+    }
+}
+
+/// Enum representing RTP payloading errors
+#[derive(Error, Debug)]
+pub enum RtpError {
+    #[error("RTP payloading only supports Opus, not {0:?}")]
+    UnsupportedCodec(Codec),
+    #[error("unsupported sample rate: {0}")]
+    UnsupportedSampleRate(u32),
+}
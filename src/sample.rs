@@ -0,0 +1,43 @@
+use crate::{i24, u24};
+use symphonia::core::{conv::ReversibleSample, sample::Sample};
+
+pub(crate) mod sealed {
+    pub trait Sealed {
+        /// Converts to any other sample type, which bounds on a generic `S` can't spell out
+        fn convert<T: super::AudyoSample>(self) -> T;
+    }
+}
+
+/// Sample type a `SampleBuffer` can hold, convertible from and into every other one
+/// Implemented for u8, u16, u24, u32, i8, i16, i24, i32, f32 and f64. It can't be implemented
+/// outside the crate, so that new sample types can be added without breaking anyone's bounds
+pub trait AudyoSample:
+    Sample
+    + ReversibleSample<u8>
+    + ReversibleSample<u16>
+    + ReversibleSample<u24>
+    + ReversibleSample<u32>
+    + ReversibleSample<i8>
+    + ReversibleSample<i16>
+    + ReversibleSample<i24>
+    + ReversibleSample<i32>
+    + ReversibleSample<f32>
+    + ReversibleSample<f64>
+    + sealed::Sealed
+{
+}
+
+macro_rules! impl_sample {
+    ($($t:ty),*) => {
+        $(
+            impl sealed::Sealed for $t {
+                fn convert<T: AudyoSample>(self) -> T {
+                    T::from_sample(self)
+                }
+            }
+            impl AudyoSample for $t {}
+        )*
+    };
+}
+
+impl_sample!(u8, u16, u24, u32, i8, i16, i24, i32, f32, f64);
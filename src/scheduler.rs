@@ -0,0 +1,392 @@
+//! Thread pool running tasks by priority class, so that realtime work like encoding a live
+//! stream doesn't wait behind batch transcodes sharing the process
+//!
+//! Preemption is cooperative: a task calling `yield_now` runs any waiting task of a higher
+//! class on its own thread before carrying on. The crate's decode and encode loops do so
+//! between packets and blocks, so transcodes running as tasks give way without any changes
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+};
+
+/// Priority class of a task, waiting tasks of a higher class always start first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Throughput work that can wait, e.g. transcoding a library
+    Batch,
+    #[default]
+    Normal,
+    /// Work that falls behind if it waits, e.g. encoding a live stream
+    Realtime,
+}
+
+const CLASSES: usize = 3;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    /// Pool of the worker running on this thread, and the priority of its current task
+    static CURRENT: RefCell<Option<(Arc<Inner>, Priority)>> = const { RefCell::new(None) };
+}
+
+struct Inner {
+    queues: Mutex<Queues>,
+    available: Condvar,
+    /// Number of waiting tasks, so `yield_now` can skip locking the queues while there are none
+    waiting: AtomicUsize,
+}
+
+/// Waiting tasks of each class, oldest first
+#[derive(Default)]
+struct Queues {
+    jobs: [VecDeque<Job>; CLASSES],
+    shutdown: bool,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, Queues> {
+        self.queues.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes the next task like `Queues::pop`
+    fn pop(&self, floor: Option<Priority>) -> Option<(Priority, Job)> {
+        let next = self.lock().pop(floor);
+        if next.is_some() {
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        next
+    }
+}
+
+impl Queues {
+    /// Takes the oldest task of the highest class, only considering classes above `floor`
+    fn pop(&mut self, floor: Option<Priority>) -> Option<(Priority, Job)> {
+        [Priority::Realtime, Priority::Normal, Priority::Batch]
+            .into_iter()
+            .take_while(|&p| floor.is_none_or(|floor| p > floor))
+            .find_map(|p| self.jobs[p as usize].pop_front().map(|job| (p, job)))
+    }
+}
+
+/// Handle to a pool of threads running tasks by priority
+/// Clones share the pool, which stops once every handle is dropped and the tasks already
+/// spawned have run
+#[derive(Clone)]
+pub struct Scheduler {
+    pool: Arc<Pool>,
+}
+
+struct Pool {
+    inner: Arc<Inner>,
+    threads: usize,
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.inner.lock().shutdown = true;
+        self.inner.available.notify_all();
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("threads", &self.pool.threads)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scheduler {
+    /// Starts a pool of the given number of threads, at least one
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let inner = Arc::new(Inner {
+            queues: Mutex::new(Queues::default()),
+            available: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        });
+        for _ in 0..threads {
+            let inner = inner.clone();
+            thread::spawn(move || work(inner));
+        }
+
+        Self {
+            pool: Arc::new(Pool { inner, threads }),
+        }
+    }
+
+    /// Returns the number of threads in the pool
+    pub fn threads(&self) -> usize {
+        self.pool.threads
+    }
+
+    /// Queues a task, which starts once a thread is free and no task of a higher class is
+    /// waiting, or earlier on the thread of a lower class task calling `yield_now`
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        priority: Priority,
+        task: impl FnOnce() -> T + Send + 'static,
+    ) -> Task<T> {
+        let slot = Arc::new(Slot {
+            result: Mutex::new(None),
+            done: Condvar::new(),
+        });
+        let finished = slot.clone();
+        let job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(task));
+            *finished.lock() = Some(result);
+            finished.done.notify_all();
+        });
+
+        let inner = &self.pool.inner;
+        let mut queues = inner.lock();
+        queues.jobs[priority as usize].push_back(job);
+        inner.waiting.fetch_add(1, Ordering::Relaxed);
+        drop(queues);
+        inner.available.notify_one();
+        Task { slot }
+    }
+}
+
+/// Runs waiting tasks of a higher class than the current one, when called from a task
+/// Long running tasks call this regularly, so realtime tasks don't wait for them to finish
+/// Outside of a task, or while no task is waiting, it returns without taking any lock
+pub fn yield_now() {
+    let Some((inner, priority)) = CURRENT.with_borrow(|current| {
+        current
+            .as_ref()
+            .filter(|(inner, _)| inner.waiting.load(Ordering::Relaxed) > 0)
+            .map(|(inner, priority)| (inner.clone(), *priority))
+    }) else {
+        return;
+    };
+    while let Some((p, job)) = inner.pop(Some(priority)) {
+        run(&inner, p, job);
+    }
+}
+
+fn work(inner: Arc<Inner>) {
+    loop {
+        let mut queues = inner.lock();
+        let (priority, job) = loop {
+            if let Some(next) = queues.pop(None) {
+                inner.waiting.fetch_sub(1, Ordering::Relaxed);
+                break next;
+            }
+            if queues.shutdown {
+                return;
+            }
+            queues = inner
+                .available
+                .wait(queues)
+                .unwrap_or_else(PoisonError::into_inner);
+        };
+        drop(queues);
+        run(&inner, priority, job);
+    }
+}
+
+/// Runs a job with the thread marked as running a task of the given priority
+fn run(inner: &Arc<Inner>, priority: Priority, job: Job) {
+    let previous = CURRENT.replace(Some((inner.clone(), priority)));
+    job();
+    CURRENT.set(previous);
+}
+
+/// Handle to a spawned task, for waiting on its result
+pub struct Task<T> {
+    slot: Arc<Slot<T>>,
+}
+
+struct Slot<T> {
+    result: Mutex<Option<thread::Result<T>>>,
+    done: Condvar,
+}
+
+impl<T> Slot<T> {
+    fn lock(&self) -> MutexGuard<'_, Option<thread::Result<T>>> {
+        self.result.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("finished", &self.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Task<T> {
+    /// Returns whether the task has run
+    pub fn is_finished(&self) -> bool {
+        self.slot.lock().is_some()
+    }
+
+    /// Waits for the task to run, returning its result, or the panic payload if it panicked
+    pub fn join(self) -> thread::Result<T> {
+        let mut result = self.slot.lock();
+        loop {
+            if let Some(result) = result.take() {
+                return result;
+            }
+            result = self
+                .slot
+                .done
+                .wait(result)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{
+            mpsc::{self, Sender, TryRecvError},
+            Barrier,
+        },
+        time::{Duration, Instant},
+    };
+
+    /// Spawns a task holding the pool's only thread until the returned sender is dropped
+    fn hold(scheduler: &Scheduler) -> (Sender<()>, Task<()>) {
+        let (release, released) = mpsc::channel::<()>();
+        let (started, start) = mpsc::channel();
+        let task = scheduler.spawn(Priority::Realtime, move || {
+            started.send(()).unwrap();
+            let _ = released.recv();
+        });
+        start.recv().unwrap();
+        (release, task)
+    }
+
+    #[test]
+    fn higher_classes_start_first() {
+        let scheduler = Scheduler::new(1);
+        let (release, held) = hold(&scheduler);
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = [
+            (Priority::Batch, "batch"),
+            (Priority::Normal, "normal 1"),
+            (Priority::Realtime, "realtime"),
+            (Priority::Normal, "normal 2"),
+        ]
+        .into_iter()
+        .map(|(priority, name)| {
+            let order = order.clone();
+            scheduler.spawn(priority, move || order.lock().unwrap().push(name))
+        })
+        .collect();
+
+        drop(release);
+        held.join().unwrap();
+        tasks.into_iter().for_each(|task| task.join().unwrap());
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["realtime", "normal 1", "normal 2", "batch"]
+        );
+    }
+
+    #[test]
+    fn yields_nest() {
+        let scheduler = Scheduler::new(1);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logger = |log: &Arc<Mutex<Vec<_>>>| {
+            let log = log.clone();
+            move |entry| log.lock().unwrap().push(entry)
+        };
+
+        // Each task queues one of the next class and yields to it on its own thread
+        let (pool, log_batch) = (scheduler.clone(), logger(&log));
+        let batch = scheduler.spawn(Priority::Batch, move || {
+            log_batch("batch");
+            let (inner_pool, log_normal) = (pool.clone(), log_batch.clone());
+            let normal = pool.spawn(Priority::Normal, move || {
+                log_normal("normal");
+                let log_realtime = log_normal.clone();
+                let realtime = inner_pool.spawn(Priority::Realtime, move || {
+                    log_realtime("realtime");
+                    // Nothing above realtime, lower classes don't run here
+                    yield_now();
+                });
+                yield_now();
+                assert!(realtime.is_finished());
+                log_normal("normal end");
+            });
+            yield_now();
+            assert!(normal.is_finished());
+            log_batch("batch end");
+        });
+        batch.join().unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["batch", "normal", "realtime", "normal end", "batch end"]
+        );
+    }
+
+    #[test]
+    fn workers_exit_after_the_last_handle() {
+        thread_local! {
+            /// Dropped along with the thread, which is how the test sees workers exit
+            static EXITED: RefCell<Option<Sender<()>>> = const { RefCell::new(None) };
+        }
+
+        let scheduler = Scheduler::new(2);
+        let (exited, exits) = mpsc::channel();
+        // Both tasks wait for each other, so every worker runs one
+        let barrier = Arc::new(Barrier::new(2));
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let (exited, barrier) = (exited.clone(), barrier.clone());
+                scheduler.spawn(Priority::Normal, move || {
+                    EXITED.set(Some(exited));
+                    barrier.wait();
+                })
+            })
+            .collect();
+        drop(exited);
+
+        // Tasks spawned before the last handle drops still run
+        let queued = scheduler.clone().spawn(Priority::Batch, || 7);
+        drop(scheduler);
+        assert_eq!(queued.join().unwrap(), 7);
+        tasks.into_iter().for_each(|task| task.join().unwrap());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while exits.try_recv() != Err(TryRecvError::Disconnected) {
+            assert!(Instant::now() < deadline, "workers still running");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn yielding_outside_a_pool_does_nothing() {
+        let scheduler = Scheduler::new(1);
+        let (release, held) = hold(&scheduler);
+        let queued = scheduler.spawn(Priority::Realtime, || thread::current().id());
+
+        // Not a task, so the waiting task isn't run here, however often this yields
+        let started = Instant::now();
+        for _ in 0..1_000_000 {
+            yield_now();
+        }
+        assert!(!queued.is_finished());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        drop(release);
+        held.join().unwrap();
+        assert_ne!(queued.join().unwrap(), thread::current().id());
+    }
+}
@@ -0,0 +1,454 @@
+//! Splitting encoded streams into fixed-duration WebM or fragmented MP4 segments with HLS and
+//! DASH manifests, for adaptive streaming packagers and servers
+
+use crate::{
+    bitrate,
+    container::{
+        demux, element, mp4_fragment, mp4_init, simple_block, uint_element, webm_header, Codec,
+        Packet, RemuxError, Track, MAX_CLUSTER_MS,
+    },
+};
+use std::{fmt::Write, io, time::Duration};
+use symphonia::core::io::MediaSource;
+
+/// Container of the segments of a `SegmentedStream`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SegmentFormat {
+    /// WebM clusters, for DASH, holding Vorbis or Opus
+    #[default]
+    Webm,
+    /// Fragmented MP4 as CMAF, for HLS and DASH, holding Opus or AAC
+    Mp4,
+}
+
+impl SegmentFormat {
+    fn extensions(self) -> (&'static str, &'static str) {
+        match self {
+            SegmentFormat::Webm => ("webm", "webm"),
+            SegmentFormat::Mp4 => ("mp4", "m4s"),
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            SegmentFormat::Webm => "audio/webm",
+            SegmentFormat::Mp4 => "audio/mp4",
+        }
+    }
+}
+
+/// Encoded stream split into an initialization segment and media segments
+/// Each media segment holds whole packets, starting at the first packet at or after a multiple
+/// of the segment duration
+#[derive(Clone, Debug)]
+pub struct SegmentedStream {
+    format: SegmentFormat,
+    /// Codec as manifests name it in their codecs attributes
+    codecs: String,
+    sample_rate: u32,
+    channels: u32,
+    init: Vec<u8>,
+    segments: Vec<Segment>,
+}
+
+/// Media segment of a `SegmentedStream`
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// WebM clusters or an MP4 movie fragment holding the segment's packets
+    pub data: Vec<u8>,
+    /// First sample of the segment
+    pub start: u64,
+    /// Number of samples in the segment
+    pub duration: u64,
+}
+
+/// Reads the encoded packets of source, e.g. an output of `transcode_multi`, and splits them
+/// into segments of about the given duration
+pub fn segment(
+    source: impl MediaSource + 'static,
+    duration: Duration,
+    format: SegmentFormat,
+) -> Result<SegmentedStream, RemuxError> {
+    let (track, packets) = demux(source)?;
+    Ok(SegmentedStream::new(&track, &packets, duration, format)?)
+}
+
+impl SegmentedStream {
+    /// Splits packets of a track into segments of about the given duration
+    /// Fails with `io::ErrorKind::Unsupported` if the format can't hold the codec, AAC in WebM
+    /// or Vorbis in MP4
+    pub fn new(
+        track: &Track,
+        packets: &[Packet],
+        duration: Duration,
+        format: SegmentFormat,
+    ) -> io::Result<Self> {
+        let rate = track.sample_rate as u64;
+        let segment_samples = ((duration.as_nanos() * rate as u128 / 1_000_000_000) as u64).max(1);
+
+        let mut groups: Vec<&[Packet]> = Vec::new();
+        let mut first = 0;
+        for (i, packet) in packets.iter().enumerate().skip(1) {
+            let boundary = (groups.len() as u64 + 1) * segment_samples;
+            if packet.timestamp >= boundary {
+                groups.push(&packets[first..i]);
+                first = i;
+            }
+        }
+        if first < packets.len() {
+            groups.push(&packets[first..]);
+        }
+
+        let segments = groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, packets)| {
+                let start = packets[0].timestamp;
+                let end = packets
+                    .iter()
+                    .map(|p| p.timestamp + p.duration)
+                    .max()
+                    .unwrap_or(start);
+                Ok(Segment {
+                    data: match format {
+                        SegmentFormat::Webm => clusters(packets, track.sample_rate),
+                        // Sequence numbers count from 1
+                        SegmentFormat::Mp4 => mp4_fragment(i as u32 + 1, packets)?,
+                    },
+                    start,
+                    duration: end - start,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let init = match format {
+            SegmentFormat::Webm => {
+                let mut header = webm_header(track)?;
+                let end = segments.last().map_or(0, |s| s.start + s.duration);
+                let duration_ms = end as f64 * 1000. / rate.max(1) as f64;
+                header.data[header.duration_at..header.duration_at + 8]
+                    .copy_from_slice(&duration_ms.to_be_bytes());
+                header.data
+            }
+            SegmentFormat::Mp4 => mp4_init(track)?,
+        };
+
+        Ok(Self {
+            format,
+            codecs: codecs(track),
+            sample_rate: track.sample_rate,
+            channels: track.channels as u32,
+            init,
+            segments,
+        })
+    }
+
+    /// Returns the format of the segments
+    pub fn format(&self) -> SegmentFormat {
+        self.format
+    }
+
+    /// Returns the initialization segment, the WebM header or MP4 movie box every media segment
+    /// follows
+    pub fn init(&self) -> &[u8] {
+        &self.init
+    }
+
+    /// Returns the media segments in order
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns duration of the whole stream
+    pub fn duration(&self) -> Duration {
+        self.samples_to_duration(self.segments.iter().map(|s| s.duration).sum())
+    }
+
+    /// Returns the average bitrate of the media segments
+    pub fn bitrate(&self) -> u64 {
+        let bytes: usize = self.segments.iter().map(|s| s.data.len()).sum();
+        bitrate(bytes as u64, self.duration().as_secs_f64())
+    }
+
+    /// Returns the highest bitrate of a single media segment
+    pub fn peak_bitrate(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| {
+                bitrate(
+                    s.data.len() as u64,
+                    self.samples_to_duration(s.duration).as_secs_f64(),
+                )
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns file names and contents of the segments for a rendition, named as the manifests
+    /// reference them: `{id}-init.webm`, then `{id}-0.webm`, `{id}-1.webm` and so on, or
+    /// `{id}-init.mp4`, then `{id}-0.m4s` and so on for MP4
+    pub fn files(&self, id: &str) -> Vec<(String, &[u8])> {
+        std::iter::once((self.init_name(id), self.init()))
+            .chain(
+                self.segments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| (self.segment_name(id, i), s.data.as_slice())),
+            )
+            .collect()
+    }
+
+    /// Returns an HLS media playlist of the rendition's segments, or None for WebM segments,
+    /// which HLS doesn't allow
+    pub fn hls_playlist(&self, id: &str) -> Option<String> {
+        if self.format != SegmentFormat::Mp4 {
+            return None;
+        }
+
+        let target = self
+            .segments
+            .iter()
+            .map(|s| self.samples_to_duration(s.duration).as_secs_f64().ceil() as u64)
+            .max()
+            .unwrap_or(0);
+
+        let mut playlist = String::new();
+        let _ = writeln!(playlist, "#EXTM3U");
+        let _ = writeln!(playlist, "#EXT-X-VERSION:7");
+        let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{target}");
+        let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:0");
+        let _ = writeln!(playlist, "#EXT-X-PLAYLIST-TYPE:VOD");
+        let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"{}\"", self.init_name(id));
+        for (i, segment) in self.segments.iter().enumerate() {
+            let seconds = self.samples_to_duration(segment.duration).as_secs_f64();
+            let _ = writeln!(playlist, "#EXTINF:{seconds:.3},");
+            let _ = writeln!(playlist, "{}", self.segment_name(id, i));
+        }
+        let _ = writeln!(playlist, "#EXT-X-ENDLIST");
+        Some(playlist)
+    }
+
+    fn init_name(&self, id: &str) -> String {
+        format!("{id}-init.{}", self.format.extensions().0)
+    }
+
+    fn segment_name(&self, id: &str, index: usize) -> String {
+        format!("{id}-{index}.{}", self.format.extensions().1)
+    }
+
+    fn samples_to_duration(&self, samples: u64) -> Duration {
+        Duration::from_nanos(
+            (samples as u128 * 1_000_000_000 / self.sample_rate.max(1) as u128) as u64,
+        )
+    }
+}
+
+/// Returns an HLS master playlist referencing a media playlist named `{id}.m3u8` per rendition,
+/// or None if a rendition has WebM segments
+pub fn hls_master_playlist(renditions: &[(&str, &SegmentedStream)]) -> Option<String> {
+    if renditions
+        .iter()
+        .any(|(_, stream)| stream.format != SegmentFormat::Mp4)
+    {
+        return None;
+    }
+
+    let mut playlist = String::new();
+    let _ = writeln!(playlist, "#EXTM3U");
+    let _ = writeln!(playlist, "#EXT-X-VERSION:7");
+    let _ = writeln!(playlist, "#EXT-X-INDEPENDENT-SEGMENTS");
+    for (id, stream) in renditions {
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},AVERAGE-BANDWIDTH={},CODECS=\"{}\"",
+            stream.peak_bitrate(),
+            stream.bitrate(),
+            stream.codecs
+        );
+        let _ = writeln!(playlist, "{id}.m3u8");
+    }
+    Some(playlist)
+}
+
+/// Returns a static DASH manifest with one representation per rendition, referencing the
+/// segments by the names `SegmentedStream::files` gives them
+pub fn dash_manifest(renditions: &[(&str, &SegmentedStream)]) -> String {
+    let duration = renditions
+        .iter()
+        .map(|(_, stream)| stream.duration())
+        .max()
+        .unwrap_or_default();
+
+    let mut mpd = String::new();
+    let _ = writeln!(mpd, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        mpd,
+        r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" profiles="urn:mpeg:dash:profile:isoff-live:2011" minBufferTime="PT2S" mediaPresentationDuration="PT{:.3}S">"#,
+        duration.as_secs_f64()
+    );
+    let _ = writeln!(mpd, r#"  <Period id="0" start="PT0S">"#);
+    let _ = writeln!(mpd, r#"    <AdaptationSet contentType="audio">"#);
+    for (id, stream) in renditions {
+        let _ = writeln!(
+            mpd,
+            r#"      <Representation id="{id}" mimeType="{}" codecs="{}" bandwidth="{}" audioSamplingRate="{}">"#,
+            stream.format.mime_type(),
+            stream.codecs,
+            stream.bitrate(),
+            stream.sample_rate
+        );
+        let _ = writeln!(
+            mpd,
+            r#"        <AudioChannelConfiguration schemeIdUri="urn:mpeg:dash:23003:3:audio_channel_configuration:2011" value="{}"/>"#,
+            stream.channels
+        );
+        let (init, media) = stream.format.extensions();
+        let _ = writeln!(
+            mpd,
+            r#"        <SegmentTemplate timescale="{}" initialization="$RepresentationID$-init.{init}" media="$RepresentationID$-$Number$.{media}" startNumber="0">"#,
+            stream.sample_rate
+        );
+        let _ = writeln!(mpd, "          <SegmentTimeline>");
+        for segment in &stream.segments {
+            let _ = writeln!(
+                mpd,
+                r#"            <S t="{}" d="{}"/>"#,
+                segment.start, segment.duration
+            );
+        }
+        let _ = writeln!(mpd, "          </SegmentTimeline>");
+        let _ = writeln!(mpd, "        </SegmentTemplate>");
+        let _ = writeln!(mpd, "      </Representation>");
+    }
+    let _ = writeln!(mpd, "    </AdaptationSet>");
+    let _ = writeln!(mpd, "  </Period>");
+    let _ = writeln!(mpd, "</MPD>");
+    mpd
+}
+
+/// Writes packets as WebM clusters, starting a new one when block timestamps would overflow
+fn clusters(packets: &[Packet], sample_rate: u32) -> Vec<u8> {
+    let milliseconds = |samples: u64| samples * 1000 / sample_rate.max(1) as u64;
+
+    let mut out = Vec::new();
+    let mut cluster = Vec::new();
+    let mut cluster_start = 0;
+    for packet in packets {
+        let start_ms = milliseconds(packet.timestamp);
+        if cluster.is_empty() || start_ms.saturating_sub(cluster_start) > MAX_CLUSTER_MS {
+            if !cluster.is_empty() {
+                element(&mut out, 0x1F43B675, &cluster);
+                cluster.clear();
+            }
+            cluster_start = start_ms;
+            uint_element(&mut cluster, 0xE7, start_ms);
+        }
+        simple_block(
+            &mut cluster,
+            start_ms.saturating_sub(cluster_start) as i16,
+            &packet.data,
+        );
+    }
+    if !cluster.is_empty() {
+        element(&mut out, 0x1F43B675, &cluster);
+    }
+    out
+}
+
+/// Returns the codecs attribute of a track, as RFC 6381 names codecs
+fn codecs(track: &Track) -> String {
+    match track.codec {
+        Codec::Vorbis => "vorbis".to_owned(),
+        Codec::Opus => "opus".to_owned(),
+        // The audio object type, e.g. 2 for AAC-LC, is the top 5 bits of the AudioSpecificConfig
+        Codec::Aac => {
+            let object_type = track
+                .headers
+                .first()
+                .and_then(|h| h.first())
+                .map_or(2, |b| b >> 3);
+            format!("mp4a.40.{object_type}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Channels;
+
+    fn aac_track() -> Track {
+        Track {
+            codec: Codec::Aac,
+            sample_rate: 48000,
+            channels: Channels::Stereo,
+            // AAC-LC at 48 kHz, stereo
+            headers: vec![vec![0x11, 0x90]],
+            delay: 0,
+        }
+    }
+
+    fn packets() -> Vec<Packet> {
+        (0..100)
+            .map(|i| Packet {
+                data: vec![i as u8; 20 + i % 7],
+                timestamp: i as u64 * 1024,
+                duration: 1024,
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "isomp4")]
+    #[test]
+    fn mp4_segments_demux_back() {
+        let packets = packets();
+        let stream = SegmentedStream::new(
+            &aac_track(),
+            &packets,
+            Duration::from_millis(500),
+            SegmentFormat::Mp4,
+        )
+        .unwrap();
+        assert_eq!(stream.segments().len(), 5);
+
+        let mut joined = stream.init().to_vec();
+        for segment in stream.segments() {
+            joined.extend_from_slice(&segment.data);
+        }
+        let (track, demuxed) = demux(std::io::Cursor::new(joined)).unwrap();
+        assert_eq!(track.codec, Codec::Aac);
+        assert_eq!(track.channels, Channels::Stereo);
+        assert_eq!(demuxed.len(), packets.len());
+        for (demuxed, packet) in demuxed.iter().zip(&packets) {
+            assert_eq!(demuxed.data, packet.data);
+            assert_eq!(demuxed.timestamp, packet.timestamp);
+        }
+    }
+
+    #[test]
+    fn hls_needs_mp4_segments() {
+        let packets = packets();
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&[0x38, 0x01, 0x80, 0xBB, 0, 0, 0, 0, 0]);
+        let track = Track {
+            codec: Codec::Opus,
+            headers: vec![head],
+            ..aac_track()
+        };
+        let duration = Duration::from_secs(1);
+        let webm = SegmentedStream::new(&track, &packets, duration, SegmentFormat::Webm).unwrap();
+        let mp4 = SegmentedStream::new(&track, &packets, duration, SegmentFormat::Mp4).unwrap();
+
+        assert!(webm.hls_playlist("a").is_none());
+        assert!(hls_master_playlist(&[("a", &webm), ("b", &mp4)]).is_none());
+        let playlist = mp4.hls_playlist("b").unwrap();
+        assert!(playlist.contains("#EXT-X-MAP:URI=\"b-init.mp4\""));
+        assert!(playlist.contains("b-0.m4s"));
+        let master = hls_master_playlist(&[("b", &mp4)]).unwrap();
+        assert!(master.contains("CODECS=\"opus\""));
+        assert!(dash_manifest(&[("a", &webm), ("b", &mp4)]).contains(r#"mimeType="audio/mp4""#));
+
+        let aac = SegmentedStream::new(&aac_track(), &packets, duration, SegmentFormat::Webm);
+        assert_eq!(aac.unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::{AudyoSample, SampleBuffer};
+use std::{ops::Deref, sync::Arc};
+
+/// Cheaply clonable handle to a buffer shared between consumers
+/// Mutating through a handle copies the samples only if other handles still exist
+#[derive(Clone, Debug)]
+pub struct SharedBuffer<S: AudyoSample>(Arc<SampleBuffer<S>>);
+
+impl<S: AudyoSample> SharedBuffer<S> {
+    /// Returns a mutable reference to the buffer, copying it first if it's shared
+    pub fn make_mut(&mut self) -> &mut SampleBuffer<S> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Returns the buffer, copying it if it's shared
+    pub fn into_owned(self) -> SampleBuffer<S> {
+        Arc::unwrap_or_clone(self.0)
+    }
+
+    /// Returns whether other handles to the same buffer exist
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+}
+
+impl<S: AudyoSample> Deref for SharedBuffer<S> {
+    type Target = SampleBuffer<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<S: AudyoSample> SampleBuffer<S> {
+    /// Moves the buffer behind a shared handle without copying it
+    pub fn share(self) -> SharedBuffer<S> {
+        SharedBuffer(Arc::new(self))
+    }
+}
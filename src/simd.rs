@@ -0,0 +1,246 @@
+use crate::{sample::sealed::Sealed, AudyoSample};
+use std::any::{Any, TypeId};
+
+/// Converts samples, using a vectorized path if there's one for the given pair of formats
+/// Results are identical to converting every sample with `FromSample`
+pub(crate) fn convert<F: AudyoSample + 'static, T: AudyoSample + 'static>(input: &[F]) -> Vec<T> {
+    let mut output: Option<Vec<T>> = None;
+    let out = &mut output as &mut dyn Any;
+
+    if let Some(input) = cast::<F, f32>(input) {
+        if let Some(out) = out.downcast_mut::<Option<Vec<i16>>>() {
+            *out = Some(f32_to_i16(input));
+        } else if let Some(out) = out.downcast_mut::<Option<Vec<i32>>>() {
+            *out = Some(f32_to_i32(input));
+        }
+    } else if let Some(input) = cast::<F, i16>(input) {
+        if let Some(out) = out.downcast_mut::<Option<Vec<f32>>>() {
+            *out = Some(i16_to_f32(input));
+        }
+    } else if let Some(input) = cast::<F, i32>(input) {
+        if let Some(out) = out.downcast_mut::<Option<Vec<f32>>>() {
+            *out = Some(i32_to_f32(input));
+        }
+    }
+
+    output.unwrap_or_else(|| {
+        let mut output = Vec::with_capacity(input.len());
+        scalar(input, &mut output);
+        output
+    })
+}
+
+/// Reinterprets a slice if its element type is `T`
+fn cast<F: 'static, T: 'static>(input: &[F]) -> Option<&[T]> {
+    // SAFETY: F and T are the same type
+    (TypeId::of::<F>() == TypeId::of::<T>())
+        .then(|| unsafe { &*(input as *const [F] as *const [T]) })
+}
+
+/// Converts the samples not covered by whole vectors
+fn scalar<F: AudyoSample, T: AudyoSample>(input: &[F], output: &mut Vec<T>) {
+    output.extend(input.iter().copied().map(Sealed::convert::<T>));
+}
+
+/// SSE2 kernels, SSE2 is part of the x86_64 baseline so no runtime detection is needed
+/// Each returns how many leading samples it converted
+#[cfg(target_arch = "x86_64")]
+mod vector {
+    use std::arch::x86_64::*;
+
+    /// Clamps to -1..=1 with NaN becoming 0, like the scalar conversion
+    #[inline(always)]
+    unsafe fn clamp(v: __m128) -> __m128 {
+        let ordered = _mm_cmpord_ps(v, v);
+        let v = _mm_min_ps(_mm_max_ps(v, _mm_set1_ps(-1.)), _mm_set1_ps(1.));
+        _mm_and_ps(v, ordered)
+    }
+
+    pub(super) fn f32_to_i16(input: &[f32], output: &mut Vec<i16>) -> usize {
+        let chunks = input.chunks_exact(8);
+        let done = input.len() - chunks.remainder().len();
+
+        for chunk in chunks {
+            let mut packed = [0i16; 8];
+            // SAFETY: chunk holds 8 floats and packed 8 shorts, unaligned loads and stores are
+            // used throughout
+            unsafe {
+                let scale = _mm_set1_ps(32_768.);
+                let a = _mm_loadu_ps(chunk.as_ptr());
+                let b = _mm_loadu_ps(chunk.as_ptr().add(4));
+                let a = _mm_cvttps_epi32(_mm_mul_ps(clamp(a), scale));
+                let b = _mm_cvttps_epi32(_mm_mul_ps(clamp(b), scale));
+                // 32768 saturates to i16::MAX, like `as` casts do
+                _mm_storeu_si128(packed.as_mut_ptr().cast(), _mm_packs_epi32(a, b));
+            }
+            output.extend_from_slice(&packed);
+        }
+
+        done
+    }
+
+    pub(super) fn f32_to_i32(input: &[f32], output: &mut Vec<i32>) -> usize {
+        let chunks = input.chunks_exact(4);
+        let done = input.len() - chunks.remainder().len();
+
+        for chunk in chunks {
+            let mut converted = [0i32; 4];
+            // SAFETY: chunk holds 4 floats and converted 4 ints
+            unsafe {
+                let scale = _mm_set1_ps(2_147_483_648.);
+                let v = _mm_mul_ps(clamp(_mm_loadu_ps(chunk.as_ptr())), scale);
+                // Out of range values convert to i32::MIN, flipping its bits turns the
+                // positive overflow into i32::MAX
+                let overflow = _mm_castps_si128(_mm_cmpge_ps(v, scale));
+                let v = _mm_xor_si128(_mm_cvttps_epi32(v), overflow);
+                _mm_storeu_si128(converted.as_mut_ptr().cast(), v);
+            }
+            output.extend_from_slice(&converted);
+        }
+
+        done
+    }
+
+    pub(super) fn i16_to_f32(input: &[i16], output: &mut Vec<f32>) -> usize {
+        let chunks = input.chunks_exact(8);
+        let done = input.len() - chunks.remainder().len();
+
+        for chunk in chunks {
+            let mut converted = [0f32; 8];
+            // SAFETY: chunk holds 8 shorts and converted 8 floats
+            unsafe {
+                let scale = _mm_set1_ps(1. / 32_768.);
+                let v = _mm_loadu_si128(chunk.as_ptr().cast());
+                // Sign extend by moving each short to the upper half and shifting back down
+                let lo = _mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16);
+                let hi = _mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16);
+                _mm_storeu_ps(
+                    converted.as_mut_ptr(),
+                    _mm_mul_ps(_mm_cvtepi32_ps(lo), scale),
+                );
+                _mm_storeu_ps(
+                    converted.as_mut_ptr().add(4),
+                    _mm_mul_ps(_mm_cvtepi32_ps(hi), scale),
+                );
+            }
+            output.extend_from_slice(&converted);
+        }
+
+        done
+    }
+
+    pub(super) fn i32_to_f32(input: &[i32], output: &mut Vec<f32>) -> usize {
+        let chunks = input.chunks_exact(4);
+        let done = input.len() - chunks.remainder().len();
+
+        for chunk in chunks {
+            let mut converted = [0f32; 4];
+            // SAFETY: chunk holds 4 ints and converted 4 floats
+            unsafe {
+                // Scaling by a power of two is exact, so rounding only happens once, as it does
+                // in the scalar conversion
+                let scale = _mm_set1_ps(1. / 2_147_483_648.);
+                let v = _mm_cvtepi32_ps(_mm_loadu_si128(chunk.as_ptr().cast()));
+                _mm_storeu_ps(converted.as_mut_ptr(), _mm_mul_ps(v, scale));
+            }
+            output.extend_from_slice(&converted);
+        }
+
+        done
+    }
+}
+
+/// Other architectures fall back to scalar conversion
+#[cfg(not(target_arch = "x86_64"))]
+mod vector {
+    pub(super) fn f32_to_i16(_: &[f32], _: &mut Vec<i16>) -> usize {
+        0
+    }
+
+    pub(super) fn f32_to_i32(_: &[f32], _: &mut Vec<i32>) -> usize {
+        0
+    }
+
+    pub(super) fn i16_to_f32(_: &[i16], _: &mut Vec<f32>) -> usize {
+        0
+    }
+
+    pub(super) fn i32_to_f32(_: &[i32], _: &mut Vec<f32>) -> usize {
+        0
+    }
+}
+
+macro_rules! kernel {
+    ($name:ident, $from:ty, $to:ty) => {
+        fn $name(input: &[$from]) -> Vec<$to> {
+            let mut output = Vec::with_capacity(input.len());
+            let done = vector::$name(input, &mut output);
+            scalar(&input[done..], &mut output);
+            output
+        }
+    };
+}
+
+kernel!(f32_to_i16, f32, i16);
+kernel!(f32_to_i32, f32, i32);
+kernel!(i16_to_f32, i16, f32);
+kernel!(i32_to_f32, i32, f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::conv::FromSample;
+
+    /// Floats in and out of range, cycled to fill inputs of any length
+    const FLOATS: [f32; 12] = [
+        0.,
+        0.5,
+        -0.25,
+        1.,
+        -1.,
+        0.999_99,
+        1.5,
+        -3.,
+        f32::NAN,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::MIN_POSITIVE,
+    ];
+
+    /// Lengths around the vector widths, including ones with a remainder
+    fn lengths() -> impl Iterator<Item = usize> {
+        (0..20).chain([31, 33, 100])
+    }
+
+    fn cycled<S: Copy>(values: &[S], len: usize) -> Vec<S> {
+        values.iter().copied().cycle().take(len).collect()
+    }
+
+    #[test]
+    fn floats_convert_like_from_sample() {
+        for len in lengths() {
+            let input = cycled(&FLOATS, len);
+            let expected: Vec<i16> = input.iter().map(|&s| i16::from_sample(s)).collect();
+            assert_eq!(convert::<f32, i16>(&input), expected, "{len}");
+            let expected: Vec<i32> = input.iter().map(|&s| i32::from_sample(s)).collect();
+            assert_eq!(convert::<f32, i32>(&input), expected, "{len}");
+        }
+    }
+
+    #[test]
+    fn integers_convert_like_from_sample() {
+        let bits = |samples: Vec<f32>| samples.into_iter().map(f32::to_bits).collect::<Vec<_>>();
+        for len in lengths() {
+            let input = cycled(&[0, 1, -1, 12_345, i16::MIN, i16::MAX], len);
+            let expected = input.iter().map(|&s| f32::from_sample(s)).collect();
+            assert_eq!(bits(convert::<i16, f32>(&input)), bits(expected), "{len}");
+
+            let input = cycled(
+                &[0, 1, -1, 123_456_789, i32::MIN, i32::MAX, i32::MAX - 64],
+                len,
+            );
+            let expected = input.iter().map(|&s| f32::from_sample(s)).collect();
+            assert_eq!(bits(convert::<i32, f32>(&input)), bits(expected), "{len}");
+        }
+    }
+}
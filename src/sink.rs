@@ -0,0 +1,374 @@
+use crate::{
+    util::deinterleave, AudyoSample, BufferError, Channels, EncodeError, SampleBuffer, Tag,
+    TimeBase,
+};
+use std::{
+    io::{self, Write},
+    num::{NonZeroU32, NonZeroU8},
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use symphonia::core::conv::IntoSample;
+use vorbis_rs::{
+    VorbisBitrateManagementStrategy, VorbisEncoder, VorbisEncoderBuilder, VorbisError,
+    VorbisLibrary, VorbisLibraryErrorKind,
+};
+
+/// Destination for encoded data, e.g. a file, a socket or a ring buffer
+/// Implementations apply backpressure by blocking in `write_block` until they can accept more
+pub trait EncodeSink {
+    /// Writes a whole block of encoded data
+    fn write_block(&mut self, block: &[u8]) -> io::Result<()>;
+
+    /// Pushes any buffered data towards its destination
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last block has been written
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write> EncodeSink for W {
+    fn write_block(&mut self, block: &[u8]) -> io::Result<()> {
+        self.write_all(block)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+}
+
+/// Sample rates libvorbis has tuned bitrate managed encoding for
+const VORBIS_SAMPLE_RATES: RangeInclusive<u32> = 8_000..=50_000;
+
+/// Checks that ogg vorbis can be encoded at the sample rate, e.g. to pick one to resample to
+/// libvorbis only encodes at an average bitrate between 8 and 50 kHz
+pub fn validate_vorbis_sample_rate(sample_rate: u32) -> Result<(), EncodeError> {
+    match VORBIS_SAMPLE_RATES.contains(&sample_rate) {
+        true => Ok(()),
+        false => Err(EncodeError::UnsupportedSampleRate(sample_rate)),
+    }
+}
+
+/// Checks that audio of the given layout and sample rate can be encoded as ogg vorbis at the
+/// average bitrate, failing with the error the encoders would before anything gets encoded
+/// Supported bitrates depend on both, e.g. 28-240 kbps for 44.1 kHz mono but 8-42 kbps for
+/// 8 kHz mono, so libvorbis gets asked by setting up a throwaway encoder
+pub fn validate_vorbis_bitrate(
+    channels: Channels,
+    sample_rate: u32,
+    bitrate: u64,
+) -> Result<(), EncodeError> {
+    let (rate, channels, average_bitrate) = vorbis_params(channels, sample_rate, bitrate)?;
+    let mut builder = VorbisEncoderBuilder::new(rate, channels, io::sink())?;
+    builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr { average_bitrate });
+    builder
+        .build()
+        .map_err(|e| setup_error(e, bitrate))
+        .map(drop)
+}
+
+/// Checks the parameters libvorbis can't take at all, leaving the bitrate given the layout and
+/// sample rate to the encoder setup
+fn vorbis_params(
+    channels: Channels,
+    sample_rate: u32,
+    bitrate: u64,
+) -> Result<(NonZeroU32, NonZeroU8, NonZeroU32), EncodeError> {
+    validate_vorbis_sample_rate(sample_rate)?;
+    let rate = NonZeroU32::new(sample_rate).expect("supported sample rates aren't zero");
+    let bitrate = u32::try_from(bitrate)
+        .ok()
+        .and_then(NonZeroU32::new)
+        .ok_or(EncodeError::BitrateOutOfRange(bitrate))?;
+    let channels = NonZeroU8::new(channels as u8).expect("channel layouts aren't empty");
+    Ok((rate, channels, bitrate))
+}
+
+/// Reports libvorbisenc refusing its setup as the bitrate being out of range, the sample rate
+/// and channels having been checked already
+fn setup_error(error: VorbisError, bitrate: u64) -> EncodeError {
+    match &error {
+        VorbisError::LibraryError(e)
+            if e.library() == VorbisLibrary::VorbisEnc
+                && matches!(
+                    e.kind(),
+                    VorbisLibraryErrorKind::NotImplemented | VorbisLibraryErrorKind::InvalidValue
+                ) =>
+        {
+            EncodeError::BitrateOutOfRange(bitrate)
+        }
+        _ => error.into(),
+    }
+}
+
+/// Adapts a sink to the writer expected by the encoder
+struct SinkWriter<K: EncodeSink> {
+    sink: K,
+    /// Granule position of the last complete page written, i.e. frames that reached the sink
+    granule: Arc<AtomicU64>,
+}
+
+impl<K: EncodeSink> Write for SinkWriter<K> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Pages get written header first, in a single call
+        if buf.len() >= 14 && buf.starts_with(b"OggS") {
+            let granule = u64::from_le_bytes(buf[6..14].try_into().unwrap());
+            // All ones marks pages without a packet ending on them
+            if granule != u64::MAX {
+                self.granule.store(granule, Ordering::Relaxed);
+            }
+        }
+
+        self.sink.write_block(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// Encodes a buffer using ogg vorbis given an average bitrate, pushing encoded data into the sink
+/// Returns the sink once it has been finished
+pub fn encode_vorbis_into<S: AudyoSample, K: EncodeSink>(
+    samples: &SampleBuffer<S>,
+    bitrate: u64,
+    sink: K,
+) -> Result<K, EncodeError> {
+    encode_vorbis_tagged(samples, bitrate, &[], sink)
+}
+
+/// Encodes like `encode_vorbis_into`, writing the tags as Vorbis comments
+pub(crate) fn encode_vorbis_tagged<S: AudyoSample, K: EncodeSink>(
+    samples: &SampleBuffer<S>,
+    bitrate: u64,
+    tags: &[Tag],
+    sink: K,
+) -> Result<K, EncodeError> {
+    let mut encoder =
+        StreamingEncoder::with_tags(samples.channels, samples.sample_rate, bitrate, tags, sink)?;
+    encoder.push(samples)?;
+    encoder.finish()
+}
+
+/// Vorbis encoder fed a buffer at a time, e.g. from a live capture, pushing encoded data into
+/// the sink as the encoder produces it
+pub struct StreamingEncoder<K: EncodeSink> {
+    encoder: VorbisEncoder<SinkWriter<K>>,
+    channels: Channels,
+    sample_rate: u32,
+    /// Frames pushed so far
+    pushed: u64,
+    granule: Arc<AtomicU64>,
+    /// Time each push must finish within, in realtime mode
+    budget: Option<Duration>,
+    missed_deadlines: u64,
+}
+
+impl<K: EncodeSink> StreamingEncoder<K> {
+    /// Creates an encoder for audio of the given layout and sample rate, given an average bitrate
+    pub fn new(
+        channels: Channels,
+        sample_rate: u32,
+        bitrate: u64,
+        sink: K,
+    ) -> Result<Self, EncodeError> {
+        Self::with_tags(channels, sample_rate, bitrate, &[], sink)
+    }
+
+    /// Creates an encoder like `new`, writing the tags as Vorbis comments
+    pub fn with_tags(
+        channels: Channels,
+        sample_rate: u32,
+        bitrate: u64,
+        tags: &[Tag],
+        sink: K,
+    ) -> Result<Self, EncodeError> {
+        Self::build(channels, sample_rate, bitrate, tags, None, sink)
+    }
+
+    /// Creates an encoder for live capture to network pipelines, where each `push` must finish
+    /// within `budget`
+    /// Chooses low delay settings: no bitrate reservoir holding packets back, and a page written
+    /// out for every packet
+    pub fn realtime(
+        channels: Channels,
+        sample_rate: u32,
+        bitrate: u64,
+        budget: Duration,
+        sink: K,
+    ) -> Result<Self, EncodeError> {
+        Self::build(channels, sample_rate, bitrate, &[], Some(budget), sink)
+    }
+
+    fn build(
+        channels: Channels,
+        sample_rate: u32,
+        bitrate: u64,
+        tags: &[Tag],
+        budget: Option<Duration>,
+        sink: K,
+    ) -> Result<Self, EncodeError> {
+        let (rate, channel_count, bitrate_nonzero) = vorbis_params(channels, sample_rate, bitrate)?;
+
+        let granule = Arc::new(AtomicU64::new(0));
+        let writer = SinkWriter {
+            sink,
+            granule: granule.clone(),
+        };
+        let mut builder = VorbisEncoderBuilder::new(rate, channel_count, writer)?;
+        if budget.is_some() {
+            builder
+                .bitrate_management_strategy(VorbisBitrateManagementStrategy::Vbr {
+                    target_bitrate: bitrate_nonzero,
+                })
+                .minimum_page_data_size(Some(1));
+        } else {
+            builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr {
+                average_bitrate: bitrate_nonzero,
+            });
+        }
+        let encoder = builder
+            .comment_tags(tags.iter().map(|t| (t.key.as_str(), t.value.as_str())))?
+            .build()
+            .map_err(|e| setup_error(e, bitrate))?;
+
+        Ok(Self {
+            encoder,
+            channels,
+            sample_rate,
+            pushed: 0,
+            granule,
+            budget,
+            missed_deadlines: 0,
+        })
+    }
+
+    /// Encodes the written samples of a buffer matching the encoder's channel layout and sample rate
+    /// In realtime mode a push taking longer than the budget still encodes all samples, then
+    /// returns `EncodeError::DeadlineMissed`
+    pub fn push<S: AudyoSample>(&mut self, samples: &SampleBuffer<S>) -> Result<(), EncodeError> {
+        if samples.channels != self.channels {
+            return Err(BufferError::ChannelsMismatch(self.channels, samples.channels).into());
+        }
+        if samples.sample_rate != self.sample_rate {
+            return Err(
+                BufferError::SampleRateMismatch(self.sample_rate, samples.sample_rate).into(),
+            );
+        }
+
+        let started = Instant::now();
+        let written = &samples.buffer[..samples.written];
+        for chunk in written.chunks(2048) {
+            crate::scheduler::yield_now();
+            let block: Vec<Vec<f32>> = deinterleave(chunk, samples.channels as usize)
+                .into_iter()
+                .map(|plane| plane.into_iter().map(IntoSample::into_sample).collect())
+                .collect();
+            self.encoder.encode_audio_block(block)?;
+        }
+        self.pushed += samples.written_frames() as u64;
+
+        match self.budget {
+            Some(budget) if started.elapsed() > budget => {
+                self.missed_deadlines += 1;
+                Err(EncodeError::DeadlineMissed(started.elapsed()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns how much of the pushed audio hasn't reached the sink yet
+    pub fn latency(&self) -> Duration {
+        let written = self.granule.load(Ordering::Relaxed);
+        TimeBase::new(1, self.sample_rate).duration(self.pushed.saturating_sub(written))
+    }
+
+    /// Returns number of pushes that took longer than the realtime budget
+    pub fn missed_deadlines(&self) -> u64 {
+        self.missed_deadlines
+    }
+
+    /// Ends the stream, finishes the sink and returns it
+    pub fn finish(self) -> Result<K, EncodeError> {
+        let SinkWriter { mut sink, .. } = self.encoder.finish()?;
+        sink.finish().map_err(VorbisError::from)?;
+        Ok(sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_vorbis, generate};
+
+    #[test]
+    fn sample_rates_outside_libvorbis_tuning() {
+        for rate in [0, 7_999, 50_001, 96_000, u32::MAX] {
+            assert!(matches!(
+                validate_vorbis_sample_rate(rate),
+                Err(EncodeError::UnsupportedSampleRate(r)) if r == rate
+            ));
+        }
+        for rate in [8_000, 44_100, 50_000] {
+            assert!(validate_vorbis_sample_rate(rate).is_ok());
+        }
+    }
+
+    #[test]
+    fn bitrates_depend_on_layout_and_sample_rate() {
+        assert!(validate_vorbis_bitrate(Channels::Mono, 44_100, 128_000).is_ok());
+        assert!(validate_vorbis_bitrate(Channels::Stereo, 8_000, 64_000).is_ok());
+        for (channels, rate, bitrate) in [
+            (Channels::Mono, 8_000, 64_000),
+            (Channels::Stereo, 44_100, 1_000),
+            (Channels::Stereo, 44_100, 0),
+            (Channels::Stereo, 44_100, u64::MAX),
+        ] {
+            assert!(matches!(
+                validate_vorbis_bitrate(channels, rate, bitrate),
+                Err(EncodeError::BitrateOutOfRange(b)) if b == bitrate
+            ));
+        }
+        assert!(matches!(
+            validate_vorbis_bitrate(Channels::Stereo, 96_000, 128_000),
+            Err(EncodeError::UnsupportedSampleRate(96_000))
+        ));
+    }
+
+    #[test]
+    fn encoders_fail_like_validation() {
+        let tone = |rate| generate::sine(440., Duration::from_millis(20), rate, Channels::Mono);
+        assert!(matches!(
+            encode_vorbis(&tone(8_000), 64_000),
+            Err(EncodeError::BitrateOutOfRange(64_000))
+        ));
+        assert!(matches!(
+            encode_vorbis(&tone(96_000), 128_000),
+            Err(EncodeError::UnsupportedSampleRate(96_000))
+        ));
+        assert!(matches!(
+            StreamingEncoder::realtime(Channels::Mono, 8_000, 64_000, Duration::MAX, Vec::new()),
+            Err(EncodeError::BitrateOutOfRange(64_000))
+        ));
+        assert!(encode_vorbis(&tone(8_000), 32_000).is_ok());
+    }
+
+    #[test]
+    fn pushes_only_written_frames() {
+        let mut partial = SampleBuffer::<f32>::new(800, Channels::Mono, 8_000);
+        partial.push_frames(&[0.5; 300]).unwrap();
+
+        let mut encoder = StreamingEncoder::new(Channels::Mono, 8_000, 32_000, Vec::new()).unwrap();
+        encoder.push(&partial).unwrap();
+        assert_eq!(encoder.pushed, 300);
+    }
+}
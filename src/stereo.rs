@@ -0,0 +1,101 @@
+use crate::{BufferError, Channels, SampleBuffer};
+use std::time::Duration;
+
+impl SampleBuffer<f32> {
+    /// Returns a stereo buffer converted to mid (first channel) and side (second channel)
+    pub fn to_mid_side(&self) -> Result<SampleBuffer<f32>, BufferError> {
+        self.require_stereo()?;
+
+        let mut converted = self.clone();
+        for frame in converted.buffer.chunks_exact_mut(2) {
+            let (left, right) = (frame[0], frame[1]);
+            frame[0] = (left + right) / 2.;
+            frame[1] = (left - right) / 2.;
+        }
+        Ok(converted)
+    }
+
+    /// Returns a mid/side buffer, as returned by `to_mid_side`, converted back to left and right
+    pub fn from_mid_side(&self) -> Result<SampleBuffer<f32>, BufferError> {
+        self.require_stereo()?;
+
+        let mut converted = self.clone();
+        for frame in converted.buffer.chunks_exact_mut(2) {
+            let (mid, side) = (frame[0], frame[1]);
+            frame[0] = mid + side;
+            frame[1] = mid - side;
+        }
+        Ok(converted)
+    }
+
+    /// Scales the side signal of a stereo buffer, 0 collapses it to mono, 1 leaves it unchanged
+    /// and larger values widen it
+    pub fn adjust_width(&mut self, width: f32) -> Result<(), BufferError> {
+        self.require_stereo()?;
+
+        for frame in self.buffer.chunks_exact_mut(2) {
+            let mid = (frame[0] + frame[1]) / 2.;
+            let side = (frame[0] - frame[1]) / 2. * width;
+            frame[0] = mid + side;
+            frame[1] = mid - side;
+        }
+        Ok(())
+    }
+
+    /// Returns the correlation between the left and right channel, from -1 to 1
+    /// Values near 1 are mono compatible, values below 0 partly cancel out when summed to mono
+    /// Mono and silent buffers are perfectly correlated
+    pub fn stereo_correlation(&self) -> f32 {
+        match self.channels {
+            Channels::Mono => 1.,
+            Channels::Stereo => correlation(&self.buffer[..self.written]),
+        }
+    }
+
+    /// Returns the stereo correlation of consecutive windows of the buffer, like a correlation
+    /// meter would show it over time
+    pub fn stereo_correlation_windows(&self, window: Duration) -> Vec<f32> {
+        let frames = ((window.as_secs_f64() * self.sample_rate as f64).round() as usize).max(1);
+        let channels = self.channels as usize;
+
+        self.buffer[..self.written]
+            .chunks(frames * channels)
+            .map(|window| match self.channels {
+                Channels::Mono => 1.,
+                Channels::Stereo => correlation(window),
+            })
+            .collect()
+    }
+
+    fn require_stereo(&self) -> Result<(), BufferError> {
+        if self.channels == Channels::Stereo {
+            Ok(())
+        } else {
+            Err(BufferError::ChannelsMismatch(
+                Channels::Stereo,
+                self.channels,
+            ))
+        }
+    }
+}
+
+/// Returns the correlation of interleaved stereo samples
+fn correlation(samples: &[f32]) -> f32 {
+    let (mut product, mut left, mut right) = (0f64, 0f64, 0f64);
+    for frame in samples.chunks_exact(2) {
+        let (l, r) = (frame[0] as f64, frame[1] as f64);
+        product += l * r;
+        left += l * l;
+        right += r * r;
+    }
+
+    let energy = (left * right).sqrt();
+    if energy > 0. {
+        (product / energy) as f32
+    } else if left + right > 0. {
+        // Only one channel carries signal
+        0.
+    } else {
+        1.
+    }
+}
@@ -0,0 +1,131 @@
+use rustfft::{Fft, FftPlanner};
+use std::{f32::consts::PI, sync::Arc};
+
+pub use rustfft::num_complex::Complex;
+
+/// Short-time Fourier transform using a Hann window
+#[derive(Clone)]
+pub struct Stft {
+    size: usize,
+    window: Vec<f32>,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+}
+
+impl Stft {
+    /// Creates a transform working on frames of the given size
+    pub fn new(size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+
+        Self {
+            size,
+            window: (0..size)
+                .map(|i| 0.5 - 0.5 * (2. * PI * i as f32 / size as f32).cos())
+                .collect(),
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size),
+        }
+    }
+
+    /// Returns frame size
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the analysis window
+    pub fn window(&self) -> &[f32] {
+        &self.window
+    }
+
+    /// Returns the spectrum of a windowed frame, zero-padded if shorter than frame size
+    pub fn forward(&self, frame: &[f32]) -> Vec<Complex<f32>> {
+        let mut spectrum: Vec<_> = self
+            .window
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| Complex::new(frame.get(i).copied().unwrap_or(0.) * w, 0.))
+            .collect();
+
+        self.forward.process(&mut spectrum);
+        spectrum
+    }
+
+    /// Returns the windowed frame back from a spectrum
+    pub fn inverse(&self, mut spectrum: Vec<Complex<f32>>) -> Vec<f32> {
+        self.inverse.process(&mut spectrum);
+
+        let scale = 1. / self.size as f32;
+        spectrum
+            .iter()
+            .zip(&self.window)
+            .map(|(c, &w)| c.re * scale * w)
+            .collect()
+    }
+
+    /// Returns spectra of frames `hop` samples apart covering the whole signal
+    pub fn spectrogram(&self, signal: &[f32], hop: usize) -> Vec<Vec<Complex<f32>>> {
+        (0..signal.len().max(1))
+            .step_by(hop.max(1))
+            .map(|start| self.forward(&signal[start..]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, Channels};
+    use std::time::Duration;
+
+    #[test]
+    fn inverse_overlap_adds_back_to_the_signal() {
+        let stft = Stft::new(1024);
+        let hop = stft.size() / 4;
+        let signal = generate::white_noise(Duration::from_millis(200), 48_000, Channels::Mono);
+        let signal = signal.samples();
+
+        // Windowed on analysis and synthesis, so the squared windows get normalized out
+        let mut output = vec![0.; signal.len() + stft.size()];
+        let mut norm = vec![0.; signal.len() + stft.size()];
+        for (frame, spectrum) in stft.spectrogram(signal, hop).into_iter().enumerate() {
+            let start = frame * hop;
+            for (i, s) in stft.inverse(spectrum).into_iter().enumerate() {
+                output[start + i] += s;
+                norm[start + i] += stft.window()[i].powi(2);
+            }
+        }
+
+        // The first frames only overlap partially
+        for i in stft.size()..signal.len() {
+            let sample = output[i] / norm[i];
+            assert!(
+                (sample - signal[i]).abs() < 1e-4,
+                "{i}: {sample} != {}",
+                signal[i]
+            );
+        }
+    }
+
+    #[test]
+    fn spectrum_of_a_tone_peaks_at_its_bin() {
+        let stft = Stft::new(1024);
+        // Bin 64 of a 1024 point transform at 48 kHz
+        let tone = generate::sine(3000., Duration::from_millis(50), 48_000, Channels::Mono);
+        let spectrum = stft.forward(tone.samples());
+        let peak = (0..stft.size() / 2)
+            .max_by(|&a, &b| spectrum[a].norm().total_cmp(&spectrum[b].norm()))
+            .unwrap();
+        assert_eq!(peak, 64);
+    }
+
+    #[test]
+    fn stretched_length_follows_the_factor() {
+        let buffer = generate::sine(440., Duration::from_millis(500), 44_100, Channels::Stereo);
+        for factor in [0.5, 0.8, 1.25, 2.] {
+            let stretched = buffer.time_stretch(factor);
+            let expected = (buffer.written_frames() as f32 * factor).round() as usize;
+            assert_eq!(stretched.written_frames(), expected, "{factor}");
+            assert_eq!(stretched.channels(), Channels::Stereo);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Memory backing the samples of a `SampleBuffer`
+
+use std::{
+    fmt, io,
+    mem::{align_of, size_of, ManuallyDrop},
+    ops::{Deref, DerefMut},
+};
+
+/// Samples either on the heap or in a memory-mapped temporary file
+pub(crate) enum Storage<S> {
+    Heap(Vec<S>),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(mapped::Mapped<S>),
+}
+
+impl<S: Copy> Storage<S> {
+    /// Resizes to `len` samples, filling new ones with `value`
+    /// Only fails for a mapping, if the file can't grow, e.g. with the disk full
+    pub(crate) fn resize(&mut self, len: usize, value: S) -> io::Result<()> {
+        match self {
+            Storage::Heap(vec) => {
+                vec.resize(len, value);
+                Ok(())
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped.resize(len, value),
+        }
+    }
+
+    /// Drops samples past `len`
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            Storage::Heap(vec) => vec.truncate(len),
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped.truncate(len),
+        }
+    }
+
+    /// Drops every sample, keeping the memory
+    pub(crate) fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the samples as a vector, copying them out of a mapping
+    pub(crate) fn into_vec(self) -> Vec<S> {
+        match self {
+            Storage::Heap(vec) => vec,
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped.to_vec(),
+        }
+    }
+
+    /// Converts every sample within the same memory
+    /// Panics unless both formats have the same size and alignment
+    pub(crate) fn map_in_place<T: Copy>(self, f: impl Fn(S) -> T) -> Storage<T> {
+        assert!(size_of::<S>() == size_of::<T>() && align_of::<S>() == align_of::<T>());
+
+        let convert = |ptr: *mut S, len: usize| {
+            for i in 0..len {
+                // SAFETY: i is in bounds and both formats have the same layout, so every slot
+                // holds either an S not yet converted or a T already written
+                unsafe {
+                    let sample = ptr.add(i).read();
+                    ptr.add(i).cast::<T>().write(f(sample));
+                }
+            }
+        };
+
+        match self {
+            Storage::Heap(vec) => {
+                let mut vec = ManuallyDrop::new(vec);
+                let (ptr, len, capacity) = (vec.as_mut_ptr(), vec.len(), vec.capacity());
+                convert(ptr, len);
+                // SAFETY: the allocation holds len initialized values of T, which has the size
+                // and alignment it was allocated with
+                Storage::Heap(unsafe { Vec::from_raw_parts(ptr.cast(), len, capacity) })
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mut mapped) => {
+                convert(mapped.as_mut_ptr(), mapped.len());
+                Storage::Mapped(mapped.cast())
+            }
+        }
+    }
+
+    /// Lets the kernel page out samples before `end`, once they won't be written again soon
+    /// Does nothing for heap memory
+    pub(crate) fn release(&mut self, end: usize) {
+        #[cfg(all(feature = "mmap", unix))]
+        if let Storage::Mapped(mapped) = self {
+            mapped.release(end);
+        }
+        #[cfg(not(all(feature = "mmap", unix)))]
+        let _ = end;
+    }
+
+    /// Returns whether the samples live in a memory-mapped file
+    pub(crate) fn is_mapped(&self) -> bool {
+        match self {
+            Storage::Heap(_) => false,
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(_) => true,
+        }
+    }
+}
+
+impl<S> From<Vec<S>> for Storage<S> {
+    fn from(vec: Vec<S>) -> Self {
+        Storage::Heap(vec)
+    }
+}
+
+impl<S> Deref for Storage<S> {
+    type Target = [S];
+
+    fn deref(&self) -> &[S] {
+        match self {
+            Storage::Heap(vec) => vec,
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped,
+        }
+    }
+}
+
+impl<S> DerefMut for Storage<S> {
+    fn deref_mut(&mut self) -> &mut [S] {
+        match self {
+            Storage::Heap(vec) => vec,
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped,
+        }
+    }
+}
+
+impl<S: Copy> Clone for Storage<S> {
+    fn clone(&self) -> Self {
+        match self {
+            Storage::Heap(vec) => Storage::Heap(vec.clone()),
+            // A clone of a mapped buffer is just as big, so it gets mapped too if possible
+            #[cfg(all(feature = "mmap", unix))]
+            Storage::Mapped(mapped) => mapped
+                .try_clone()
+                .map(Storage::Mapped)
+                .unwrap_or_else(|_| Storage::Heap(mapped.to_vec())),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Storage<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+pub(crate) mod mapped {
+    use std::{
+        fs::{self, File},
+        io,
+        mem::{size_of, ManuallyDrop},
+        ops::{Deref, DerefMut},
+        os::fd::AsRawFd,
+        path::{Path, PathBuf},
+        ptr::{self, NonNull},
+        slice,
+    };
+
+    /// Written samples are dropped from the resident set once this many bytes have piled up
+    const RELEASE_BYTES: usize = 16 << 20;
+
+    /// Growable array of samples in an unlinked temporary file, paged in and out by the kernel
+    pub(crate) struct Mapped<S> {
+        file: File,
+        dir: PathBuf,
+        ptr: NonNull<S>,
+        len: usize,
+        capacity: usize,
+        /// Samples past the longest length so far are still zeroed in the file
+        touched: usize,
+        /// Samples before this have been released
+        released: usize,
+    }
+
+    // SAFETY: the mapping is owned exclusively, like a Vec's allocation
+    unsafe impl<S: Send> Send for Mapped<S> {}
+    unsafe impl<S: Sync> Sync for Mapped<S> {}
+
+    impl<S: Copy> Mapped<S> {
+        /// Creates an empty array backed by a new file in dir, which is deleted right away
+        pub(crate) fn new(dir: &Path) -> io::Result<Self> {
+            let path = dir.join(format!(".audyo-{:016x}", rand::random::<u64>()));
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            fs::remove_file(&path)?;
+
+            Ok(Self {
+                file,
+                dir: dir.to_owned(),
+                ptr: NonNull::dangling(),
+                len: 0,
+                capacity: 0,
+                touched: 0,
+                released: 0,
+            })
+        }
+
+        pub(crate) fn try_clone(&self) -> io::Result<Self> {
+            let mut clone = Self::new(&self.dir)?;
+            clone.reserve(self.len)?;
+            clone.len = self.len;
+            clone.touched = self.len;
+            clone.copy_from_slice(self);
+            clone.release(self.len);
+            Ok(clone)
+        }
+
+        pub(crate) fn resize(&mut self, len: usize, value: S) -> io::Result<()> {
+            if len > self.len {
+                self.reserve(len)?;
+                // SAFETY: the range is within the mapping, and zeroed file contents are valid
+                // samples, so every element is initialized
+                let samples = unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), len) };
+                // Filling never written samples with zeroes would only page them in
+                let zeroed = self.touched.clamp(self.len, len);
+                samples[self.len..zeroed].fill(value);
+                if !is_zero(value) {
+                    samples[zeroed..].fill(value);
+                }
+                self.touched = self.touched.max(len);
+            }
+            self.len = len;
+            Ok(())
+        }
+
+        pub(crate) fn release(&mut self, end: usize) {
+            let end = end.min(self.len);
+            if end.saturating_sub(self.released) * size_of::<S>() < RELEASE_BYTES {
+                return;
+            }
+
+            // SAFETY: sysconf has no preconditions
+            let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+            let start = self.released * size_of::<S>() / page * page;
+            let stop = end * size_of::<S>() / page * page;
+            // SAFETY: the range is within the mapping, dropping pages of a shared file mapping
+            // keeps their contents, which get faulted back in from the file when touched
+            unsafe {
+                libc::madvise(
+                    self.ptr.as_ptr().cast::<u8>().add(start).cast(),
+                    stop - start,
+                    libc::MADV_DONTNEED,
+                );
+            }
+            self.released = end;
+        }
+
+        pub(crate) fn truncate(&mut self, len: usize) {
+            self.len = self.len.min(len);
+            self.released = self.released.min(self.len);
+        }
+
+        /// Grows the file and the mapping to hold at least `len` samples
+        fn reserve(&mut self, len: usize) -> io::Result<()> {
+            if len <= self.capacity {
+                return Ok(());
+            }
+            // Grow geometrically like a Vec, so appending stays amortized constant
+            let capacity = len.max(self.capacity * 2).max(4096 / size_of::<S>().max(1));
+            let bytes = capacity
+                .checked_mul(size_of::<S>())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "capacity overflow"))?;
+            let end = libc::off_t::try_from(bytes)
+                .map_err(|_| io::Error::new(io::ErrorKind::OutOfMemory, "capacity overflow"))?;
+            // Allocating the blocks upfront, unlike only setting the length, makes a full disk
+            // fail here rather than fault on a later write to the mapping
+            // SAFETY: posix_fallocate has no memory safety preconditions
+            let result = unsafe { libc::posix_fallocate(self.file.as_raw_fd(), 0, end) };
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result));
+            }
+
+            // SAFETY: mapping a file this struct owns, at an address picked by the kernel
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    bytes,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    self.file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Both mappings show the same file, so the samples are already in place
+            self.unmap();
+            self.ptr = NonNull::new(ptr.cast()).ok_or_else(io::Error::last_os_error)?;
+            self.capacity = capacity;
+            Ok(())
+        }
+    }
+
+    impl<S> Mapped<S> {
+        /// Reinterprets the samples as another format of the same size and alignment
+        pub(crate) fn cast<T>(self) -> Mapped<T> {
+            let this = ManuallyDrop::new(self);
+            Mapped {
+                // SAFETY: this is never used or dropped again, so the file moves exactly once
+                file: unsafe { ptr::read(&this.file) },
+                // SAFETY: as above
+                dir: unsafe { ptr::read(&this.dir) },
+                ptr: this.ptr.cast(),
+                len: this.len,
+                capacity: this.capacity,
+                touched: this.touched,
+                released: this.released,
+            }
+        }
+
+        fn unmap(&mut self) {
+            if self.capacity > 0 {
+                // SAFETY: the pointer and length are those of the current mapping
+                unsafe {
+                    libc::munmap(self.ptr.as_ptr().cast(), self.capacity * size_of::<S>());
+                }
+            }
+        }
+    }
+
+    fn is_zero<S: Copy>(value: S) -> bool {
+        // SAFETY: samples are plain numbers without padding, so every byte is initialized
+        let bytes =
+            unsafe { slice::from_raw_parts(ptr::addr_of!(value).cast::<u8>(), size_of::<S>()) };
+        bytes.iter().all(|&b| b == 0)
+    }
+
+    impl<S> Deref for Mapped<S> {
+        type Target = [S];
+
+        fn deref(&self) -> &[S] {
+            // SAFETY: the first len samples are initialized and within the mapping
+            unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+        }
+    }
+
+    impl<S> DerefMut for Mapped<S> {
+        fn deref_mut(&mut self) -> &mut [S] {
+            // SAFETY: as in deref, and the mapping is owned exclusively
+            unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+        }
+    }
+
+    impl<S> Drop for Mapped<S> {
+        fn drop(&mut self) {
+            self.unmap();
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    channels, probe_format, sample_rate, AudyoSample, DecodeError, DecodeOptions, SampleBuffer,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+use symphonia::core::{errors::Error as SymphoniaError, io::ReadOnlySource};
+
+#[derive(Default)]
+struct State {
+    data: VecDeque<u8>,
+    /// No more bytes will be pushed
+    finished: bool,
+    /// The decoder thread is blocked waiting for bytes
+    waiting: bool,
+    /// The decoder thread has exited
+    done: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// Blocking reader over the pushed bytes, returns EOF once input is finished
+struct ChunkReader(Arc<Shared>);
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.0.state.lock().unwrap();
+
+        loop {
+            if !state.data.is_empty() {
+                let n = buf.len().min(state.data.len());
+                for (b, d) in buf.iter_mut().zip(state.data.drain(..n)) {
+                    *b = d;
+                }
+                return Ok(n);
+            }
+
+            if state.finished {
+                return Ok(0);
+            }
+
+            state.waiting = true;
+            self.0.changed.notify_all();
+            state = self.0.changed.wait(state).unwrap();
+            state.waiting = false;
+        }
+    }
+}
+
+/// Decoder fed incrementally with raw bytes, e.g. received over a network
+/// Decoded frames become available as soon as the pushed bytes contain whole packets
+pub struct StreamingDecoder<S: AudyoSample> {
+    shared: Arc<Shared>,
+    chunks: Receiver<Result<SampleBuffer<S>, DecodeError>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: AudyoSample + Send + 'static> StreamingDecoder<S> {
+    /// Creates a decoder waiting for input
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+
+    /// Creates a decoder waiting for input using given options
+    pub fn with_options(options: DecodeOptions) -> Self {
+        let shared = Arc::new(Shared::default());
+        let (sender, chunks) = mpsc::channel();
+
+        let reader = ChunkReader(shared.clone());
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            if let Err(e) = run(reader, &options, &sender) {
+                let _ = sender.send(Err(e));
+            }
+            thread_shared.update(|s| s.done = true);
+        });
+
+        Self {
+            shared,
+            chunks,
+            thread: Some(thread),
+        }
+    }
+
+    /// Feeds bytes to the decoder
+    /// Returns once all complete packets in the input so far have been decoded
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+
+        state.data.extend(bytes);
+        self.shared.changed.notify_all();
+
+        while !(state.done || state.data.is_empty() && state.waiting) {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Signals the end of input and waits for the remaining data to be decoded
+    pub fn finish(&mut self) {
+        self.shared.update(|s| s.finished = true);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Returns the next decoded chunk of frames, if one is available
+    pub fn next_chunk(&mut self) -> Option<Result<SampleBuffer<S>, DecodeError>> {
+        self.chunks.try_recv().ok()
+    }
+}
+
+impl<S: AudyoSample + Send + 'static> Default for StreamingDecoder<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: AudyoSample> Drop for StreamingDecoder<S> {
+    fn drop(&mut self) {
+        self.shared.update(|s| s.finished = true);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<S: AudyoSample>(
+    reader: ChunkReader,
+    options: &DecodeOptions,
+    sender: &Sender<Result<SampleBuffer<S>, DecodeError>>,
+) -> Result<(), DecodeError> {
+    let mut reader = probe_format(Box::new(ReadOnlySource::new(reader)), options)?.format;
+
+    let track = options.select_track(&*reader)?;
+    let id = track.id;
+    let channels = channels(&track.codec_params, options.downmix)?;
+    let sample_rate = sample_rate(&track.codec_params)?;
+
+    let mut decoder = options
+        .codecs()
+        .make(&track.codec_params, &options.decoder_options)?;
+
+    while let Ok(packet) = reader.next_packet() {
+        if packet.track_id() != id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let mut chunk = SampleBuffer::new(0, channels, sample_rate);
+                chunk.copy_samples(decoded)?;
+
+                if sender.send(Ok(chunk)).is_err() {
+                    break;
+                }
+            }
+            Err(SymphoniaError::DecodeError(_)) => (),
+            _ => break,
+        }
+    }
+
+    Ok(())
+}
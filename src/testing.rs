@@ -0,0 +1,140 @@
+//! Metrics and assertions for validating pipelines built on audyo
+
+use crate::{
+    decode, encode_wav, stft::Stft, util::deinterleave, SampleBuffer, WavFormat, WavOptions,
+};
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+/// Spectral frames are compared with this FFT size
+const FFT_SIZE: usize = 2048;
+/// Distance between consecutive spectral frames
+const HOP: usize = 512;
+/// Spectral levels are floored here, so that noise in silent parts doesn't dominate
+const FLOOR_DB: f64 = -80.;
+/// Setting this environment variable rewrites golden files instead of comparing against them
+pub const UPDATE_GOLDEN_VAR: &str = "AUDYO_UPDATE_GOLDEN";
+
+/// Returns the peak signal to noise ratio in dB between two buffers, relative to full scale
+/// Only the frames both buffers have are compared
+pub fn psnr(reference: &SampleBuffer<f32>, other: &SampleBuffer<f32>) -> f64 {
+    let len = reference.buffer.len().min(other.buffer.len());
+    if len == 0 {
+        return f64::INFINITY;
+    }
+
+    let error: f64 = reference.buffer[..len]
+        .iter()
+        .zip(&other.buffer[..len])
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    let mse = error / len as f64;
+
+    if mse == 0. {
+        f64::INFINITY
+    } else {
+        -10. * mse.log10()
+    }
+}
+
+/// Returns the log spectral distance in dB between two buffers, averaged over frames and channels
+/// Unlike `psnr` this ignores phase, so it suits comparing the output of lossy codecs
+pub fn spectral_difference(reference: &SampleBuffer<f32>, other: &SampleBuffer<f32>) -> f64 {
+    let frames = reference.buffer.len().min(other.buffer.len()) / reference.channels as usize;
+    if frames == 0 {
+        return 0.;
+    }
+
+    let stft = Stft::new(FFT_SIZE);
+    let len = frames * reference.channels as usize;
+    let a = deinterleave(&reference.buffer[..len], reference.channels as usize);
+    let b = deinterleave(&other.buffer[..len], other.channels as usize);
+
+    let mut total = 0.;
+    let mut count = 0;
+    for (a, b) in a.iter().zip(&b) {
+        for (a, b) in stft
+            .spectrogram(a, HOP)
+            .iter()
+            .zip(&stft.spectrogram(b, HOP))
+        {
+            let squared: f64 = a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (level_db(a.norm()) - level_db(b.norm())).powi(2))
+                .sum();
+            total += (squared / a.len() as f64).sqrt();
+            count += 1;
+        }
+    }
+
+    total / count as f64
+}
+
+/// Converts an FFT bin magnitude to dB relative to a full scale sine
+fn level_db(magnitude: f32) -> f64 {
+    // A full scale sine peaks at a quarter of the FFT size with a Hann window
+    let relative = magnitude as f64 / (FFT_SIZE as f64 / 4.);
+    (20. * relative.max(f64::MIN_POSITIVE).log10()).max(FLOOR_DB)
+}
+
+/// Panics unless a decoded buffer matches the original within `tolerance_db` of spectral
+/// difference, sample rates and channel layouts must match too
+pub fn assert_roundtrip_close(
+    original: &SampleBuffer<f32>,
+    decoded: &SampleBuffer<f32>,
+    tolerance_db: f64,
+) {
+    assert_eq!(
+        original.channels, decoded.channels,
+        "channel layouts differ"
+    );
+    assert_eq!(
+        original.sample_rate, decoded.sample_rate,
+        "sample rates differ"
+    );
+
+    let difference = spectral_difference(original, decoded);
+    assert!(
+        difference <= tolerance_db,
+        "spectral difference of {difference:.2} dB exceeds tolerance of {tolerance_db:.2} dB"
+    );
+}
+
+/// Panics unless the buffer matches the golden file at `path` within `tolerance_db`
+/// Missing golden files are created, set `AUDYO_UPDATE_GOLDEN` to rewrite existing ones
+pub fn assert_matches_golden(
+    buffer: &SampleBuffer<f32>,
+    path: impl AsRef<Path>,
+    tolerance_db: f64,
+) {
+    let path = path.as_ref();
+    if !path.exists() || std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        write_wav(buffer, path)
+            .unwrap_or_else(|e| panic!("couldn't write golden file {}: {e}", path.display()));
+        return;
+    }
+
+    let file = File::open(path)
+        .unwrap_or_else(|e| panic!("couldn't open golden file {}: {e}", path.display()));
+    let (_, golden) = decode::<f32>(file)
+        .unwrap_or_else(|e| panic!("couldn't decode golden file {}: {e}", path.display()));
+
+    assert_eq!(
+        golden.duration,
+        buffer.duration,
+        "duration differs from golden file {}",
+        path.display()
+    );
+    assert_roundtrip_close(&golden, buffer, tolerance_db);
+}
+
+/// Writes the buffer as a 32 bit float WAV file
+fn write_wav(buffer: &SampleBuffer<f32>, path: &Path) -> io::Result<()> {
+    let options = WavOptions::new().format(WavFormat::Float32);
+    let wav = encode_wav(buffer, &options).map_err(io::Error::other)?;
+    fs::write(path, wav)
+}
@@ -0,0 +1,921 @@
+use crate::{
+    container::Codec,
+    decode, decode_with, encode_vorbis,
+    metadata::{chapter_tags, is_chapter_tag, padding_tag, PADDING_KEY},
+    sink::encode_vorbis_tagged,
+    AudyoSample, BufferError, Channels, Chapter, DecodeError, DecodeOptions, EncodeError, Metadata,
+    Padding, Picture, SampleBuffer, Tag, TimeBase,
+};
+use std::{borrow::Cow, fmt, io::Cursor, ops::Range, sync::Arc, time::Duration};
+use symphonia::core::{conv::IntoSample, io::MediaSource};
+use thiserror::Error;
+
+/// Loudness two-pass encodes normalize to by default, in LUFS, what streaming services play at
+const DEFAULT_TARGET_LOUDNESS: f32 = -14.;
+/// True peak ceiling two-pass encodes are limited to after normalizing, in dBFS, leaving room
+/// for the lossy encoder's overshoot
+const TWO_PASS_CEILING_DB: f32 = -1.;
+/// Complexity of typical music, which suggested bitrates are tuned for
+const TYPICAL_COMPLEXITY: f64 = 0.15;
+/// Range an automatic bitrate may be scaled within by a two-pass encode
+const BITRATE_SCALE: Range<f64> = 0.75..1.25;
+
+/// Kind of content being encoded, speech holds up at much lower bitrates than music
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContentHint {
+    Speech,
+    #[default]
+    Music,
+}
+
+/// Named settings for common kinds of encodes, applied with `TranscodeOptions::preset`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Spoken word in mono at 48 kbps, normalized to -16 LUFS as podcast platforms recommend
+    PodcastMono,
+    /// Music at 192 kbps, normalized to -14 LUFS
+    MusicHq,
+    /// Renditions at 64, 96, 128 and 192 kbps for adaptive streaming, normalized to -14 LUFS
+    StreamingLadder,
+}
+
+impl Preset {
+    /// Returns the bitrate of each rendition, lowest first
+    pub fn bitrates(self) -> &'static [u64] {
+        match self {
+            Preset::PodcastMono => &[48_000],
+            Preset::MusicHq => &[192_000],
+            Preset::StreamingLadder => &[64_000, 96_000, 128_000, 192_000],
+        }
+    }
+
+    /// Returns the channel layout the preset encodes to, None keeping the source's
+    pub fn channels(self) -> Option<Channels> {
+        match self {
+            Preset::PodcastMono => Some(Channels::Mono),
+            Preset::MusicHq | Preset::StreamingLadder => None,
+        }
+    }
+
+    /// Returns the integrated loudness the preset normalizes to, in LUFS
+    pub fn target_loudness(self) -> f32 {
+        match self {
+            Preset::PodcastMono => -16.,
+            Preset::MusicHq | Preset::StreamingLadder => -14.,
+        }
+    }
+
+    /// Returns the kind of content the preset is meant for
+    pub fn content(self) -> ContentHint {
+        match self {
+            Preset::PodcastMono => ContentHint::Speech,
+            Preset::MusicHq | Preset::StreamingLadder => ContentHint::Music,
+        }
+    }
+
+    /// Returns options for each rendition, lowest bitrate first, e.g. for `transcode_multi`
+    pub fn targets(self) -> Vec<TranscodeOptions> {
+        self.bitrates()
+            .iter()
+            .map(|&bitrate| TranscodeOptions::new().preset(self).bitrate(bitrate))
+            .collect()
+    }
+}
+
+/// Returns a sensible average bitrate for encoding content with the given codec
+pub fn suggest_bitrate(
+    codec: Codec,
+    channels: Channels,
+    sample_rate: u32,
+    content: ContentHint,
+) -> u32 {
+    let is_stereo = channels == Channels::Stereo;
+
+    match codec {
+        // Opus resamples internally, so only content and channels matter
+        Codec::Opus => match (content, is_stereo) {
+            (ContentHint::Speech, false) => 24_000,
+            (ContentHint::Speech, true) => 32_000,
+            (ContentHint::Music, false) => 64_000,
+            (ContentHint::Music, true) => 96_000,
+        },
+        // AAC-LC needs more than Opus for the same quality
+        Codec::Aac => match (content, is_stereo) {
+            (ContentHint::Speech, false) => 32_000,
+            (ContentHint::Speech, true) => 48_000,
+            (ContentHint::Music, false) => 64_000,
+            (ContentHint::Music, true) => 128_000,
+        },
+        // Tiers stay within the range libvorbis accepts for each sample rate
+        Codec::Vorbis => {
+            let (mono, stereo) = match (content, sample_rate) {
+                (ContentHint::Music, 32_000..) => (80_000, 128_000),
+                (ContentHint::Music, 22_050..) => (56_000, 80_000),
+                (ContentHint::Music, 16_000..) => (48_000, 64_000),
+                (ContentHint::Music, 11_025..) => (32_000, 48_000),
+                (ContentHint::Music, _) => (16_000, 24_000),
+                (ContentHint::Speech, 32_000..) => (40_000, 64_000),
+                (ContentHint::Speech, 22_050..) => (32_000, 48_000),
+                (ContentHint::Speech, 16_000..) => (24_000, 32_000),
+                (ContentHint::Speech, 11_025..) => (16_000, 24_000),
+                (ContentHint::Speech, _) => (12_000, 16_000),
+            };
+            if is_stereo {
+                stereo
+            } else {
+                mono
+            }
+        }
+    }
+}
+
+/// Options controlling `transcode_vorbis` and `transcode_multi`
+#[derive(Clone, Debug, Default)]
+pub struct TranscodeOptions {
+    decode: DecodeOptions,
+    bitrate: Bitrate,
+    content: ContentHint,
+    artwork: Option<ArtworkHook>,
+    trim: Option<Range<Duration>>,
+    #[cfg(feature = "dsp")]
+    tempo: Option<f32>,
+    #[cfg(feature = "resampler")]
+    sample_rate: Option<u32>,
+    channels: Option<Channels>,
+    two_pass: bool,
+    target_loudness: Option<f32>,
+    pad_start: Duration,
+    pad_end: Duration,
+}
+
+#[derive(Clone)]
+struct ArtworkHook(Arc<dyn Fn(Picture) -> Option<Picture> + Send + Sync>);
+
+impl fmt::Debug for ArtworkHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ArtworkHook")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum Bitrate {
+    #[default]
+    Auto,
+    Source,
+    Fixed(u64),
+}
+
+impl TranscodeOptions {
+    /// Creates default options, picking the bitrate automatically
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets options used for decoding the source
+    pub fn decode_options(mut self, options: DecodeOptions) -> Self {
+        self.decode = options;
+        self
+    }
+
+    /// Sets the filename extension hint used for probing the source
+    pub fn hint_extension(mut self, extension: impl Into<String>) -> Self {
+        self.decode = self.decode.hint_extension(extension);
+        self
+    }
+
+    /// Encodes at the given average bitrate
+    pub fn bitrate(mut self, bitrate: u64) -> Self {
+        self.bitrate = Bitrate::Fixed(bitrate);
+        self
+    }
+
+    /// Encodes at the bitrate of the source's audio stream
+    pub fn source_bitrate(mut self) -> Self {
+        self.bitrate = Bitrate::Source;
+        self
+    }
+
+    /// Encodes at a bitrate suggested for the output's format and content
+    pub fn auto_bitrate(mut self) -> Self {
+        self.bitrate = Bitrate::Auto;
+        self
+    }
+
+    /// Sets the kind of content, used for picking the bitrate automatically
+    pub fn content(mut self, content: ContentHint) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Passes each embedded picture through the hook before writing it, e.g. to re-encode or
+    /// downscale it, returning None drops the picture
+    pub fn artwork(
+        mut self,
+        hook: impl Fn(Picture) -> Option<Picture> + Send + Sync + 'static,
+    ) -> Self {
+        self.artwork = Some(ArtworkHook(Arc::new(hook)));
+        self
+    }
+
+    /// Keeps only the audio from `start` to `end`, cut at exact frames
+    /// The source gets seeked to the start instead of decoded from the beginning, and chapters
+    /// are moved to match the output. Only used by `transcode_vorbis` and `transcode_multi`
+    pub fn trim(mut self, start: Duration, end: Duration) -> Self {
+        self.trim = Some(start..end.max(start));
+        self
+    }
+
+    /// Speeds playback up by `factor` without changing the pitch, e.g. 1.5 for audiobooks, or
+    /// slows it down with factors below 1. Chapters are moved to match the output. Only used by
+    /// `transcode_vorbis` and `transcode_multi`
+    #[cfg(feature = "dsp")]
+    pub fn tempo(mut self, factor: f32) -> Self {
+        self.tempo = (factor.is_finite() && factor > 0. && factor != 1.).then_some(factor);
+        self
+    }
+
+    /// Resamples the output to the given sample rate, e.g. 48 kHz renditions of a 44.1 kHz
+    /// source. Only used by `transcode_vorbis` and `transcode_multi`
+    #[cfg(feature = "resampler")]
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Applies a preset's bitrate, channel layout and loudness target, a ladder preset using its
+    /// highest rendition, see `Preset::targets` for all of them
+    /// Options set afterwards override the preset's
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.bitrate = Bitrate::Fixed(*preset.bitrates().last().expect("presets have renditions"));
+        self.content = preset.content();
+        self.channels = preset.channels();
+        self.two_pass = true;
+        self.target_loudness = Some(preset.target_loudness());
+        self
+    }
+
+    /// Sets the channel layout of the output, mixing stereo down to mono or copying mono to
+    /// both sides
+    pub fn channels(mut self, channels: Channels) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    /// Analyzes the audio before encoding it: its loudness gets normalized to the target,
+    /// -14 LUFS by default, with a limiter keeping true peaks below -1 dBFS, and an automatically
+    /// picked bitrate gets scaled by how complex the audio is, down for e.g. speech or sparse
+    /// instrumentation and up for dense, bright mixes
+    pub fn two_pass(mut self, two_pass: bool) -> Self {
+        self.two_pass = two_pass;
+        self
+    }
+
+    /// Sets the loudness in LUFS a two-pass encode normalizes to
+    pub fn target_loudness(mut self, lufs: f32) -> Self {
+        self.target_loudness = Some(lufs);
+        self
+    }
+
+    /// Adds silence lasting `duration` before the audio, recorded in an iTunSMPB tag so that
+    /// gapless-aware players can skip it. Chapters are moved to match the output
+    pub fn pad_start(mut self, duration: Duration) -> Self {
+        self.pad_start = duration;
+        self
+    }
+
+    /// Adds silence lasting `duration` after the audio, recorded like `pad_start`
+    pub fn pad_end(mut self, duration: Duration) -> Self {
+        self.pad_end = duration;
+        self
+    }
+
+    /// Returns the options the source gets decoded with, decoding only the trimmed range
+    fn decoding(&self) -> DecodeOptions {
+        self.decode.clone().range(self.trim.clone())
+    }
+
+    fn tempo_factor(&self) -> Option<f32> {
+        #[cfg(feature = "dsp")]
+        return self.tempo;
+        #[cfg(not(feature = "dsp"))]
+        None
+    }
+
+    fn output_rate(&self) -> Option<u32> {
+        #[cfg(feature = "resampler")]
+        return self.sample_rate;
+        #[cfg(not(feature = "resampler"))]
+        None
+    }
+
+    /// Returns the decoded audio resampled to the output rate and time stretched according to
+    /// the tempo
+    fn process(&self, buffer: SampleBuffer<f32>) -> SampleBuffer<f32> {
+        let buffer = match self.output_rate() {
+            #[cfg(feature = "resampler")]
+            Some(rate) if rate != buffer.sample_rate => {
+                buffer.resampled(rate, crate::resample::Quality::default())
+            }
+            _ => buffer,
+        };
+        match self.tempo_factor() {
+            #[cfg(feature = "dsp")]
+            Some(tempo) => buffer.time_stretch(1. / tempo),
+            _ => buffer,
+        }
+    }
+
+    /// Encodes the processed audio of a source with the given metadata and bitrate
+    fn encode(
+        &self,
+        buffer: &SampleBuffer<f32>,
+        metadata: &Metadata,
+        source_bitrate: u64,
+    ) -> Result<Vec<u8>, TranscodeError> {
+        let chapters = self.output_chapters(&metadata.chapters);
+        self.encode_tagged(
+            buffer,
+            &self.tags(metadata, chapters.as_deref()),
+            source_bitrate,
+        )
+    }
+
+    /// Encodes the processed audio with the given tags, padding it and running the analysis
+    /// pass first if needed
+    fn encode_tagged(
+        &self,
+        buffer: &SampleBuffer<f32>,
+        tags: &[Tag],
+        source_bitrate: u64,
+    ) -> Result<Vec<u8>, TranscodeError> {
+        let mut tags = tags.to_vec();
+        let buffer = match self.channels {
+            Some(channels) if channels != buffer.channels => {
+                Cow::Owned(convert_channels(buffer, channels))
+            }
+            _ => Cow::Borrowed(buffer),
+        };
+        let buffer = if self.pad_start.is_zero() && self.pad_end.is_zero() {
+            buffer
+        } else {
+            let mut padded = buffer.clone().into_owned();
+            padded.pad_start(self.pad_start)?;
+            padded.pad_end(self.pad_end)?;
+            // Padding of the source doesn't apply to the output
+            tags.retain(|tag| !tag.key.eq_ignore_ascii_case(PADDING_KEY));
+            tags.push(padding_tag(&Padding {
+                start: buffer.frames_in(self.pad_start) as u64,
+                end: buffer.frames_in(self.pad_end) as u64,
+                frames: buffer.written_frames() as u64,
+            }));
+            Cow::Owned(padded)
+        };
+
+        if !self.two_pass {
+            let bitrate = self.pick_bitrate(&buffer, source_bitrate);
+            return Ok(encode_vorbis_tagged(&buffer, bitrate, &tags, Vec::new())?);
+        }
+
+        let analysis = Analysis::new(&buffer);
+        let mut buffer = buffer.into_owned();
+        if let Some(loudness) = analysis.loudness {
+            let target = self.target_loudness.unwrap_or(DEFAULT_TARGET_LOUDNESS);
+            let gain = 10f32.powf((target - loudness) / 20.);
+            buffer.buffer.iter_mut().for_each(|sample| *sample *= gain);
+            buffer.limit(TWO_PASS_CEILING_DB);
+        }
+
+        let mut bitrate = self.pick_bitrate(&buffer, source_bitrate);
+        if matches!(self.bitrate, Bitrate::Auto) {
+            bitrate = (bitrate as f64 * analysis.bitrate_scale()).round() as u64;
+        }
+        Ok(encode_vorbis_tagged(&buffer, bitrate, &tags, Vec::new())?)
+    }
+
+    /// Returns the chapters moved to match the output, None if it keeps source's timing
+    fn output_chapters(&self, chapters: &[Chapter]) -> Option<Vec<Chapter>> {
+        let tempo = self.tempo_factor();
+        if self.trim.is_none() && tempo.is_none() && self.pad_start.is_zero() {
+            return None;
+        }
+
+        let chapters = match &self.trim {
+            Some(range) => trim_chapters(chapters, range),
+            None => chapters.to_vec(),
+        };
+        Some(
+            chapters
+                .into_iter()
+                .map(|c| Chapter {
+                    start: c.start.div_f64(tempo.unwrap_or(1.) as f64) + self.pad_start,
+                    ..c
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the tags to write, with the pictures passed through the artwork hook and the
+    /// chapter tags replaced if given
+    fn tags(&self, metadata: &Metadata, chapters: Option<&[Chapter]>) -> Vec<Tag> {
+        let pictures =
+            metadata
+                .pictures
+                .iter()
+                .cloned()
+                .filter_map(|picture| match &self.artwork {
+                    Some(hook) => (hook.0)(picture),
+                    None => Some(picture),
+                });
+
+        let Some(chapters) = chapters else {
+            return metadata
+                .tags
+                .iter()
+                .cloned()
+                .chain(pictures.map(|picture| picture.to_tag()))
+                .collect();
+        };
+
+        metadata
+            .tags
+            .iter()
+            .filter(|tag| !is_chapter_tag(tag))
+            .cloned()
+            .chain(chapter_tags(chapters))
+            .chain(pictures.map(|picture| picture.to_tag()))
+            .collect()
+    }
+
+    fn pick_bitrate(&self, buffer: &SampleBuffer<f32>, source_bitrate: u64) -> u64 {
+        match self.bitrate {
+            Bitrate::Auto => suggest_bitrate(
+                Codec::Vorbis,
+                buffer.channels,
+                buffer.sample_rate,
+                self.content,
+            ) as u64,
+            Bitrate::Source => source_bitrate,
+            Bitrate::Fixed(bitrate) => bitrate,
+        }
+    }
+}
+
+/// Measurements of the analysis pass of a two-pass encode
+struct Analysis {
+    /// Integrated loudness, None for silence
+    loudness: Option<f32>,
+    /// How much of the energy sits in high frequencies, from 0 for DC to 1 at Nyquist,
+    /// `sin(pi * freq / sample_rate)` for a pure tone
+    complexity: f64,
+}
+
+impl Analysis {
+    fn new(buffer: &SampleBuffer<f32>) -> Self {
+        let channels = buffer.channels as usize;
+        let (mut energy, mut difference) = (0f64, 0f64);
+        for (frame, next) in buffer
+            .samples()
+            .chunks_exact(channels)
+            .zip(buffer.samples().chunks_exact(channels).skip(1))
+        {
+            for (&x, &y) in frame.iter().zip(next) {
+                energy += (x as f64).powi(2);
+                difference += (y as f64 - x as f64).powi(2);
+            }
+        }
+
+        Self {
+            loudness: buffer.loudness(),
+            complexity: if energy > 0. {
+                (difference / (4. * energy)).sqrt()
+            } else {
+                0.
+            },
+        }
+    }
+
+    /// Returns the factor a suggested bitrate gets scaled by
+    fn bitrate_scale(&self) -> f64 {
+        (self.complexity / TYPICAL_COMPLEXITY)
+            .sqrt()
+            .clamp(BITRATE_SCALE.start, BITRATE_SCALE.end)
+    }
+}
+
+/// Returns the chapters overlapping the range, relative to its start
+/// The chapter playing at the start of the range moves to the start of the output
+fn trim_chapters(chapters: &[Chapter], range: &Range<Duration>) -> Vec<Chapter> {
+    let first = chapters
+        .iter()
+        .rposition(|c| c.start <= range.start)
+        .unwrap_or(0);
+
+    chapters[first..]
+        .iter()
+        .filter(|c| c.start < range.end)
+        .map(|c| Chapter {
+            start: c.start.saturating_sub(range.start),
+            ..c.clone()
+        })
+        .collect()
+}
+
+/// Decodes an audio file in source and encodes it using ogg vorbis, keeping its tags and
+/// artwork
+pub fn transcode_vorbis(
+    source: impl MediaSource + 'static,
+    options: &TranscodeOptions,
+) -> Result<Vec<u8>, TranscodeError> {
+    let (info, buffer) = decode_with::<f32>(source, &options.decoding())?;
+
+    options.encode(
+        &options.process(buffer),
+        &info.metadata,
+        info.stream_bitrate,
+    )
+}
+
+/// Decodes the source once and encodes it once per target, e.g. renditions at several
+/// bitrates, returning the outputs in the order of the targets
+/// Every target is encoded using ogg vorbis, the only encoder so far
+/// The source is decoded once per distinct decode options and trim among the targets, read into
+/// memory first if that's more than once. Resampling and tempo changes are done once for all
+/// targets sharing them
+pub fn transcode_multi(
+    source: impl MediaSource + 'static,
+    targets: &[TranscodeOptions],
+) -> Result<Vec<Vec<u8>>, TranscodeError> {
+    if targets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut source = source;
+    // Decode options of each distinct configuration among the targets, trim included
+    let mut configurations: Vec<DecodeOptions> = Vec::new();
+    for options in targets {
+        let decoding = options.decoding();
+        if !configurations.iter().any(|c| c.decodes_like(&decoding)) {
+            configurations.push(decoding);
+        }
+    }
+
+    // Sources decoded more than once are read into memory first, since they can only be read
+    // through once
+    let mut decoded = Vec::new();
+    if let [options] = &configurations[..] {
+        decoded.push(decode_with::<f32>(source, options)?);
+    } else {
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes).map_err(DecodeError::Io)?;
+        for options in &configurations {
+            decoded.push(decode_with::<f32>(Cursor::new(bytes.clone()), options)?);
+        }
+    }
+
+    // Audio processed for each distinct combination of decoding, output rate and tempo, so
+    // targets sharing a rate share its resampling
+    let mut processed: Vec<(_, _, _, SampleBuffer<f32>)> = Vec::new();
+
+    targets
+        .iter()
+        .map(|options| {
+            let decoding = options.decoding();
+            let d = configurations
+                .iter()
+                .position(|c| c.decodes_like(&decoding))
+                .expect("every target's configuration is listed");
+            let (info, buffer) = &decoded[d];
+            let (rate, tempo) = (options.output_rate(), options.tempo_factor());
+            let i = match processed
+                .iter()
+                .position(|p| p.0 == d && p.1 == rate && p.2 == tempo)
+            {
+                Some(i) => i,
+                None => {
+                    processed.push((d, rate, tempo, options.process(buffer.clone())));
+                    processed.len() - 1
+                }
+            };
+            options.encode(&processed[i].3, &info.metadata, info.stream_bitrate)
+        })
+        .collect()
+}
+
+/// Returns the buffer mixed down to mono, or with mono copied to both sides
+fn convert_channels(buffer: &SampleBuffer<f32>, channels: Channels) -> SampleBuffer<f32> {
+    let samples = match channels {
+        Channels::Mono => buffer
+            .samples()
+            .chunks_exact(2)
+            .map(|frame| (frame[0] + frame[1]) / 2.)
+            .collect(),
+        Channels::Stereo => buffer.samples().iter().flat_map(|&s| [s, s]).collect(),
+    };
+    SampleBuffer::from_samples(samples, channels, buffer.sample_rate)
+}
+
+/// Decodes audio files and encodes them back to back into a single ogg vorbis stream, keeping
+/// the tags and artwork of the first one
+/// Joins are sample accurate. All sources must share a channel layout, and a sample rate
+/// unless the `resampler` feature is enabled, in which case they get resampled to the rate of
+/// the first one
+pub fn concat_transcode<M: MediaSource + 'static>(
+    sources: impl IntoIterator<Item = M>,
+    options: &TranscodeOptions,
+) -> Result<Vec<u8>, TranscodeError> {
+    let mut sources = sources.into_iter();
+    let (first_info, mut joined) = decode_with::<f32>(
+        sources.next().ok_or(BufferError::NoInputs)?,
+        &options.decode,
+    )?;
+    // Sources that decode shorter than they claimed leave silence at the end, not to be joined
+    joined.truncate_to_written();
+
+    // Bits of every stream, so that a source bitrate can be averaged over the whole output
+    let mut stream_bits = first_info.stream_bitrate as f64 * first_info.duration.as_secs_f64();
+
+    for source in sources {
+        let (info, mut buffer) = decode_with::<f32>(source, &options.decode)?;
+        buffer.truncate_to_written();
+        stream_bits += info.stream_bitrate as f64 * info.duration.as_secs_f64();
+
+        #[cfg(feature = "resampler")]
+        let buffer = if buffer.sample_rate == joined.sample_rate {
+            buffer
+        } else {
+            buffer.resampled(joined.sample_rate, crate::resample::Quality::default())
+        };
+        joined.extend_from_buffer(&buffer)?;
+    }
+
+    let seconds = joined.duration as f64 / joined.sample_rate as f64;
+    let source_bitrate = if seconds > 0. {
+        (stream_bits / seconds).round() as u64
+    } else {
+        0
+    };
+
+    options.encode_tagged(
+        &joined,
+        &options.tags(&first_info.metadata, None),
+        source_bitrate,
+    )
+}
+
+/// Options controlling `split_on_silence`
+#[derive(Clone, Debug)]
+pub struct SplitOptions {
+    transcode: TranscodeOptions,
+    threshold_db: f32,
+    min_silence: Duration,
+    min_track: Duration,
+    padding: Duration,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            transcode: TranscodeOptions::default(),
+            threshold_db: -50.,
+            min_silence: Duration::from_secs(2),
+            min_track: Duration::from_secs(30),
+            padding: Duration::from_millis(500),
+        }
+    }
+}
+
+impl SplitOptions {
+    /// Creates default options, splitting at gaps below -50 dBFS lasting 2 s into tracks of at
+    /// least 30 s, keeping 500 ms of each gap on either side
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the source gets decoded and the tracks encoded
+    /// Trimming and tempo changes apply to the whole source before it's split
+    pub fn transcode(mut self, options: TranscodeOptions) -> Self {
+        self.transcode = options;
+        self
+    }
+
+    /// Sets the level in dBFS below which audio counts as silence
+    pub fn threshold_db(mut self, threshold_db: f32) -> Self {
+        self.threshold_db = threshold_db;
+        self
+    }
+
+    /// Sets the shortest silence the source gets split at, shorter ones stay within a track
+    pub fn min_silence(mut self, min_silence: Duration) -> Self {
+        self.min_silence = min_silence;
+        self
+    }
+
+    /// Sets the shortest track, shorter ones get joined to the track before them, or the one
+    /// after them for the first, e.g. so that quiet intros aren't split off
+    pub fn min_track(mut self, min_track: Duration) -> Self {
+        self.min_track = min_track;
+        self
+    }
+
+    /// Sets how much of the silence is kept at the start and end of each track, at most half the
+    /// gap, so that tracks don't overlap
+    pub fn padding(mut self, padding: Duration) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+/// Track split off by `split_on_silence`
+#[derive(Clone, Debug)]
+pub struct SplitTrack {
+    /// Frames of the source the track covers, padding included
+    pub frames: Range<u64>,
+    /// Time range of the source the track covers, padding included
+    pub range: Range<Duration>,
+    /// Encoded track
+    pub data: Vec<u8>,
+}
+
+/// Length of the blocks whose level decides what counts as silence, so that clicks and crackle
+/// within a gap don't end it
+const SILENCE_BLOCK: Duration = Duration::from_millis(10);
+
+/// Decodes an audio file in source, e.g. a live recording, and splits it into tracks at gaps of
+/// silence, encoding each using ogg vorbis
+/// Tracks keep the source's tags, chapters aside, and get TRACKNUMBER and TRACKTOTAL tags. Their
+/// edges are sample accurate, each starting at the first sample above the threshold after a gap,
+/// padding aside
+pub fn split_on_silence(
+    source: impl MediaSource + 'static,
+    options: &SplitOptions,
+) -> Result<Vec<SplitTrack>, TranscodeError> {
+    let transcode = &options.transcode;
+    let (info, buffer) = decode_with::<f32>(source, &transcode.decoding())?;
+    let (metadata, source_bitrate) = (info.metadata, info.stream_bitrate);
+    let buffer = transcode.process(buffer);
+
+    let tracks = split_ranges(&buffer, options);
+    let tags: Vec<Tag> = transcode
+        .tags(&metadata, Some(&[]))
+        .into_iter()
+        .filter(|tag| {
+            !["TRACKNUMBER", "TRACKTOTAL"]
+                .iter()
+                .any(|key| tag.key.eq_ignore_ascii_case(key))
+        })
+        .collect();
+
+    let channels = buffer.channels as usize;
+    let time_base = TimeBase::new(1, buffer.sample_rate);
+    tracks
+        .iter()
+        .enumerate()
+        .map(|(i, frames)| {
+            let track = SampleBuffer::from_samples(
+                buffer.samples()[frames.start * channels..frames.end * channels].to_vec(),
+                buffer.channels,
+                buffer.sample_rate,
+            );
+            let mut tags = tags.clone();
+            tags.push(Tag {
+                key: "TRACKNUMBER".to_owned(),
+                value: (i + 1).to_string(),
+            });
+            tags.push(Tag {
+                key: "TRACKTOTAL".to_owned(),
+                value: tracks.len().to_string(),
+            });
+
+            let (start, end) = (frames.start as u64, frames.end as u64);
+            Ok(SplitTrack {
+                frames: start..end,
+                range: time_base.duration(start)..time_base.duration(end),
+                data: transcode.encode_tagged(&track, &tags, source_bitrate)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns the frames of each track, padding included
+fn split_ranges(buffer: &SampleBuffer<f32>, options: &SplitOptions) -> Vec<Range<usize>> {
+    let channels = buffer.channels as usize;
+    let samples = buffer.samples();
+    let total = samples.len() / channels;
+    let time_base = TimeBase::new(1, buffer.sample_rate);
+    let frames_in = |d: Duration| time_base.timestamp(d) as usize;
+    let block = frames_in(SILENCE_BLOCK).max(1);
+    let threshold = 10f32.powf(options.threshold_db / 20.);
+
+    // Runs of blocks above the threshold, in frames
+    let mut sounds: Vec<Range<usize>> = Vec::new();
+    for (i, chunk) in samples.chunks(block * channels).enumerate() {
+        let power = chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32;
+        if power.sqrt() <= threshold {
+            continue;
+        }
+        let frames = i * block..(i * block + block).min(total);
+        match sounds.last_mut() {
+            Some(last) if frames.start - last.end < frames_in(options.min_silence) => {
+                last.end = frames.end
+            }
+            _ => sounds.push(frames),
+        }
+    }
+
+    // Edges move in to the first and last frame with a sample above the threshold
+    let loud = |frame: &[f32]| frame.iter().any(|s| s.abs() > threshold);
+    for sound in &mut sounds {
+        let frames = &samples[sound.start * channels..sound.end * channels];
+        let first = frames.chunks_exact(channels).position(loud).unwrap_or(0);
+        let last = frames
+            .chunks_exact(channels)
+            .rposition(loud)
+            .map_or(sound.len(), |last| last + 1);
+        *sound = sound.start + first..sound.start + last;
+    }
+
+    // Tracks too short to stand alone get joined to a neighbour
+    let min_track = frames_in(options.min_track);
+    let mut tracks: Vec<Range<usize>> = Vec::new();
+    for sound in sounds {
+        match tracks.last_mut() {
+            Some(last) if last.len() < min_track || sound.len() < min_track => last.end = sound.end,
+            _ => tracks.push(sound),
+        }
+    }
+
+    let padding = frames_in(options.padding);
+    let edges: Vec<Range<usize>> = tracks.clone();
+    for (i, track) in tracks.iter_mut().enumerate() {
+        let before = i
+            .checked_sub(1)
+            .map_or(0, |i| (edges[i].end + track.start).div_ceil(2));
+        let after = edges
+            .get(i + 1)
+            .map_or(total, |next| (track.end + next.start) / 2);
+        *track = track.start.saturating_sub(padding).max(before)..(track.end + padding).min(after);
+    }
+    tracks
+}
+
+/// Comparison of encoder input with its output decoded again
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncodeReport {
+    pub input_frames: u64,
+    pub output_frames: u64,
+    /// Absolute peak sample of the input
+    pub input_peak: f32,
+    /// Absolute peak sample of the decoded output
+    pub output_peak: f32,
+}
+
+impl EncodeReport {
+    /// Returns how many frames more (positive) or less (negative) the output has
+    pub fn frame_difference(&self) -> i64 {
+        self.output_frames as i64 - self.input_frames as i64
+    }
+
+    /// Returns how many dB louder (positive) or quieter (negative) the output peaks
+    pub fn peak_difference_db(&self) -> f32 {
+        20. * (self.output_peak.max(f32::MIN_POSITIVE) / self.input_peak.max(f32::MIN_POSITIVE))
+            .log10()
+    }
+}
+
+/// Decodes freshly encoded data and compares its duration and peak against the encoder input
+pub fn verify_encoded<S: AudyoSample>(
+    input: &SampleBuffer<S>,
+    encoded: &[u8],
+) -> Result<EncodeReport, DecodeError> {
+    let (info, output) = decode::<f32>(Cursor::new(encoded.to_vec()))?;
+
+    Ok(EncodeReport {
+        input_frames: input.duration as u64,
+        output_frames: info.decoded_frames,
+        input_peak: input
+            .buffer
+            .iter()
+            .map(|&s| IntoSample::<f32>::into_sample(s).abs())
+            .fold(0., f32::max),
+        output_peak: output.buffer.iter().map(|s| s.abs()).fold(0., f32::max),
+    })
+}
+
+/// Encodes a buffer using ogg vorbis given an average bitrate, then verifies the output
+/// Returns the encoded data along with the verification report
+pub fn encode_vorbis_verified<S: AudyoSample>(
+    samples: &SampleBuffer<S>,
+    bitrate: u64,
+) -> Result<(Vec<u8>, EncodeReport), TranscodeError> {
+    let encoded = encode_vorbis(samples, bitrate)?;
+    let report = verify_encoded(samples, &encoded)?;
+    Ok((encoded, report))
+}
+
+/// Enum representing transcoding errors
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub enum TranscodeError {
+    Decode(#[from] DecodeError),
+    Encode(#[from] EncodeError),
+    Buffer(#[from] BufferError),
+}
@@ -0,0 +1,93 @@
+//! Conversions between interleaved samples and separate channel planes, for any number of
+//! channels
+
+/// Returns the planes interleaved into frames, as long as the shortest plane
+pub fn interleave<T: Copy, V: AsRef<[T]>>(planes: &[V]) -> Vec<T> {
+    // Mono and stereo are by far the most common, and much faster without the inner loop
+    match planes {
+        [mono] => return mono.as_ref().to_vec(),
+        [left, right] => {
+            return left
+                .as_ref()
+                .iter()
+                .zip(right.as_ref())
+                .flat_map(|(&l, &r)| [l, r])
+                .collect()
+        }
+        _ => (),
+    }
+
+    let frames = planes.iter().map(|p| p.as_ref().len()).min().unwrap_or(0);
+    let mut samples = Vec::with_capacity(frames * planes.len());
+    for i in 0..frames {
+        samples.extend(planes.iter().map(|p| p.as_ref()[i]));
+    }
+    samples
+}
+
+/// Interleaves the planes into a preallocated output, returning the number of frames written,
+/// limited by the shortest plane and the room in the output
+pub fn interleave_into<T: Copy, V: AsRef<[T]>>(planes: &[V], output: &mut [T]) -> usize {
+    if planes.is_empty() {
+        return 0;
+    }
+    let frames = planes
+        .iter()
+        .map(|p| p.as_ref().len())
+        .min()
+        .unwrap_or(0)
+        .min(output.len() / planes.len());
+    if frames == 0 {
+        return 0;
+    }
+
+    for (c, plane) in planes.iter().enumerate() {
+        let outputs = output[c..].iter_mut().step_by(planes.len());
+        for (out, &sample) in outputs.zip(&plane.as_ref()[..frames]) {
+            *out = sample;
+        }
+    }
+    frames
+}
+
+/// Returns interleaved samples split into one plane per channel
+/// A partial frame at the end is dropped, and no channels give no planes
+pub fn deinterleave<T: Copy>(samples: &[T], channels: usize) -> Vec<Vec<T>> {
+    if channels == 0 {
+        return Vec::new();
+    }
+    let frames = samples.len() / channels;
+    let mut planes = vec![Vec::with_capacity(frames); channels];
+    for frame in samples.chunks_exact(channels) {
+        for (plane, &sample) in planes.iter_mut().zip(frame) {
+            plane.push(sample);
+        }
+    }
+    planes
+}
+
+/// Splits interleaved samples into preallocated planes, one per channel, returning the number
+/// of frames written, limited by the whole frames in the input and the shortest plane
+pub fn deinterleave_into<T: Copy, V: AsMut<[T]>>(samples: &[T], planes: &mut [V]) -> usize {
+    let channels = planes.len();
+    if channels == 0 {
+        return 0;
+    }
+    let frames = planes
+        .iter_mut()
+        .map(|p| p.as_mut().len())
+        .min()
+        .unwrap_or(0)
+        .min(samples.len() / channels);
+    if frames == 0 {
+        return 0;
+    }
+
+    for (c, plane) in planes.iter_mut().enumerate() {
+        let inputs = samples[c..].iter().step_by(channels);
+        for (out, &sample) in plane.as_mut()[..frames].iter_mut().zip(inputs) {
+            *out = sample;
+        }
+    }
+    frames
+}
@@ -0,0 +1,216 @@
+//! Voice activity detection based on frame energy and zero crossing rate
+
+use crate::{frames_to_duration, SampleBuffer};
+use std::{ops::Range, time::Duration};
+
+/// Length of the frames energy and zero crossings are measured over
+const FRAME: Duration = Duration::from_millis(20);
+/// Frames quieter than this never count as speech, whatever the noise floor
+const ABSOLUTE_FLOOR_DB: f32 = -60.;
+/// Noise floors are assumed to stay below this, so that buffers of wall to wall speech still
+/// get detected
+const MAX_NOISE_FLOOR_DB: f32 = -45.;
+/// Fraction of the quietest frames used to estimate the noise floor
+const NOISE_PERCENTILE: f32 = 0.1;
+/// Zero crossing rate above which quieter frames may still be unvoiced speech, e.g. fricatives
+const UNVOICED_ZCR: f32 = 0.25;
+
+/// Options controlling `detect`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VadOptions {
+    threshold_db: f32,
+    min_speech: Duration,
+    min_silence: Duration,
+    padding: Duration,
+}
+
+impl Default for VadOptions {
+    fn default() -> Self {
+        Self {
+            threshold_db: 10.,
+            min_speech: Duration::from_millis(100),
+            min_silence: Duration::from_millis(300),
+            padding: Duration::from_millis(100),
+        }
+    }
+}
+
+impl VadOptions {
+    /// Creates default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many dB above the noise floor a frame must be to count as speech
+    pub fn threshold_db(mut self, threshold_db: f32) -> Self {
+        self.threshold_db = threshold_db;
+        self
+    }
+
+    /// Sets the shortest segment reported, shorter bursts are treated as noise
+    pub fn min_speech(mut self, min_speech: Duration) -> Self {
+        self.min_speech = min_speech;
+        self
+    }
+
+    /// Sets the shortest pause that splits segments, shorter ones are bridged
+    pub fn min_silence(mut self, min_silence: Duration) -> Self {
+        self.min_silence = min_silence;
+        self
+    }
+
+    /// Sets how much audio is kept around each segment, so that soft onsets aren't cut off
+    pub fn padding(mut self, padding: Duration) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+/// Returns the time ranges of the buffer containing speech, in order
+pub fn detect(buffer: &SampleBuffer<f32>, options: &VadOptions) -> Vec<Range<Duration>> {
+    let channels = buffer.channels as usize;
+    let frame_len = (FRAME.as_secs_f64() * buffer.sample_rate as f64).round() as usize;
+    if frame_len == 0 {
+        return Vec::new();
+    }
+
+    // Channels are mixed down, speech is rarely out of phase between them
+    let mono: Vec<f32> = buffer.buffer[..buffer.written]
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    let frames: Vec<(f32, f32)> = mono
+        .chunks(frame_len)
+        .map(|frame| {
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            let crossings = frame
+                .windows(2)
+                .filter(|pair| (pair[0] >= 0.) != (pair[1] >= 0.))
+                .count();
+            (
+                10. * power.max(f32::MIN_POSITIVE).log10(),
+                crossings as f32 / frame.len() as f32,
+            )
+        })
+        .collect();
+    if frames.is_empty() {
+        return Vec::new();
+    }
+
+    let mut levels: Vec<f32> = frames.iter().map(|&(level, _)| level).collect();
+    levels.sort_by(f32::total_cmp);
+    let noise_floor =
+        levels[((levels.len() - 1) as f32 * NOISE_PERCENTILE) as usize].min(MAX_NOISE_FLOOR_DB);
+    let threshold = (noise_floor + options.threshold_db).max(ABSOLUTE_FLOOR_DB);
+    let unvoiced_threshold = (threshold - options.threshold_db / 2.).max(ABSOLUTE_FLOOR_DB);
+
+    let active = frames.iter().map(|&(level, zcr)| {
+        level > threshold || (level > unvoiced_threshold && zcr > UNVOICED_ZCR)
+    });
+
+    // Runs of active frames, in frames
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (i, active) in active.enumerate() {
+        if !active {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end += 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+
+    let to_frames = |d: Duration| (d.as_secs_f64() / FRAME.as_secs_f64()).ceil() as usize;
+    let min_silence = to_frames(options.min_silence);
+    let min_speech = to_frames(options.min_speech);
+    let padding = to_frames(options.padding);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for run in runs {
+        match merged.last_mut() {
+            Some(last) if run.start - last.end < min_silence => last.end = run.end,
+            _ => merged.push(run),
+        }
+    }
+
+    let total = buffer.written / channels;
+    let mut segments: Vec<Range<Duration>> = Vec::new();
+    for run in merged.into_iter().filter(|r| r.len() >= min_speech) {
+        let start = run.start.saturating_sub(padding) * frame_len;
+        let end = ((run.end + padding) * frame_len).min(total);
+        let start = frames_to_duration(start as u64, buffer.sample_rate);
+        let end = frames_to_duration(end as u64, buffer.sample_rate);
+
+        // Padding may make neighbouring segments overlap
+        match segments.last_mut() {
+            Some(last) if last.end >= start => last.end = end,
+            _ => segments.push(start..end),
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, Channels};
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    /// Silence and tones at -12 dBFS of the given lengths in ms, alternating, silence first
+    fn alternating(lengths: &[u64]) -> SampleBuffer<f32> {
+        let mut buffer = SampleBuffer::new(0, Channels::Mono, SAMPLE_RATE);
+        for (i, &length) in lengths.iter().enumerate() {
+            let length = Duration::from_millis(length);
+            let mut part = match i % 2 {
+                0 => generate::silence(length, SAMPLE_RATE, Channels::Mono),
+                _ => generate::sine(200., length, SAMPLE_RATE, Channels::Mono),
+            };
+            part.buffer.iter_mut().for_each(|s| *s *= 0.25);
+            buffer.extend_from_buffer(&part).unwrap();
+        }
+        buffer
+    }
+
+    /// Returns the start and end of a segment in ms
+    fn millis(range: &Range<Duration>) -> (u128, u128) {
+        (range.start.as_millis(), range.end.as_millis())
+    }
+
+    #[test]
+    fn silence_has_no_speech() {
+        let silence = generate::silence(Duration::from_secs(3), SAMPLE_RATE, Channels::Stereo);
+        assert!(detect(&silence, &VadOptions::new()).is_empty());
+        let empty = SampleBuffer::new(0, Channels::Mono, SAMPLE_RATE);
+        assert!(detect(&empty, &VadOptions::new()).is_empty());
+    }
+
+    #[test]
+    fn tone_is_detected_with_padding() {
+        let segments = detect(&alternating(&[1000, 1000, 1000]), &VadOptions::new());
+        assert_eq!(
+            segments.iter().map(millis).collect::<Vec<_>>(),
+            [(900, 2100)]
+        );
+
+        let unpadded = VadOptions::new().padding(Duration::ZERO);
+        let segments = detect(&alternating(&[1000, 1000, 1000]), &unpadded);
+        assert_eq!(
+            segments.iter().map(millis).collect::<Vec<_>>(),
+            [(1000, 2000)]
+        );
+    }
+
+    #[test]
+    fn short_pauses_are_bridged_and_bursts_dropped() {
+        let options = VadOptions::new().padding(Duration::ZERO);
+        // A 200 ms pause is shorter than the 300 ms minimum, the 60 ms burst than the 100 ms one
+        let buffer = alternating(&[1000, 500, 200, 500, 1000, 60, 1000]);
+        let segments = detect(&buffer, &options);
+        assert_eq!(
+            segments.iter().map(millis).collect::<Vec<_>>(),
+            [(1000, 2200)]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    channels, default_track, probe_format, sample_rate, AudyoSample, Channels, DecodeError,
+    DecodeOptions,
+};
+use std::ops::ControlFlow;
+use symphonia::core::{
+    audio::{AudioBuffer, AudioPlanes},
+    errors::Error as SymphoniaError,
+    io::MediaSource,
+    sample::Sample,
+};
+
+/// Decoded packet borrowed by `decode_visit` callbacks
+pub struct AudioChunk<'a, S: Sample> {
+    timestamp: u64,
+    channels: Channels,
+    sample_rate: u32,
+    planes: AudioPlanes<'a, S>,
+}
+
+impl<S: Sample> AudioChunk<'_, S> {
+    /// Returns the packet timestamp in the track's time base units
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns chunk duration in samples
+    pub fn frames(&self) -> usize {
+        self.planes.planes()[0].len()
+    }
+
+    /// Returns chunk's channel layout
+    pub fn channels(&self) -> Channels {
+        self.channels
+    }
+
+    /// Returns chunk's sample rate
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns samples of each channel
+    pub fn planes(&self) -> &[&[S]] {
+        self.planes.planes()
+    }
+}
+
+/// Decodes an audio file in source, passing each decoded packet to the callback
+/// Packets are converted into a reused buffer instead of being collected, decoding stops once
+/// the callback returns `ControlFlow::Break`
+pub fn decode_visit<S: AudyoSample>(
+    source: impl MediaSource + 'static,
+    mut f: impl FnMut(AudioChunk<'_, S>) -> ControlFlow<()>,
+) -> Result<(), DecodeError> {
+    let options = DecodeOptions::default();
+    let mut reader = probe_format(Box::new(source), &options)?.format;
+
+    let track = default_track(&*reader).ok_or(DecodeError::PropertyLacking("default track"))?;
+    let id = track.id;
+    let channels = channels(&track.codec_params, false)?;
+    let sample_rate = sample_rate(&track.codec_params)?;
+
+    let mut decoder = options
+        .codecs()
+        .make(&track.codec_params, &options.decoder_options)?;
+
+    let mut scratch: Option<AudioBuffer<S>> = None;
+
+    while let Ok(packet) = reader.next_packet() {
+        if packet.track_id() != id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            _ => break,
+        };
+
+        let scratch = match &mut scratch {
+            Some(s) if s.capacity() >= decoded.capacity() && s.spec() == decoded.spec() => s,
+            s => s.insert(decoded.make_equivalent()),
+        };
+        decoded.convert(scratch);
+
+        let chunk = AudioChunk {
+            timestamp: packet.ts(),
+            channels,
+            sample_rate,
+            planes: scratch.planes(),
+        };
+
+        if f(chunk).is_break() {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! Watching a directory and transcoding files as they appear
+
+use crate::batch::{
+    collect_files, output_path, transcode_files, BatchError, BatchOptions, FileResult,
+};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Options controlling `watch_dir`
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    batch: BatchOptions,
+    poll_interval: Duration,
+    debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            batch: BatchOptions::default(),
+            poll_interval: Duration::from_millis(500),
+            debounce: Duration::from_secs(2),
+        }
+    }
+}
+
+impl WatchOptions {
+    /// Creates default options, polling every 500ms and waiting 2s for files to settle
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets options used for transcoding each batch of new files
+    pub fn batch_options(mut self, options: BatchOptions) -> Self {
+        self.batch = options;
+        self
+    }
+
+    /// Sets how often the directory is scanned
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how long a file's size and modification time must stay unchanged before it's
+    /// transcoded, so that files still being copied in are left alone
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+}
+
+/// Event reported to the `watch_dir` callback
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// A file was transcoded, or failed to
+    Finished(FileResult),
+    /// Scanning the watched directory failed, it's retried on the next poll
+    ScanFailed(io::Error),
+}
+
+/// Handle to a running watcher, dropping it stops watching
+#[derive(Debug)]
+pub struct Watcher {
+    stop: Sender<()>,
+    stopped: Arc<AtomicBool>,
+    poller: Option<JoinHandle<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Stops watching, waiting for files being transcoded to finish
+    /// Batches found but not started yet are dropped without being reported
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let _ = self.stop.send(());
+        // The worker runs out of batches once the poller is gone
+        for handle in [self.poller.take(), self.worker.take()]
+            .into_iter()
+            .flatten()
+        {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Size and modification time of a file, a change in either means it's still being written
+type Snapshot = (u64, Option<SystemTime>);
+
+/// Watches `input_dir` on a background thread, transcoding files that appear or change in it
+/// to ogg vorbis in `output_dir` like `transcode_dir` does
+/// Files already present when watching starts are left alone. A file only differing from another
+/// in extension, which would overwrite its output, fails with `BatchError::OutputCollision`
+/// Files are transcoded a batch at a time on a thread of their own, so that polling and reporting
+/// scan failures go on meanwhile
+pub fn watch_dir(
+    input_dir: impl AsRef<Path>,
+    output_dir: impl AsRef<Path>,
+    options: WatchOptions,
+    callback: impl FnMut(WatchEvent) + Send + 'static,
+) -> io::Result<Watcher> {
+    let input_dir = input_dir.as_ref().to_path_buf();
+    let output_dir = output_dir.as_ref().to_path_buf();
+
+    let mut done = scan(&input_dir, &output_dir, &options)?;
+    let (stop, stop_requested) = mpsc::channel();
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    // Both threads report events, only one at a time gets to
+    let callback = Arc::new(Mutex::new(callback));
+    let report = move |event| callback.lock().unwrap_or_else(|e| e.into_inner())(event);
+
+    let (queue, queued) = mpsc::channel::<Vec<(PathBuf, PathBuf)>>();
+    let worker = {
+        let stopped = stopped.clone();
+        let report = report.clone();
+        let batch = options.batch.clone();
+        thread::spawn(move || {
+            for jobs in queued {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                for result in transcode_files(jobs, &batch) {
+                    report(WatchEvent::Finished(result));
+                }
+            }
+        })
+    };
+
+    let poller = thread::spawn(move || {
+        let mut pending: HashMap<PathBuf, (Snapshot, Instant)> = HashMap::new();
+
+        while let Err(RecvTimeoutError::Timeout) =
+            stop_requested.recv_timeout(options.poll_interval)
+        {
+            let files = match scan(&input_dir, &output_dir, &options) {
+                Ok(files) => files,
+                Err(e) => {
+                    report(WatchEvent::ScanFailed(e));
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            pending.retain(|path, _| files.contains_key(path));
+            done.retain(|path, _| files.contains_key(path));
+
+            let mut ready = Vec::new();
+            for (path, &snapshot) in &files {
+                if done.get(path) == Some(&snapshot) {
+                    continue;
+                }
+
+                match pending.get(path) {
+                    Some((last, since)) if *last == snapshot => {
+                        if now.duration_since(*since) >= options.debounce {
+                            pending.remove(path);
+                            done.insert(path.clone(), snapshot);
+                            ready.push(path.clone());
+                        }
+                    }
+                    _ => {
+                        pending.insert(path.clone(), (snapshot, now));
+                    }
+                }
+            }
+
+            if ready.is_empty() {
+                continue;
+            }
+            ready.sort();
+
+            let mut jobs = Vec::new();
+            for input in ready {
+                let output = output_path(&input_dir, &output_dir, &input);
+                // Files only differing in extension would overwrite each other's output
+                let other = files.keys().find(|other| {
+                    **other != input && output_path(&input_dir, &output_dir, other) == output
+                });
+                match other {
+                    Some(other) => report(WatchEvent::Finished(FileResult {
+                        result: Err(BatchError::OutputCollision(other.clone())),
+                        input,
+                        output,
+                        resumed: false,
+                    })),
+                    None => jobs.push((input, output)),
+                }
+            }
+            if !jobs.is_empty() {
+                let _ = queue.send(jobs);
+            }
+        }
+    });
+
+    Ok(Watcher {
+        stop,
+        stopped,
+        poller: Some(poller),
+        worker: Some(worker),
+    })
+}
+
+/// Lists files in the watched directory, skipping the output directory if it's inside
+fn scan(
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &WatchOptions,
+) -> io::Result<HashMap<PathBuf, Snapshot>> {
+    let mut paths = Vec::new();
+    collect_files(input_dir, options.batch.recursive, &mut paths)?;
+
+    Ok(paths
+        .into_iter()
+        .filter(|path| !path.starts_with(output_dir))
+        // Files removed since being listed are skipped
+        .filter_map(|path| {
+            let metadata = path.metadata().ok()?;
+            Some((path, (metadata.len(), metadata.modified().ok())))
+        })
+        .collect())
+}
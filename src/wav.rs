@@ -0,0 +1,466 @@
+use crate::{i24, AudyoSample, DecodeError, EncodeError, Metadata, SampleBuffer};
+use std::io::{self, SeekFrom};
+use symphonia::core::{conv::IntoSample, io::MediaSource, sample::SampleFormat};
+
+/// Largest metadata chunk read, bigger ones are skipped
+const MAX_CHUNK_LEN: u32 = 1 << 20;
+
+/// Sampler metadata from a WAV smpl chunk, as used by instrument samples
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SamplerInfo {
+    /// MIDI note the sample plays at its original pitch
+    pub root_note: u8,
+    /// Fraction of a semitone above the root note, out of 2^32
+    pub pitch_fraction: u32,
+    /// Loops in the order they're stored
+    pub loops: Vec<SampleLoop>,
+}
+
+/// Loop of a sampler instrument
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleLoop {
+    /// First frame of the loop
+    pub start: u32,
+    /// Frame after the last frame of the loop
+    pub end: u32,
+    pub kind: LoopKind,
+    /// Number of times the loop plays, 0 for infinitely
+    pub play_count: u32,
+}
+
+/// Direction a loop plays in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopKind {
+    #[default]
+    Forward,
+    /// Forward then backward
+    PingPong,
+    Backward,
+}
+
+impl SamplerInfo {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let word = |i: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(
+                data.get(i * 4..i * 4 + 4)?.try_into().ok()?,
+            ))
+        };
+
+        let loops = (0..word(7)? as usize)
+            .map(|i| {
+                let base = 9 + i * 6;
+                Some(SampleLoop {
+                    start: word(base + 2)?,
+                    end: word(base + 3)?.saturating_add(1),
+                    kind: match word(base + 1)? {
+                        1 => LoopKind::PingPong,
+                        2 => LoopKind::Backward,
+                        _ => LoopKind::Forward,
+                    },
+                    play_count: word(base + 5)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            root_note: word(3)?.min(127) as u8,
+            pitch_fraction: word(4)?,
+            loops,
+        })
+    }
+
+    fn write(&self, sample_rate: u32) -> Vec<u8> {
+        let mut data = Vec::with_capacity(36 + self.loops.len() * 24);
+        // No manufacturer or product
+        data.extend_from_slice(&[0; 8]);
+        let period = 1_000_000_000 / sample_rate.max(1);
+        for word in [period, self.root_note as u32, self.pitch_fraction, 0, 0] {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&(self.loops.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        for (i, l) in self.loops.iter().enumerate() {
+            let kind = match l.kind {
+                LoopKind::Forward => 0,
+                LoopKind::PingPong => 1,
+                LoopKind::Backward => 2,
+            };
+            let end = l.end.saturating_sub(1).max(l.start);
+            for word in [i as u32, kind, l.start, end, 0, l.play_count] {
+                data.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        data
+    }
+}
+
+/// Broadcast Wave metadata from a WAV bext chunk
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BroadcastInfo {
+    /// Up to 256 characters
+    pub description: String,
+    /// Up to 32 characters
+    pub originator: String,
+    /// Up to 32 characters
+    pub originator_reference: String,
+    /// Date of creation as yyyy-mm-dd
+    pub origination_date: String,
+    /// Time of creation as hh:mm:ss
+    pub origination_time: String,
+    /// Frames since midnight at the first frame, the timecode reference
+    pub time_reference: u64,
+    /// How the audio has been coded and processed, one line per step
+    pub coding_history: String,
+}
+
+/// Length of the fixed size part of a bext chunk
+const BEXT_LEN: usize = 602;
+
+impl BroadcastInfo {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < BEXT_LEN {
+            return None;
+        }
+        let text = |range: std::ops::Range<usize>| {
+            let field = &data[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+
+        Some(Self {
+            description: text(0..256),
+            originator: text(256..288),
+            originator_reference: text(288..320),
+            origination_date: text(320..330),
+            origination_time: text(330..338),
+            time_reference: u64::from_le_bytes(data[338..346].try_into().ok()?),
+            coding_history: text(BEXT_LEN..data.len()),
+        })
+    }
+
+    fn write(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(BEXT_LEN + self.coding_history.len());
+        let mut text = |value: &str, len: usize| {
+            let bytes = &value.as_bytes()[..value.len().min(len)];
+            data.extend_from_slice(bytes);
+            data.resize(data.len() + len - bytes.len(), 0);
+        };
+        text(&self.description, 256);
+        text(&self.originator, 32);
+        text(&self.originator_reference, 32);
+        text(&self.origination_date, 10);
+        text(&self.origination_time, 8);
+
+        data.extend_from_slice(&self.time_reference.to_le_bytes());
+        // Version 1, without a UMID or loudness values
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.resize(BEXT_LEN, 0);
+        data.extend_from_slice(self.coding_history.as_bytes());
+        data
+    }
+}
+
+/// Metadata chunks of a WAV file symphonia doesn't expose
+#[derive(Default)]
+pub(crate) struct WavChunks {
+    sampler: Option<SamplerInfo>,
+    broadcast: Option<BroadcastInfo>,
+}
+
+impl WavChunks {
+    /// Reads the chunks of a seekable WAV source, then rewinds it
+    /// Other sources are left untouched
+    pub(crate) fn read(source: &mut dyn MediaSource) -> io::Result<Self> {
+        let mut chunks = Self::default();
+        if !source.is_seekable() {
+            return Ok(chunks);
+        }
+
+        let mut header = [0; 12];
+        if source.read_exact(&mut header).is_ok()
+            && &header[..4] == b"RIFF"
+            && &header[8..] == b"WAVE"
+        {
+            chunks.read_chunks(source)?;
+        }
+
+        source.seek(SeekFrom::Start(0))?;
+        Ok(chunks)
+    }
+
+    fn read_chunks(&mut self, source: &mut dyn MediaSource) -> io::Result<()> {
+        let mut header = [0; 8];
+        while source.read_exact(&mut header).is_ok() {
+            let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+            // Chunks are padded to an even length
+            let padded = len as i64 + (len % 2) as i64;
+
+            let id = &header[..4];
+            if (id == b"smpl" || id == b"bext") && len <= MAX_CHUNK_LEN {
+                let mut data = vec![0; padded as usize];
+                if source.read_exact(&mut data).is_err() {
+                    break;
+                }
+                let data = &data[..len as usize];
+                if id == b"smpl" {
+                    self.sampler = SamplerInfo::parse(data);
+                } else {
+                    self.broadcast = BroadcastInfo::parse(data);
+                }
+            } else {
+                source.seek(SeekFrom::Current(padded))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the chunks' metadata
+    pub(crate) fn apply(self, metadata: &mut Metadata) {
+        metadata.sampler = self.sampler.or(metadata.sampler.take());
+        metadata.broadcast = self.broadcast.or(metadata.broadcast.take());
+    }
+}
+
+/// Checks the format of a WAV or AIFF file starting with head for values symphonia panics on,
+/// a zero sample rate or ADPCM blocks too short for their headers
+/// Anything else, including a format chunk past the end of head, is left to symphonia
+pub(crate) fn check_header(head: &[u8]) -> Result<(), DecodeError> {
+    match head.get(..12) {
+        Some([b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E']) => {
+            chunk(&head[12..], b"fmt ", u32::from_le_bytes).map_or(Ok(()), check_fmt)
+        }
+        Some([b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C']) => {
+            chunk(&head[12..], b"COMM", u32::from_be_bytes).map_or(Ok(()), check_comm)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the data of the first chunk with the given id, as far as it's in chunks
+fn chunk<'a>(mut chunks: &'a [u8], id: &[u8; 4], len: fn([u8; 4]) -> u32) -> Option<&'a [u8]> {
+    while chunks.len() >= 8 {
+        let data_len = len(chunks[4..8].try_into().unwrap()) as usize;
+        let data = &chunks[8..];
+        if &chunks[..4] == id {
+            return Some(&data[..data_len.min(data.len())]);
+        }
+        chunks = data.get(data_len.next_multiple_of(2)..)?;
+    }
+    None
+}
+
+fn check_fmt(fmt: &[u8]) -> Result<(), DecodeError> {
+    let [tag, channels, rate_low, rate_high, _, _, block_align, ..] = *fmt
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect::<Vec<_>>()
+    else {
+        return Ok(());
+    };
+    let sample_rate = (rate_high as u32) << 16 | rate_low as u32;
+    if sample_rate == 0 {
+        return Err(DecodeError::UnsupportedSampleRate(sample_rate));
+    }
+
+    // Bytes of each channel's header in a block, with MS and IMA ADPCM
+    let header = match tag {
+        0x2 => 7,
+        0x11 => 4,
+        _ => return Ok(()),
+    };
+    match block_align.checked_sub(header * channels) {
+        Some(samples) if channels > 0 && samples.checked_mul(8).is_some() => Ok(()),
+        _ => Err(DecodeError::Malformed),
+    }
+}
+
+fn check_comm(comm: &[u8]) -> Result<(), DecodeError> {
+    // 80 bit extended float, after the channels, frames and sample size
+    let Some(rate) = comm.get(8..18) else {
+        return Ok(());
+    };
+    let exponent = u16::from_be_bytes([rate[0], rate[1]]);
+    let mantissa = u64::from_be_bytes(rate[2..].try_into().unwrap());
+    let sample_rate = if exponent & 0x8000 == 0 {
+        (mantissa as f64 * 2f64.powi(exponent as i32 - 16383 - 63)) as u32
+    } else {
+        0
+    };
+    if sample_rate == 0 {
+        return Err(DecodeError::UnsupportedSampleRate(sample_rate));
+    }
+    Ok(())
+}
+
+/// Sample format of WAV files written by `encode_wav`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavFormat {
+    #[default]
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+}
+
+impl WavFormat {
+    /// Returns the smallest format holding samples of the given format without loss, e.g.
+    /// `S::FORMAT` of a buffer or the format of a `DynSampleBuffer`, so that 24 bit sources
+    /// get written back as 24 bit
+    /// Doubles are written as floats, the only WAV format losing precision
+    pub fn lossless(format: SampleFormat) -> Self {
+        match format {
+            SampleFormat::U8 | SampleFormat::U16 | SampleFormat::S8 | SampleFormat::S16 => {
+                WavFormat::Pcm16
+            }
+            SampleFormat::U24 | SampleFormat::S24 => WavFormat::Pcm24,
+            SampleFormat::U32 | SampleFormat::S32 => WavFormat::Pcm32,
+            SampleFormat::F32 | SampleFormat::F64 => WavFormat::Float32,
+        }
+    }
+}
+
+/// Options controlling `encode_wav`
+#[derive(Clone, Debug, Default)]
+pub struct WavOptions {
+    format: WavFormat,
+    sampler: Option<SamplerInfo>,
+    broadcast: Option<BroadcastInfo>,
+}
+
+impl WavOptions {
+    /// Creates default options, writing 16 bit PCM without extra metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the sample format
+    pub fn format(mut self, format: WavFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Writes sampler metadata, e.g. loop points, in a smpl chunk
+    pub fn sampler(mut self, sampler: SamplerInfo) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Writes Broadcast Wave metadata in a bext chunk
+    pub fn broadcast(mut self, broadcast: BroadcastInfo) -> Self {
+        self.broadcast = Some(broadcast);
+        self
+    }
+
+    /// Writes the WAV specific metadata of a decoded source, so it survives a transcode
+    pub fn keep_metadata(mut self, metadata: &Metadata) -> Self {
+        self.sampler = metadata.sampler.clone().or(self.sampler);
+        self.broadcast = metadata.broadcast.clone().or(self.broadcast);
+        self
+    }
+}
+
+/// Encodes the written frames of a buffer as an uncompressed WAV file
+pub fn encode_wav<S: AudyoSample>(
+    samples: &SampleBuffer<S>,
+    options: &WavOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let sample_rate = samples.sample_rate;
+    if sample_rate == 0 {
+        return Err(EncodeError::UnsupportedSampleRate(sample_rate));
+    }
+
+    let (tag, bytes): (u16, u16) = match options.format {
+        WavFormat::Pcm16 => (1, 2),
+        WavFormat::Pcm24 => (1, 3),
+        WavFormat::Pcm32 => (1, 4),
+        WavFormat::Float32 => (3, 4),
+    };
+    let written = &samples.buffer[..samples.written];
+    let mut data = Vec::with_capacity(written.len() * bytes as usize);
+    for &sample in written {
+        match options.format {
+            WavFormat::Pcm16 => {
+                data.extend_from_slice(&IntoSample::<i16>::into_sample(sample).to_le_bytes())
+            }
+            WavFormat::Pcm24 => data.extend_from_slice(
+                &IntoSample::<i24>::into_sample(sample).inner().to_le_bytes()[..3],
+            ),
+            WavFormat::Pcm32 => {
+                data.extend_from_slice(&IntoSample::<i32>::into_sample(sample).to_le_bytes())
+            }
+            WavFormat::Float32 => {
+                data.extend_from_slice(&IntoSample::<f32>::into_sample(sample).to_le_bytes())
+            }
+        }
+    }
+
+    let channels = samples.channels as u16;
+    let byte_rate = sample_rate
+        .checked_mul((channels * bytes) as u32)
+        .ok_or(EncodeError::UnsupportedSampleRate(sample_rate))?;
+    let mut fmt = Vec::with_capacity(16);
+    fmt.extend_from_slice(&tag.to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&byte_rate.to_le_bytes());
+    fmt.extend_from_slice(&(channels * bytes).to_le_bytes());
+    fmt.extend_from_slice(&(bytes * 8).to_le_bytes());
+
+    let mut chunks = vec![(*b"fmt ", fmt)];
+    // Broadcast Wave recommends bext to come before the audio
+    if let Some(broadcast) = &options.broadcast {
+        chunks.push((*b"bext", broadcast.write()));
+    }
+    if let Some(sampler) = &options.sampler {
+        chunks.push((*b"smpl", sampler.write(sample_rate)));
+    }
+    chunks.push((*b"data", data));
+    riff_wave(&chunks)
+}
+
+/// Builds a RIFF WAVE file out of chunks, padding each to an even length
+/// Fails with `EncodeError::TooLarge` past the 4 GiB RIFF sizes can count
+pub(crate) fn riff_wave(chunks: &[([u8; 4], Vec<u8>)]) -> Result<Vec<u8>, EncodeError> {
+    let len = riff_len(chunks.iter().map(|(_, data)| data.len()))?;
+
+    let mut riff = Vec::with_capacity(8 + len as usize);
+    riff.extend_from_slice(b"RIFF");
+    riff.extend_from_slice(&len.to_le_bytes());
+    riff.extend_from_slice(b"WAVE");
+    for (id, data) in chunks {
+        riff.extend_from_slice(id);
+        riff.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        riff.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            riff.push(0);
+        }
+    }
+    Ok(riff)
+}
+
+/// Returns the size a RIFF WAVE file of chunks with the given lengths states, after its header
+fn riff_len(lens: impl Iterator<Item = usize>) -> Result<u32, EncodeError> {
+    let len = lens.fold(4, |len: u64, data| {
+        len.saturating_add(8 + data.next_multiple_of(2) as u64)
+    });
+    len.try_into().map_err(|_| EncodeError::TooLarge(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn riff_size_limit() {
+        assert_eq!(riff_len([16, 3].into_iter()).unwrap(), 4 + 24 + 12);
+        assert_eq!(
+            riff_len([u32::MAX as usize - 13].into_iter()).unwrap(),
+            u32::MAX - 1
+        );
+        assert!(matches!(
+            riff_len([u32::MAX as usize - 12].into_iter()),
+            Err(EncodeError::TooLarge(_))
+        ));
+    }
+}
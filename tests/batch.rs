@@ -0,0 +1,18 @@
+#![cfg(feature = "batch")]
+
+use audyo::batch::{transcode_dir, BatchOptions};
+use std::{fs, io};
+
+#[test]
+fn inputs_sharing_an_output_fail_upfront() {
+    let dir = std::env::temp_dir().join(format!("audyo-batch-{}", std::process::id()));
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.mp3"), b"").unwrap();
+    fs::write(input.join("a.flac"), b"").unwrap();
+
+    let error = transcode_dir(&input, &output, &BatchOptions::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(!output.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
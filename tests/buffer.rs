@@ -0,0 +1,100 @@
+use audyo::{encode_ima_adpcm, encode_wav, mix, BufferError, Channels, SampleBuffer, WavOptions};
+
+#[test]
+fn extend_from_partially_written_buffer() {
+    let mut partial = SampleBuffer::<f32>::new(800, Channels::Mono, 8000);
+    partial.push_frames(&[0.5; 400]).unwrap();
+
+    let mut joined = SampleBuffer::from_samples(vec![0.25; 100], Channels::Mono, 8000);
+    joined.extend_from_buffer(&partial).unwrap();
+    assert_eq!(joined.written_frames(), 500);
+    assert!(joined.samples()[..500].iter().all(|&s| s > 0.));
+}
+
+#[test]
+fn frames_of_partially_written_buffer() {
+    let mut buffer = SampleBuffer::<i16>::new(4, Channels::Stereo, 8000);
+    buffer.push_frames(&[1, 2, 3, 4]).unwrap();
+
+    let frames: Vec<[i16; 2]> = buffer.frames().unwrap().collect();
+    assert_eq!(frames, [[1, 2], [3, 4]]);
+    assert!(buffer.frames::<1>().is_err());
+    let collected = SampleBuffer::from_frames(frames, 8000).unwrap();
+    assert_eq!(collected.samples(), &[1, 2, 3, 4]);
+}
+
+#[test]
+fn chunks_of_written_frames() {
+    let mut buffer = SampleBuffer::<i16>::new(8, Channels::Mono, 8000);
+    buffer.push_frames(&[1, 2, 3, 4, 5]).unwrap();
+
+    let chunks: Vec<_> = buffer.chunks_frames(2, false).unwrap().collect();
+    let chunks: Vec<_> = chunks.iter().map(|c| c.samples().to_vec()).collect();
+    assert_eq!(chunks, [vec![1, 2], vec![3, 4], vec![5]]);
+    let last = buffer.chunks_frames(2, true).unwrap().last().unwrap();
+    assert_eq!(last.samples(), &[5, 0]);
+    assert!(matches!(
+        buffer.chunks_frames(0, false),
+        Err(BufferError::EmptyChunk)
+    ));
+}
+
+#[test]
+fn encoders_and_mix_take_written_frames() {
+    let mut partial = SampleBuffer::<f32>::new(800, Channels::Stereo, 8000);
+    partial.push_frames(&[0.5; 600]).unwrap();
+    let mut truncated = partial.clone();
+    truncated.truncate_to_written();
+
+    let options = WavOptions::new();
+    assert_eq!(
+        encode_wav(&partial, &options).unwrap(),
+        encode_wav(&truncated, &options).unwrap()
+    );
+    assert_eq!(
+        encode_ima_adpcm(&partial).unwrap(),
+        encode_ima_adpcm(&truncated).unwrap()
+    );
+    let mixed = mix(&[(&partial, 1.)]).unwrap();
+    assert_eq!(mixed.samples(), truncated.samples());
+}
+
+#[test]
+fn mix_rejects_zero_sample_rate() {
+    let tone = SampleBuffer::from_samples(vec![0.5; 100], Channels::Mono, 8000);
+    let broken = SampleBuffer::from_samples(vec![0.5; 100], Channels::Mono, 0);
+    for inputs in [[(&tone, 1.), (&broken, 1.)], [(&broken, 1.), (&tone, 1.)]] {
+        assert!(matches!(
+            mix(&inputs),
+            Err(BufferError::UnsupportedSampleRate(0))
+        ));
+    }
+}
+
+#[test]
+#[cfg(feature = "dasp")]
+fn dasp_signal_roundtrip() {
+    use audyo::dasp::{signal, Signal};
+
+    let mut partial = SampleBuffer::<i16>::new(8, Channels::Stereo, 8000);
+    partial.push_frames(&[1, -1, 2, -2, 3, -3]).unwrap();
+
+    // Only written frames are yielded, then the signal is exhausted
+    let mut frames = partial.signal::<2>().unwrap();
+    assert_eq!(frames.next(), [1, -1]);
+    let rest: Vec<_> = frames.until_exhausted().collect();
+    assert_eq!(rest, [[2, -2], [3, -3]]);
+    assert!(partial.signal::<1>().is_err());
+
+    // Doubled through dasp, e.g. to apply a gain
+    let doubled = partial.signal::<2>().unwrap().scale_amp(2.);
+    let back = SampleBuffer::from_signal(doubled, 100, 8000).unwrap();
+    assert_eq!(back.samples(), [2, -2, 4, -4, 6, -6]);
+    assert_eq!(back.channels(), Channels::Stereo);
+
+    // dasp's generators yield mono frames as plain samples
+    let sine = signal::rate(8000.).const_hz(440.).sine().map(|s| [s]);
+    let tone = SampleBuffer::<f64>::from_signal(sine, 80, 8000).unwrap();
+    assert_eq!(tone.written_frames(), 80);
+    assert_eq!(tone.channels(), Channels::Mono);
+}
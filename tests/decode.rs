@@ -0,0 +1,44 @@
+#![cfg(all(feature = "wav", feature = "pcm"))]
+
+use audyo::{decode_with, encode_wav, generate, Channels, DecodeOptions, WavOptions};
+use std::{io::Cursor, time::Duration};
+
+fn wav(channels: Channels) -> Vec<u8> {
+    let tone = generate::sine(440., Duration::from_millis(100), 8000, channels);
+    encode_wav(&tone, &WavOptions::new()).unwrap()
+}
+
+#[test]
+fn downmix_keeps_mono_and_stereo() {
+    let options = DecodeOptions::new().downmix(true);
+    for channels in [Channels::Mono, Channels::Stereo] {
+        let (_, buffer) = decode_with::<f32>(Cursor::new(wav(channels)), &options).unwrap();
+        assert_eq!(buffer.channels(), channels);
+        assert_eq!(buffer.written_frames(), 800);
+    }
+}
+
+#[test]
+fn forged_length_does_not_reserve_memory() {
+    // Claims almost 4 GiB of audio, about 2 billion frames
+    let mut wav = wav(Channels::Mono);
+    let data = wav.windows(4).position(|w| w == b"data").unwrap();
+    wav[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+    wav[data + 4..data + 8].copy_from_slice(&0xffff_0000u32.to_le_bytes());
+
+    let (info, buffer) = decode_with::<f32>(Cursor::new(wav), &DecodeOptions::new()).unwrap();
+    assert_eq!(info.expected_frames, Some(0x7fff_8000));
+    assert_eq!(buffer.written_frames(), 800);
+    assert!(buffer.duration() < 1 << 24);
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn decode_memory_mapped() {
+    let options = DecodeOptions::new().memory_mapped(std::env::temp_dir());
+    let wav = wav(Channels::Stereo);
+    let (_, mapped) = decode_with::<f32>(Cursor::new(wav.clone()), &options).unwrap();
+    let (_, heap) = decode_with::<f32>(Cursor::new(wav), &DecodeOptions::new()).unwrap();
+    assert!(mapped.is_memory_mapped());
+    assert_eq!(mapped.samples(), heap.samples());
+}
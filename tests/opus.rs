@@ -0,0 +1,52 @@
+#![cfg(all(feature = "opus", feature = "ogg"))]
+
+use audyo::{decode, probe};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use std::io::Cursor;
+
+const PRE_SKIP: u16 = 312;
+/// 20ms CELT fullband frames, mono, one per packet
+const TOC: u8 = 31 << 3;
+
+/// Ogg Opus stream of `packets` 20ms packets, its last granule position cut `end_trim` short
+/// The packets hold no frame data, which decodes as concealed silence
+fn ogg_opus(packets: u64, end_trim: u64) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, 1]);
+    head.extend_from_slice(&PRE_SKIP.to_le_bytes());
+    head.extend_from_slice(&48_000u32.to_le_bytes());
+    head.extend_from_slice(&[0, 0, 0]);
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&[0; 8]);
+
+    let mut writer = PacketWriter::new(Vec::new());
+    writer
+        .write_packet(head, 1, PacketWriteEndInfo::EndPage, 0)
+        .unwrap();
+    writer
+        .write_packet(tags, 1, PacketWriteEndInfo::EndPage, 0)
+        .unwrap();
+    for i in 1..=packets {
+        let (end, granule) = match i == packets {
+            true => (PacketWriteEndInfo::EndStream, i * 960 - end_trim),
+            false => (PacketWriteEndInfo::EndPage, i * 960),
+        };
+        writer.write_packet(vec![TOC], 1, end, granule).unwrap();
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn pre_skip_and_end_trim_are_dropped() {
+    let ogg = ogg_opus(50, 500);
+    let expected = 50 * 960 - 500 - PRE_SKIP as u64;
+
+    let (info, buffer) = decode::<f32>(Cursor::new(ogg.clone())).unwrap();
+    assert_eq!(info.decoded_frames, expected);
+    assert_eq!(info.expected_frames, Some(expected));
+    assert_eq!(buffer.sample_rate(), 48_000);
+    assert_eq!(buffer.written_frames() as u64, expected);
+
+    let report = probe(Cursor::new(ogg)).unwrap();
+    assert_eq!(report.frames, expected);
+}
@@ -0,0 +1,102 @@
+#![cfg(all(feature = "testing", feature = "ogg", feature = "vorbis"))]
+
+use audyo::{
+    decode, decode_stream_with, encode_wav, generate,
+    testing::{assert_roundtrip_close, psnr},
+    transcode_vorbis, Channels, DecodeOptions, SampleBuffer, TranscodeOptions, WavFormat,
+    WavOptions,
+};
+use std::{io::Cursor, time::Duration};
+
+fn sweep() -> SampleBuffer<f32> {
+    let sweep = generate::sweep(
+        50.,
+        15_000.,
+        Duration::from_millis(500),
+        44_100,
+        Channels::Stereo,
+    );
+    // Kept below full scale, so that lossy codecs don't clip
+    let samples = sweep.samples().iter().map(|s| s * 0.5).collect();
+    SampleBuffer::from_samples(samples, Channels::Stereo, 44_100)
+}
+
+fn wav(buffer: &SampleBuffer<f32>, format: WavFormat) -> Vec<u8> {
+    encode_wav(buffer, &WavOptions::new().format(format)).unwrap()
+}
+
+#[test]
+fn wav_roundtrip() {
+    let original = sweep();
+    for (format, min_psnr) in [
+        (WavFormat::Pcm16, 90.),
+        (WavFormat::Pcm24, 140.),
+        (WavFormat::Pcm32, 140.),
+        (WavFormat::Float32, f64::INFINITY),
+    ] {
+        let (info, decoded) = decode::<f32>(Cursor::new(wav(&original, format))).unwrap();
+        assert_eq!(info.decoded_frames, original.duration() as u64);
+        assert_eq!(decoded.written_frames(), original.duration());
+        assert_roundtrip_close(&original, &decoded, 0.1);
+        let psnr = psnr(&original, &decoded);
+        assert!(psnr >= min_psnr, "{format:?} PSNR of {psnr:.1} dB");
+    }
+}
+
+#[test]
+fn vorbis_roundtrip() {
+    let original = sweep();
+    let source = Cursor::new(wav(&original, WavFormat::Float32));
+    let ogg = transcode_vorbis(source, &TranscodeOptions::new().bitrate(128_000)).unwrap();
+    let (_, decoded) = decode::<f32>(Cursor::new(ogg)).unwrap();
+    assert_roundtrip_close(&original, &decoded, 3.);
+}
+
+#[test]
+fn content_hash_survives_lossless_roundtrips() {
+    let original = sweep();
+    let (_, pcm16) = decode::<f32>(Cursor::new(wav(&original, WavFormat::Pcm16))).unwrap();
+    let (_, pcm24) = decode::<i32>(Cursor::new(wav(&pcm16, WavFormat::Pcm24))).unwrap();
+    let (_, float) = decode::<f64>(Cursor::new(wav(&pcm16, WavFormat::Float32))).unwrap();
+    assert_eq!(pcm24.content_hash(), pcm16.content_hash());
+    assert_eq!(float.content_hash(), pcm16.content_hash());
+
+    let ogg = transcode_vorbis(
+        Cursor::new(wav(&pcm16, WavFormat::Pcm16)),
+        &TranscodeOptions::new(),
+    )
+    .unwrap();
+    let (_, lossy) = decode::<f32>(Cursor::new(ogg)).unwrap();
+    assert_ne!(lossy.content_hash(), pcm16.content_hash());
+}
+
+#[test]
+#[cfg(feature = "aes-gcm")]
+fn encrypted_roundtrip() {
+    use audyo::{
+        aes_gcm::{Aes256Gcm, KeyInit},
+        DecryptingReader, EncodeSink, EncryptingSink,
+    };
+
+    let original = sweep();
+    let plain = wav(&original, WavFormat::Pcm24);
+    let mut sink =
+        EncryptingSink::new(Vec::new(), Aes256Gcm::new(&[7; 32].into())).chunk_size(4096);
+    for block in plain.chunks(1000) {
+        sink.write_block(block).unwrap();
+    }
+    sink.finish().unwrap();
+    let encrypted = sink.into_inner();
+    assert!(!encrypted.windows(4).any(|w| w == b"RIFF" || w == b"data"));
+
+    let reader = DecryptingReader::new(
+        Cursor::new(encrypted.clone()),
+        Aes256Gcm::new(&[7; 32].into()),
+    );
+    let (_, decoded) = decode_stream_with::<f32>(reader, &DecodeOptions::new()).unwrap();
+    assert_roundtrip_close(&original, &decoded, 0.1);
+    assert!(psnr(&original, &decoded) >= 140.);
+
+    let wrong_key = DecryptingReader::new(Cursor::new(encrypted), Aes256Gcm::new(&[8; 32].into()));
+    assert!(decode_stream_with::<f32>(wrong_key, &DecodeOptions::new()).is_err());
+}
@@ -0,0 +1,115 @@
+#![cfg(all(feature = "wav", feature = "pcm", feature = "ogg", feature = "vorbis"))]
+
+use audyo::{
+    concat_transcode, decode, encode_wav, generate, transcode_multi, try_probe, Channels,
+    DecodeError, DecodeOptions, TranscodeError, TranscodeOptions, WavOptions,
+};
+use std::{io::Cursor, time::Duration};
+
+fn tone(millis: u64) -> Vec<u8> {
+    let tone = generate::sine(440., Duration::from_millis(millis), 8000, Channels::Mono);
+    encode_wav(&tone, &WavOptions::new()).unwrap()
+}
+
+fn concat_frames(sources: Vec<Vec<u8>>) -> usize {
+    let joined = concat_transcode(
+        sources.into_iter().map(Cursor::new),
+        &TranscodeOptions::new(),
+    );
+    let (_, buffer) = decode::<f32>(Cursor::new(joined.unwrap())).unwrap();
+    buffer.written_frames()
+}
+
+#[test]
+fn concat_skips_what_short_sources_did_not_decode() {
+    // Claims 800 frames of 16-bit mono, holds 400
+    let mut truncated = tone(100);
+    truncated.truncate(truncated.len() - 800);
+
+    for sources in [
+        vec![truncated.clone(), tone(150)],
+        vec![tone(150), truncated.clone()],
+        vec![truncated.clone()],
+    ] {
+        let expected = sources.len() as u64 * 150 - 100;
+        assert_eq!(concat_frames(sources), concat_frames(vec![tone(expected)]));
+    }
+}
+
+#[test]
+fn multi_decodes_with_each_targets_options() {
+    let limited = TranscodeOptions::new().decode_options(DecodeOptions::new().max_frames(100));
+    let targets = [TranscodeOptions::new(), limited];
+    assert!(matches!(
+        transcode_multi(Cursor::new(tone(100)), &targets),
+        Err(TranscodeError::Decode(DecodeError::FrameLimitExceeded(100)))
+    ));
+
+    let roomy = TranscodeOptions::new().decode_options(DecodeOptions::new().max_frames(1000));
+    let targets = [roomy, TranscodeOptions::new()];
+    assert_eq!(
+        transcode_multi(Cursor::new(tone(100)), &targets)
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn trims_decode_only_their_range() {
+    let limited = DecodeOptions::new().max_frames(1000);
+    let trimmed = |start, end| {
+        TranscodeOptions::new()
+            .decode_options(limited.clone())
+            .trim(Duration::from_millis(start), Duration::from_millis(end))
+    };
+    // 8000 frames in all, past the limit unless trimmed
+    assert!(matches!(
+        transcode_multi(
+            Cursor::new(tone(1000)),
+            &[TranscodeOptions::new().decode_options(limited.clone())]
+        ),
+        Err(TranscodeError::Decode(DecodeError::FrameLimitExceeded(
+            1000
+        )))
+    ));
+
+    let outputs = transcode_multi(
+        Cursor::new(tone(1000)),
+        &[trimmed(500, 600), trimmed(0, 50), trimmed(900, 2000)],
+    )
+    .unwrap();
+    // Decoded Vorbis runs on to the end of its last block, the granule position is exact
+    let frames: Vec<_> = outputs
+        .into_iter()
+        .map(|output| try_probe(Cursor::new(output)).unwrap().frames)
+        .collect();
+    assert_eq!(frames, [Some(800), Some(400), Some(800)]);
+}
+
+#[cfg(feature = "resampler")]
+#[test]
+fn multi_resamples_each_target() {
+    let upsampled = TranscodeOptions::new().sample_rate(16_000);
+    let targets = [
+        upsampled.clone(),
+        TranscodeOptions::new(),
+        upsampled.bitrate(96_000),
+    ];
+    let outputs = transcode_multi(Cursor::new(tone(500)), &targets).unwrap();
+    let formats: Vec<_> = outputs
+        .into_iter()
+        .map(|output| {
+            let info = try_probe(Cursor::new(output)).unwrap();
+            (info.sample_rate, info.frames)
+        })
+        .collect();
+    assert_eq!(
+        formats,
+        [
+            (16_000, Some(8000)),
+            (8000, Some(4000)),
+            (16_000, Some(8000))
+        ]
+    );
+}
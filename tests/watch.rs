@@ -0,0 +1,84 @@
+#![cfg(all(feature = "watch", feature = "wav", feature = "pcm"))]
+
+use audyo::{
+    batch::{BatchError, BatchOptions},
+    encode_wav, generate,
+    scheduler::{Priority, Scheduler},
+    watch::{watch_dir, WatchEvent, WatchOptions},
+    Channels, WavOptions,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("audyo-watch-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("in")).unwrap();
+    dir
+}
+
+#[test]
+fn stop_returns_without_waiting_for_a_poll() {
+    let dir = temp_dir("stop");
+    let options = WatchOptions::new().poll_interval(Duration::from_secs(60));
+    let watcher = watch_dir(dir.join("in"), dir.join("out"), options, |_| {}).unwrap();
+
+    let start = Instant::now();
+    watcher.stop();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn polling_goes_on_while_transcoding() {
+    let dir = temp_dir("poll");
+    let tone = generate::sine(440., Duration::from_millis(100), 8000, Channels::Mono);
+
+    // Transcoding can't start while the only thread of the scheduler is held
+    let scheduler = Scheduler::new(1);
+    let (release, released) = mpsc::channel::<()>();
+    let held = scheduler.spawn(Priority::Normal, move || released.recv());
+
+    let (events, received) = mpsc::channel();
+    let options = WatchOptions::new()
+        .poll_interval(Duration::from_millis(10))
+        .debounce(Duration::ZERO)
+        .batch_options(BatchOptions::new().scheduler(scheduler));
+    let watcher = watch_dir(dir.join("in"), dir.join("out"), options, move |event| {
+        let _ = events.send(event);
+    })
+    .unwrap();
+    let next = || match received.recv_timeout(Duration::from_secs(30)).unwrap() {
+        WatchEvent::Finished(result) => result,
+        WatchEvent::ScanFailed(e) => panic!("{e}"),
+    };
+
+    fs::write(
+        dir.join("in/tone.wav"),
+        encode_wav(&tone, &WavOptions::new()).unwrap(),
+    )
+    .unwrap();
+    // Waits for the tone to be queued before adding files that get reported without transcoding
+    std::thread::sleep(Duration::from_millis(200));
+    fs::write(dir.join("in/a.mp3"), b"").unwrap();
+    fs::write(dir.join("in/a.flac"), b"").unwrap();
+    for _ in 0..2 {
+        let collision = next();
+        assert!(matches!(
+            collision.result,
+            Err(BatchError::OutputCollision(_))
+        ));
+    }
+
+    release.send(()).unwrap();
+    held.join().unwrap().unwrap();
+    let transcoded = next();
+    assert!(transcoded.input.ends_with("tone.wav"));
+    assert!(transcoded.result.is_ok());
+    watcher.stop();
+    fs::remove_dir_all(dir).unwrap();
+}